@@ -0,0 +1,374 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+#[derive(PartialEq, Eq, Clone, Default, Debug)]
+pub enum Command {
+    #[default]
+    Post,
+    Lint,
+    Check,
+    List,
+    Extremes,
+    Doctor,
+    Prune,
+    MigrateLog,
+    Daemon,
+    Snapshot { path: PathBuf },
+    Explain,
+    Simulate { iterations: usize },
+    Merge { paths: Vec<PathBuf> },
+    SelfTest,
+    AddMessage { id: String, count: Option<u64> },
+    RemoveMessage { id: String },
+    Replay { path: PathBuf },
+    Serve { addr: SocketAddr },
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Default, Debug)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<OutputFormat, ()> {
+        match value {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Default, Debug)]
+pub struct Args {
+    pub command: Command,
+    pub confirm: bool,
+    pub burst: Option<usize>,
+    pub delay_secs: u64,
+    pub settings_path: Option<String>,
+    pub env: Option<String>,
+    pub output_format: OutputFormat,
+    pub dry_run: bool,
+    pub edit_last: bool,
+    pub combine: Option<usize>,
+    pub force: Option<String>,
+    pub count_forced: bool,
+    // read instead of the log by the commands which never write it
+    pub from_snapshot: Option<PathBuf>,
+}
+
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Args, String> {
+    let mut parsed_args = Args::default();
+    let mut count = None;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--lint" => parsed_args.command = Command::Lint,
+            "--check" => parsed_args.command = Command::Check,
+            "--list" => parsed_args.command = Command::List,
+            "--extremes" => parsed_args.command = Command::Extremes,
+            "--doctor" => parsed_args.command = Command::Doctor,
+            "--prune" => parsed_args.command = Command::Prune,
+            "--migrate-log" => parsed_args.command = Command::MigrateLog,
+            "--daemon" => parsed_args.command = Command::Daemon,
+            "--snapshot" => parsed_args.command = Command::Snapshot { path: PathBuf::from(parse_value::<String>(&arg, args.next())?) },
+            "--from-snapshot" => parsed_args.from_snapshot = Some(PathBuf::from(parse_value::<String>(&arg, args.next())?)),
+            "--explain" => parsed_args.command = Command::Explain,
+            "--self-test" => parsed_args.command = Command::SelfTest,
+            "--simulate" => {
+                let iterations = parse_value(&arg, args.next())?;
+                if iterations == 0 {
+                    return Err(String::from("--simulate must be positive"));
+                }
+                parsed_args.command = Command::Simulate { iterations };
+            },
+            "--merge" => {
+                let path = PathBuf::from(parse_value::<String>(&arg, args.next())?);
+                match &mut parsed_args.command {
+                    Command::Merge { paths } => paths.push(path),
+                    command => *command = Command::Merge { paths: vec![path] },
+                }
+            },
+            "--add-message" => parsed_args.command = Command::AddMessage { id: parse_value(&arg, args.next())?, count: None },
+            "--count" => count = Some(parse_value(&arg, args.next())?),
+            "--remove-message" => parsed_args.command = Command::RemoveMessage { id: parse_value(&arg, args.next())? },
+            "--serve" => parsed_args.command = Command::Serve { addr: parse_value(&arg, args.next())? },
+            "--replay" => parsed_args.command = Command::Replay { path: PathBuf::from(parse_value::<String>(&arg, args.next())?) },
+            "--confirm" => parsed_args.confirm = true,
+            "--dry-run" => parsed_args.dry_run = true,
+            "--edit-last" => parsed_args.edit_last = true,
+            "--force" => parsed_args.force = Some(parse_value(&arg, args.next())?),
+            "--count-forced" => parsed_args.count_forced = true,
+            "--output" => parsed_args.output_format = parse_value(&arg, args.next())?,
+            "--burst" => {
+                let burst = parse_value(&arg, args.next())?;
+                if burst == 0 {
+                    return Err(String::from("--burst must be positive"));
+                }
+                parsed_args.burst = Some(burst);
+            },
+            "--combine" => {
+                let combine = parse_value(&arg, args.next())?;
+                if combine == 0 {
+                    return Err(String::from("--combine must be positive"));
+                }
+                parsed_args.combine = Some(combine);
+            },
+            "--delay" => parsed_args.delay_secs = parse_value(&arg, args.next())?,
+            "--settings" => parsed_args.settings_path = Some(parse_value(&arg, args.next())?),
+            "--env" => parsed_args.env = Some(parse_value(&arg, args.next())?),
+            _ => return Err(format!("unknown argument: {}", arg)),
+        }
+    }
+
+    if count.is_some() {
+        match &mut parsed_args.command {
+            Command::AddMessage { count: add_count, .. } => *add_count = count,
+            _ => return Err(String::from("--count requires --add-message")),
+        }
+    }
+    if parsed_args.from_snapshot.is_some() && !matches!(parsed_args.command, Command::Simulate { .. } | Command::List | Command::Extremes) {
+        return Err(String::from("--from-snapshot requires --simulate, --list or --extremes"));
+    }
+    if parsed_args.count_forced && parsed_args.force.is_none() {
+        return Err(String::from("--count-forced requires --force"));
+    }
+    if parsed_args.force.is_some() && (parsed_args.burst.is_some() || parsed_args.combine.is_some()) {
+        return Err(String::from("--force cannot be used with --burst or --combine"));
+    }
+    Ok(parsed_args)
+}
+
+fn parse_value<T: std::str::FromStr>(arg: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("{} requires a value", arg))?;
+    value.parse().map_err(|_| format!("invalid value for {}: {}", arg, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_args_should_return_the_post_command_if_no_arguments_are_given() {
+        let args = parse_args(Vec::new()).unwrap();
+        assert_eq!(args, Args { command: Command::Post, ..Args::default() });
+    }
+
+    #[test]
+    fn parse_args_should_return_the_lint_command() {
+        let args = parse_args(vec![String::from("--lint")]).unwrap();
+        assert_eq!(args, Args { command: Command::Lint, ..Args::default() });
+    }
+
+    #[test]
+    fn parse_args_should_return_the_check_command() {
+        let args = parse_args(vec![String::from("--check")]).unwrap();
+        assert_eq!(args, Args { command: Command::Check, ..Args::default() });
+    }
+
+    #[test]
+    fn parse_args_should_return_the_list_command() {
+        let args = parse_args(vec![String::from("--list")]).unwrap();
+        assert_eq!(args, Args { command: Command::List, ..Args::default() });
+    }
+
+    #[test]
+    fn parse_args_should_return_the_extremes_command() {
+        let args = parse_args(vec![String::from("--extremes")]).unwrap();
+        assert_eq!(args, Args { command: Command::Extremes, ..Args::default() });
+    }
+
+    #[test]
+    fn parse_args_should_return_the_doctor_and_the_prune_commands() {
+        let args = parse_args(vec![String::from("--doctor")]).unwrap();
+        assert_eq!(args, Args { command: Command::Doctor, ..Args::default() });
+
+        let args = parse_args(vec![String::from("--prune")]).unwrap();
+        assert_eq!(args, Args { command: Command::Prune, ..Args::default() });
+    }
+
+    #[test]
+    fn parse_args_should_read_the_forced_message() {
+        let args = parse_args(vec![String::from("--force"), String::from("b")]).unwrap();
+        assert_eq!(args, Args { force: Some(String::from("b")), ..Args::default() });
+
+        let args = parse_args(vec![String::from("--force"), String::from("b"), String::from("--count-forced")]).unwrap();
+        assert_eq!(args, Args { force: Some(String::from("b")), count_forced: true, ..Args::default() });
+    }
+
+    #[test]
+    fn parse_args_should_fail_if_the_forced_message_is_used_wrongly() {
+        assert!(parse_args(vec![String::from("--force")]).is_err());
+        assert!(parse_args(vec![String::from("--count-forced")]).is_err());
+        assert!(parse_args(vec![String::from("--force"), String::from("b"), String::from("--burst"), String::from("2")]).is_err());
+        assert!(parse_args(vec![String::from("--force"), String::from("b"), String::from("--combine"), String::from("2")]).is_err());
+    }
+
+    #[test]
+    fn parse_args_should_read_the_snapshot_paths() {
+        let args = parse_args(vec![String::from("--snapshot"), String::from("baseline.json")]).unwrap();
+        assert_eq!(args, Args { command: Command::Snapshot { path: PathBuf::from("baseline.json") }, ..Args::default() });
+
+        let args = parse_args(vec![String::from("--from-snapshot"), String::from("baseline.json"), String::from("--simulate"), String::from("100")]).unwrap();
+        assert_eq!(args, Args { command: Command::Simulate { iterations: 100 }, from_snapshot: Some(PathBuf::from("baseline.json")), ..Args::default() });
+
+        assert!(parse_args(vec![String::from("--from-snapshot"), String::from("baseline.json")]).is_err());
+    }
+
+    #[test]
+    fn parse_args_should_return_the_daemon_command() {
+        let args = parse_args(vec![String::from("--daemon")]).unwrap();
+        assert_eq!(args, Args { command: Command::Daemon, ..Args::default() });
+    }
+
+    #[test]
+    fn parse_args_should_return_the_migrate_log_command() {
+        let args = parse_args(vec![String::from("--migrate-log")]).unwrap();
+        assert_eq!(args, Args { command: Command::MigrateLog, ..Args::default() });
+    }
+
+    #[test]
+    fn parse_args_should_read_the_address_to_serve_on() {
+        let args = parse_args(vec![String::from("--serve"), String::from("127.0.0.1:8080")]).unwrap();
+        assert_eq!(args, Args { command: Command::Serve { addr: "127.0.0.1:8080".parse().unwrap() }, ..Args::default() });
+
+        assert!(parse_args(vec![String::from("--serve"), String::from("localhost")]).is_err());
+    }
+
+    #[test]
+    fn parse_args_should_read_the_settings_path() {
+        let args = parse_args(vec![String::from("--settings"), String::from("-")]).unwrap();
+        assert_eq!(args, Args { settings_path: Some(String::from("-")), ..Args::default() });
+    }
+
+    #[test]
+    fn parse_args_should_read_the_env() {
+        let args = parse_args(vec![String::from("--env"), String::from("prod")]).unwrap();
+        assert_eq!(args, Args { env: Some(String::from("prod")), ..Args::default() });
+    }
+
+    #[test]
+    fn parse_args_should_return_the_explain_command() {
+        let args = parse_args(vec![String::from("--explain")]).unwrap();
+        assert_eq!(args, Args { command: Command::Explain, ..Args::default() });
+    }
+
+    #[test]
+    fn parse_args_should_return_the_self_test_command() {
+        let args = parse_args(vec![String::from("--self-test")]).unwrap();
+        assert_eq!(args, Args { command: Command::SelfTest, ..Args::default() });
+    }
+
+    #[test]
+    fn parse_args_should_read_the_output_format_and_the_dry_run_flag() {
+        let args = parse_args(vec![String::from("--output"), String::from("json"), String::from("--dry-run")]).unwrap();
+        assert_eq!(args, Args { output_format: OutputFormat::Json, dry_run: true, ..Args::default() });
+    }
+
+    #[test]
+    fn parse_args_should_read_the_edit_last_flag() {
+        let args = parse_args(vec![String::from("--edit-last")]).unwrap();
+        assert_eq!(args, Args { command: Command::Post, edit_last: true, ..Args::default() });
+    }
+
+    #[test]
+    fn parse_args_should_fail_if_the_output_format_is_unknown() {
+        let result = parse_args(vec![String::from("--output"), String::from("yaml")]);
+        assert_eq!(result, Err(String::from("invalid value for --output: yaml")));
+    }
+
+    #[test]
+    fn parse_args_should_return_the_simulate_command() {
+        let args = parse_args(vec![String::from("--simulate"), String::from("1000")]).unwrap();
+        assert_eq!(args, Args { command: Command::Simulate { iterations: 1000 }, ..Args::default() });
+    }
+
+    #[test]
+    fn parse_args_should_return_the_add_message_command_with_the_count() {
+        let args = parse_args(vec![String::from("--count"), String::from("5"), String::from("--add-message"), String::from("abc")]).unwrap();
+        assert_eq!(args, Args { command: Command::AddMessage { id: String::from("abc"), count: Some(5) }, ..Args::default() });
+
+        let args = parse_args(vec![String::from("--add-message"), String::from("abc")]).unwrap();
+        assert_eq!(args, Args { command: Command::AddMessage { id: String::from("abc"), count: None }, ..Args::default() });
+    }
+
+    #[test]
+    fn parse_args_should_fail_if_the_count_is_given_without_add_message() {
+        let result = parse_args(vec![String::from("--remove-message"), String::from("abc"), String::from("--count"), String::from("5")]);
+        assert_eq!(result, Err(String::from("--count requires --add-message")));
+    }
+
+    #[test]
+    fn parse_args_should_return_the_remove_message_command() {
+        let args = parse_args(vec![String::from("--remove-message"), String::from("abc")]).unwrap();
+        assert_eq!(args, Args { command: Command::RemoveMessage { id: String::from("abc") }, ..Args::default() });
+    }
+
+    #[test]
+    fn parse_args_should_return_the_replay_command() {
+        let args = parse_args(vec![String::from("--replay"), String::from("dead-letters.jsonl")]).unwrap();
+        assert_eq!(args, Args { command: Command::Replay { path: PathBuf::from("dead-letters.jsonl") }, ..Args::default() });
+    }
+
+    #[test]
+    fn parse_args_should_fail_if_the_simulate_iterations_is_zero() {
+        let result = parse_args(vec![String::from("--simulate"), String::from("0")]);
+        assert_eq!(result, Err(String::from("--simulate must be positive")));
+    }
+
+    #[test]
+    fn parse_args_should_return_the_merge_command_with_every_path() {
+        let args = parse_args(vec![String::from("--merge"), String::from("a.json"), String::from("--merge"), String::from("b.json")]).unwrap();
+        assert_eq!(args, Args { command: Command::Merge { paths: vec![PathBuf::from("a.json"), PathBuf::from("b.json")] }, ..Args::default() });
+    }
+
+    #[test]
+    fn parse_args_should_enable_confirmation() {
+        let args = parse_args(vec![String::from("--confirm")]).unwrap();
+        assert_eq!(args, Args { confirm: true, ..Args::default() });
+    }
+
+    #[test]
+    fn parse_args_should_read_the_burst_and_the_delay() {
+        let args = parse_args(vec![String::from("--burst"), String::from("3"), String::from("--delay"), String::from("60")]).unwrap();
+        assert_eq!(args, Args { burst: Some(3), delay_secs: 60, ..Args::default() });
+    }
+
+    #[test]
+    fn parse_args_should_read_the_combine_count() {
+        let args = parse_args(vec![String::from("--combine"), String::from("3")]).unwrap();
+        assert_eq!(args, Args { combine: Some(3), ..Args::default() });
+
+        let result = parse_args(vec![String::from("--combine"), String::from("0")]);
+        assert_eq!(result, Err(String::from("--combine must be positive")));
+    }
+
+    #[test]
+    fn parse_args_should_fail_if_a_value_is_missing() {
+        let result = parse_args(vec![String::from("--burst")]);
+        assert_eq!(result, Err(String::from("--burst requires a value")));
+    }
+
+    #[test]
+    fn parse_args_should_fail_if_a_value_is_invalid() {
+        let result = parse_args(vec![String::from("--delay"), String::from("abc")]);
+        assert_eq!(result, Err(String::from("invalid value for --delay: abc")));
+    }
+
+    #[test]
+    fn parse_args_should_fail_if_the_burst_is_zero() {
+        let result = parse_args(vec![String::from("--burst"), String::from("0")]);
+        assert_eq!(result, Err(String::from("--burst must be positive")));
+    }
+
+    #[test]
+    fn parse_args_should_fail_if_an_unknown_argument_is_given() {
+        let result = parse_args(vec![String::from("--unknown")]);
+        assert_eq!(result, Err(String::from("unknown argument: --unknown")));
+    }
+}
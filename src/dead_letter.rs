@@ -0,0 +1,99 @@
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use std::fs::OpenOptions;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use super::file::write_atomically;
+
+// A post which failed permanently, with the JSON body so that it can be replayed as it was.
+#[derive(PartialEq, Eq, Clone, Serialize, Deserialize, Debug)]
+pub struct DeadLetter {
+    pub failed_at: DateTime<Utc>,
+    pub message_id: String,
+    pub url: String,
+    pub body: String,
+    pub error: String,
+}
+
+pub fn append_dead_letters(path: &Path, entries: &[DeadLetter]) -> Result<(), String> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path).map_err(|_| format!("could not open file: {}", path.display()))?;
+    let mut file_writer = BufWriter::new(&mut file);
+    write_entries(&mut file_writer, entries)?;
+    file_writer.flush().map_err(|e| format!("failed to write dead letters: {}", e))
+}
+
+pub fn read_dead_letters(path: &Path) -> Result<Vec<DeadLetter>, String> {
+    let text = std::fs::read_to_string(path).map_err(|_| format!("could not open file: {}", path.display()))?;
+    text.lines().enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| serde_json::from_str(line).map_err(|e| format!("invalid dead letter at line {}: {}", index + 1, e)))
+        .collect()
+}
+
+// Replaces the file with the given entries, which is used to drop the replayed ones.
+pub fn write_dead_letters(path: &Path, entries: &[DeadLetter]) -> Result<(), String> {
+    write_atomically(path, |file_writer| write_entries(file_writer, entries))
+}
+
+fn write_entries<W: Write>(writer: &mut W, entries: &[DeadLetter]) -> Result<(), String> {
+    let to_error = |e: std::io::Error| format!("failed to write dead letters: {}", e);
+    for entry in entries {
+        serde_json::to_writer(&mut *writer, entry).map_err(|e| format!("failed to write dead letters: {}", e))?;
+        writer.write_all(b"\n").map_err(to_error)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate tempfile;
+
+    use super::*;
+    use chrono::TimeZone;
+    use tempfile::TempDir;
+
+    fn dead_letter(message_id: &str) -> DeadLetter {
+        DeadLetter {
+            failed_at: Utc.with_ymd_and_hms(2021, 9, 1, 12, 0, 0).unwrap(),
+            message_id: String::from(message_id),
+            url: String::from("https://example.com/webhook"),
+            body: format!(r#"{{"content":"{}"}}"#, message_id),
+            error: String::from("failed to post: status 400"),
+        }
+    }
+
+    #[test]
+    fn append_dead_letters_should_append_json_lines_which_read_dead_letters_reads() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("dead-letters.jsonl");
+
+        append_dead_letters(&path, &[dead_letter("abc")]).unwrap();
+        append_dead_letters(&path, &[dead_letter("def")]).unwrap();
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(text.lines().next(), Some(r#"{"failed_at":"2021-09-01T12:00:00Z","message_id":"abc","url":"https://example.com/webhook","body":"{\"content\":\"abc\"}","error":"failed to post: status 400"}"#));
+        assert_eq!(read_dead_letters(&path), Ok(vec![dead_letter("abc"), dead_letter("def")]));
+    }
+
+    #[test]
+    fn write_dead_letters_should_replace_the_entries() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("dead-letters.jsonl");
+
+        append_dead_letters(&path, &[dead_letter("abc"), dead_letter("def")]).unwrap();
+        write_dead_letters(&path, &[dead_letter("def")]).unwrap();
+
+        assert_eq!(read_dead_letters(&path), Ok(vec![dead_letter("def")]));
+    }
+
+    #[test]
+    fn read_dead_letters_should_fail_with_the_line_number() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("dead-letters.jsonl");
+        std::fs::write(&path, "\n{}\n").unwrap();
+
+        let result = read_dead_letters(&path);
+        assert!(result.unwrap_err().starts_with("invalid dead letter at line 2: "));
+    }
+}
@@ -0,0 +1,118 @@
+use std::path::Path;
+
+use super::settings::Settings;
+use super::{list_entries, load_picker, lock_log};
+
+#[derive(PartialEq, Eq, Debug)]
+struct DoctorReport {
+    in_both: Vec<String>,
+    // initialized with their initial counts on the next post
+    only_in_settings: Vec<String>,
+    // ignored when picking, and dropped from the log on the next post
+    only_in_log: Vec<String>,
+}
+
+pub fn doctor(settings: &Settings, log_path: &Path) -> Result<(), String> {
+    let report = doctor_report(settings, log_path)?;
+    let print_ids = |title: &str, ids: &[String]| {
+        let ids: Vec<String> = ids.iter().map(|id| format!("`{}`", id)).collect();
+        println!("{} ({}): {}", title, ids.len(), if ids.is_empty() { String::from("none") } else { ids.join(", ") });
+    };
+    print_ids("in the settings and the log", &report.in_both);
+    print_ids("only in the settings, which start with their initial counts", &report.only_in_settings);
+    print_ids("only in the log, which are ignored", &report.only_in_log);
+    if !report.only_in_log.is_empty() {
+        println!("run with --prune to remove the messages only in the log from it");
+    }
+    Ok(())
+}
+
+fn doctor_report(settings: &Settings, log_path: &Path) -> Result<DoctorReport, String> {
+    let mut report = DoctorReport { in_both: Vec::new(), only_in_settings: Vec::new(), only_in_log: Vec::new() };
+    for entry in list_entries(settings, log_path)? {
+        match (entry.in_settings, entry.in_log) {
+            (true, true) => report.in_both.push(entry.id),
+            (true, false) => report.only_in_settings.push(entry.id),
+            _ => report.only_in_log.push(entry.id),
+        }
+    }
+    Ok(report)
+}
+
+// Writing the log drops the messages which are only in it, and records the messages only in the settings with their initial counts.
+pub fn prune(settings: &Settings, log_path: &Path) -> Result<(), String> {
+    let _lock = lock_log(settings, log_path)?;
+    let only_in_log = doctor_report(settings, log_path)?.only_in_log;
+    if only_in_log.is_empty() {
+        println!("no messages to prune");
+        return Ok(());
+    }
+    load_picker(settings, log_path)?.write_log()?;
+    let ids: Vec<String> = only_in_log.iter().map(|id| format!("`{}`", id)).collect();
+    println!("removed {} from {}", ids.join(", "), log_path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate indoc;
+    extern crate tempfile;
+
+    use super::*;
+    use indoc::indoc;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    use super::super::random::read_log_counts;
+
+    #[test]
+    fn doctor_report_should_split_the_ids_in_the_settings_and_the_log() {
+        let mut log_file = NamedTempFile::new().unwrap();
+        write!(log_file, r#"[{{ "value": "abc", "count": 3 }}, {{ "value": "old", "count": 5 }}]"#).unwrap();
+
+        let settings: Settings = serde_yaml::from_str(indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/XXXX/YYYY"
+              weight_type:
+                type: "Uniform"
+            messages:
+              def: "message2"
+              abc: "message1"
+        "#}).unwrap();
+
+        let report = doctor_report(&settings, log_file.path()).unwrap();
+        let expected = DoctorReport {
+            in_both: vec![String::from("abc")],
+            only_in_settings: vec![String::from("def")],
+            only_in_log: vec![String::from("old")],
+        };
+        assert_eq!(report, expected);
+    }
+
+    #[test]
+    fn prune_should_remove_the_ids_only_in_the_log() {
+        let mut log_file = NamedTempFile::new().unwrap();
+        write!(log_file, r#"[{{ "value": "abc", "count": 3 }}, {{ "value": "old", "count": 5 }}]"#).unwrap();
+
+        let settings: Settings = serde_yaml::from_str(indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/XXXX/YYYY"
+              weight_type:
+                type: "Uniform"
+            messages:
+              def: "message2"
+              abc: "message1"
+        "#}).unwrap();
+
+        prune(&settings, log_file.path()).unwrap();
+
+        let report = doctor_report(&settings, log_file.path()).unwrap();
+        let expected = DoctorReport {
+            in_both: vec![String::from("abc"), String::from("def")],
+            only_in_settings: Vec::new(),
+            only_in_log: Vec::new(),
+        };
+        assert_eq!(report, expected);
+        assert_eq!(read_log_counts::<String>(log_file.path(), settings.environment.log_format).unwrap().get("abc"), Some(&3));
+    }
+}
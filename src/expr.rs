@@ -0,0 +1,197 @@
+// A small arithmetic expression for `WeightType::Expr`, with `+`, `-`, `*`, `/`, `^`, parentheses, a few functions and variables.
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum Expr {
+    Number(f64),
+    Variable(String),
+    Negate(Box<Expr>),
+    Binary(char, Box<Expr>, Box<Expr>),
+    Function(String, Box<Expr>),
+}
+
+const FUNCTIONS: &[&str] = &["abs", "exp", "ln", "sqrt"];
+
+impl Expr {
+    // Fails if the formula is malformed or uses a variable which is not in `variables`.
+    pub fn parse(formula: &str, variables: &[&str]) -> Result<Expr, String> {
+        let mut parser = Parser { chars: formula.chars().collect(), position: 0, variables };
+        let expr = parser.parse_sum()?;
+        parser.skip_whitespace();
+        if let Some(c) = parser.peek() {
+            return Err(format!("unexpected `{}` at {}", c, parser.position));
+        }
+        Ok(expr)
+    }
+
+    // Returns NaN for the variables missing from `values`, which `parse` already rejects.
+    pub fn evaluate(&self, values: &dyn Fn(&str) -> Option<f64>) -> f64 {
+        match self {
+            Expr::Number(number) => *number,
+            Expr::Variable(name) => values(name).unwrap_or(f64::NAN),
+            Expr::Negate(expr) => -expr.evaluate(values),
+            Expr::Binary(operator, left, right) => {
+                let (left, right) = (left.evaluate(values), right.evaluate(values));
+                match operator {
+                    '+' => left + right,
+                    '-' => left - right,
+                    '*' => left * right,
+                    '/' => left / right,
+                    _ => left.powf(right),
+                }
+            },
+            Expr::Function(name, argument) => {
+                let argument = argument.evaluate(values);
+                match name.as_str() {
+                    "abs" => argument.abs(),
+                    "exp" => argument.exp(),
+                    "ln" => argument.ln(),
+                    _ => argument.sqrt(),
+                }
+            },
+        }
+    }
+}
+
+struct Parser<'a> {
+    chars: Vec<char>,
+    position: usize,
+    variables: &'a [&'a str],
+}
+
+impl<'a> Parser<'a> {
+    fn parse_sum(&mut self) -> Result<Expr, String> {
+        let mut expr = self.parse_product()?;
+        while let Some(operator) = self.next_operator(&['+', '-']) {
+            expr = Expr::Binary(operator, Box::new(expr), Box::new(self.parse_product()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_product(&mut self) -> Result<Expr, String> {
+        let mut expr = self.parse_unary()?;
+        while let Some(operator) = self.next_operator(&['*', '/']) {
+            expr = Expr::Binary(operator, Box::new(expr), Box::new(self.parse_unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, String> {
+        if self.next_operator(&['-']).is_some() {
+            return Ok(Expr::Negate(Box::new(self.parse_unary()?)));
+        }
+        self.parse_power()
+    }
+
+    // `^` is right associative and binds tighter than a unary minus on its left, so `-2^2` is -4.
+    fn parse_power(&mut self) -> Result<Expr, String> {
+        let base = self.parse_atom()?;
+        if self.next_operator(&['^']).is_some() {
+            return Ok(Expr::Binary('^', Box::new(base), Box::new(self.parse_unary()?)));
+        }
+        Ok(base)
+    }
+
+    fn parse_atom(&mut self) -> Result<Expr, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('(') => {
+                self.position += 1;
+                let expr = self.parse_sum()?;
+                self.expect(')')?;
+                Ok(expr)
+            },
+            Some(c) if c.is_ascii_digit() || c == '.' => {
+                let number = self.take_while(|c| c.is_ascii_digit() || c == '.');
+                number.parse().map(Expr::Number).map_err(|_| format!("invalid number `{}`", number))
+            },
+            Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+                let name = self.take_while(|c| c.is_ascii_alphanumeric() || c == '_');
+                if FUNCTIONS.contains(&name.as_str()) {
+                    self.expect('(')?;
+                    let argument = self.parse_sum()?;
+                    self.expect(')')?;
+                    Ok(Expr::Function(name, Box::new(argument)))
+                } else if self.variables.contains(&name.as_str()) {
+                    Ok(Expr::Variable(name))
+                } else {
+                    Err(format!("unknown variable `{}`", name))
+                }
+            },
+            Some(c) => Err(format!("unexpected `{}` at {}", c, self.position)),
+            None => Err(String::from("unexpected end of the formula")),
+        }
+    }
+
+    fn next_operator(&mut self, operators: &[char]) -> Option<char> {
+        self.skip_whitespace();
+        let c = self.peek().filter(|c| operators.contains(c))?;
+        self.position += 1;
+        Some(c)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.position += 1;
+                Ok(())
+            },
+            Some(c) => Err(format!("expected `{}`, but found `{}` at {}", expected, c, self.position)),
+            None => Err(format!("expected `{}`, but the formula ended", expected)),
+        }
+    }
+
+    fn take_while<F: Fn(char) -> bool>(&mut self, predicate: F) -> String {
+        let start = self.position;
+        while self.peek().is_some_and(&predicate) {
+            self.position += 1;
+        }
+        self.chars[start..self.position].iter().collect()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.position += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate(formula: &str) -> f64 {
+        let expr = Expr::parse(formula, &["x"]).unwrap();
+        expr.evaluate(&|name| if name == "x" { Some(3.0) } else { None })
+    }
+
+    #[test]
+    fn evaluate_should_follow_the_precedence_of_the_operators() {
+        assert_eq!(evaluate("1 + 2 * 3"), 7.0);
+        assert_eq!(evaluate("(1 + 2) * 3"), 9.0);
+        assert_eq!(evaluate("10 - 4 - 3"), 3.0);
+        assert_eq!(evaluate("2 ^ 3 ^ 2"), 512.0);
+        assert_eq!(evaluate("-2 ^ 2"), -4.0);
+        assert_eq!(evaluate("12 / x / 2"), 2.0);
+    }
+
+    #[test]
+    fn evaluate_should_use_the_variables_and_the_functions() {
+        assert_eq!(evaluate("x * x - 1"), 8.0);
+        assert_eq!(evaluate("exp(0) + abs(-x) + sqrt(16)"), 8.0);
+        assert_eq!(evaluate("ln(1)"), 0.0);
+    }
+
+    #[test]
+    fn parse_should_fail_if_the_formula_is_malformed() {
+        assert_eq!(Expr::parse("1 +", &["x"]), Err(String::from("unexpected end of the formula")));
+        assert_eq!(Expr::parse("(1 + 2", &["x"]), Err(String::from("expected `)`, but the formula ended")));
+        assert_eq!(Expr::parse("1 2", &["x"]), Err(String::from("unexpected `2` at 2")));
+        assert_eq!(Expr::parse("y + 1", &["x"]), Err(String::from("unknown variable `y`")));
+        assert_eq!(Expr::parse("1..2", &["x"]), Err(String::from("invalid number `1..2`")));
+    }
+}
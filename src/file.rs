@@ -0,0 +1,107 @@
+use serde::Deserialize;
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+// Whether a run waits for another run holding the lock of the log, or fails at once.
+#[derive(PartialEq, Eq, Clone, Copy, Deserialize, Debug)]
+pub enum LockMode {
+    Wait,
+    Fail,
+}
+
+// An advisory lock, which is released when this is dropped.
+pub struct FileLock {
+    _file: File,
+}
+
+// Locks a `.lock` file next to `path` rather than `path` itself, which write_atomically replaces with another file.
+pub fn lock(path: &Path, mode: LockMode) -> Result<FileLock, String> {
+    let lock_path = sibling_path(path, ".lock");
+    let file = OpenOptions::new().create(true).truncate(false).write(true).open(&lock_path)
+        .map_err(|_| format!("could not open file: {}", lock_path.display()))?;
+    match mode {
+        LockMode::Wait => file.lock().map_err(|e| format!("failed to lock {}: {}", lock_path.display(), e))?,
+        LockMode::Fail => file.try_lock().map_err(|e| match e {
+            TryLockError::WouldBlock => format!("{} is locked by another run", path.display()),
+            TryLockError::Error(e) => format!("failed to lock {}: {}", lock_path.display(), e),
+        })?,
+    }
+    Ok(FileLock { _file: file })
+}
+
+// Writes into a temporary file next to the destination and renames it, so that the destination is never left half-written.
+pub fn write_atomically<F: FnOnce(&mut BufWriter<&mut File>) -> Result<(), String>>(path: &Path, write: F) -> Result<(), String> {
+    let temp_path = temp_path(path);
+    let result = write_and_rename(path, &temp_path, write);
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+    }
+    result
+}
+
+fn write_and_rename<F: FnOnce(&mut BufWriter<&mut File>) -> Result<(), String>>(path: &Path, temp_path: &Path, write: F) -> Result<(), String> {
+    let mut file = File::create(temp_path).map_err(|_| format!("could not open file: {}", temp_path.display()))?;
+    let mut file_writer = BufWriter::new(&mut file);
+    write(&mut file_writer)?;
+    file_writer.flush().map_err(|e| format!("failed to write file: {}", e))?;
+    drop(file_writer);
+    file.sync_all().map_err(|e| format!("failed to write file: {}", e))?;
+
+    std::fs::rename(temp_path, path).map_err(|e| format!("failed to rename file: {}", e))
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+    sibling_path(path, ".tmp")
+}
+
+fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut file_name = path.file_name().map(|name| name.to_owned()).unwrap_or_default();
+    file_name.push(suffix);
+    path.with_file_name(file_name)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate tempfile;
+
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn write_atomically_should_replace_the_file() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("file.txt");
+        std::fs::write(&path, "old").unwrap();
+
+        write_atomically(&path, |writer| writer.write_all(b"new").map_err(|e| e.to_string())).unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new");
+        assert!(!dir.path().join("file.txt.tmp").exists());
+    }
+
+    #[test]
+    fn lock_should_fail_while_another_lock_is_held_in_the_fail_mode() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("file.txt");
+
+        let first_lock = lock(&path, LockMode::Fail).unwrap();
+        assert_eq!(lock(&path, LockMode::Fail).err(), Some(format!("{} is locked by another run", path.display())));
+        drop(first_lock);
+        assert!(lock(&path, LockMode::Fail).is_ok());
+        assert!(dir.path().join("file.txt.lock").exists());
+    }
+
+    #[test]
+    fn write_atomically_should_keep_the_file_if_writing_fails() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("file.txt");
+        std::fs::write(&path, "old").unwrap();
+
+        let result = write_atomically(&path, |_| Err(String::from("failed")));
+
+        assert_eq!(result, Err(String::from("failed")));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "old");
+        assert!(!dir.path().join("file.txt.tmp").exists());
+    }
+}
@@ -0,0 +1,252 @@
+// A minimal gzip encoder for request bodies, using a single deflate block with the fixed Huffman codes and a greedy LZ77 search.
+// It is far simpler than zlib, but JSON bodies with many similar embeds compress well enough with it.
+
+const WINDOW_SIZE: usize = 32768;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const HASH_SIZE: usize = 1 << 15;
+
+const LENGTH_BASES: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA_BITS: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASES: [u16; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+const DISTANCE_EXTRA_BITS: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+pub fn gzip(data: &[u8]) -> Vec<u8> {
+    // no file name and no modification time, and 255 for an unknown OS
+    let mut output = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 255];
+    output.extend(deflate(data));
+    output.extend(crc32(data).to_le_bytes());
+    output.extend((data.len() as u32).to_le_bytes());
+    output
+}
+
+fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter::default();
+    // BFINAL = 1 and BTYPE = 01 for the fixed Huffman codes
+    writer.write_bits(1, 1);
+    writer.write_bits(1, 2);
+
+    let mut last_positions = vec![usize::MAX; HASH_SIZE];
+    let mut position = 0;
+    while position < data.len() {
+        let (length, distance) = find_match(data, position, &last_positions);
+        if length >= MIN_MATCH {
+            write_length(&mut writer, length);
+            write_distance(&mut writer, distance);
+            for skipped in position..position + length {
+                insert_hash(data, skipped, &mut last_positions);
+            }
+            position += length;
+        } else {
+            write_literal(&mut writer, data[position] as u16);
+            insert_hash(data, position, &mut last_positions);
+            position += 1;
+        }
+    }
+    write_literal(&mut writer, 256);
+    writer.finish()
+}
+
+fn hash(data: &[u8], position: usize) -> Option<usize> {
+    let bytes = data.get(position..position + MIN_MATCH)?;
+    Some(((bytes[0] as usize) << 10 ^ (bytes[1] as usize) << 5 ^ bytes[2] as usize) % HASH_SIZE)
+}
+
+fn insert_hash(data: &[u8], position: usize, last_positions: &mut [usize]) {
+    if let Some(hash) = hash(data, position) {
+        last_positions[hash] = position;
+    }
+}
+
+fn find_match(data: &[u8], position: usize, last_positions: &[usize]) -> (usize, usize) {
+    let candidate = match hash(data, position).map(|hash| last_positions[hash]) {
+        Some(candidate) if candidate != usize::MAX && position - candidate <= WINDOW_SIZE => candidate,
+        _ => return (0, 0),
+    };
+    let max_length = MAX_MATCH.min(data.len() - position);
+    let length = (0..max_length).take_while(|offset| data[candidate + offset] == data[position + offset]).count();
+    (length, position - candidate)
+}
+
+fn write_literal(writer: &mut BitWriter, value: u16) {
+    let (code, length) = match value {
+        0..=143 => (0x30 + value, 8),
+        144..=255 => (0x190 + value - 144, 9),
+        256..=279 => (value - 256, 7),
+        _ => (0xc0 + value - 280, 8),
+    };
+    writer.write_huffman_code(code, length);
+}
+
+fn write_length(writer: &mut BitWriter, length: usize) {
+    let index = LENGTH_BASES.iter().rposition(|base| *base as usize <= length).unwrap();
+    write_literal(writer, 257 + index as u16);
+    writer.write_bits(length as u32 - LENGTH_BASES[index] as u32, LENGTH_EXTRA_BITS[index]);
+}
+
+fn write_distance(writer: &mut BitWriter, distance: usize) {
+    let index = DISTANCE_BASES.iter().rposition(|base| *base as usize <= distance).unwrap();
+    writer.write_huffman_code(index as u16, 5);
+    writer.write_bits(distance as u32 - DISTANCE_BASES[index] as u32, DISTANCE_EXTRA_BITS[index]);
+}
+
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    current: u32,
+    bit_count: u8,
+}
+
+impl BitWriter {
+    // deflate packs values from the least significant bit
+    fn write_bits(&mut self, value: u32, count: u8) {
+        for bit in 0..count {
+            self.current |= ((value >> bit) & 1) << self.bit_count;
+            self.bit_count += 1;
+            if self.bit_count == 8 {
+                self.bytes.push(self.current as u8);
+                self.current = 0;
+                self.bit_count = 0;
+            }
+        }
+    }
+
+    // while Huffman codes are packed from the most significant bit
+    fn write_huffman_code(&mut self, code: u16, length: u8) {
+        for bit in (0..length).rev() {
+            self.write_bits(((code >> bit) & 1) as u32, 1);
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bit_count > 0 {
+            self.bytes.push(self.current as u8);
+        }
+        self.bytes
+    }
+}
+
+// Decodes the output of `gzip` for the tests, which only needs the fixed Huffman codes.
+#[cfg(test)]
+pub fn gunzip(data: &[u8]) -> Vec<u8> {
+    let mut reader = BitReader { bytes: &data[10..data.len() - 8], position: 0 };
+    assert_eq!((reader.read_bits(1), reader.read_bits(2)), (1, 1), "not a single block with the fixed Huffman codes");
+    let mut output: Vec<u8> = Vec::new();
+    loop {
+        match reader.read_literal() {
+            symbol @ 0..=255 => output.push(symbol as u8),
+            256 => break,
+            symbol => {
+                let index = (symbol - 257) as usize;
+                let length = LENGTH_BASES[index] as usize + reader.read_bits(LENGTH_EXTRA_BITS[index]) as usize;
+                let distance_index = reader.read_huffman_code(5) as usize;
+                let distance = DISTANCE_BASES[distance_index] as usize + reader.read_bits(DISTANCE_EXTRA_BITS[distance_index]) as usize;
+                for _ in 0..length {
+                    output.push(output[output.len() - distance]);
+                }
+            },
+        }
+    }
+    assert_eq!(data[data.len() - 8..data.len() - 4], crc32(&output).to_le_bytes());
+    output
+}
+
+#[cfg(test)]
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+#[cfg(test)]
+impl BitReader<'_> {
+    fn read_bits(&mut self, count: u8) -> u32 {
+        let mut value = 0;
+        for bit in 0..count {
+            value |= (((self.bytes[self.position / 8] >> (self.position % 8)) & 1) as u32) << bit;
+            self.position += 1;
+        }
+        value
+    }
+
+    fn read_huffman_code(&mut self, length: u8) -> u32 {
+        (0..length).fold(0, |code, _| (code << 1) | self.read_bits(1))
+    }
+
+    // The fixed codes are 7 bits for 256-279, 8 bits for 0-143 and 280-287, and 9 bits for 144-255.
+    fn read_literal(&mut self) -> u32 {
+        let code = self.read_huffman_code(7);
+        if code <= 0b0010111 {
+            return 256 + code;
+        }
+        let code = (code << 1) | self.read_bits(1);
+        match code {
+            0b00110000..=0b10111111 => code - 0b00110000,
+            0b11000000..=0b11000111 => 280 + code - 0b11000000,
+            _ => 144 + ((code << 1) | self.read_bits(1)) - 0b110010000,
+        }
+    }
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb88320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32_should_return_the_standard_checksum() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+    }
+
+    #[test]
+    fn gzip_should_encode_a_short_input_with_literals_only() {
+        let expected = vec![
+            0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 255,
+            0x4b, 0x4c, 0x4a, 0x06, 0x00,
+            0xc2, 0x41, 0x24, 0x35, 0x03, 0x00, 0x00, 0x00,
+        ];
+        assert_eq!(gzip(b"abc"), expected);
+    }
+
+    #[test]
+    fn gzip_should_end_with_the_crc32_and_the_size_of_the_input() {
+        // the same trailer as `printf 'hello world' | gzip`
+        assert_eq!(gzip(b"hello world")[10 + 13..], [0x85, 0x11, 0x4a, 0x0d, 0x0b, 0x00, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn gzip_should_encode_a_back_reference_as_the_system_gzip_reads_it() {
+        // checked with `gzip -d`, which decodes it to the input
+        let expected = vec![
+            0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 255,
+            0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0x40, 0x26, 0xcb, 0xf3, 0x8b, 0x72, 0x52, 0x00,
+            0x26, 0xe6, 0x5a, 0x81, 0x17, 0x00, 0x00, 0x00,
+        ];
+        assert_eq!(gzip(b"hello hello hello world"), expected);
+    }
+
+    #[test]
+    fn gzip_should_compress_repeated_input() {
+        let data = r#"{"title": "title", "url": "https://example.com/"}"#.repeat(100);
+        let compressed = gzip(data.as_bytes());
+        assert!(compressed.len() < data.len() / 10, "{} bytes", compressed.len());
+        assert_eq!(compressed[compressed.len() - 4..], (data.len() as u32).to_le_bytes());
+        assert_eq!(gunzip(&compressed), data.as_bytes());
+    }
+
+    #[test]
+    fn gunzip_should_decode_every_byte() {
+        let data: Vec<u8> = (0..=255).chain(0..=255).collect();
+        assert_eq!(gunzip(&gzip(&data)), data);
+    }
+}
@@ -0,0 +1,48 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::fs::OpenOptions;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+#[derive(Serialize)]
+pub struct HistoryEntry<'a> {
+    pub posted_at: DateTime<Utc>,
+    pub message_id: &'a str,
+    pub status: u16,
+}
+
+pub fn append_history(path: &Path, entry: &HistoryEntry<'_>) -> Result<(), String> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path).map_err(|_| format!("could not open file: {}", path.display()))?;
+    let mut file_writer = BufWriter::new(&mut file);
+
+    let to_error = |e: std::io::Error| format!("failed to write history: {}", e);
+    serde_json::to_writer(&mut file_writer, entry).map_err(|e| format!("failed to write history: {}", e))?;
+    file_writer.write_all(b"\n").map_err(to_error)?;
+    file_writer.flush().map_err(to_error)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate tempfile;
+
+    use super::*;
+    use chrono::TimeZone;
+    use tempfile::TempDir;
+
+    #[test]
+    fn append_history_should_append_json_lines() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("history.jsonl");
+
+        let entry1 = HistoryEntry { posted_at: Utc.with_ymd_and_hms(2021, 9, 1, 12, 0, 0).unwrap(), message_id: "abc", status: 204 };
+        let entry2 = HistoryEntry { posted_at: Utc.with_ymd_and_hms(2021, 9, 1, 13, 0, 0).unwrap(), message_id: "def", status: 200 };
+        append_history(&path, &entry1).unwrap();
+        append_history(&path, &entry2).unwrap();
+
+        let expected = concat!(
+            r#"{"posted_at":"2021-09-01T12:00:00Z","message_id":"abc","status":204}"#, "\n",
+            r#"{"posted_at":"2021-09-01T13:00:00Z","message_id":"def","status":200}"#, "\n",
+        );
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), expected);
+    }
+}
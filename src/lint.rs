@@ -0,0 +1,56 @@
+use serde::{de::DeserializeOwned, Serialize};
+use std::hash::Hash;
+
+use super::random::RandomPicker;
+
+pub const DEFAULT_THRESHOLD: f64 = 0.001;
+
+pub fn find_unreachable<T: Hash + Eq + Clone + Serialize + DeserializeOwned>(picker: &RandomPicker<T>, threshold: f64) -> Vec<(&T, f64)> {
+    picker.probabilities().into_iter().filter(|(_, probability)| *probability < threshold).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate indoc;
+    extern crate tempfile;
+
+    use super::*;
+    use indoc::indoc;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    use super::super::random::InitialCountType;
+    use super::super::weight::WeightType;
+
+    #[test]
+    fn find_unreachable_should_report_messages_which_are_never_picked() {
+        let mut file = NamedTempFile::new().unwrap();
+        let log = indoc! {r#"
+            [
+                { "value": "a", "count": 1 },
+                { "value": "b", "count": 0 },
+                { "value": "c", "count": 2 }
+            ]
+        "#};
+        write!(file, "{}", log).unwrap();
+
+        let values = vec![String::from("a"), String::from("b"), String::from("c")];
+        let picker = RandomPicker::from_log_file(file.path(), values, WeightType::MinOnly, InitialCountType::Zero).unwrap();
+
+        let a = String::from("a");
+        let c = String::from("c");
+        assert_eq!(find_unreachable(&picker, DEFAULT_THRESHOLD), vec![(&a, 0.0), (&c, 0.0)]);
+    }
+
+    #[test]
+    fn find_unreachable_should_report_nothing_if_all_messages_are_likely_enough() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_owned();
+        file.close().unwrap();
+
+        let values = vec![String::from("a"), String::from("b"), String::from("c")];
+        let picker = RandomPicker::from_log_file(path, values, WeightType::Uniform, InitialCountType::Zero).unwrap();
+
+        assert!(find_unreachable(&picker, DEFAULT_THRESHOLD).is_empty());
+    }
+}
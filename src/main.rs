@@ -1,3 +1,5 @@
+extern crate chrono;
+extern crate openssl;
 extern crate rand;
 extern crate reqwest;
 extern crate serde;
@@ -5,44 +7,3451 @@ extern crate serde_json;
 extern crate serde_yaml;
 extern crate tokio;
 
+mod args;
+mod dead_letter;
+mod doctor;
+mod expr;
+mod file;
+mod gzip;
+mod history;
+mod lint;
+mod merge;
 mod message;
+mod metrics;
+mod msgpack;
 mod random;
+mod replay;
 mod request;
+mod schedule;
+#[cfg(feature = "serve")]
+mod serve;
 mod settings;
 mod weight;
 
-use settings::read_settings;
-use random::RandomPicker;
-use request::{SimpleWebhookRequest, post};
+use chrono::{DateTime, Local, Timelike, Utc};
+use rand::Rng;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use args::{Args, Command, OutputFormat, parse_args};
+use dead_letter::{DeadLetter, append_dead_letters};
+use doctor::{doctor, prune};
+use file::{FileLock, lock};
+use history::{HistoryEntry, append_history};
+use merge::merge;
+use message::{Message, validate_embeds};
+use settings::{FieldSource, OnEmpty, Settings, Strategy, check_settings, explain_settings, overlay_path, read_settings_with_overlay};
+use random::{LogState, RandomPicker, RecentPick, RandomPickerBuilder, migrate_log, read_log_counts, write_snapshot};
+use replay::replay;
+use request::{DEFAULT_SIGNATURE_HEADER, MessageReference, Platform, PostResponse, RetrySettings, Signing, SimpleWebhookRequest, check_reachable, json_body, post_with_retry, Poster, ReqwestPoster};
+use weight::{WeightType, read_external_weights};
+
+const SETTINGS_PATH: &str = "conf/settings.yaml";
+const LOG_PATH: &str = "conf/message-log.json";
+const DEFAULT_COMBINE_SEPARATOR: &str = "\n";
+const DEFAULT_PREFERRED_HOURS_FACTOR: f64 = 4.0;
+const DEFAULT_EXCLUSION_RUNS: u64 = 1;
+// selects the overlay of the settings if `--env` is not given
+const ENV_VARIABLE: &str = "RANDOM_POSTER_ENV";
 
 #[tokio::main]
 async fn main() {
-    let result = run().await;
+    let args = parse_args(std::env::args().skip(1));
+    let output_format = args.as_ref().map_or(OutputFormat::Text, |args| args.output_format);
+    let result = match args {
+        Ok(args) => run(args, &ReqwestPoster::shared()).await,
+        Err(message) => Err(message),
+    };
     if let Err(message) = result {
-        eprintln!("{}", message);
+        match output_format {
+            OutputFormat::Text => eprintln!("{}", message),
+            OutputFormat::Json => println!("{}", serde_json::json!({ "error": message })),
+        }
         std::process::exit(1);
     }
 }
 
-async fn run() -> Result<(), String> {
-    let settings = read_settings("conf/settings.yaml")?;
-    let mut random_picker =
-        RandomPicker::from_log_file(
-            "conf/message-log.json",
-            settings.messages.keys().cloned().collect(),
-            settings.environment.weight_type,
-            settings.environment.initial_count_type,
-        )?;
+async fn run<P: Poster>(args: Args, poster: &P) -> Result<(), String> {
+    let settings_path = args.settings_path.as_deref().unwrap_or(SETTINGS_PATH);
+    let env = args.env.clone().or_else(|| std::env::var(ENV_VARIABLE).ok().filter(|env| !env.is_empty()));
+    let overlay_path = env.map(|env| overlay_path(Path::new(settings_path), &env)).transpose()?;
+    let overlay_path = overlay_path.as_deref();
+    // the snapshot is read instead of the log only by the commands which never write it
+    let read_log_path = args.from_snapshot.clone().unwrap_or_else(|| PathBuf::from(LOG_PATH));
+
+    match args.command {
+        Command::Post => {
+            let settings = read_settings_with_overlay(settings_path, overlay_path)?;
+            let transform = |message: &mut Message| settings.environment.transformers.iter().for_each(|transformer| transformer.apply(message));
+            let options = PostOptions {
+                confirm: if args.confirm { Some(&confirm_on_stdin) } else { None },
+                burst: args.burst,
+                delay: Duration::from_secs(args.delay_secs),
+                dry_run: args.dry_run,
+                edit_last: args.edit_last,
+                combine: args.combine,
+                transform: Some(&transform),
+                forced_id: args.force.as_deref(),
+                count_forced: args.count_forced,
+            };
+            let outcome = post_random(poster, &settings, Path::new(LOG_PATH), Local::now(), &options).await?;
+            write_outcome(&mut std::io::stdout(), &outcome, Path::new(LOG_PATH), args.output_format, args.dry_run).map_err(|e| format!("failed to write the output: {}", e))
+        },
+        Command::Daemon => {
+            let settings = read_settings_with_overlay(settings_path, overlay_path)?;
+            let interval = settings.environment.post_interval.ok_or_else(|| String::from("--daemon requires post_interval in the settings"))?;
+            daemon(poster, &settings, Path::new(LOG_PATH), interval.to_std().max(Duration::from_secs(1)), args.output_format, shutdown_signal()).await
+        },
+        Command::Lint => lint(&read_settings_with_overlay(settings_path, overlay_path)?, Path::new(LOG_PATH)),
+        Command::Check => check(Path::new(settings_path), overlay_path),
+        Command::List => list(&read_settings_with_overlay(settings_path, overlay_path)?, &read_log_path),
+        Command::Extremes => extremes(&read_settings_with_overlay(settings_path, overlay_path)?, &read_log_path),
+        Command::Doctor => doctor(&read_settings_with_overlay(settings_path, overlay_path)?, Path::new(LOG_PATH)),
+        Command::Prune => prune(&read_settings_with_overlay(settings_path, overlay_path)?, Path::new(LOG_PATH)),
+        Command::MigrateLog => {
+            let settings = read_settings_with_overlay(settings_path, overlay_path)?;
+            let _lock = lock_log(&settings, Path::new(LOG_PATH))?;
+            let changes = migrate_log::<String>(Path::new(LOG_PATH), settings.environment.log_format)?;
+            if changes.is_empty() {
+                println!("{} is already in the current version", LOG_PATH);
+            } else {
+                println!("migrated {}: {}", LOG_PATH, changes.join(", "));
+            }
+            Ok(())
+        },
+        Command::Explain => explain(Path::new(settings_path), overlay_path),
+        Command::Simulate { iterations } => simulate(&read_settings_with_overlay(settings_path, overlay_path)?, &read_log_path, iterations),
+        Command::Snapshot { path } => {
+            let settings = read_settings_with_overlay(settings_path, overlay_path)?;
+            let message_count = write_snapshot::<String>(Path::new(LOG_PATH), &path, settings.environment.log_format)?;
+            println!("saved the counts of {} messages in {} to {}", message_count, LOG_PATH, path.display());
+            Ok(())
+        },
+        Command::Merge { paths } => {
+            let settings = read_settings_with_overlay(settings_path, overlay_path)?;
+            let _lock = lock_log(&settings, Path::new(LOG_PATH))?;
+            merge(Path::new(LOG_PATH), &paths, settings.environment.log_format)
+        },
+        Command::AddMessage { id, count } => add_message(&read_settings_with_overlay(settings_path, overlay_path)?, Path::new(LOG_PATH), &id, count),
+        Command::RemoveMessage { id } => remove_message(&read_settings_with_overlay(settings_path, overlay_path)?, Path::new(LOG_PATH), &id),
+        Command::SelfTest => self_test(Path::new(settings_path), overlay_path, Path::new(LOG_PATH)).await,
+        Command::Replay { path } => replay(poster, &path).await,
+        #[cfg(feature = "serve")]
+        Command::Serve { addr } => serve::serve_on(poster, &read_settings_with_overlay(settings_path, overlay_path)?, Path::new(LOG_PATH), addr).await,
+        #[cfg(not(feature = "serve"))]
+        Command::Serve { addr } => Err(format!("--serve {} requires building with the serve feature", addr)),
+    }
+}
+
+#[derive(Default)]
+struct PostOptions<'a> {
+    confirm: Option<&'a dyn Fn(&str) -> bool>,
+    burst: Option<usize>,
+    delay: Duration,
+    // picks a message without posting it or writing the log
+    dry_run: bool,
+    // edits the last message posted to Discord instead of posting a new one, if its id is in the log
+    edit_last: bool,
+    // picks this many distinct messages without files for each post, and posts them as one message
+    combine: Option<usize>,
+    // changes the picked message before it is posted, after the messages are combined and the placeholders are replaced
+    transform: Option<&'a dyn Fn(&mut Message)>,
+    // posts this message instead of picking one, ignoring the quiet hours, the intervals and the schedules
+    forced_id: Option<&'a str>,
+    // writes the log after a forced post as after a picked one, instead of leaving it as it is
+    count_forced: bool,
+}
+
+#[derive(PartialEq, Eq, Default, Debug)]
+struct PostOutcome {
+    // the last picked message, which is not posted if the run is a dry run or canceled
+    picked_id: Option<String>,
+    status: Option<u16>,
+    posted: bool,
+    skipped: Option<String>,
+    // such as the retries of a post which succeeded after all
+    warnings: Vec<String>,
+}
+
+impl PostOutcome {
+    fn skipped(reason: String) -> PostOutcome {
+        PostOutcome { skipped: Some(reason), ..PostOutcome::default() }
+    }
+}
+
+fn write_outcome<W: Write>(writer: &mut W, outcome: &PostOutcome, log_path: &Path, output_format: OutputFormat, dry_run: bool) -> std::io::Result<()> {
+    match output_format {
+        OutputFormat::Json => {
+            let json = serde_json::json!({
+                "picked_id": outcome.picked_id,
+                "status": outcome.status,
+                "posted": outcome.posted,
+                "skipped": outcome.skipped,
+                "log_path": log_path.display().to_string(),
+                "dry_run": dry_run,
+                "warnings": outcome.warnings,
+            });
+            writeln!(writer, "{}", json)
+        },
+        OutputFormat::Text => {
+            for warning in &outcome.warnings {
+                writeln!(writer, "warning: {}", warning)?;
+            }
+            match (&outcome.skipped, &outcome.picked_id) {
+                (Some(reason), _) => writeln!(writer, "skipped: {}", reason),
+                (None, Some(picked_id)) if dry_run => writeln!(writer, "dry run: picked `{}` without posting", picked_id),
+                _ => Ok(()),
+            }
+        },
+    }
+}
+
+async fn post_random<P: Poster>(poster: &P, settings: &Settings, log_path: &Path, now: DateTime<Local>, options: &PostOptions<'_>) -> Result<PostOutcome, String> {
+    if let Some(forced_id) = options.forced_id.filter(|forced_id| !settings.messages.contains_key(*forced_id)) {
+        return Err(format!("message `{}` is not in the settings", forced_id));
+    }
+    let writes_log = !options.dry_run && (options.forced_id.is_none() || options.count_forced);
+
+    // checked before loading the log, so that the log is left as it is until the next run
+    if options.forced_id.is_none() && settings.environment.quiet_hours.as_ref().is_some_and(|quiet_hours| quiet_hours.contains(now.time())) {
+        return Ok(PostOutcome::skipped(format!("{} is in the quiet hours", now.format("%H:%M"))));
+    }
+
+    let _lock = lock_log(settings, log_path)?;
+    let mut random_picker = load_picker_at(settings, log_path, now.with_timezone(&Utc))?;
+    // the corrupt log is already moved aside, so it is reported whatever the outcome is
+    let corrupt_log = random_picker.corrupt_log().map(String::from);
+    let with_warnings = |outcome: PostOutcome| PostOutcome { warnings: corrupt_log.iter().cloned().chain(outcome.warnings).collect(), ..outcome };
+    if let Some(stale_boost) = settings.environment.stale_boost {
+        random_picker.set_stale_boost(stale_boost)?;
+    }
+    random_picker.extend_weight_multipliers(preferred_hours_multipliers(settings, now.hour()));
+    if settings.environment.grab_bag && options.forced_id.is_none() && settings.messages.keys().all(|id| random_picker.is_retired(id)) {
+        return Ok(with_warnings(PostOutcome::skipped(String::from("every message in the grab bag is retired"))));
+    }
+
+    if let Some(last_posted_at) = posted_within_min_interval(settings, &random_picker, now).filter(|_| options.forced_id.is_none()) {
+        return Ok(with_warnings(PostOutcome::skipped(format!("the last post was at {}, within the minimum interval", last_posted_at.with_timezone(&Local)))));
+    }
+
+    if let Some(decay_rate) = settings.environment.decay_rate {
+        random_picker.decay_counts(decay_rate)?;
+    }
+
+    let today = now.date_naive();
+    let mut excluded_values = excluded_values(settings, &random_picker, now);
+    if options.combine.is_some() {
+        excluded_values.extend(settings.messages.iter()
+            .filter(|(_, message_settings)| message_settings.message.variants().iter().any(|message| message.attachment().is_some()))
+            .map(|(id, _)| id.clone()));
+    }
+    let eligible_count = settings.messages.keys().filter(|id| !excluded_values.contains(*id)).count();
+    random_picker.set_excluded_values(excluded_values);
+    if options.forced_id.is_none() && !random_picker.has_eligible_values() {
+        let reason = format!("no messages are scheduled for {}", today);
+        return match settings.environment.on_empty {
+            OnEmpty::Skip => Ok(with_warnings(PostOutcome::skipped(reason))),
+            OnEmpty::Error => Err(reason),
+        };
+    }
+    if let Some(combine) = options.combine.filter(|combine| *combine > eligible_count) {
+        return Err(format!("--combine {} needs as many messages without files which can be picked, but there are {}", combine, eligible_count));
+    }
+
+    // if a post fails or is canceled, the counts consumed by the previous posts are still written
+    let burst = post_burst(poster, settings, &mut random_picker, now, options);
+    // a run which writes no log, and the prompt of --confirm which blocks the task which would flush the log, keep the default handling of the signals
+    let burst = if options.confirm.is_some() || !writes_log {
+        burst.await
+    } else {
+        match until_signal(burst, shutdown_signal()).await {
+            Ok(burst) => burst,
+            Err(signal) => return persist_interrupted(&random_picker, signal),
+        }
+    };
+    let Burst { outcome, last_response, failed_message_id, result } = burst;
+
+    if !writes_log {
+        return result.map(|()| with_warnings(outcome));
+    }
+    if last_response.is_none() && failed_message_id.is_some() {
+        random_picker.write_log()?;
+    }
+    if let Some(response) = last_response {
+        random_picker.state_mut().last_posted_at = Some(now.with_timezone(&Utc));
+        let run_number = random_picker.state().run_number.unwrap_or(0);
+        random_picker.state_mut().run_number = Some(run_number.saturating_add(1));
+        if let Some(post_interval) = settings.environment.post_interval {
+            let max_jitter = settings.environment.post_interval_jitter.map_or(0, |jitter| jitter.to_chrono().num_milliseconds());
+            let jitter = random_picker.with_rng(|rng| rng.gen_range(-max_jitter..=max_jitter));
+            let interval = post_interval.to_chrono() + chrono::Duration::milliseconds(jitter);
+            // the settings reject the intervals which could overflow, but a post which is already delivered must not panic before the log is written
+            random_picker.state_mut().next_post_after = now.with_timezone(&Utc).checked_add_signed(interval);
+        }
+        random_picker.write_log()?;
+
+        if let (Some(metrics_path), Ok(())) = (&settings.environment.metrics_path, &result) {
+            metrics::write_metrics(metrics_path, &random_picker.counts(), response.status)?;
+        }
+    }
+
+    result.map(|()| with_warnings(outcome))
+}
+
+// Posts at once and then every `interval` until `shutdown` completes, and a failed post is reported without stopping the loop.
+// A signal during a post is handled by post_random, which writes the log, and then stops the loop at the next check.
+async fn daemon<P: Poster, S: Future<Output = &'static str>>(poster: &P, settings: &Settings, log_path: &Path, interval: Duration, output_format: OutputFormat, shutdown: S) -> Result<(), String> {
+    let transform = |message: &mut Message| settings.environment.transformers.iter().for_each(|transformer| transformer.apply(message));
+    let options = PostOptions { transform: Some(&transform), ..PostOptions::default() };
+    let mut interval = tokio::time::interval(interval);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    tokio::pin!(shutdown);
+    loop {
+        tokio::select! {
+            biased;
+            signal = &mut shutdown => {
+                eprintln!("stopped by {}", signal);
+                return Ok(());
+            },
+            _ = interval.tick() => (),
+        }
+
+        match post_random(poster, settings, log_path, Local::now(), &options).await {
+            Ok(outcome) => {
+                if let (OutputFormat::Text, true, Some(picked_id)) = (output_format, outcome.posted, &outcome.picked_id) {
+                    println!("{}: posted `{}`", Local::now().format("%Y-%m-%d %H:%M:%S"), picked_id);
+                }
+                write_outcome(&mut std::io::stdout(), &outcome, log_path, output_format, false).map_err(|e| format!("failed to write the output: {}", e))?;
+            },
+            Err(message) => eprintln!("{}: {}", Local::now().format("%Y-%m-%d %H:%M:%S"), message),
+        }
+    }
+}
+
+// What a burst leaves to be written to the log, even if one of its posts fails.
+struct Burst {
+    outcome: PostOutcome,
+    last_response: Option<PostResponse>,
+    failed_message_id: Option<String>,
+    result: Result<(), String>,
+}
+
+async fn post_burst<P: Poster>(poster: &P, settings: &Settings, random_picker: &mut RandomPicker<String>, now: DateTime<Local>, options: &PostOptions<'_>) -> Burst {
+    let started_at = std::time::Instant::now();
+    let mut burst = Burst { outcome: PostOutcome::default(), last_response: None, failed_message_id: None, result: Ok(()) };
+    let mut warnings = Vec::new();
+    for index in 0..options.burst.unwrap_or(1) {
+        if index > 0 {
+            tokio::time::sleep(options.delay).await;
+        }
+
+        let previous_picker = random_picker.clone();
+        match post_once(poster, settings, random_picker, now, options, &mut warnings).await {
+            Ok((message_ids, Some((destination_index, response)))) => {
+                burst.outcome = PostOutcome { picked_id: Some(message_ids.join(", ")), status: Some(response.status), posted: true, ..PostOutcome::default() };
+                for message_id in &message_ids {
+                    if settings.messages[message_id].min_interval.is_some() || settings.environment.stale_boost.is_some() || uses_oldest_first(settings) {
+                        random_picker.record_posted_at(message_id, now.with_timezone(&Utc));
+                    }
+                }
+                if settings.environment.grab_bag {
+                    message_ids.iter().for_each(|message_id| random_picker.retire(message_id));
+                }
+                if settings.messages.values().any(|message_settings| !message_settings.excludes.is_empty()) {
+                    record_recent_picks(settings, random_picker.state_mut(), &message_ids);
+                }
+                let total_posts = random_picker.state().total_posts.unwrap_or(0);
+                random_picker.state_mut().total_posts = Some(total_posts.saturating_add(1));
+                if response.message_id.is_some() {
+                    random_picker.state_mut().last_message_id = response.message_id.clone();
+                    random_picker.state_mut().last_destination_index = Some(destination_index);
+                }
+                let history_result = match &settings.environment.history_file {
+                    Some(history_file) => {
+                        let posted_at = now.with_timezone(&Utc) + chrono::Duration::from_std(started_at.elapsed()).unwrap();
+                        message_ids.iter().try_for_each(|message_id| append_history(history_file, &HistoryEntry { posted_at, message_id, status: response.status }))
+                    },
+                    None => Ok(()),
+                };
+                burst.last_response = Some(response);
+                // the post is delivered, so it is still written to the log, and the rest of the burst is not posted
+                if let Err(message) = history_result {
+                    burst.result = Err(message);
+                    break;
+                }
+            },
+            Ok((message_ids, None)) => {
+                *random_picker = previous_picker;
+                burst.outcome.picked_id = Some(message_ids.join(", "));
+                if !options.dry_run {
+                    burst.outcome.skipped = Some(String::from("canceled"));
+                }
+                break;
+            },
+            Err((message_ids, message)) => {
+                *random_picker = previous_picker;
+                if settings.environment.penalize_failures {
+                    for message_id in &message_ids {
+                        random_picker.record_failure(message_id);
+                    }
+                    burst.failed_message_id = Some(message_ids.join(", "));
+                }
+                burst.result = Err(message);
+                break;
+            },
+        }
+    }
+
+    burst.outcome.warnings = warnings;
+    burst
+}
+
+fn uses_oldest_first(settings: &Settings) -> bool {
+    std::iter::once(&settings.environment.weight_type)
+        .chain(settings.groups.values().filter_map(|group_settings| group_settings.weight_type.as_ref()))
+        .any(|weight_type| *weight_type == WeightType::OldestFirst)
+}
+
+// Returns the name of the signal if it comes before the future completes.
+async fn until_signal<T, F: Future<Output = T>, S: Future<Output = &'static str>>(future: F, signal: S) -> Result<T, &'static str> {
+    tokio::select! {
+        output = future => Ok(output),
+        signal = signal => Err(signal),
+    }
+}
+
+// Resolves on SIGINT, or SIGTERM on Unix, and never resolves if the handlers cannot be installed.
+async fn shutdown_signal() -> &'static str {
+    #[cfg(unix)]
+    if let Ok(mut terminate) = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+        return tokio::select! {
+            Ok(()) = tokio::signal::ctrl_c() => "SIGINT",
+            Some(()) = terminate.recv() => "SIGTERM",
+            else => std::future::pending().await,
+        };
+    }
+    match tokio::signal::ctrl_c().await {
+        Ok(()) => "SIGINT",
+        Err(_) => std::future::pending().await,
+    }
+}
+
+// The picker keeps the pick of the interrupted post, which may have been delivered already, so its count is written rather than lost.
+fn persist_interrupted(random_picker: &RandomPicker<String>, signal: &str) -> Result<PostOutcome, String> {
+    random_picker.write_log()?;
+    Err(format!("interrupted by {} after writing the log", signal))
+}
+
+// Excludes the messages which are not scheduled for today, the retired messages of the grab bag, and the messages posted within their own minimum interval.
+// If every scheduled message is within its interval, the one whose interval ends first can still be picked.
+fn excluded_values(settings: &Settings, random_picker: &RandomPicker<String>, now: DateTime<Local>) -> HashSet<String> {
+    let today = now.date_naive();
+    let mut excluded_values: HashSet<String> = settings.messages.iter()
+        .filter(|(_, message_settings)| message_settings.schedule.as_ref().is_some_and(|schedule| !schedule.is_active(today)))
+        .map(|(id, _)| id.clone())
+        .collect();
+    if settings.environment.grab_bag {
+        excluded_values.extend(grab_bag_excluded_values(settings, random_picker));
+    }
+
+    // messages can exclude each other in a cycle, so the exclusions are ignored if they would leave no message to pick
+    let mutually_excluded_values = mutually_excluded_values(settings, random_picker.state());
+    if settings.messages.keys().any(|id| !excluded_values.contains(id) && !mutually_excluded_values.contains(id)) {
+        excluded_values.extend(mutually_excluded_values);
+    }
+
+    let now = now.with_timezone(&Utc);
+    let mut throttled_values: Vec<(DateTime<Utc>, &String)> = settings.messages.iter()
+        .filter(|(id, _)| !excluded_values.contains(*id))
+        .filter_map(|(id, message_settings)| {
+            let min_interval = message_settings.min_interval?.to_chrono();
+            let eligible_at = random_picker.last_posted_at(id)?.checked_add_signed(min_interval).unwrap_or(DateTime::<Utc>::MAX_UTC);
+            if eligible_at > now { Some((eligible_at, id)) } else { None }
+        })
+        .collect();
+    if !throttled_values.is_empty() && throttled_values.len() == settings.messages.len() - excluded_values.len() {
+        throttled_values.sort();
+        throttled_values.remove(0);
+    }
+    excluded_values.extend(throttled_values.into_iter().map(|(_, id)| id.clone()));
+    excluded_values
+}
+
+// The retired messages, and the done message until every other message is retired.
+fn grab_bag_excluded_values(settings: &Settings, random_picker: &RandomPicker<String>) -> HashSet<String> {
+    let done_message = settings.environment.grab_bag_done_message.as_ref();
+    let is_exhausted = settings.messages.keys().filter(|id| Some(*id) != done_message).all(|id| random_picker.is_retired(id));
+    settings.messages.keys()
+        .filter(|id| random_picker.is_retired(id) || (!is_exhausted && Some(*id) == done_message))
+        .cloned()
+        .collect()
+}
+
+// The messages which exclude a message posted in the last `exclusion_runs` runs, or which such a message excludes.
+fn mutually_excluded_values(settings: &Settings, state: &LogState) -> HashSet<String> {
+    let exclusion_runs = settings.environment.exclusion_runs.unwrap_or(DEFAULT_EXCLUSION_RUNS);
+    let run_number = state.run_number.unwrap_or(0).saturating_add(1);
+    let recent_values: HashSet<&String> = state.recent_picks.iter()
+        .filter(|recent_pick| run_number.saturating_sub(recent_pick.run_number) <= exclusion_runs)
+        .map(|recent_pick| &recent_pick.value)
+        .collect();
+    settings.messages.iter()
+        .filter(|(id, message_settings)| recent_values.iter().any(|value| {
+            message_settings.excludes.contains(value) || settings.messages.get(*value).is_some_and(|recent| recent.excludes.contains(id))
+        }))
+        .map(|(id, _)| id.clone())
+        .collect()
+}
+
+// Drops the picks which can no longer exclude a message in the next run.
+fn record_recent_picks(settings: &Settings, state: &mut LogState, message_ids: &[String]) {
+    let exclusion_runs = settings.environment.exclusion_runs.unwrap_or(DEFAULT_EXCLUSION_RUNS);
+    let run_number = state.run_number.unwrap_or(0).saturating_add(1);
+    state.recent_picks.retain(|recent_pick| run_number.saturating_add(1).saturating_sub(recent_pick.run_number) <= exclusion_runs);
+    state.recent_picks.extend(message_ids.iter().map(|id| RecentPick { run_number, value: id.clone() }));
+}
+
+// The counters count the post being made, and `run_number` is the same for every post of a burst.
+fn placeholders(state: &LogState, now: DateTime<Local>) -> Vec<(&'static str, String)> {
+    vec![
+        ("run_number", state.run_number.unwrap_or(0).saturating_add(1).to_string()),
+        ("total_posts", state.total_posts.unwrap_or(0).saturating_add(1).to_string()),
+        ("date", now.format("%Y-%m-%d").to_string()),
+    ]
+}
+
+// The emoji of the rotation comes first with a space, and advances with `run_number`, which is the same for every post of a burst.
+fn wrap_content(settings: &Settings, state: &LogState, message: &mut Message) {
+    let environment = &settings.environment;
+    let emoji = match environment.emoji_rotation.len() {
+        0 => String::new(),
+        len => format!("{} ", environment.emoji_rotation[(state.run_number.unwrap_or(0) % len as u64) as usize]),
+    };
+    if emoji.is_empty() && environment.content_prefix.is_none() && environment.content_suffix.is_none() {
+        return;
+    }
+    let prefix = environment.content_prefix.as_deref().unwrap_or("");
+    let suffix = environment.content_suffix.as_deref().unwrap_or("");
+    message.map_text(&|text| format!("{}{}{}{}", emoji, prefix, text, suffix));
+}
+
+// Returns the ids of the picked messages with the error, so that the failure can be recorded for them.
+// The response is None if the message is not posted because of a dry run or a cancellation.
+async fn post_once<P: Poster>(poster: &P, settings: &Settings, random_picker: &mut RandomPicker<String>, now: DateTime<Local>, options: &PostOptions<'_>, warnings: &mut Vec<String>) -> Result<(Vec<String>, Option<(usize, PostResponse)>), (Vec<String>, String)> {
+    let last_message = random_picker.state().last_message_id.clone().map(|message_id| (message_id, random_picker.state().last_destination_index.unwrap_or(0)));
+    let message_ids = match (options.forced_id, options.combine) {
+        // the forced id is checked against the settings, and so it is in the picker
+        (Some(forced_id), _) => vec![random_picker.force_pick(&forced_id.to_owned()).unwrap().clone()],
+        (None, Some(combine)) => pick_distinct(random_picker, combine).map_err(|message| (Vec::new(), message))?,
+        (None, None) => vec![random_picker.pick().map_err(|message| (Vec::new(), message))?.clone()],
+    };
+    let mut messages = Vec::new();
+    for message_id in &message_ids {
+        messages.push(random_picker.with_rng(|rng| settings.messages[message_id].message.resolve(rng)));
+    }
+    let mut message = match messages.len() {
+        1 => messages.pop().unwrap(),
+        _ => Message::combine(&messages, settings.environment.combine_separator.as_deref().unwrap_or(DEFAULT_COMBINE_SEPARATOR)),
+    };
+    // an embed is always serialized into an object
+    if let Some(serde_json::Value::Object(embed_defaults)) = settings.defaults.embed.as_ref().map(|embed| serde_json::to_value(embed).unwrap()) {
+        message.fill_embed_defaults(&embed_defaults);
+    }
+    message = message.replace_placeholders(&placeholders(random_picker.state(), now));
+    if let Some(transform) = options.transform {
+        transform(&mut message);
+    }
+    wrap_content(settings, random_picker.state(), &mut message);
+    let last_message = last_message.as_ref().map(|(message_id, destination_index)| (message_id.as_str(), *destination_index));
+    match post_message(poster, settings, &message_ids, &message, last_message, options, warnings).await {
+        Ok(response) => Ok((message_ids, response)),
+        Err(error) => Err((message_ids, error)),
+    }
+}
+
+// Excludes each picked value from the next picks, so `count` must not be more than the number of the eligible values.
+fn pick_distinct(random_picker: &mut RandomPicker<String>, count: usize) -> Result<Vec<String>, String> {
+    let excluded_values = random_picker.excluded_values().clone();
+    let mut picked_values = Vec::new();
+    for _ in 0..count {
+        let picked_value = random_picker.pick()?.clone();
+        let mut next_excluded_values = random_picker.excluded_values().clone();
+        next_excluded_values.insert(picked_value.clone());
+        random_picker.set_excluded_values(next_excluded_values);
+        picked_values.push(picked_value);
+    }
+    random_picker.set_excluded_values(excluded_values);
+    Ok(picked_values)
+}
+
+// The last message, with the index of the destination which it is posted to, is replied to with `reply_to_previous`,
+// and with --edit-last it is edited instead, while the other destinations get new posts.
+// Returns the index of the destination whose response is kept, with the response.
+// A combined message uses the settings of the first picked message.
+async fn post_message<P: Poster>(poster: &P, settings: &Settings, message_ids: &[String], message: &Message, last_message: Option<(&str, usize)>, options: &PostOptions<'_>, warnings: &mut Vec<String>) -> Result<Option<(usize, PostResponse)>, String> {
+    let message_reference = &last_message.filter(|_| settings.environment.reply_to_previous).map(|(message_id, _)| MessageReference { message_id: message_id.to_owned() });
+    let edited_message_id = last_message.map(|(message_id, _)| message_id).filter(|_| options.edit_last);
+    let last_destination_index = last_message.map_or(0, |(_, destination_index)| destination_index);
+    let message_settings = &settings.messages[&message_ids[0]];
+    let destinations = destinations(settings, options.edit_last)?;
+    if destinations.iter().any(|(_, platform, _)| **platform == Platform::Discord) {
+        validate_embeds(message.embeds())?;
+    }
+
+    let user_settings = &settings.environment.user_settings;
+    let username = message_settings.user_settings.name.as_ref().or(user_settings.name.as_ref()).cloned();
+    let avatar_url = message_settings.user_settings.icon_url.as_ref().or(user_settings.icon_url.as_ref()).cloned();
+    let flags = message_settings.flags.or(settings.environment.flags);
+    let thread_name = message_settings.thread_name.as_ref().or(settings.environment.thread_name.as_ref()).cloned();
+    let signing = settings.environment.signing_secret.clone().map(|secret| Signing {
+        secret,
+        header: settings.environment.signature_header.clone().unwrap_or_else(|| String::from(DEFAULT_SIGNATURE_HEADER)),
+    });
+
+    // each destination splits the message by its own limit
+    let messages: Vec<Vec<Message>> = destinations.iter().map(|(_, platform, _)| message.split_content(platform.max_content_length())).collect();
+    let failover = settings.environment.strategy == Strategy::Failover;
+    // a nonce is generated for each message rather than each attempt so that retries can be deduplicated
+    let nonces: Vec<Vec<Option<String>>> = messages.iter()
+        .map(|messages| messages.iter().map(|_| settings.environment.retry.as_ref().map(|_| rand::random::<u64>().to_string())).collect())
+        .collect();
+    let contents: Vec<Vec<SimpleWebhookRequest>> = messages.iter().zip(&nonces).enumerate().map(|(destination_index, (messages, nonces))| {
+        let accepted_statuses = destinations[destination_index].2;
+        messages.iter().zip(nonces).enumerate().map(|(index, (message, nonce))| SimpleWebhookRequest {
+            username: &username,
+            avatar_url: &avatar_url,
+            nonce,
+            enforce_nonce: nonce.is_some(),
+            // only the first part of a split message replies to the previous message, and only on the destination which it is posted to
+            message_reference: if destination_index == last_destination_index && index == 0 { message_reference } else { &None },
+            flags: &flags,
+            // a forum post is created by the first part, and the other parts would create their own posts
+            thread_name: if index == 0 { &thread_name } else { &None },
+            signing: &signing,
+            compress: settings.environment.compress,
+            accepted_statuses,
+            message,
+        }).collect()
+    }).collect();
+
+    // the failover tries the destination of the last message first, where it can be edited
+    let mut order: Vec<usize> = (0..destinations.len()).collect();
+    if failover && edited_message_id.is_some() {
+        order.retain(|index| *index != last_destination_index);
+        order.insert(0, last_destination_index);
+    }
+    if let Some(confirm) = options.confirm {
+        let preview: Vec<String> = contents[order[0]].iter().map(|content| serde_json::to_string_pretty(content).unwrap()).collect();
+        if !confirm(&preview.join("\n")) {
+            return Ok(None);
+        }
+    }
+    if options.dry_run {
+        return Ok(None);
+    }
+
+    // every destination is tried even if another one fails, and the pick is kept only if all of them succeed,
+    // unless in the failover, where the first destination which gets any part ends the post and the pick is kept unless all of them fail
+    let mut first_response = None;
+    let mut errors = Vec::new();
+    let mut dead_letters = Vec::new();
+    for index in order {
+        let ((url, platform, _), contents) = (&destinations[index], &contents[index]);
+        let result = match edited_message_id {
+            Some(edited_message_id) if index == last_destination_index && **platform == Platform::Discord => edit_contents(poster, url, edited_message_id, contents).await.map_err(|message| (None, None, message)),
+            _ => post_contents(poster, url, platform, contents, settings.environment.retry.as_ref(), warnings).await
+                .map_err(|(failed_index, delivered_response, message)| (Some(failed_index), delivered_response, message)),
+        };
+        let destination_prefix = if destinations.len() == 1 { String::new() } else { format!("destination {}: ", index + 1) };
+        // the parts after the failed one are never sent, so they are kept to be replayed in order
+        let mut destination_dead_letters = Vec::new();
+        if let (Err((Some(failed_index), _, message)), Some(_)) = (&result, &settings.environment.dead_letter_file) {
+            for content in &contents[*failed_index..] {
+                if let Some(body) = json_body(platform, content)? {
+                    destination_dead_letters.push(DeadLetter { failed_at: Utc::now(), message_id: message_ids.join(", "), url: url.clone(), body, error: message.clone() });
+                }
+            }
+        }
+        // the delivered parts cannot be taken back, so the message counts as posted with the rest missing
+        let result = match result {
+            Err((Some(failed_index), Some(delivered_response), message)) => {
+                warnings.push(format!("{}only {} of {} parts are posted: {}", destination_prefix, failed_index, contents.len(), message));
+                Ok(Some(delivered_response))
+            },
+            Err((_, _, message)) => Err(message),
+            Ok(response) => Ok(response),
+        };
+        match result {
+            Ok(response) if failover => {
+                first_response = response.map(|response| (index, response));
+                errors.clear();
+                dead_letters = destination_dead_letters;
+                break;
+            },
+            Ok(response) if index == 0 => first_response = response.map(|response| (0, response)),
+            Ok(_) => {},
+            Err(message) => errors.push(format!("{}{}", destination_prefix, message)),
+        }
+        dead_letters.extend(destination_dead_letters);
+    }
+    if let (Some(dead_letter_file), false) = (&settings.environment.dead_letter_file, dead_letters.is_empty()) {
+        append_dead_letters(dead_letter_file, &dead_letters)?;
+    }
+    if !errors.is_empty() {
+        return Err(errors.join("; "));
+    }
+
+    Ok(first_response)
+}
+
+// Returns the index of the part which failed with the error, and the response of the part before it if any.
+async fn post_contents<P: Poster>(poster: &P, url: &str, platform: &Platform, contents: &[SimpleWebhookRequest<'_>], retry: Option<&RetrySettings>, warnings: &mut Vec<String>) -> Result<Option<PostResponse>, (usize, Option<PostResponse>, String)> {
+    let mut last_response = None;
+    for (index, content) in contents.iter().enumerate() {
+        let response = match retry {
+            Some(retry) => post_with_retry(poster, url, platform, content, retry, warnings).await,
+            None => poster.post(url, platform, content).await.map_err(String::from),
+        };
+        match response {
+            Ok(response) => last_response = Some(response),
+            Err(message) => return Err((index, last_response, message)),
+        }
+    }
+    Ok(last_response)
+}
+
+// A message cannot be split when it is edited, since the edit replaces a single message.
+async fn edit_contents<P: Poster>(poster: &P, url: &str, message_id: &str, contents: &[SimpleWebhookRequest<'_>]) -> Result<Option<PostResponse>, String> {
+    if contents.len() > 1 {
+        return Err(String::from("the message is too long to edit"));
+    }
+    poster.edit(url, message_id, contents[0].message).await.map(Some)
+}
+
+// the URL, the platform and the accepted statuses
+type DestinationRef<'a> = (String, &'a Platform, &'a Option<Vec<u16>>);
+
+// The webhook URL comes first, followed by the other destinations which the same message is posted to.
+// In the failover, any of them can post the message which is replied to or edited later.
+fn destinations(settings: &Settings, edit_last: bool) -> Result<Vec<DestinationRef<'_>>, String> {
+    let mut destinations = vec![(webhook_url(&settings.environment.webhook_url, &settings.environment.platform, settings, edit_last)?, &settings.environment.platform, &settings.environment.accepted_statuses)];
+    for destination in &settings.environment.destinations {
+        let url = if settings.environment.strategy == Strategy::Failover { webhook_url(&destination.url, &destination.platform, settings, edit_last)? } else { destination.url.clone() };
+        destinations.push((url, &destination.platform, &destination.accepted_statuses));
+    }
+    Ok(destinations)
+}
+
+// Discord returns the posted message only if `wait=true` is given, and it is needed to reply to the message or edit it later.
+fn webhook_url(webhook_url: &str, platform: &Platform, settings: &Settings, edit_last: bool) -> Result<String, String> {
+    if !(settings.environment.reply_to_previous || edit_last) || *platform != Platform::Discord {
+        return Ok(webhook_url.to_owned());
+    }
+
+    let mut url = reqwest::Url::parse(webhook_url).map_err(|e| format!("invalid webhook URL: {}", e))?;
+    url.query_pairs_mut().append_pair("wait", "true");
+    Ok(url.into())
+}
+
+fn confirm_on_stdin(preview: &str) -> bool {
+    println!("{}", preview);
+    print!("post this message? [y/n] ");
+    std::io::stdout().flush().unwrap();
+
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer).is_ok() && answer.trim().eq_ignore_ascii_case("y")
+}
+
+fn posted_within_min_interval(settings: &Settings, random_picker: &RandomPicker<String>, now: DateTime<Local>) -> Option<DateTime<Utc>> {
+    let min_interval = settings.environment.min_interval?.to_chrono();
+    let last_posted_at = random_picker.state().last_posted_at?;
+    if now.signed_duration_since(last_posted_at) < min_interval {
+        Some(last_posted_at)
+    } else {
+        None
+    }
+}
+
+fn lint(settings: &Settings, log_path: &Path) -> Result<(), String> {
+    let random_picker = load_picker(settings, log_path)?;
 
-    let message_id = random_picker.pick();
-    let content = SimpleWebhookRequest {
-        username: &settings.environment.user_settings.name,
-        avatar_url: &settings.environment.user_settings.icon_url,
-        message: &settings.messages[message_id],
+    let mut unreachable = lint::find_unreachable(&random_picker, lint::DEFAULT_THRESHOLD);
+    if unreachable.is_empty() {
+        println!("all messages are reachable");
+        return Ok(());
+    }
+
+    unreachable.sort_by(|a, b| a.0.cmp(b.0));
+    println!("messages with a probability below {}%:", lint::DEFAULT_THRESHOLD * 100.0);
+    for (message_id, probability) in unreachable {
+        println!("  {}: {:.4}%", message_id, probability * 100.0);
+    }
+
+    Ok(())
+}
+
+fn check(settings_path: &Path, overlay_path: Option<&Path>) -> Result<(), String> {
+    let problems = match read_settings_with_overlay(settings_path, overlay_path) {
+        Ok(settings) => check_settings(&settings),
+        Err(message) => vec![message],
+    };
+    if problems.is_empty() {
+        println!("OK");
+        return Ok(());
+    }
+
+    for (index, problem) in problems.iter().enumerate() {
+        println!("{}. {}", index + 1, problem);
+    }
+    Err(format!("found {} problems in the settings", problems.len()))
+}
+
+async fn self_test(settings_path: &Path, overlay_path: Option<&Path>, log_path: &Path) -> Result<(), String> {
+    let results = self_test_results(settings_path, overlay_path, log_path).await;
+    for (name, result) in &results {
+        match result {
+            Ok(()) => println!("{}: pass", name),
+            Err(message) => println!("{}: fail: {}", name, message),
+        }
+    }
+
+    let failures = results.iter().filter(|(_, result)| result.is_err()).count();
+    if failures == 0 { Ok(()) } else { Err(format!("{} of {} self-test checks failed", failures, results.len())) }
+}
+
+// The other checks need the settings, so they are skipped if the settings cannot be read.
+async fn self_test_results(settings_path: &Path, overlay_path: Option<&Path>, log_path: &Path) -> Vec<(&'static str, Result<(), String>)> {
+    let settings = match read_settings_with_overlay(settings_path, overlay_path) {
+        Ok(settings) => settings,
+        Err(message) => return vec![("settings", Err(message))],
+    };
+    let problems = check_settings(&settings);
+    let settings_result = if problems.is_empty() { Ok(()) } else { Err(problems.join("; ")) };
+
+    let mut results = vec![("settings", settings_result)];
+    results.extend(self_test_environment(&settings, log_path).await);
+    results
+}
+
+// Reads the log and the external weights without writing anything, and sends a GET request to the webhook instead of posting.
+async fn self_test_environment(settings: &Settings, log_path: &Path) -> Vec<(&'static str, Result<(), String>)> {
+    let log_directory = log_path.parent().filter(|path| !path.as_os_str().is_empty()).unwrap_or_else(|| Path::new("."));
+    let log_result = if !log_path.exists() && !log_directory.is_dir() {
+        Err(format!("the directory of the log does not exist: {}", log_directory.display()))
+    } else {
+        load_picker(settings, log_path).map(|_| ())
     };
-    post(&settings.environment.webhook_url, &content).await?;
+    let webhook_result = check_reachable(&settings.environment.webhook_url, &settings.environment.platform).await;
+    vec![("log", log_result), ("webhook", webhook_result)]
+}
+
+fn explain(settings_path: &Path, overlay_path: Option<&Path>) -> Result<(), String> {
+    for (name, source) in explain_settings(settings_path, overlay_path)? {
+        let source = match source {
+            FieldSource::File => "from the file",
+            FieldSource::Default => "default",
+            FieldSource::Unknown => "unknown field, which is ignored",
+        };
+        println!("{}: {}", name, source);
+    }
+    Ok(())
+}
+
+// Without `count`, the message is recorded with the count which it would get on the next post.
+fn add_message(settings: &Settings, log_path: &Path, id: &str, count: Option<u64>) -> Result<(), String> {
+    if !settings.messages.contains_key(id) {
+        return Err(format!("message `{}` is not in the settings", id));
+    }
+    let _lock = lock_log(settings, log_path)?;
+    let mut random_picker = load_picker(settings, log_path)?;
+    let initial_count = random_picker.counts().into_iter().find(|(value, _)| *value == id).map(|(_, count)| count).unwrap();
+    let count = count.unwrap_or(initial_count);
+    random_picker.add_item(id.to_owned(), count).map_err(|_| format!("message `{}` is already in the log", id))?;
+    random_picker.write_log()?;
+    println!("added `{}` to {} with count {}", id, log_path.display(), count);
+    Ok(())
+}
 
+fn remove_message(settings: &Settings, log_path: &Path, id: &str) -> Result<(), String> {
+    let _lock = lock_log(settings, log_path)?;
+    let mut random_picker = load_picker(settings, log_path)?;
+    // a message which is no longer in the settings is not in the picker, but `write_log` drops it as well
+    let removed = random_picker.remove_item(&id.to_owned());
+    let stale = !settings.messages.contains_key(id) && read_log_counts::<String>(log_path, settings.environment.log_format)?.contains_key(id);
+    if !removed && !stale {
+        eprintln!("warning: message `{}` is not in the log", id);
+        return Ok(());
+    }
     random_picker.write_log()?;
+    println!("removed `{}` from {}", id, log_path.display());
+    Ok(())
+}
+
+fn simulate(settings: &Settings, log_path: &Path, iterations: usize) -> Result<(), String> {
+    let random_picker = load_picker(settings, log_path)?;
+    println!("picks in {} simulated posts:", iterations);
+    for (message_id, count) in simulate_picks(&random_picker, iterations)? {
+        println!("  {}: {} ({:.1}%)", message_id, count, count as f64 / iterations as f64 * 100.0);
+    }
+    Ok(())
+}
+
+// Picks with a copy of the picker, so that neither the log nor the counts of the picker are changed.
+fn simulate_picks(random_picker: &RandomPicker<String>, iterations: usize) -> Result<Vec<(String, u64)>, String> {
+    let mut simulated_picker = random_picker.clone();
+    let mut picks: HashMap<String, u64> = random_picker.counts().into_iter().map(|(message_id, _)| (message_id.clone(), 0)).collect();
+    for _ in 0..iterations {
+        *picks.get_mut(simulated_picker.pick()?).unwrap() += 1;
+    }
+
+    let mut picks: Vec<(String, u64)> = picks.into_iter().collect();
+    picks.sort();
+    Ok(picks)
+}
+
+#[derive(PartialEq, Eq, Debug)]
+struct ListEntry {
+    id: String,
+    count: u64,
+    in_settings: bool,
+    in_log: bool,
+}
+
+fn list(settings: &Settings, log_path: &Path) -> Result<(), String> {
+    let entries = list_entries(settings, log_path)?;
+    let id_width = entries.iter().map(|entry| entry.id.chars().count()).chain(std::iter::once(2)).max().unwrap();
+    println!("{:<width$}  {:>10}  status", "id", "count", width = id_width);
+    for entry in entries {
+        let status = match (entry.in_settings, entry.in_log) {
+            (true, true) => "ok",
+            (true, false) => "not in the log",
+            _ => "not in the settings",
+        };
+        println!("{:<width$}  {:>10}  {}", entry.id, entry.count, status, width = id_width);
+    }
+    Ok(())
+}
 
+fn extremes(settings: &Settings, log_path: &Path) -> Result<(), String> {
+    let random_picker = load_picker(settings, log_path)?;
+    for line in extremes_lines(&random_picker) {
+        println!("{}", line);
+    }
     Ok(())
 }
+
+fn extremes_lines(random_picker: &RandomPicker<String>) -> Vec<String> {
+    let counts: HashMap<&String, u64> = random_picker.counts().into_iter().collect();
+    let least_picked = random_picker.least_picked();
+    let most_picked = random_picker.most_picked();
+    vec![
+        format!("least picked: {} ({})", least_picked, counts[least_picked]),
+        format!("most picked: {} ({})", most_picked, counts[most_picked]),
+    ]
+}
+
+// Lists the messages in the settings with the counts used to pick them, and the messages which remain only in the log.
+fn list_entries(settings: &Settings, log_path: &Path) -> Result<Vec<ListEntry>, String> {
+    let random_picker = load_picker(settings, log_path)?;
+    let mut log_counts = read_log_counts::<String>(log_path, settings.environment.log_format)?;
+
+    let mut entries: Vec<ListEntry> = random_picker.counts().into_iter().map(|(id, count)| ListEntry {
+        id: id.clone(),
+        count,
+        in_settings: true,
+        in_log: log_counts.remove(id).is_some(),
+    }).collect();
+    entries.extend(log_counts.into_iter().map(|(id, count)| ListEntry { id, count, in_settings: false, in_log: true }));
+    entries.sort_by(|a, b| a.id.cmp(&b.id));
+    Ok(entries)
+}
+
+// Held by the commands which write the log from when they read it, if `lock` is set.
+fn lock_log(settings: &Settings, log_path: &Path) -> Result<Option<FileLock>, String> {
+    settings.environment.lock.map(|mode| lock(log_path, mode)).transpose()
+}
+
+// For the commands which read the log as of when they run.
+fn load_picker(settings: &Settings, log_path: &Path) -> Result<RandomPicker<String>, String> {
+    load_picker_at(settings, log_path, Utc::now())
+}
+
+// `now` decides both the stale messages and the oldest ones for OldestFirst.
+fn load_picker_at(settings: &Settings, log_path: &Path, now: DateTime<Utc>) -> Result<RandomPicker<String>, String> {
+    let mut builder = RandomPickerBuilder::new()
+        .now(now)
+        .weight_type(settings.environment.weight_type.clone())
+        .initial_count_type(settings.environment.initial_count_type)
+        .avoid_repeat(settings.environment.avoid_repeat)
+        .cooldown(settings.environment.cooldown.unwrap_or(0))
+        .on_corrupt_log(settings.environment.on_corrupt_log)
+        .log_format(settings.environment.log_format)
+        .penalize_failures(settings.environment.penalize_failures)
+        .normalize_counts(settings.environment.normalize_counts);
+    if let Some(seed) = settings.environment.seed {
+        builder = builder.seed(seed);
+    }
+    if let Some(max_ratio) = settings.environment.max_ratio {
+        builder = builder.max_ratio(max_ratio);
+    }
+    if let Some(epsilon) = settings.environment.epsilon {
+        builder = builder.epsilon(epsilon);
+    }
+    if let Some(group_smoothing) = settings.environment.group_smoothing {
+        builder = builder.group_smoothing(group_smoothing);
+    }
+    let mut random_picker = builder.build(log_path, settings.messages.keys().cloned().collect())?;
+    if settings.environment.stable_order {
+        random_picker.sort_items();
+    }
+    random_picker.set_targets(
+        settings.messages.iter()
+            .filter_map(|(id, message_settings)| message_settings.target.map(|target| (id.clone(), target)))
+            .collect()
+    );
+    random_picker.set_importances(
+        settings.messages.iter()
+            .filter_map(|(id, message_settings)| message_settings.importance.map(|importance| (id.clone(), importance)))
+            .collect()
+    );
+    random_picker.set_new_value_counts(
+        settings.messages.iter()
+            .filter_map(|(id, message_settings)| message_settings.initial_count.map(|count| (id.clone(), count as u64)))
+            .collect()
+    );
+    if let Some(boost) = settings.environment.new_message_boost {
+        random_picker.set_new_value_boost(boost)?;
+    }
+    random_picker.set_groups(
+        settings.messages.iter()
+            .filter_map(|(id, message_settings)| message_settings.group.clone().map(|group| (id.clone(), group)))
+            .collect()
+    );
+    random_picker.set_group_weight_types(
+        settings.groups.iter()
+            .filter_map(|(name, group_settings)| group_settings.weight_type.clone().map(|weight_type| (name.clone(), weight_type)))
+            .collect()
+    )?;
+    random_picker.set_weight_multipliers(weight_multipliers(settings)?);
+
+    Ok(random_picker)
+}
+
+fn weight_multipliers(settings: &Settings) -> Result<HashMap<String, f64>, String> {
+    let mut weight_multipliers = external_weights(settings)?;
+    if settings.environment.weight_by_cost {
+        for (id, message_settings) in &settings.messages {
+            if let Some(cost) = message_settings.cost {
+                *weight_multipliers.entry(id.clone()).or_insert(1.0) /= 1.0 + cost;
+            }
+        }
+    }
+    Ok(weight_multipliers)
+}
+
+// The messages with preferred hours are multiplied by the factor within them and divided by it outside them.
+fn preferred_hours_multipliers(settings: &Settings, hour: u32) -> HashMap<String, f64> {
+    let factor = settings.environment.preferred_hours_factor.unwrap_or(DEFAULT_PREFERRED_HOURS_FACTOR);
+    settings.messages.iter()
+        .filter_map(|(id, message_settings)| message_settings.preferred_hours.map(|preferred_hours| (id, preferred_hours)))
+        .map(|(id, preferred_hours)| (id.clone(), if preferred_hours.contains(hour) { factor } else { 1.0 / factor }))
+        .collect()
+}
+
+fn external_weights(settings: &Settings) -> Result<HashMap<String, f64>, String> {
+    let mut weights_by_path: HashMap<&Path, HashMap<String, f64>> = HashMap::new();
+    let mut external_weights = HashMap::new();
+    for (id, message_settings) in &settings.messages {
+        let group_weight_type = message_settings.group.as_ref()
+            .and_then(|group| settings.groups.get(group))
+            .and_then(|group_settings| group_settings.weight_type.as_ref());
+        let path = match group_weight_type.unwrap_or(&settings.environment.weight_type) {
+            WeightType::External { path } => path.as_path(),
+            _ => continue,
+        };
+
+        if !weights_by_path.contains_key(path) {
+            weights_by_path.insert(path, read_external_weights(path)?);
+        }
+        if let Some(weight) = weights_by_path[path].get(id) {
+            external_weights.insert(id.clone(), *weight);
+        }
+    }
+    Ok(external_weights)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate indoc;
+    extern crate mockito;
+    extern crate tempfile;
+
+    use super::*;
+    use chrono::TimeZone;
+    use dead_letter::read_dead_letters;
+    use indoc::{formatdoc, indoc};
+    use mockito::Matcher;
+    use random::LogFormat;
+    use request::PostError;
+    use settings::HumanDuration;
+    use std::cell::RefCell;
+    use tempfile::NamedTempFile;
+
+    // records the URL and the JSON body of each request instead of sending it
+    #[derive(Default)]
+    struct RecordingPoster {
+        requests: RefCell<Vec<(String, String)>>,
+    }
+
+    impl Poster for RecordingPoster {
+        async fn post(&self, webhook_url: &str, _: &Platform, request: &SimpleWebhookRequest<'_>) -> Result<PostResponse, PostError> {
+            self.requests.borrow_mut().push((webhook_url.to_owned(), serde_json::to_string(request).unwrap()));
+            Ok(PostResponse { status: 204, message_id: None })
+        }
+
+        async fn edit(&self, webhook_url: &str, message_id: &str, message: &Message) -> Result<PostResponse, String> {
+            self.requests.borrow_mut().push((format!("{}/messages/{}", webhook_url, message_id), serde_json::to_string(message).unwrap()));
+            Ok(PostResponse { status: 200, message_id: Some(message_id.to_owned()) })
+        }
+
+        async fn post_json(&self, webhook_url: &str, body: &str) -> Result<PostResponse, PostError> {
+            self.requests.borrow_mut().push((webhook_url.to_owned(), body.to_owned()));
+            Ok(PostResponse { status: 204, message_id: None })
+        }
+    }
+
+    #[tokio::test]
+    async fn post_random_should_send_the_picked_message_through_the_given_poster() {
+        let settings = settings_from_str(indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/123/abc"
+              weight_type:
+                type: "Uniform"
+              user:
+                name: "username"
+            messages:
+              abc: "message1"
+        "#});
+        let log_file = NamedTempFile::new().unwrap();
+        let log_path = log_file.path().to_owned();
+        log_file.close().unwrap();
+
+        let poster = RecordingPoster::default();
+        let outcome = post_random(&poster, &settings, &log_path, Local::now(), &PostOptions::default()).await.unwrap();
+
+        assert_eq!(outcome.status, Some(204));
+        let expected_body = r#"{"username":"username","avatar_url":null,"content":"message1"}"#;
+        assert_eq!(*poster.requests.borrow(), vec![(String::from("https://discord.com/api/webhooks/123/abc"), String::from(expected_body))]);
+        assert!(log_path.exists());
+    }
+
+    #[tokio::test]
+    async fn until_signal_should_return_the_signal_if_it_comes_first() {
+        assert_eq!(until_signal(std::future::pending::<()>(), async { "SIGTERM" }).await, Err("SIGTERM"));
+        assert_eq!(until_signal(async { 1 }, std::future::pending()).await, Ok(1));
+    }
+
+    #[tokio::test]
+    async fn daemon_should_post_on_each_interval_until_the_shutdown() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server.mock("POST", "/")
+            .with_status(204)
+            .expect_at_least(3)
+            .create_async().await;
+
+        let settings = settings_from_str(&formatdoc! {r#"
+            environment:
+              webhook_url: "{}"
+              weight_type:
+                type: "Uniform"
+            messages:
+              abc: "message1"
+        "#, server.url()});
+        let log_file = NamedTempFile::new().unwrap();
+        let log_path = log_file.path().to_owned();
+        log_file.close().unwrap();
+
+        let shutdown = async {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            "the test"
+        };
+        daemon(&ReqwestPoster::shared(), &settings, &log_path, Duration::from_millis(20), OutputFormat::Text, shutdown).await.unwrap();
+
+        mock.assert_async().await;
+        let counts = read_log_counts::<String>(&log_path, settings.environment.log_format).unwrap();
+        assert!(counts["abc"] >= 3, "{:?}", counts);
+    }
+
+    #[tokio::test]
+    async fn daemon_should_keep_posting_after_a_failed_post() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server.mock("POST", "/")
+            .with_status(400)
+            .expect_at_least(2)
+            .create_async().await;
+
+        let settings = settings_from_str(&formatdoc! {r#"
+            environment:
+              webhook_url: "{}"
+              weight_type:
+                type: "Uniform"
+            messages:
+              abc: "message1"
+        "#, server.url()});
+        let log_file = NamedTempFile::new().unwrap();
+        let log_path = log_file.path().to_owned();
+        log_file.close().unwrap();
+
+        let shutdown = async {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            "the test"
+        };
+        daemon(&ReqwestPoster::shared(), &settings, &log_path, Duration::from_millis(20), OutputFormat::Text, shutdown).await.unwrap();
+
+        mock.assert_async().await;
+    }
+
+    #[test]
+    fn persist_interrupted_should_write_the_count_of_the_interrupted_pick() {
+        let settings = settings_from_str(indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/123/abc"
+              weight_type:
+                type: "Uniform"
+            messages:
+              abc: "message1"
+        "#});
+        let log_file = NamedTempFile::new().unwrap();
+        let log_path = log_file.path().to_owned();
+        log_file.close().unwrap();
+
+        let mut random_picker = load_picker(&settings, &log_path).unwrap();
+        random_picker.pick().unwrap();
+        let result = persist_interrupted(&random_picker, "SIGTERM");
+
+        assert_eq!(result, Err(String::from("interrupted by SIGTERM after writing the log")));
+        let random_picker = load_picker(&settings, &log_path).unwrap();
+        assert_eq!(random_picker.counts(), vec![(&String::from("abc"), 1)]);
+    }
+
+    #[tokio::test]
+    async fn post_random_should_post_the_message_changed_by_the_transform() {
+        let settings = settings_from_str(indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/123/abc"
+              weight_type:
+                type: "Uniform"
+              transformers:
+                - type: "Prefix"
+                  text: "[tip] "
+            messages:
+              abc: "message1"
+        "#});
+        let log_file = NamedTempFile::new().unwrap();
+        let log_path = log_file.path().to_owned();
+        log_file.close().unwrap();
+
+        let poster = RecordingPoster::default();
+        let transform = |message: &mut Message| {
+            settings.environment.transformers.iter().for_each(|transformer| transformer.apply(message));
+            message.map_text(&|text| text.to_uppercase());
+        };
+        let options = PostOptions { transform: Some(&transform), ..PostOptions::default() };
+        post_random(&poster, &settings, &log_path, Local::now(), &options).await.unwrap();
+
+        let body: serde_json::Value = serde_json::from_str(&poster.requests.borrow()[0].1).unwrap();
+        assert_eq!(body["content"], "[TIP] MESSAGE1");
+    }
+
+    #[tokio::test]
+    async fn post_random_should_rotate_the_emoji_in_the_prefix_on_successive_runs() {
+        let settings = settings_from_str(indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/123/abc"
+              weight_type:
+                type: "Uniform"
+              content_prefix: "[tip] "
+              content_suffix: " #daily"
+              emoji_rotation: ["🌱", "🌻", "🍂"]
+            messages:
+              abc: "message1"
+              def:
+                embeds:
+                  - title: "title1"
+        "#});
+        let log_file = NamedTempFile::new().unwrap();
+        let log_path = log_file.path().to_owned();
+        log_file.close().unwrap();
+
+        let poster = RecordingPoster::default();
+        for _ in 0..4 {
+            post_random(&poster, &settings, &log_path, Local::now(), &PostOptions { forced_id: Some("abc"), count_forced: true, ..PostOptions::default() }).await.unwrap();
+        }
+        post_random(&poster, &settings, &log_path, Local::now(), &PostOptions { forced_id: Some("def"), count_forced: true, ..PostOptions::default() }).await.unwrap();
+
+        let bodies: Vec<serde_json::Value> = poster.requests.borrow().iter().map(|(_, body)| serde_json::from_str(body).unwrap()).collect();
+        let contents: Vec<&str> = bodies[..4].iter().map(|body| body["content"].as_str().unwrap()).collect();
+        assert_eq!(contents, vec!["🌱 [tip] message1 #daily", "🌻 [tip] message1 #daily", "🍂 [tip] message1 #daily", "🌱 [tip] message1 #daily"]);
+        assert_eq!(bodies[4].get("content"), None);
+        assert_eq!(bodies[4]["embeds"][0]["title"], "title1");
+    }
+
+    #[tokio::test]
+    async fn post_random_should_post_the_message_posted_the_longest_ago_with_oldest_first() {
+        let settings = settings_from_str(indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/123/abc"
+              weight_type: "OldestFirst"
+            messages:
+              a: "message1"
+              b: "message2"
+              c: "message3"
+        "#});
+        let mut log_file = NamedTempFile::new().unwrap();
+        write!(log_file, r#"[
+            {{ "value": "a", "count": 1, "last_posted_at": "2021-09-01T12:00:00Z" }},
+            {{ "value": "b", "count": 5, "last_posted_at": "2021-08-01T12:00:00Z" }},
+            {{ "value": "c", "count": 0, "last_posted_at": "2021-08-15T12:00:00Z" }}
+        ]"#).unwrap();
+
+        let poster = RecordingPoster::default();
+        let now = Local.with_ymd_and_hms(2021, 9, 2, 12, 0, 0).unwrap();
+        let mut picked_ids = Vec::new();
+        for days in 0..4 {
+            let outcome = post_random(&poster, &settings, log_file.path(), now + chrono::Duration::days(days), &PostOptions::default()).await.unwrap();
+            picked_ids.push(outcome.picked_id.unwrap());
+        }
+        assert_eq!(picked_ids, vec!["b", "c", "a", "b"]);
+    }
+
+    #[tokio::test]
+    async fn post_random_should_break_the_ties_of_oldest_first_within_a_burst() {
+        let settings = settings_from_str(indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/123/abc"
+              weight_type: "OldestFirst"
+            messages:
+              a: "message1"
+              b: "message2"
+              c: "message3"
+        "#});
+        let mut log_file = NamedTempFile::new().unwrap();
+        write!(log_file, r#"[
+            {{ "value": "a", "count": 1, "last_posted_at": "2021-09-01T12:00:00Z" }},
+            {{ "value": "b", "count": 1, "last_posted_at": "2021-09-01T12:00:00Z" }},
+            {{ "value": "c", "count": 1, "last_posted_at": "2021-09-01T12:00:00Z" }}
+        ]"#).unwrap();
+
+        // every post of the burst is recorded at the same time, after which the others are the oldest
+        let poster = RecordingPoster::default();
+        let now = Local.with_ymd_and_hms(2021, 9, 2, 12, 0, 0).unwrap();
+        post_random(&poster, &settings, log_file.path(), now, &PostOptions { burst: Some(3), ..PostOptions::default() }).await.unwrap();
+        let mut contents: Vec<String> = poster.requests.borrow().iter()
+            .map(|(_, body)| serde_json::from_str::<serde_json::Value>(body).unwrap()["content"].as_str().unwrap().to_owned())
+            .collect();
+        contents.sort();
+        assert_eq!(contents, vec!["message1", "message2", "message3"]);
+    }
+
+    #[tokio::test]
+    async fn post_random_should_post_the_forced_message_regardless_of_the_weights() {
+        let settings = settings_from_str(indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/123/abc"
+              weight_type:
+                type: "MinOnly"
+            messages:
+              a: "message1"
+              b: "message2"
+        "#});
+        let log = r#"[{ "value": "a", "count": 0 }, { "value": "b", "count": 100 }]"#;
+
+        for count_forced in [false, true] {
+            let mut log_file = NamedTempFile::new().unwrap();
+            write!(log_file, "{}", log).unwrap();
+
+            let poster = RecordingPoster::default();
+            let options = PostOptions { forced_id: Some("b"), count_forced, ..PostOptions::default() };
+            let outcome = post_random(&poster, &settings, log_file.path(), Local::now(), &options).await.unwrap();
+
+            let body: serde_json::Value = serde_json::from_str(&poster.requests.borrow()[0].1).unwrap();
+            assert_eq!(body["content"], "message2");
+            assert_eq!(outcome.picked_id, Some(String::from("b")));
+            assert!(outcome.posted);
+
+            let counts = read_log_counts::<String>(log_file.path(), settings.environment.log_format).unwrap();
+            assert_eq!(counts.get("a"), Some(&0));
+            assert_eq!(counts.get("b"), Some(if count_forced { &101 } else { &100 }));
+        }
+    }
+
+    #[tokio::test]
+    async fn post_random_should_fail_if_the_forced_message_is_not_in_the_settings() {
+        let settings = settings_from_str(indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/123/abc"
+              weight_type:
+                type: "Uniform"
+            messages:
+              a: "message1"
+        "#});
+        let log_file = NamedTempFile::new().unwrap();
+
+        let poster = RecordingPoster::default();
+        let options = PostOptions { forced_id: Some("b"), ..PostOptions::default() };
+        let result = post_random(&poster, &settings, log_file.path(), Local::now(), &options).await;
+        assert_eq!(result, Err(String::from("message `b` is not in the settings")));
+        assert!(poster.requests.borrow().is_empty());
+    }
+
+    #[tokio::test]
+    async fn post_random_should_fill_the_embeds_with_the_defaults() {
+        let settings = settings_from_str(indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/123/abc"
+              weight_type:
+                type: "Uniform"
+            messages:
+              abc:
+                content: "message1"
+                embeds:
+                  - title: "without a footer"
+                  - title: "with a footer"
+                    footer:
+                      text: "own footer"
+            defaults:
+              embed:
+                color: "red"
+                footer:
+                  text: "default footer"
+        "#});
+        let log_file = NamedTempFile::new().unwrap();
+        let log_path = log_file.path().to_owned();
+        log_file.close().unwrap();
+
+        let poster = RecordingPoster::default();
+        post_random(&poster, &settings, &log_path, Local::now(), &PostOptions::default()).await.unwrap();
+
+        let body: serde_json::Value = serde_json::from_str(&poster.requests.borrow()[0].1).unwrap();
+        assert_eq!(body["embeds"], serde_json::json!([
+            { "title": "without a footer", "color": 0xFF0000, "footer": { "text": "default footer" } },
+            { "title": "with a footer", "color": 0xFF0000, "footer": { "text": "own footer" } },
+        ]));
+    }
+
+    #[tokio::test]
+    async fn post_random_should_combine_distinct_messages_into_one_post() {
+        let settings = settings_from_str(indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/123/abc"
+              weight_type:
+                type: "Uniform"
+            messages:
+              abc: "tip1"
+              def: "tip2"
+              ghi: "tip3"
+              jkl:
+                file: "image.png"
+        "#});
+        let log_file = NamedTempFile::new().unwrap();
+        let log_path = log_file.path().to_owned();
+        log_file.close().unwrap();
+
+        let poster = RecordingPoster::default();
+        let options = PostOptions { combine: Some(3), ..PostOptions::default() };
+        let outcome = post_random(&poster, &settings, &log_path, Local::now(), &options).await.unwrap();
+
+        let requests = poster.requests.borrow();
+        assert_eq!(requests.len(), 1);
+        let body: serde_json::Value = serde_json::from_str(&requests[0].1).unwrap();
+        let mut lines: Vec<&str> = body["content"].as_str().unwrap().split('\n').collect();
+        lines.sort();
+        assert_eq!(lines, vec!["tip1", "tip2", "tip3"]);
+        assert!(outcome.posted);
+
+        let random_picker = load_picker(&settings, &log_path).unwrap();
+        let mut counts: Vec<(&String, u64)> = random_picker.counts();
+        counts.sort();
+        assert_eq!(counts, vec![(&String::from("abc"), 1), (&String::from("def"), 1), (&String::from("ghi"), 1), (&String::from("jkl"), 0)]);
+    }
+
+    #[tokio::test]
+    async fn post_random_should_fail_if_there_are_not_enough_messages_to_combine() {
+        let settings = settings_from_str(indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/123/abc"
+              weight_type:
+                type: "Uniform"
+            messages:
+              abc: "tip1"
+              def:
+                file: "image.png"
+        "#});
+        let log_file = NamedTempFile::new().unwrap();
+        let log_path = log_file.path().to_owned();
+        log_file.close().unwrap();
+
+        let options = PostOptions { combine: Some(2), ..PostOptions::default() };
+        let result = post_random(&RecordingPoster::default(), &settings, &log_path, Local::now(), &options).await;
+        assert_eq!(result, Err(String::from("--combine 2 needs as many messages without files which can be picked, but there are 1")));
+    }
+
+    #[tokio::test]
+    async fn post_random_should_substitute_increasing_run_numbers_in_consecutive_runs() {
+        let settings = settings_from_str(indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/123/abc"
+              weight_type:
+                type: "Uniform"
+            messages:
+              abc: "Day #{{run_number}}, post #{{total_posts}} on {{date}}"
+        "#});
+        let log_file = NamedTempFile::new().unwrap();
+        let log_path = log_file.path().to_owned();
+        log_file.close().unwrap();
+
+        let poster = RecordingPoster::default();
+        let first_day = Local.with_ymd_and_hms(2021, 9, 1, 12, 0, 0).unwrap();
+        post_random(&poster, &settings, &log_path, first_day, &PostOptions { burst: Some(2), ..PostOptions::default() }).await.unwrap();
+        let second_day = Local.with_ymd_and_hms(2021, 9, 2, 12, 0, 0).unwrap();
+        post_random(&poster, &settings, &log_path, second_day, &PostOptions::default()).await.unwrap();
+
+        let contents: Vec<String> = poster.requests.borrow().iter()
+            .map(|(_, body)| serde_json::from_str::<serde_json::Value>(body).unwrap()["content"].as_str().unwrap().to_owned())
+            .collect();
+        assert_eq!(contents, vec![
+            String::from("Day #1, post #1 on 2021-09-01"),
+            String::from("Day #1, post #2 on 2021-09-01"),
+            String::from("Day #2, post #3 on 2021-09-02"),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn post_random_should_not_count_a_failed_run() {
+        let mut server = mockito::Server::new_async().await;
+        let failed_mock = server.mock("POST", "/")
+            .with_status(400)
+            .expect(1)
+            .create_async().await;
+
+        let settings = settings_from_str(&formatdoc! {r#"
+            environment:
+              webhook_url: "{}"
+              weight_type:
+                type: "Uniform"
+              penalize_failures: true
+            messages:
+              abc: "Day #{{{{run_number}}}}"
+        "#, server.url()});
+        let log_file = NamedTempFile::new().unwrap();
+        let log_path = log_file.path().to_owned();
+        log_file.close().unwrap();
+
+        assert!(post_random(&ReqwestPoster::shared(), &settings, &log_path, Local::now(), &PostOptions::default()).await.is_err());
+        failed_mock.assert_async().await;
+
+        let random_picker = load_picker(&settings, &log_path).unwrap();
+        assert_eq!((random_picker.state().run_number, random_picker.state().total_posts), (None, None));
+    }
+
+    #[tokio::test]
+    async fn post_random_should_write_a_dead_letter_if_posting_fails() {
+        let mut server = mockito::Server::new_async().await;
+        let failed_mock = server.mock("POST", "/")
+            .with_status(400)
+            .expect(1)
+            .create_async().await;
+
+        let dead_letter_file = NamedTempFile::new().unwrap();
+        let dead_letter_path = dead_letter_file.path().to_owned();
+        dead_letter_file.close().unwrap();
+        let settings = settings_from_str(&formatdoc! {r#"
+            environment:
+              webhook_url: "{}/"
+              weight_type:
+                type: "Uniform"
+              dead_letter_file: "{}"
+            messages:
+              abc: "message1"
+        "#, server.url(), dead_letter_path.display()});
+        let log_file = NamedTempFile::new().unwrap();
+        let log_path = log_file.path().to_owned();
+        log_file.close().unwrap();
+
+        let error = post_random(&ReqwestPoster::shared(), &settings, &log_path, Local::now(), &PostOptions::default()).await.unwrap_err();
+        failed_mock.assert_async().await;
+
+        let entries = read_dead_letters(&dead_letter_path).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!((entries[0].message_id.as_str(), entries[0].url.clone(), entries[0].error.as_str()), ("abc", format!("{}/", server.url()), error.as_str()));
+        assert_eq!(entries[0].body, r#"{"username":null,"avatar_url":null,"content":"message1"}"#);
+    }
+
+    #[tokio::test]
+    async fn post_random_should_skip_posting_within_the_minimum_interval() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server.mock("POST", "/")
+            .with_status(204)
+            .expect(1)
+            .create_async().await;
+
+        let settings = settings_from_str(&formatdoc! {r#"
+            environment:
+              webhook_url: "{}"
+              weight_type:
+                type: "Uniform"
+              min_interval: 3600
+            messages:
+              abc: "message1"
+        "#, server.url()});
+        let log_file = NamedTempFile::new().unwrap();
+        let log_path = log_file.path().to_owned();
+        log_file.close().unwrap();
+
+        let first_time = Local.with_ymd_and_hms(2021, 9, 1, 12, 0, 0).unwrap();
+        post_random(&ReqwestPoster::shared(), &settings, &log_path, first_time, &PostOptions::default()).await.unwrap();
+        let log = std::fs::read_to_string(&log_path).unwrap();
+
+        let second_time = Local.with_ymd_and_hms(2021, 9, 1, 12, 59, 59).unwrap();
+        post_random(&ReqwestPoster::shared(), &settings, &log_path, second_time, &PostOptions::default()).await.unwrap();
+
+        mock.assert_async().await;
+        assert_eq!(std::fs::read_to_string(&log_path).unwrap(), log);
+    }
+
+    #[tokio::test]
+    async fn post_random_should_post_the_same_message_to_discord_and_slack() {
+        let mut discord_server = mockito::Server::new_async().await;
+        let discord_mock = discord_server.mock("POST", "/")
+            .match_body(Matcher::Json(serde_json::json!({ "username": "user", "avatar_url": null, "content": "message1", "embeds": [] })))
+            .with_status(204)
+            .expect(1)
+            .create_async().await;
+        let mut slack_server = mockito::Server::new_async().await;
+        let slack_mock = slack_server.mock("POST", "/services/XXXX")
+            .match_body(Matcher::Json(serde_json::json!({ "text": "message1", "username": "user" })))
+            .with_status(200)
+            .with_body("ok")
+            .expect(1)
+            .create_async().await;
+
+        let settings = settings_from_str(&formatdoc! {r#"
+            environment:
+              webhook_url: "{}/"
+              destinations:
+                - url: "{}/services/XXXX"
+                  platform:
+                    type: "Slack"
+              weight_type:
+                type: "Uniform"
+              user:
+                name: "user"
+            messages:
+              abc:
+                content: "message1"
+                embeds: []
+        "#, discord_server.url(), slack_server.url()});
+        let log_file = NamedTempFile::new().unwrap();
+        let log_path = log_file.path().to_owned();
+        log_file.close().unwrap();
+
+        post_random(&ReqwestPoster::shared(), &settings, &log_path, Local::now(), &PostOptions::default()).await.unwrap();
+        discord_mock.assert_async().await;
+        slack_mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn post_random_should_report_the_failures_of_every_destination() {
+        let mut discord_server = mockito::Server::new_async().await;
+        let discord_mock = discord_server.mock("POST", "/")
+            .with_status(500)
+            .with_body("discord error")
+            .expect(1)
+            .create_async().await;
+        let mut slack_server = mockito::Server::new_async().await;
+        let slack_mock = slack_server.mock("POST", "/")
+            .with_status(404)
+            .with_body("no_service")
+            .expect(1)
+            .create_async().await;
+
+        let settings = settings_from_str(&formatdoc! {r#"
+            environment:
+              webhook_url: "{}/"
+              destinations:
+                - url: "{}/"
+                  platform:
+                    type: "Slack"
+              weight_type:
+                type: "Uniform"
+            messages:
+              abc: "message1"
+        "#, discord_server.url(), slack_server.url()});
+        let log_file = NamedTempFile::new().unwrap();
+        let log_path = log_file.path().to_owned();
+        log_file.close().unwrap();
+
+        let result = post_random(&ReqwestPoster::shared(), &settings, &log_path, Local::now(), &PostOptions::default()).await;
+        assert_eq!(result, Err(String::from("destination 1: failed with 500 Internal Server Error: discord error; destination 2: failed with 404 Not Found: no_service")));
+        discord_mock.assert_async().await;
+        slack_mock.assert_async().await;
+        assert!(!log_path.exists());
+    }
+
+    #[tokio::test]
+    async fn post_random_should_fail_over_to_the_next_destination() {
+        let mut first_server = mockito::Server::new_async().await;
+        let first_mock = first_server.mock("POST", "/")
+            .with_status(500)
+            .expect(1)
+            .create_async().await;
+        let mut second_server = mockito::Server::new_async().await;
+        let second_mock = second_server.mock("POST", "/")
+            .match_body(Matcher::PartialJson(serde_json::json!({ "content": "message1" })))
+            .with_status(204)
+            .expect(1)
+            .create_async().await;
+        let mut third_server = mockito::Server::new_async().await;
+        let third_mock = third_server.mock("POST", "/")
+            .expect(0)
+            .create_async().await;
+
+        let settings = settings_from_str(&formatdoc! {r#"
+            environment:
+              webhook_url: "{}/"
+              destinations:
+                - url: "{}/"
+                - url: "{}/"
+              strategy: "Failover"
+              weight_type:
+                type: "Uniform"
+            messages:
+              abc: "message1"
+        "#, first_server.url(), second_server.url(), third_server.url()});
+        let log_file = NamedTempFile::new().unwrap();
+        let log_path = log_file.path().to_owned();
+        log_file.close().unwrap();
+
+        let outcome = post_random(&ReqwestPoster::shared(), &settings, &log_path, Local::now(), &PostOptions::default()).await.unwrap();
+        assert_eq!(outcome.status, Some(204));
+        first_mock.assert_async().await;
+        second_mock.assert_async().await;
+        third_mock.assert_async().await;
+        assert_eq!(read_log_counts::<String>(&log_path, settings.environment.log_format).unwrap()["abc"], 1);
+    }
+
+    #[tokio::test]
+    async fn post_random_should_fail_if_every_destination_fails_over() {
+        let mut first_server = mockito::Server::new_async().await;
+        let first_mock = first_server.mock("POST", "/")
+            .with_status(500)
+            .with_body("first error")
+            .expect(1)
+            .create_async().await;
+        let mut second_server = mockito::Server::new_async().await;
+        let second_mock = second_server.mock("POST", "/")
+            .with_status(500)
+            .with_body("second error")
+            .expect(1)
+            .create_async().await;
+
+        let settings = settings_from_str(&formatdoc! {r#"
+            environment:
+              webhook_url: "{}/"
+              destinations:
+                - url: "{}/"
+              strategy: "Failover"
+              weight_type:
+                type: "Uniform"
+            messages:
+              abc: "message1"
+        "#, first_server.url(), second_server.url()});
+        let log_file = NamedTempFile::new().unwrap();
+        let log_path = log_file.path().to_owned();
+        log_file.close().unwrap();
+
+        let result = post_random(&ReqwestPoster::shared(), &settings, &log_path, Local::now(), &PostOptions::default()).await;
+        assert_eq!(result, Err(String::from("destination 1: failed with 500 Internal Server Error: first error; destination 2: failed with 500 Internal Server Error: second error")));
+        first_mock.assert_async().await;
+        second_mock.assert_async().await;
+        assert!(!log_path.exists());
+    }
+
+    #[tokio::test]
+    async fn post_random_should_not_fail_over_once_a_part_is_delivered() {
+        let mut first_server = mockito::Server::new_async().await;
+        let delivered_mock = first_server.mock("POST", "/")
+            .match_body(Matcher::PartialJson(serde_json::json!({ "content": "a".repeat(2000) })))
+            .with_status(204)
+            .expect(1)
+            .create_async().await;
+        let failed_mock = first_server.mock("POST", "/")
+            .match_body(Matcher::PartialJson(serde_json::json!({ "content": "b".repeat(100) })))
+            .with_status(500)
+            .expect(1)
+            .create_async().await;
+        let mut second_server = mockito::Server::new_async().await;
+        let second_mock = second_server.mock("POST", Matcher::Any)
+            .expect(0)
+            .create_async().await;
+
+        let settings = settings_from_str(&formatdoc! {r#"
+            environment:
+              webhook_url: "{}/"
+              destinations:
+                - url: "{}/"
+              strategy: "Failover"
+              weight_type:
+                type: "Uniform"
+            messages:
+              abc: "{}{}"
+        "#, first_server.url(), second_server.url(), "a".repeat(2000), "b".repeat(100)});
+        let log_file = NamedTempFile::new().unwrap();
+        let log_path = log_file.path().to_owned();
+        log_file.close().unwrap();
+
+        let outcome = post_random(&ReqwestPoster::shared(), &settings, &log_path, Local::now(), &PostOptions::default()).await.unwrap();
+        delivered_mock.assert_async().await;
+        failed_mock.assert_async().await;
+        second_mock.assert_async().await;
+        assert!(outcome.posted);
+        assert_eq!(outcome.warnings, vec![String::from("destination 1: only 1 of 2 parts are posted: failed with 500 Internal Server Error: ")]);
+    }
+
+    #[tokio::test]
+    async fn post_random_should_edit_the_message_on_the_destination_which_posted_it() {
+        let mut first_server = mockito::Server::new_async().await;
+        let first_mock = first_server.mock("POST", "/?wait=true")
+            .with_status(500)
+            .expect(1)
+            .create_async().await;
+        let mut second_server = mockito::Server::new_async().await;
+        let post_mock = second_server.mock("POST", "/?wait=true")
+            .with_status(200)
+            .with_body(r#"{"id": "2222", "content": "now playing"}"#)
+            .expect(1)
+            .create_async().await;
+        let edit_mock = second_server.mock("PATCH", "/messages/2222?wait=true")
+            .match_body(Matcher::Json(serde_json::json!({ "content": "now playing" })))
+            .with_status(200)
+            .with_body(r#"{"id": "2222", "content": "now playing"}"#)
+            .expect(1)
+            .create_async().await;
+
+        let settings = settings_from_str(&formatdoc! {r#"
+            environment:
+              webhook_url: "{}/"
+              destinations:
+                - url: "{}/"
+              strategy: "Failover"
+              weight_type:
+                type: "Uniform"
+            messages:
+              abc: "now playing"
+        "#, first_server.url(), second_server.url()});
+        let log_file = NamedTempFile::new().unwrap();
+        let log_path = log_file.path().to_owned();
+        log_file.close().unwrap();
+
+        let options = PostOptions { edit_last: true, ..PostOptions::default() };
+        post_random(&ReqwestPoster::shared(), &settings, &log_path, Local::now(), &options).await.unwrap();
+        let random_picker = load_picker(&settings, &log_path).unwrap();
+        assert_eq!((random_picker.state().last_message_id.as_deref(), random_picker.state().last_destination_index), (Some("2222"), Some(1)));
+
+        post_random(&ReqwestPoster::shared(), &settings, &log_path, Local::now(), &options).await.unwrap();
+        first_mock.assert_async().await;
+        post_mock.assert_async().await;
+        edit_mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn post_random_should_record_a_failed_post_and_reset_it_after_a_successful_post() {
+        let mut server = mockito::Server::new_async().await;
+        let failed_mock = server.mock("POST", "/")
+            .with_status(400)
+            .expect(1)
+            .create_async().await;
+
+        let settings = settings_from_str(&formatdoc! {r#"
+            environment:
+              webhook_url: "{}"
+              weight_type:
+                type: "Uniform"
+              penalize_failures: true
+            messages:
+              abc: "message1"
+        "#, server.url()});
+        let mut log_file = NamedTempFile::new().unwrap();
+        write!(log_file, r#"[{{ "value": "abc", "count": 3 }}]"#).unwrap();
+
+        assert!(post_random(&ReqwestPoster::shared(), &settings, log_file.path(), Local::now(), &PostOptions::default()).await.is_err());
+        failed_mock.remove_async().await;
+        let log: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(log_file.path()).unwrap()).unwrap();
+        assert_eq!(log["items"], serde_json::json!([{ "value": "abc", "count": 3, "fail_count": 1 }]));
+
+        server.mock("POST", "/").with_status(204).create_async().await;
+        post_random(&ReqwestPoster::shared(), &settings, log_file.path(), Local::now(), &PostOptions::default()).await.unwrap();
+        let log: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(log_file.path()).unwrap()).unwrap();
+        assert_eq!(log["items"], serde_json::json!([{ "value": "abc", "count": 4 }]));
+    }
+
+    #[tokio::test]
+    async fn post_random_should_print_the_outcome_of_a_dry_run_in_json() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server.mock("POST", "/")
+            .expect(0)
+            .create_async().await;
+
+        let settings = settings_from_str(&formatdoc! {r#"
+            environment:
+              webhook_url: "{}"
+              weight_type:
+                type: "Uniform"
+            messages:
+              abc: "message1"
+        "#, server.url()});
+        let log_file = NamedTempFile::new().unwrap();
+        let log_path = log_file.path().to_owned();
+        log_file.close().unwrap();
+
+        let options = PostOptions { dry_run: true, ..PostOptions::default() };
+        let outcome = post_random(&ReqwestPoster::shared(), &settings, &log_path, Local::now(), &options).await.unwrap();
+        let mut output = Vec::new();
+        write_outcome(&mut output, &outcome, &log_path, OutputFormat::Json, true).unwrap();
+
+        let json: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        let expected = serde_json::json!({
+            "picked_id": "abc",
+            "status": null,
+            "posted": false,
+            "skipped": null,
+            "log_path": log_path.display().to_string(),
+            "dry_run": true,
+            "warnings": [],
+        });
+        assert_eq!(json, expected);
+        assert_eq!(output.iter().filter(|byte| **byte == b'\n').count(), 1);
+        mock.assert_async().await;
+        assert!(!log_path.exists());
+    }
+
+    #[test]
+    fn write_outcome_should_print_a_posted_outcome_in_json() {
+        let outcome = PostOutcome { picked_id: Some(String::from("abc")), status: Some(204), posted: true, ..PostOutcome::default() };
+        let mut output = Vec::new();
+        write_outcome(&mut output, &outcome, Path::new("conf/message-log.json"), OutputFormat::Json, false).unwrap();
+
+        let json: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        let expected = serde_json::json!({
+            "picked_id": "abc",
+            "status": 204,
+            "posted": true,
+            "skipped": null,
+            "log_path": "conf/message-log.json",
+            "dry_run": false,
+            "warnings": [],
+        });
+        assert_eq!(json, expected);
+    }
+
+    #[test]
+    fn write_outcome_should_print_only_the_skipped_reason_in_text() {
+        let mut output = Vec::new();
+        write_outcome(&mut output, &PostOutcome::skipped(String::from("canceled")), Path::new("log.json"), OutputFormat::Text, false).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "skipped: canceled\n");
+
+        let mut output = Vec::new();
+        let outcome = PostOutcome { picked_id: Some(String::from("abc")), status: Some(204), posted: true, ..PostOutcome::default() };
+        write_outcome(&mut output, &outcome, Path::new("log.json"), OutputFormat::Text, false).unwrap();
+        assert_eq!(output, b"");
+    }
+
+    #[test]
+    fn write_outcome_should_print_the_warnings_in_text() {
+        let outcome = PostOutcome { warnings: vec![String::from("retrying in 0ms: failed")], ..PostOutcome::skipped(String::from("canceled")) };
+        let mut output = Vec::new();
+        write_outcome(&mut output, &outcome, Path::new("log.json"), OutputFormat::Text, false).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "warning: retrying in 0ms: failed\nskipped: canceled\n");
+    }
+
+    #[tokio::test]
+    async fn post_random_should_skip_posting_in_the_quiet_hours_over_midnight() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server.mock("POST", "/")
+            .with_status(204)
+            .expect(1)
+            .create_async().await;
+
+        let settings = settings_from_str(&formatdoc! {r#"
+            environment:
+              webhook_url: "{}"
+              weight_type:
+                type: "Uniform"
+              quiet_hours:
+                from: "22:00"
+                to: "07:00"
+            messages:
+              abc: "message1"
+        "#, server.url()});
+        let log_file = NamedTempFile::new().unwrap();
+        let log_path = log_file.path().to_owned();
+        log_file.close().unwrap();
+
+        let quiet_time = Local.with_ymd_and_hms(2021, 9, 1, 3, 0, 0).unwrap();
+        post_random(&ReqwestPoster::shared(), &settings, &log_path, quiet_time, &PostOptions::default()).await.unwrap();
+        assert!(!log_path.exists());
+
+        let active_time = Local.with_ymd_and_hms(2021, 9, 1, 7, 0, 0).unwrap();
+        post_random(&ReqwestPoster::shared(), &settings, &log_path, active_time, &PostOptions::default()).await.unwrap();
+
+        mock.assert_async().await;
+        assert!(log_path.exists());
+    }
+
+    #[tokio::test]
+    async fn post_random_should_post_again_after_the_minimum_interval() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server.mock("POST", "/")
+            .with_status(204)
+            .expect(2)
+            .create_async().await;
+
+        let settings = settings_from_str(&formatdoc! {r#"
+            environment:
+              webhook_url: "{}"
+              weight_type:
+                type: "Uniform"
+              min_interval: 3600
+            messages:
+              abc: "message1"
+        "#, server.url()});
+        let log_file = NamedTempFile::new().unwrap();
+        let log_path = log_file.path().to_owned();
+        log_file.close().unwrap();
+
+        let first_time = Local.with_ymd_and_hms(2021, 9, 1, 12, 0, 0).unwrap();
+        post_random(&ReqwestPoster::shared(), &settings, &log_path, first_time, &PostOptions::default()).await.unwrap();
+        let second_time = Local.with_ymd_and_hms(2021, 9, 1, 13, 0, 0).unwrap();
+        post_random(&ReqwestPoster::shared(), &settings, &log_path, second_time, &PostOptions::default()).await.unwrap();
+
+        mock.assert_async().await;
+        let random_picker = load_picker(&settings, &log_path).unwrap();
+        assert_eq!(random_picker.state().last_posted_at, Some(second_time.with_timezone(&Utc)));
+    }
+
+    #[tokio::test]
+    async fn post_random_should_record_the_time_of_the_next_post() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server.mock("POST", "/")
+            .with_status(204)
+            .expect(1)
+            .create_async().await;
+
+        let settings = settings_from_str(&formatdoc! {r#"
+            environment:
+              webhook_url: "{}"
+              weight_type:
+                type: "Uniform"
+              post_interval: 3600
+              post_interval_jitter: 60
+            messages:
+              abc: "message1"
+        "#, server.url()});
+        let log_file = NamedTempFile::new().unwrap();
+        let log_path = log_file.path().to_owned();
+        log_file.close().unwrap();
+
+        let now = Local.with_ymd_and_hms(2021, 9, 1, 12, 0, 0).unwrap();
+        post_random(&ReqwestPoster::shared(), &settings, &log_path, now, &PostOptions::default()).await.unwrap();
+
+        mock.assert_async().await;
+        let random_picker = load_picker(&settings, &log_path).unwrap();
+        let secs_until_next_post = random_picker.state().next_post_after.unwrap().signed_duration_since(now).num_seconds();
+        assert!((3540..=3660).contains(&secs_until_next_post), "{}", secs_until_next_post);
+    }
+
+    #[tokio::test]
+    async fn post_random_should_not_panic_if_the_intervals_overflow_the_time() {
+        let mut settings = settings_from_str(indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/123/abc"
+              weight_type:
+                type: "Uniform"
+            messages:
+              abc:
+                content: "message1"
+              def:
+                content: "message2"
+        "#});
+        settings.environment.post_interval = Some(HumanDuration::from_secs(u64::MAX));
+        settings.messages.get_mut("abc").unwrap().min_interval = Some(HumanDuration::from_secs(u64::MAX));
+        let log_file = NamedTempFile::new().unwrap();
+        let log_path = log_file.path().to_owned();
+        log_file.close().unwrap();
+
+        let poster = RecordingPoster::default();
+        let now = Local.with_ymd_and_hms(2021, 9, 1, 12, 0, 0).unwrap();
+        post_random(&poster, &settings, &log_path, now, &PostOptions { forced_id: Some("abc"), count_forced: true, ..PostOptions::default() }).await.unwrap();
+        let outcome = post_random(&poster, &settings, &log_path, now, &PostOptions::default()).await.unwrap();
+
+        // abc waits for its interval forever
+        assert_eq!(outcome.picked_id, Some(String::from("def")));
+        let random_picker = load_picker(&settings, &log_path).unwrap();
+        assert_eq!(random_picker.counts().into_iter().map(|(_, count)| count).sum::<u64>(), 2);
+    }
+
+    #[tokio::test]
+    async fn post_random_should_split_long_content_into_multiple_posts() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server.mock("POST", "/")
+            .with_status(204)
+            .expect(3)
+            .create_async().await;
+
+        let settings = settings_from_str(&formatdoc! {r#"
+            environment:
+              webhook_url: "{}"
+              weight_type:
+                type: "Uniform"
+            messages:
+              abc: "{}"
+        "#, server.url(), "a".repeat(4500)});
+        let log_file = NamedTempFile::new().unwrap();
+        let log_path = log_file.path().to_owned();
+        log_file.close().unwrap();
+
+        post_random(&ReqwestPoster::shared(), &settings, &log_path, Local::now(), &PostOptions::default()).await.unwrap();
+
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn post_random_should_count_a_split_message_as_posted_if_a_later_part_fails() {
+        let mut server = mockito::Server::new_async().await;
+        let delivered_mock = server.mock("POST", "/")
+            .match_body(Matcher::PartialJson(serde_json::json!({ "content": "a".repeat(2000) })))
+            .with_status(204)
+            .expect(1)
+            .create_async().await;
+        let failed_mock = server.mock("POST", "/")
+            .match_body(Matcher::PartialJson(serde_json::json!({ "content": "b".repeat(100) })))
+            .with_status(500)
+            .expect(1)
+            .create_async().await;
+
+        let dead_letter_file = NamedTempFile::new().unwrap();
+        let dead_letter_path = dead_letter_file.path().to_owned();
+        dead_letter_file.close().unwrap();
+        let settings = settings_from_str(&formatdoc! {r#"
+            environment:
+              webhook_url: "{}/"
+              weight_type:
+                type: "Uniform"
+              dead_letter_file: "{}"
+            messages:
+              abc: "{}{}"
+        "#, server.url(), dead_letter_path.display(), "a".repeat(2000), "b".repeat(100)});
+        let log_file = NamedTempFile::new().unwrap();
+        let log_path = log_file.path().to_owned();
+        log_file.close().unwrap();
+
+        let outcome = post_random(&ReqwestPoster::shared(), &settings, &log_path, Local::now(), &PostOptions::default()).await.unwrap();
+        delivered_mock.assert_async().await;
+        failed_mock.assert_async().await;
+
+        assert!(outcome.posted);
+        assert_eq!(outcome.warnings, vec![String::from("only 1 of 2 parts are posted: failed with 500 Internal Server Error: ")]);
+        assert_eq!(read_log_counts::<String>(&log_path, settings.environment.log_format).unwrap()["abc"], 1);
+        let entries = read_dead_letters(&dead_letter_path).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert!(entries[0].body.contains(&"b".repeat(100)));
+    }
+
+    #[tokio::test]
+    async fn post_random_should_post_if_the_confirmation_is_accepted() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server.mock("POST", "/")
+            .with_status(204)
+            .expect(1)
+            .create_async().await;
+
+        let settings = settings_from_str(&formatdoc! {r#"
+            environment:
+              webhook_url: "{}"
+              weight_type:
+                type: "Uniform"
+            messages:
+              abc: "message1"
+        "#, server.url()});
+        let log_file = NamedTempFile::new().unwrap();
+        let log_path = log_file.path().to_owned();
+        log_file.close().unwrap();
+
+        let previews = std::cell::RefCell::new(Vec::new());
+        let confirm = |preview: &str| {
+            previews.borrow_mut().push(preview.to_owned());
+            true
+        };
+        let options = PostOptions { confirm: Some(&confirm), ..PostOptions::default() };
+        post_random(&ReqwestPoster::shared(), &settings, &log_path, Local::now(), &options).await.unwrap();
+
+        mock.assert_async().await;
+        assert_eq!(previews.borrow().len(), 1);
+        assert!(previews.borrow()[0].contains(r#""content": "message1""#));
+        assert!(log_path.exists());
+    }
+
+    #[tokio::test]
+    async fn post_random_should_neither_post_nor_write_the_log_if_the_confirmation_is_rejected() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server.mock("POST", "/")
+            .expect(0)
+            .create_async().await;
+
+        let settings = settings_from_str(&formatdoc! {r#"
+            environment:
+              webhook_url: "{}"
+              weight_type:
+                type: "Uniform"
+            messages:
+              abc: "message1"
+        "#, server.url()});
+        let log_file = NamedTempFile::new().unwrap();
+        let log_path = log_file.path().to_owned();
+        log_file.close().unwrap();
+
+        let confirm = |_: &str| false;
+        let options = PostOptions { confirm: Some(&confirm), ..PostOptions::default() };
+        post_random(&ReqwestPoster::shared(), &settings, &log_path, Local::now(), &options).await.unwrap();
+
+        mock.assert_async().await;
+        assert!(!log_path.exists());
+    }
+
+    #[tokio::test]
+    async fn post_random_should_post_the_given_number_of_messages_in_a_burst() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server.mock("POST", "/")
+            .with_status(204)
+            .expect(3)
+            .create_async().await;
+
+        let settings = settings_from_str(&formatdoc! {r#"
+            environment:
+              webhook_url: "{}"
+              weight_type:
+                type: "Uniform"
+            messages:
+              abc: "message1"
+              def: "message2"
+        "#, server.url()});
+        let log_file = NamedTempFile::new().unwrap();
+        let log_path = log_file.path().to_owned();
+        log_file.close().unwrap();
+
+        let options = PostOptions { burst: Some(3), ..PostOptions::default() };
+        post_random(&ReqwestPoster::shared(), &settings, &log_path, Local::now(), &options).await.unwrap();
+
+        mock.assert_async().await;
+        let random_picker = load_picker(&settings, &log_path).unwrap();
+        assert!(random_picker.state().last_posted_at.is_some());
+        let log = std::fs::read_to_string(&log_path).unwrap();
+        let log: serde_json::Value = serde_json::from_str(&log).unwrap();
+        let total_count: u64 = log["items"].as_array().unwrap().iter().map(|item| item["count"].as_u64().unwrap()).sum();
+        assert_eq!(total_count, 3);
+    }
+
+    #[tokio::test]
+    async fn post_random_should_write_the_counts_of_the_posts_before_a_failed_one() {
+        let mut server = mockito::Server::new_async().await;
+        let succeeded_mock = server.mock("POST", "/")
+            .with_status(204)
+            .expect(2)
+            .create_async().await;
+        let failed_mock = server.mock("POST", "/")
+            .with_status(500)
+            .expect(1)
+            .create_async().await;
+
+        let settings = settings_from_str(&formatdoc! {r#"
+            environment:
+              webhook_url: "{}"
+              weight_type:
+                type: "Uniform"
+            messages:
+              abc: "message1"
+        "#, server.url()});
+        let log_file = NamedTempFile::new().unwrap();
+        let log_path = log_file.path().to_owned();
+        log_file.close().unwrap();
+
+        let options = PostOptions { burst: Some(3), ..PostOptions::default() };
+        let result = post_random(&ReqwestPoster::shared(), &settings, &log_path, Local::now(), &options).await;
+
+        succeeded_mock.assert_async().await;
+        failed_mock.assert_async().await;
+        assert!(result.is_err());
+        let log = std::fs::read_to_string(&log_path).unwrap();
+        let log: serde_json::Value = serde_json::from_str(&log).unwrap();
+        assert_eq!(log["items"][0]["count"], 2);
+    }
+
+    #[tokio::test]
+    async fn post_random_should_write_the_metrics() {
+        let mut server = mockito::Server::new_async().await;
+        server.mock("POST", "/")
+            .with_status(204)
+            .create_async().await;
+
+        let metrics_file = NamedTempFile::new().unwrap();
+        let settings = settings_from_str(&formatdoc! {r#"
+            environment:
+              webhook_url: "{}"
+              weight_type:
+                type: "Uniform"
+              metrics_path: "{}"
+            messages:
+              abc: "message1"
+        "#, server.url(), metrics_file.path().display()});
+        let log_file = NamedTempFile::new().unwrap();
+        let log_path = log_file.path().to_owned();
+        log_file.close().unwrap();
+
+        post_random(&ReqwestPoster::shared(), &settings, &log_path, Local::now(), &PostOptions::default()).await.unwrap();
+
+        let metrics = std::fs::read_to_string(metrics_file.path()).unwrap();
+        assert!(metrics.contains("random_poster_total_posts 1\n"));
+        assert!(metrics.contains("random_poster_pick_count{message=\"abc\"} 1\n"));
+        assert!(metrics.contains("random_poster_last_status 204\n"));
+    }
+
+    #[tokio::test]
+    async fn post_random_should_append_the_history() {
+        let mut server = mockito::Server::new_async().await;
+        server.mock("POST", "/")
+            .with_status(204)
+            .create_async().await;
+
+        let history_file = NamedTempFile::new().unwrap();
+        let history_path = history_file.path().to_owned();
+        history_file.close().unwrap();
+        let settings = settings_from_str(&formatdoc! {r#"
+            environment:
+              webhook_url: "{}"
+              weight_type:
+                type: "Uniform"
+              history_file: "{}"
+            messages:
+              abc: "message1"
+        "#, server.url(), history_path.display()});
+        let log_file = NamedTempFile::new().unwrap();
+        let log_path = log_file.path().to_owned();
+        log_file.close().unwrap();
+
+        let first_time = Local.with_ymd_and_hms(2021, 9, 1, 12, 0, 0).unwrap();
+        post_random(&ReqwestPoster::shared(), &settings, &log_path, first_time, &PostOptions::default()).await.unwrap();
+        let second_time = Local.with_ymd_and_hms(2021, 9, 1, 13, 0, 0).unwrap();
+        post_random(&ReqwestPoster::shared(), &settings, &log_path, second_time, &PostOptions::default()).await.unwrap();
+
+        let history = std::fs::read_to_string(&history_path).unwrap();
+        let entries: Vec<serde_json::Value> = history.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(entries.len(), 2);
+        for (entry, time) in entries.iter().zip([first_time, second_time]) {
+            let posted_at: DateTime<Utc> = serde_json::from_value(entry["posted_at"].clone()).unwrap();
+            assert!(posted_at >= time && posted_at.signed_duration_since(time).num_seconds() < 60);
+            assert_eq!(entry["message_id"], "abc");
+            assert_eq!(entry["status"], 204);
+        }
+    }
+
+    #[tokio::test]
+    async fn post_random_should_report_the_reset_of_a_corrupt_log() {
+        let settings = settings_from_str(indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/123/abc"
+              weight_type:
+                type: "Uniform"
+              on_corrupt_log: "Reset"
+            messages:
+              abc: "message1"
+        "#});
+        let mut log_file = NamedTempFile::new().unwrap();
+        write!(log_file, r#"[{{ "value": "abc", "count": 1 "#).unwrap();
+        let corrupt_path = PathBuf::from(format!("{}.corrupt", log_file.path().display()));
+
+        let outcome = post_random(&RecordingPoster::default(), &settings, log_file.path(), Local::now(), &PostOptions::default()).await.unwrap();
+
+        assert!(outcome.posted);
+        assert_eq!(outcome.warnings.len(), 1);
+        assert!(outcome.warnings[0].ends_with(&format!("so the log is moved to {} and reset", corrupt_path.display())), "{:?}", outcome.warnings);
+        assert_eq!(read_log_counts::<String>(log_file.path(), settings.environment.log_format).unwrap()["abc"], 1);
+        std::fs::remove_file(corrupt_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn post_random_should_write_the_log_even_if_the_history_cannot_be_appended() {
+        let directory = tempfile::tempdir().unwrap();
+        let history_path = directory.path().join("missing").join("history.jsonl");
+        let settings = settings_from_str(&formatdoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/123/abc"
+              weight_type:
+                type: "Uniform"
+              history_file: "{}"
+            messages:
+              abc: "message1"
+        "#, history_path.display()});
+        let log_file = NamedTempFile::new().unwrap();
+        let log_path = log_file.path().to_owned();
+        log_file.close().unwrap();
+
+        let poster = RecordingPoster::default();
+        let result = post_random(&poster, &settings, &log_path, Local::now(), &PostOptions { burst: Some(2), ..PostOptions::default() }).await;
+
+        assert!(result.is_err());
+        assert_eq!(poster.requests.borrow().len(), 1);
+        assert_eq!(read_log_counts::<String>(&log_path, settings.environment.log_format).unwrap()["abc"], 1);
+        let random_picker = load_picker(&settings, &log_path).unwrap();
+        assert_eq!(random_picker.state().run_number, Some(1));
+        assert!(random_picker.state().last_posted_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn post_random_should_post_a_scheduled_message_within_its_schedule() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server.mock("POST", "/")
+            .match_body(Matcher::PartialJson(serde_json::json!({ "content": "weekend" })))
+            .with_status(204)
+            .expect(1)
+            .create_async().await;
+
+        let settings = settings_from_str(&formatdoc! {r#"
+            environment:
+              webhook_url: "{}"
+              weight_type:
+                type: "Uniform"
+            messages:
+              abc:
+                content: "weekend"
+                embeds: []
+                schedule:
+                  weekdays: [Sat, Sun]
+        "#, server.url()});
+        let log_file = NamedTempFile::new().unwrap();
+        let log_path = log_file.path().to_owned();
+        log_file.close().unwrap();
+
+        let saturday = Local.with_ymd_and_hms(2021, 9, 4, 12, 0, 0).unwrap();
+        post_random(&ReqwestPoster::shared(), &settings, &log_path, saturday, &PostOptions::default()).await.unwrap();
+
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn post_random_should_not_post_a_scheduled_message_outside_its_schedule() {
+        let mut server = mockito::Server::new_async().await;
+        let weekend_mock = server.mock("POST", "/")
+            .match_body(Matcher::PartialJson(serde_json::json!({ "content": "weekend" })))
+            .expect(0)
+            .create_async().await;
+        let other_mock = server.mock("POST", "/")
+            .match_body(Matcher::PartialJson(serde_json::json!({ "content": "message2" })))
+            .with_status(204)
+            .expect(5)
+            .create_async().await;
+
+        let settings = settings_from_str(&formatdoc! {r#"
+            environment:
+              webhook_url: "{}"
+              weight_type:
+                type: "Uniform"
+            messages:
+              abc:
+                content: "weekend"
+                embeds: []
+                schedule:
+                  weekdays: [Sat, Sun]
+              def: "message2"
+        "#, server.url()});
+        let log_file = NamedTempFile::new().unwrap();
+        let log_path = log_file.path().to_owned();
+        log_file.close().unwrap();
+
+        let friday = Local.with_ymd_and_hms(2021, 9, 3, 12, 0, 0).unwrap();
+        let options = PostOptions { burst: Some(5), ..PostOptions::default() };
+        post_random(&ReqwestPoster::shared(), &settings, &log_path, friday, &options).await.unwrap();
+
+        weekend_mock.assert_async().await;
+        other_mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn post_random_should_skip_posting_if_no_messages_are_scheduled() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server.mock("POST", "/")
+            .expect(0)
+            .create_async().await;
+
+        let settings = settings_from_str(&formatdoc! {r#"
+            environment:
+              webhook_url: "{}"
+              weight_type:
+                type: "Uniform"
+            messages:
+              abc:
+                content: "weekend"
+                embeds: []
+                schedule:
+                  weekdays: [Sat, Sun]
+        "#, server.url()});
+        let log_file = NamedTempFile::new().unwrap();
+        let log_path = log_file.path().to_owned();
+        log_file.close().unwrap();
+
+        let friday = Local.with_ymd_and_hms(2021, 9, 3, 12, 0, 0).unwrap();
+        post_random(&ReqwestPoster::shared(), &settings, &log_path, friday, &PostOptions::default()).await.unwrap();
+
+        mock.assert_async().await;
+        assert!(!log_path.exists());
+    }
+
+    #[tokio::test]
+    async fn post_random_should_skip_or_fail_by_on_empty_if_no_messages_are_scheduled() {
+        let friday = Local.with_ymd_and_hms(2021, 9, 3, 12, 0, 0).unwrap();
+        for (on_empty, expected) in [
+            ("Skip", Ok(PostOutcome::skipped(String::from("no messages are scheduled for 2021-09-03")))),
+            ("Error", Err(String::from("no messages are scheduled for 2021-09-03"))),
+        ] {
+            let settings = settings_from_str(&formatdoc! {r#"
+                environment:
+                  webhook_url: "https://discord.com/api/webhooks/123/abc"
+                  weight_type:
+                    type: "Uniform"
+                  on_empty: "{}"
+                messages:
+                  abc:
+                    content: "weekend"
+                    embeds: []
+                    schedule:
+                      weekdays: [Sat, Sun]
+            "#, on_empty});
+            let log_file = NamedTempFile::new().unwrap();
+            let log_path = log_file.path().to_owned();
+            log_file.close().unwrap();
+
+            let poster = RecordingPoster::default();
+            assert_eq!(post_random(&poster, &settings, &log_path, friday, &PostOptions::default()).await, expected);
+            assert!(poster.requests.borrow().is_empty());
+            assert!(!log_path.exists());
+        }
+    }
+
+    #[tokio::test]
+    async fn post_random_should_not_pick_a_message_again_within_its_minimum_interval() {
+        let mut server = mockito::Server::new_async().await;
+        let throttled_mock = server.mock("POST", "/")
+            .match_body(Matcher::PartialJson(serde_json::json!({ "content": "throttled" })))
+            .with_status(204)
+            .expect(2)
+            .create_async().await;
+        let other_mock = server.mock("POST", "/")
+            .match_body(Matcher::PartialJson(serde_json::json!({ "content": "other" })))
+            .with_status(204)
+            .expect(1)
+            .create_async().await;
+
+        let first_settings = settings_from_str(&formatdoc! {r#"
+            environment:
+              webhook_url: "{}"
+              weight_type:
+                type: "MinOnly"
+            messages:
+              abc:
+                content: "throttled"
+                embeds: []
+                min_interval: 604800
+        "#, server.url()});
+        let settings = settings_from_str(&formatdoc! {r#"
+            environment:
+              webhook_url: "{}"
+              weight_type:
+                type: "MinOnly"
+            messages:
+              abc:
+                content: "throttled"
+                embeds: []
+                min_interval: 604800
+              def:
+                content: "other"
+                embeds: []
+                initial_count: 10
+        "#, server.url()});
+        let log_file = NamedTempFile::new().unwrap();
+        let log_path = log_file.path().to_owned();
+        log_file.close().unwrap();
+
+        let first_time = Local.with_ymd_and_hms(2021, 9, 1, 12, 0, 0).unwrap();
+        post_random(&ReqwestPoster::shared(), &first_settings, &log_path, first_time, &PostOptions::default()).await.unwrap();
+        // abc has the smaller count, but it was posted a day ago
+        let next_day = Local.with_ymd_and_hms(2021, 9, 2, 12, 0, 0).unwrap();
+        let outcome = post_random(&ReqwestPoster::shared(), &settings, &log_path, next_day, &PostOptions::default()).await.unwrap();
+        assert_eq!(outcome.picked_id, Some(String::from("def")));
+        let next_week = Local.with_ymd_and_hms(2021, 9, 8, 12, 0, 0).unwrap();
+        let outcome = post_random(&ReqwestPoster::shared(), &settings, &log_path, next_week, &PostOptions::default()).await.unwrap();
+        assert_eq!(outcome.picked_id, Some(String::from("abc")));
+
+        throttled_mock.assert_async().await;
+        other_mock.assert_async().await;
+    }
+
+    #[test]
+    fn excluded_values_should_keep_the_message_which_becomes_eligible_first_if_every_message_is_within_its_interval() {
+        let settings = settings_from_str(indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/123/abc"
+              weight_type:
+                type: "Uniform"
+            messages:
+              abc:
+                content: "abc"
+                embeds: []
+                min_interval: 3600
+              def:
+                content: "def"
+                embeds: []
+                min_interval: 600
+        "#});
+        let log_file = NamedTempFile::new().unwrap();
+        std::fs::write(log_file.path(), "[]").unwrap();
+        let mut random_picker = load_picker(&settings, log_file.path()).unwrap();
+        let posted_at = Utc.with_ymd_and_hms(2021, 9, 1, 12, 0, 0).unwrap();
+        random_picker.record_posted_at(&String::from("abc"), posted_at);
+        random_picker.record_posted_at(&String::from("def"), posted_at);
+
+        let now = posted_at.with_timezone(&Local) + chrono::Duration::seconds(60);
+        assert_eq!(excluded_values(&settings, &random_picker, now), HashSet::from([String::from("abc")]));
+        let now = posted_at.with_timezone(&Local) + chrono::Duration::seconds(3600);
+        assert_eq!(excluded_values(&settings, &random_picker, now), HashSet::new());
+    }
+
+    #[tokio::test]
+    async fn post_random_should_suppress_the_excluded_messages_on_the_next_run() {
+        let settings = settings_from_str(indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/123/abc"
+              weight_type:
+                type: "Uniform"
+            messages:
+              a:
+                content: "message1"
+                excludes: ["b"]
+              b: "message2"
+              c: "message3"
+        "#});
+        let log_file = NamedTempFile::new().unwrap();
+        let log_path = log_file.path().to_owned();
+        log_file.close().unwrap();
+        let poster = RecordingPoster::default();
+
+        let options = PostOptions { forced_id: Some("a"), count_forced: true, ..PostOptions::default() };
+        post_random(&poster, &settings, &log_path, Local::now(), &options).await.unwrap();
+        let random_picker = load_picker(&settings, &log_path).unwrap();
+        assert_eq!(excluded_values(&settings, &random_picker, Local::now()), HashSet::from([String::from("b")]));
+
+        // the exclusion is reciprocal, and lasts for one run by default
+        let options = PostOptions { forced_id: Some("b"), count_forced: true, ..PostOptions::default() };
+        post_random(&poster, &settings, &log_path, Local::now(), &options).await.unwrap();
+        let random_picker = load_picker(&settings, &log_path).unwrap();
+        assert_eq!(excluded_values(&settings, &random_picker, Local::now()), HashSet::from([String::from("a")]));
+        assert_eq!(random_picker.state().recent_picks, vec![RecentPick { run_number: 2, value: String::from("b") }]);
+    }
+
+    #[tokio::test]
+    async fn post_random_should_post_each_message_in_the_grab_bag_once() {
+        let settings = settings_from_str(indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/123/abc"
+              weight_type:
+                type: "Uniform"
+              grab_bag: true
+            messages:
+              abc: "message1"
+              def: "message2"
+              ghi: "message3"
+        "#});
+        let log_file = NamedTempFile::new().unwrap();
+        let log_path = log_file.path().to_owned();
+        log_file.close().unwrap();
+
+        let poster = RecordingPoster::default();
+        for _ in 0..3 {
+            assert!(post_random(&poster, &settings, &log_path, Local::now(), &PostOptions::default()).await.unwrap().posted);
+        }
+        let mut contents: Vec<String> = poster.requests.borrow().iter()
+            .map(|(_, body)| serde_json::from_str::<serde_json::Value>(body).unwrap()["content"].as_str().unwrap().to_owned())
+            .collect();
+        contents.sort();
+        assert_eq!(contents, vec!["message1", "message2", "message3"]);
+        let random_picker = load_picker(&settings, &log_path).unwrap();
+        assert!(settings.messages.keys().all(|id| random_picker.is_retired(id)));
+
+        let outcome = post_random(&poster, &settings, &log_path, Local::now(), &PostOptions::default()).await.unwrap();
+        assert_eq!(outcome, PostOutcome::skipped(String::from("every message in the grab bag is retired")));
+        assert_eq!(poster.requests.borrow().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn post_random_should_post_the_done_message_after_the_grab_bag_is_exhausted() {
+        let settings = settings_from_str(indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/123/abc"
+              weight_type:
+                type: "Uniform"
+              grab_bag: true
+              grab_bag_done_message: "done"
+            messages:
+              abc: "message1"
+              def: "message2"
+              done: "all done"
+        "#});
+        let log_file = NamedTempFile::new().unwrap();
+        let log_path = log_file.path().to_owned();
+        log_file.close().unwrap();
+
+        let poster = RecordingPoster::default();
+        for _ in 0..4 {
+            post_random(&poster, &settings, &log_path, Local::now(), &PostOptions::default()).await.unwrap();
+        }
+        let contents: Vec<String> = poster.requests.borrow().iter()
+            .map(|(_, body)| serde_json::from_str::<serde_json::Value>(body).unwrap()["content"].as_str().unwrap().to_owned())
+            .collect();
+        assert_eq!(contents.len(), 3);
+        assert_eq!(contents[2], "all done");
+    }
+
+    #[test]
+    fn excluded_values_should_ignore_the_exclusions_if_they_would_exclude_every_message() {
+        let settings = settings_from_str(indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/123/abc"
+              weight_type:
+                type: "Uniform"
+            messages:
+              a:
+                content: "message1"
+                excludes: ["b"]
+              b:
+                content: "message2"
+                excludes: ["a"]
+        "#});
+        let log_file = NamedTempFile::new().unwrap();
+        std::fs::write(log_file.path(), "[]").unwrap();
+        let mut random_picker = load_picker(&settings, log_file.path()).unwrap();
+        random_picker.state_mut().run_number = Some(1);
+        random_picker.state_mut().recent_picks = vec![
+            RecentPick { run_number: 1, value: String::from("a") },
+            RecentPick { run_number: 1, value: String::from("b") },
+        ];
+
+        assert_eq!(excluded_values(&settings, &random_picker, Local::now()), HashSet::new());
+    }
+
+    #[tokio::test]
+    async fn post_random_should_not_post_the_meta_fields() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server.mock("POST", "/")
+            .match_body(Matcher::Json(serde_json::json!({
+                "username": null,
+                "avatar_url": null,
+                "content": "message1",
+                "embeds": [],
+            })))
+            .with_status(204)
+            .expect(1)
+            .create_async().await;
+
+        let settings = settings_from_str(&formatdoc! {r#"
+            environment:
+              webhook_url: "{}"
+              weight_type:
+                type: "Uniform"
+            messages:
+              abc:
+                content: "message1"
+                meta:
+                  note: "a note"
+        "#, server.url()});
+        let log_file = NamedTempFile::new().unwrap();
+        let log_path = log_file.path().to_owned();
+        log_file.close().unwrap();
+
+        post_random(&ReqwestPoster::shared(), &settings, &log_path, Local::now(), &PostOptions::default()).await.unwrap();
+
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn post_random_should_send_the_same_nonce_on_every_retry() {
+        let mut server = mockito::Server::new_async().await;
+        let bodies = std::sync::Arc::new(std::sync::Mutex::new(Vec::<String>::new()));
+        let failed_bodies = bodies.clone();
+        let failed_mock = server.mock("POST", "/")
+            .with_status(500)
+            .with_body_from_request(move |request| {
+                failed_bodies.lock().unwrap().push(request.utf8_lossy_body().unwrap().into_owned());
+                Vec::new()
+            })
+            .expect(2)
+            .create_async().await;
+        let succeeded_bodies = bodies.clone();
+        let succeeded_mock = server.mock("POST", "/")
+            .with_status(204)
+            .with_body_from_request(move |request| {
+                succeeded_bodies.lock().unwrap().push(request.utf8_lossy_body().unwrap().into_owned());
+                Vec::new()
+            })
+            .expect(1)
+            .create_async().await;
+
+        let settings = settings_from_str(&formatdoc! {r#"
+            environment:
+              webhook_url: "{}"
+              weight_type:
+                type: "Uniform"
+              retry:
+                max_attempts: 3
+                initial_delay: 0
+            messages:
+              abc: "message1"
+        "#, server.url()});
+        let log_file = NamedTempFile::new().unwrap();
+        let log_path = log_file.path().to_owned();
+        log_file.close().unwrap();
+
+        let outcome = post_random(&ReqwestPoster::shared(), &settings, &log_path, Local::now(), &PostOptions::default()).await.unwrap();
+
+        failed_mock.assert_async().await;
+        succeeded_mock.assert_async().await;
+        let bodies: Vec<serde_json::Value> = bodies.lock().unwrap().iter()
+            .map(|body| serde_json::from_str::<serde_json::Value>(body).unwrap())
+            .collect();
+        assert_eq!(bodies.len(), 3);
+        assert!(bodies[0]["nonce"].is_string());
+        assert!(bodies.iter().all(|body| body["nonce"] == bodies[0]["nonce"] && body["enforce_nonce"] == true), "{:?}", bodies);
+        assert_eq!(outcome.warnings, vec![String::from("retrying in 0ms: failed with 500 Internal Server Error: "); 2]);
+    }
+
+    #[tokio::test]
+    async fn post_random_should_prefer_the_flags_of_the_message() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server.mock("POST", "/")
+            .match_body(Matcher::Json(serde_json::json!({ "username": null, "avatar_url": null, "flags": 4096, "content": "message1", "embeds": [] })))
+            .with_status(204)
+            .expect(1)
+            .create_async().await;
+
+        let settings = settings_from_str(&formatdoc! {r#"
+            environment:
+              webhook_url: "{}/"
+              weight_type:
+                type: "Uniform"
+              flags: 4
+            messages:
+              abc:
+                content: "message1"
+                flags: 4096
+        "#, server.url()});
+        let log_file = NamedTempFile::new().unwrap();
+        let log_path = log_file.path().to_owned();
+        log_file.close().unwrap();
+
+        post_random(&ReqwestPoster::shared(), &settings, &log_path, Local::now(), &PostOptions::default()).await.unwrap();
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn post_random_should_prefer_the_thread_name_of_the_message() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server.mock("POST", "/")
+            .match_body(Matcher::Json(serde_json::json!({ "username": null, "avatar_url": null, "thread_name": "message thread", "content": "message1", "embeds": [] })))
+            .with_status(204)
+            .expect(1)
+            .create_async().await;
+
+        let settings = settings_from_str(&formatdoc! {r#"
+            environment:
+              webhook_url: "{}/"
+              weight_type:
+                type: "Uniform"
+              thread_name: "global thread"
+            messages:
+              abc:
+                content: "message1"
+                thread_name: "message thread"
+        "#, server.url()});
+        let log_file = NamedTempFile::new().unwrap();
+        let log_path = log_file.path().to_owned();
+        log_file.close().unwrap();
+
+        post_random(&ReqwestPoster::shared(), &settings, &log_path, Local::now(), &PostOptions::default()).await.unwrap();
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn post_random_should_sign_the_request_if_the_signing_secret_is_set() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server.mock("POST", "/")
+            .match_header("x-hub-signature", Matcher::Regex(String::from("^[0-9a-f]{64}$")))
+            .with_status(204)
+            .expect(1)
+            .create_async().await;
+
+        let settings = settings_from_str(&formatdoc! {r#"
+            environment:
+              webhook_url: "{}/"
+              weight_type:
+                type: "Uniform"
+              signing_secret: "secret"
+              signature_header: "X-Hub-Signature"
+            messages:
+              abc: "message1"
+        "#, server.url()});
+        let log_file = NamedTempFile::new().unwrap();
+        let log_path = log_file.path().to_owned();
+        log_file.close().unwrap();
+
+        post_random(&ReqwestPoster::shared(), &settings, &log_path, Local::now(), &PostOptions::default()).await.unwrap();
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn post_random_should_reply_to_the_previous_message() {
+        let mut server = mockito::Server::new_async().await;
+        let first_mock = server.mock("POST", "/?wait=true")
+            .match_body(Matcher::Json(serde_json::json!({ "username": null, "avatar_url": null, "content": "message1" })))
+            .with_status(200)
+            .with_body(r#"{"id": "1111", "content": "message1"}"#)
+            .expect(1)
+            .create_async().await;
+
+        let settings = settings_from_str(&formatdoc! {r#"
+            environment:
+              webhook_url: "{}/"
+              weight_type:
+                type: "Uniform"
+              reply_to_previous: true
+            messages:
+              abc: "message1"
+        "#, server.url()});
+        let log_file = NamedTempFile::new().unwrap();
+        let log_path = log_file.path().to_owned();
+        log_file.close().unwrap();
+
+        post_random(&ReqwestPoster::shared(), &settings, &log_path, Local::now(), &PostOptions::default()).await.unwrap();
+        first_mock.assert_async().await;
+
+        let second_mock = server.mock("POST", "/?wait=true")
+            .match_body(Matcher::PartialJson(serde_json::json!({ "message_reference": { "message_id": "1111" } })))
+            .with_status(200)
+            .with_body(r#"{"id": "2222", "content": "message1"}"#)
+            .expect(1)
+            .create_async().await;
+        post_random(&ReqwestPoster::shared(), &settings, &log_path, Local::now(), &PostOptions::default()).await.unwrap();
+
+        second_mock.assert_async().await;
+        let random_picker = load_picker(&settings, &log_path).unwrap();
+        assert_eq!(random_picker.state().last_message_id, Some(String::from("2222")));
+    }
+
+    #[tokio::test]
+    async fn post_random_should_edit_the_last_message_if_its_id_is_in_the_log() {
+        let mut server = mockito::Server::new_async().await;
+        let edit_mock = server.mock("PATCH", "/messages/1111?wait=true")
+            .match_body(Matcher::Json(serde_json::json!({ "content": "now playing" })))
+            .with_status(200)
+            .with_body(r#"{"id": "1111", "content": "now playing"}"#)
+            .expect(1)
+            .create_async().await;
+        let post_mock = server.mock("POST", Matcher::Any)
+            .expect(0)
+            .create_async().await;
+
+        let settings = settings_from_str(&formatdoc! {r#"
+            environment:
+              webhook_url: "{}/"
+              weight_type:
+                type: "Uniform"
+            messages:
+              abc: "now playing"
+        "#, server.url()});
+        let mut log_file = NamedTempFile::new().unwrap();
+        write!(log_file, r#"{{ "version": 1, "last_message_id": "1111", "items": [] }}"#).unwrap();
+
+        let options = PostOptions { edit_last: true, ..PostOptions::default() };
+        let outcome = post_random(&ReqwestPoster::shared(), &settings, log_file.path(), Local::now(), &options).await.unwrap();
+
+        edit_mock.assert_async().await;
+        post_mock.assert_async().await;
+        assert_eq!(outcome.status, Some(200));
+        let random_picker = load_picker(&settings, log_file.path()).unwrap();
+        assert_eq!(random_picker.state().last_message_id, Some(String::from("1111")));
+    }
+
+    #[tokio::test]
+    async fn post_random_should_post_and_store_the_message_id_if_there_is_no_message_to_edit() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server.mock("POST", "/?wait=true")
+            .with_status(200)
+            .with_body(r#"{"id": "1111", "content": "now playing"}"#)
+            .expect(1)
+            .create_async().await;
+
+        let settings = settings_from_str(&formatdoc! {r#"
+            environment:
+              webhook_url: "{}/"
+              weight_type:
+                type: "Uniform"
+            messages:
+              abc: "now playing"
+        "#, server.url()});
+        let log_file = NamedTempFile::new().unwrap();
+        let log_path = log_file.path().to_owned();
+        log_file.close().unwrap();
+
+        let options = PostOptions { edit_last: true, ..PostOptions::default() };
+        post_random(&ReqwestPoster::shared(), &settings, &log_path, Local::now(), &options).await.unwrap();
+
+        mock.assert_async().await;
+        let random_picker = load_picker(&settings, &log_path).unwrap();
+        assert_eq!(random_picker.state().last_message_id, Some(String::from("1111")));
+    }
+
+    #[tokio::test]
+    async fn post_random_should_prefer_the_user_settings_of_the_message() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server.mock("POST", "/")
+            .match_body(Matcher::Json(serde_json::json!({
+                "username": "persona",
+                "avatar_url": "https://example.com/icon.png",
+                "content": "message1",
+                "embeds": [],
+            })))
+            .with_status(204)
+            .expect(1)
+            .create_async().await;
+
+        let settings = settings_from_str(&formatdoc! {r#"
+            environment:
+              webhook_url: "{}"
+              weight_type:
+                type: "Uniform"
+              user:
+                name: "user_name"
+                icon_url: "https://example.com/icon.png"
+            messages:
+              abc:
+                content: "message1"
+                embeds: []
+                user:
+                  name: "persona"
+        "#, server.url()});
+        let log_file = NamedTempFile::new().unwrap();
+        let log_path = log_file.path().to_owned();
+        log_file.close().unwrap();
+
+        post_random(&ReqwestPoster::shared(), &settings, &log_path, Local::now(), &PostOptions::default()).await.unwrap();
+
+        mock.assert_async().await;
+    }
+
+    #[test]
+    fn lock_log_should_keep_the_counts_of_concurrent_runs() {
+        let settings = settings_from_str(indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/XXXX/YYYY"
+              weight_type:
+                type: "Uniform"
+              lock: "Wait"
+            messages:
+              abc: "message1"
+              def: "message2"
+        "#});
+        let dir = tempfile::TempDir::new().unwrap();
+        let log_path = dir.path().join("message-log.json");
+
+        let posted = std::thread::scope(|scope| {
+            let runs: Vec<_> = (0..2).map(|_| scope.spawn(|| {
+                let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+                let poster = RecordingPoster::default();
+                for _ in 0..10 {
+                    runtime.block_on(post_random(&poster, &settings, &log_path, Local::now(), &PostOptions::default())).unwrap();
+                }
+                poster.requests.into_inner().len()
+            })).collect();
+            runs.into_iter().map(|run| run.join().unwrap()).sum::<usize>()
+        });
+
+        assert_eq!(posted, 20);
+        let counts = read_log_counts::<String>(&log_path, settings.environment.log_format).unwrap();
+        assert_eq!(counts.values().sum::<u64>(), 20);
+    }
+
+    #[test]
+    fn load_picker_should_use_the_external_weights() {
+        let mut weights_file = NamedTempFile::new().unwrap();
+        write!(weights_file, "abc,1\ndef,3\n").unwrap();
+
+        let settings = settings_from_str(&formatdoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/XXXX/YYYY"
+              weight_type:
+                type: "External"
+                path: "{}"
+              stable_order: true
+            messages:
+              abc: "message1"
+              def: "message2"
+              ghi: "message3"
+        "#, weights_file.path().display()});
+        let log_file = NamedTempFile::new().unwrap();
+        let log_path = log_file.path().to_owned();
+        log_file.close().unwrap();
+
+        let random_picker = load_picker(&settings, &log_path).unwrap();
+        let probabilities: Vec<f64> = random_picker.probabilities().into_iter().map(|(_, probability)| probability).collect();
+        assert_eq!(probabilities, vec![0.2, 0.6, 0.2]);
+    }
+
+    #[test]
+    fn load_picker_should_use_the_initial_count_of_new_messages() {
+        let mut log_file = NamedTempFile::new().unwrap();
+        write!(log_file, r#"[{{ "value": "abc", "count": 3 }}]"#).unwrap();
+
+        let settings = settings_from_str(indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/XXXX/YYYY"
+              weight_type:
+                type: "Uniform"
+              initial_count_type: "Max"
+              stable_order: true
+            messages:
+              abc:
+                content: "message1"
+                initial_count: 10
+              def:
+                content: "message2"
+                initial_count: 5
+              ghi: "message3"
+        "#});
+
+        let random_picker = load_picker(&settings, log_file.path()).unwrap();
+        let counts: Vec<u64> = random_picker.counts().into_iter().map(|(_, count)| count).collect();
+        assert_eq!(counts, vec![3, 5, 3]);
+    }
+
+    #[test]
+    fn load_picker_should_favor_messages_with_lower_costs() {
+        let settings = settings_from_str(indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/XXXX/YYYY"
+              weight_type:
+                type: "Uniform"
+              stable_order: true
+              weight_by_cost: true
+            messages:
+              abc:
+                content: "message1"
+                cost: 1.0
+              def:
+                content: "message2"
+                cost: 0.0
+              ghi: "message3"
+        "#});
+        let log_file = NamedTempFile::new().unwrap();
+        let log_path = log_file.path().to_owned();
+        log_file.close().unwrap();
+
+        let random_picker = load_picker(&settings, &log_path).unwrap();
+        let probabilities: Vec<f64> = random_picker.probabilities().into_iter().map(|(_, probability)| probability).collect();
+        assert_eq!(probabilities, vec![0.2, 0.4, 0.4]);
+    }
+
+    #[test]
+    fn preferred_hours_multipliers_should_favor_the_messages_preferring_the_current_hour() {
+        let settings = settings_from_str(indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/XXXX/YYYY"
+              weight_type:
+                type: "Uniform"
+              stable_order: true
+            messages:
+              morning:
+                content: "good morning"
+                preferred_hours: [6, 10]
+              night:
+                content: "good night"
+                preferred_hours: [20, 23]
+              other: "hello"
+        "#});
+        let log_file = NamedTempFile::new().unwrap();
+        let log_path = log_file.path().to_owned();
+        log_file.close().unwrap();
+
+        let probabilities_at = |hour: u32| {
+            let mut random_picker = load_picker(&settings, &log_path).unwrap();
+            random_picker.extend_weight_multipliers(preferred_hours_multipliers(&settings, hour));
+            random_picker.probabilities().into_iter().map(|(_, probability)| probability).collect::<Vec<f64>>()
+        };
+
+        // the weights are 4, 1/4 and 1 at 8, and 1/4, 4 and 1 at 21
+        let morning = probabilities_at(8);
+        assert!(morning[0] > morning[2] && morning[2] > morning[1], "{:?}", morning);
+        assert!((morning[0] / morning[1] - 16.0).abs() < 1e-9, "{:?}", morning);
+        let night = probabilities_at(21);
+        assert!(night[1] > night[2] && night[2] > night[0], "{:?}", night);
+    }
+
+    #[test]
+    fn simulate_picks_should_use_the_snapshot_without_changing_it_or_the_log() {
+        let mut log_file = NamedTempFile::new().unwrap();
+        write!(log_file, r#"[{{ "value": "abc", "count": 0 }}, {{ "value": "def", "count": 5 }}]"#).unwrap();
+        let snapshot_file = NamedTempFile::new().unwrap();
+        write_snapshot::<String>(log_file.path(), snapshot_file.path(), LogFormat::Json).unwrap();
+        let snapshot = std::fs::read_to_string(snapshot_file.path()).unwrap();
+        std::fs::write(log_file.path(), r#"[{ "value": "abc", "count": 5 }, { "value": "def", "count": 0 }]"#).unwrap();
+
+        let settings = settings_from_str(indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/XXXX/YYYY"
+              weight_type:
+                type: "MinOnly"
+            messages:
+              abc: "message1"
+              def: "message2"
+        "#});
+        let random_picker = load_picker(&settings, snapshot_file.path()).unwrap();
+        assert_eq!(simulate_picks(&random_picker, 1).unwrap(), vec![(String::from("abc"), 1), (String::from("def"), 0)]);
+        assert_eq!(std::fs::read_to_string(snapshot_file.path()).unwrap(), snapshot);
+        assert_eq!(std::fs::read_to_string(log_file.path()).unwrap(), r#"[{ "value": "abc", "count": 5 }, { "value": "def", "count": 0 }]"#);
+    }
+
+    #[test]
+    fn simulate_picks_should_pick_each_message_about_equally_if_the_weight_type_is_uniform() {
+        let settings = settings_from_str(indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/XXXX/YYYY"
+              weight_type:
+                type: "Uniform"
+              seed: 0
+            messages:
+              abc: "message1"
+              def: "message2"
+              ghi: "message3"
+        "#});
+        let log_file = NamedTempFile::new().unwrap();
+        let log_path = log_file.path().to_owned();
+        log_file.close().unwrap();
+
+        let random_picker = load_picker(&settings, &log_path).unwrap();
+        let picks = simulate_picks(&random_picker, 3000).unwrap();
+
+        assert_eq!(picks.iter().map(|(message_id, _)| message_id.as_str()).collect::<Vec<&str>>(), vec!["abc", "def", "ghi"]);
+        for (message_id, count) in &picks {
+            assert!((900..=1100).contains(count), "{}: {}", message_id, count);
+        }
+        assert!(random_picker.counts().iter().all(|(_, count)| *count == 0));
+        assert!(!log_path.exists());
+    }
+
+    #[test]
+    fn list_entries_should_list_the_messages_in_the_settings_and_the_log() {
+        let mut log_file = NamedTempFile::new().unwrap();
+        write!(log_file, r#"[{{ "value": "abc", "count": 3 }}, {{ "value": "old", "count": 5 }}]"#).unwrap();
+
+        let settings = settings_from_str(indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/XXXX/YYYY"
+              weight_type:
+                type: "Uniform"
+            messages:
+              def: "message2"
+              abc: "message1"
+        "#});
+
+        let entries = list_entries(&settings, log_file.path()).unwrap();
+        let expected = vec![
+            ListEntry { id: String::from("abc"), count: 3, in_settings: true, in_log: true },
+            ListEntry { id: String::from("def"), count: 0, in_settings: true, in_log: false },
+            ListEntry { id: String::from("old"), count: 5, in_settings: false, in_log: true },
+        ];
+        assert_eq!(entries, expected);
+    }
+
+    #[test]
+    fn extremes_lines_should_print_the_least_and_the_most_picked_messages() {
+        let mut log_file = NamedTempFile::new().unwrap();
+        write!(log_file, r#"[{{ "value": "abc", "count": 3 }}, {{ "value": "def", "count": 7 }}, {{ "value": "ghi", "count": 3 }}]"#).unwrap();
+
+        let settings = settings_from_str(indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/XXXX/YYYY"
+              weight_type:
+                type: "Uniform"
+            messages:
+              ghi: "message3"
+              def: "message2"
+              abc: "message1"
+        "#});
+
+        let random_picker = load_picker(&settings, log_file.path()).unwrap();
+        assert_eq!(extremes_lines(&random_picker), vec![String::from("least picked: abc (3)"), String::from("most picked: def (7)")]);
+    }
+
+    #[tokio::test]
+    async fn self_test_results_should_report_only_the_settings_if_they_cannot_be_read() {
+        let mut settings_file = NamedTempFile::new().unwrap();
+        write!(settings_file, "environment: {{}}").unwrap();
+        let log_file = NamedTempFile::new().unwrap();
+
+        let results = self_test_results(settings_file.path(), None, log_file.path()).await;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].0, "settings");
+        assert!(results[0].1.is_err());
+    }
+
+    #[tokio::test]
+    async fn self_test_environment_should_pass_if_the_log_is_readable_and_the_webhook_is_reachable() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server.mock("GET", "/")
+            .with_status(200)
+            .expect(1)
+            .create_async().await;
+        let mut log_file = NamedTempFile::new().unwrap();
+        write!(log_file, r#"[{{ "value": "abc", "count": 3 }}]"#).unwrap();
+
+        let settings = settings_from_str(&formatdoc! {r#"
+            environment:
+              webhook_url: "{}/"
+              weight_type:
+                type: "Uniform"
+            messages:
+              abc: "message1"
+        "#, server.url()});
+
+        let results = self_test_environment(&settings, log_file.path()).await;
+        assert_eq!(results, vec![("log", Ok(())), ("webhook", Ok(()))]);
+        mock.assert_async().await;
+        assert_eq!(std::fs::read_to_string(log_file.path()).unwrap(), r#"[{ "value": "abc", "count": 3 }]"#);
+    }
+
+    #[tokio::test]
+    async fn self_test_environment_should_fail_if_the_log_is_corrupt_or_the_webhook_fails() {
+        let mut server = mockito::Server::new_async().await;
+        server.mock("GET", "/").with_status(404).create_async().await;
+        let mut log_file = NamedTempFile::new().unwrap();
+        write!(log_file, "not json").unwrap();
+
+        let settings = settings_from_str(&formatdoc! {r#"
+            environment:
+              webhook_url: "{}/"
+              weight_type:
+                type: "Uniform"
+            messages:
+              abc: "message1"
+        "#, server.url()});
+
+        let results = self_test_environment(&settings, log_file.path()).await;
+        assert_eq!(results[0].0, "log");
+        assert!(results[0].1.is_err());
+        assert_eq!(results[1], ("webhook", Err(String::from("failed with 404 Not Found"))));
+    }
+
+    #[tokio::test]
+    async fn self_test_environment_should_fail_if_the_directory_of_the_log_does_not_exist() {
+        let mut server = mockito::Server::new_async().await;
+        server.mock("GET", "/").with_status(200).create_async().await;
+        let directory = tempfile::tempdir().unwrap();
+        let log_path = directory.path().join("missing").join("message-log.json");
+
+        let settings = settings_from_str(&formatdoc! {r#"
+            environment:
+              webhook_url: "{}/"
+              weight_type:
+                type: "Uniform"
+            messages:
+              abc: "message1"
+        "#, server.url()});
+
+        let results = self_test_environment(&settings, &log_path).await;
+        assert_eq!(results[0], ("log", Err(format!("the directory of the log does not exist: {}", directory.path().join("missing").display()))));
+    }
+
+    #[test]
+    fn add_message_should_record_the_message_with_the_given_count() {
+        let mut log_file = NamedTempFile::new().unwrap();
+        write!(log_file, r#"[{{ "value": "abc", "count": 3 }}]"#).unwrap();
+
+        let settings = settings_from_str(indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/XXXX/YYYY"
+              weight_type:
+                type: "Uniform"
+            messages:
+              abc: "message1"
+              def: "message2"
+        "#});
+
+        add_message(&settings, log_file.path(), "def", Some(7)).unwrap();
+        let expected: HashMap<String, u64> = vec![(String::from("abc"), 3), (String::from("def"), 7)].into_iter().collect();
+        assert_eq!(read_log_counts::<String>(log_file.path(), LogFormat::Json).unwrap(), expected);
+
+        assert_eq!(add_message(&settings, log_file.path(), "def", None), Err(String::from("message `def` is already in the log")));
+        assert_eq!(add_message(&settings, log_file.path(), "ghi", None), Err(String::from("message `ghi` is not in the settings")));
+    }
+
+    #[test]
+    fn remove_message_should_remove_the_message_from_the_log() {
+        let mut log_file = NamedTempFile::new().unwrap();
+        write!(log_file, r#"[{{ "value": "abc", "count": 3 }}, {{ "value": "def", "count": 5 }}, {{ "value": "old", "count": 2 }}]"#).unwrap();
+
+        let settings = settings_from_str(indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/XXXX/YYYY"
+              weight_type:
+                type: "Uniform"
+            messages:
+              abc: "message1"
+              def: "message2"
+        "#});
+
+        remove_message(&settings, log_file.path(), "def").unwrap();
+        let expected: HashMap<String, u64> = vec![(String::from("abc"), 3)].into_iter().collect();
+        assert_eq!(read_log_counts::<String>(log_file.path(), LogFormat::Json).unwrap(), expected);
+    }
+
+    #[test]
+    fn remove_message_should_not_change_the_log_if_the_message_is_not_in_it() {
+        let mut log_file = NamedTempFile::new().unwrap();
+        write!(log_file, r#"[{{ "value": "abc", "count": 3 }}]"#).unwrap();
+
+        let settings = settings_from_str(indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/XXXX/YYYY"
+              weight_type:
+                type: "Uniform"
+            messages:
+              abc: "message1"
+              def: "message2"
+        "#});
+
+        remove_message(&settings, log_file.path(), "def").unwrap();
+        assert_eq!(std::fs::read_to_string(log_file.path()).unwrap(), r#"[{ "value": "abc", "count": 3 }]"#);
+    }
+
+    fn settings_from_str(input: &str) -> Settings {
+        serde_yaml::from_str(input).unwrap()
+    }
+}
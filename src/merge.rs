@@ -0,0 +1,18 @@
+use std::path::{Path, PathBuf};
+
+use super::random::{LogFormat, merge_logs, write_merged_log};
+
+// Adds the counts in the other logs to the log, such as the logs of the other machines which post the same messages.
+// The other logs are read in the same format as the log.
+pub fn merge(log_path: &Path, paths: &[PathBuf], log_format: LogFormat) -> Result<(), String> {
+    let mut all_paths = Vec::new();
+    if log_path.exists() {
+        all_paths.push(log_path.to_owned());
+    }
+    all_paths.extend(paths.iter().cloned());
+
+    let items = merge_logs::<String>(&all_paths, log_format)?;
+    write_merged_log(log_path, &items, log_format)?;
+    println!("merged {} logs into {} with {} messages", all_paths.len(), log_path.display(), items.len());
+    Ok(())
+}
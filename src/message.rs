@@ -1,13 +1,430 @@
-use serde::{Serialize, Serializer, Deserialize, ser::SerializeStruct};
+use rand::RngCore;
+use rand::seq::SliceRandom;
+use serde::{Serialize, Serializer, Deserialize, Deserializer, de::Error, ser::{self, SerializeStruct}};
+use std::path::{Path, PathBuf};
 
-#[derive(PartialEq, Deserialize, Debug)]
+pub const MAX_EMBEDS: usize = 10;
+pub const MAX_EMBEDS_LENGTH: usize = 6000;
+const MAX_TITLE_LENGTH: usize = 256;
+const MAX_DESCRIPTION_LENGTH: usize = 4096;
+const MAX_FIELDS: usize = 25;
+const MAX_FIELD_NAME_LENGTH: usize = 256;
+const MAX_FIELD_VALUE_LENGTH: usize = 1024;
+const MAX_FOOTER_TEXT_LENGTH: usize = 2048;
+const MAX_AUTHOR_NAME_LENGTH: usize = 256;
+const MAX_COLOR: u32 = 0xFFFFFF;
+const NAMED_COLORS: [(&str, u32); 9] = [
+    ("black", 0x000000),
+    ("white", 0xFFFFFF),
+    ("gray", 0x808080),
+    ("red", 0xFF0000),
+    ("orange", 0xFFA500),
+    ("yellow", 0xFFFF00),
+    ("green", 0x00FF00),
+    ("blue", 0x0000FF),
+    ("purple", 0x800080),
+];
+
+#[derive(PartialEq, Clone, Deserialize, Debug)]
 #[serde(untagged)]
 pub enum Message {
     String(String),
+    WithAttachment {
+        content: Option<String>,
+        file: PathBuf,
+    },
+    // also accepts a map with only `content`
     WithEmbeds {
         content: Option<String>,
+        #[serde(default)]
         embeds: Vec<serde_json::Value>,
+        // posted instead of the content on platforms without embeds
+        #[serde(default)]
+        plain_fallback: Option<String>,
     },
+    // one of the messages is picked uniformly by `resolve` on each post, regardless of the counts
+    OneOf(Vec<Message>),
+}
+
+// A built-in change to the content of the picked message, which is made before it is posted.
+#[derive(PartialEq, Eq, Clone, Deserialize, Debug)]
+#[serde(tag = "type")]
+pub enum Transformer {
+    Prefix { text: String },
+    Suffix { text: String },
+}
+
+impl Transformer {
+    // The messages without content, such as the ones with only embeds, are left as they are.
+    pub fn apply(&self, message: &mut Message) {
+        match self {
+            Transformer::Prefix { text: prefix } => message.map_text(&|text| format!("{}{}", prefix, text)),
+            Transformer::Suffix { text: suffix } => message.map_text(&|text| format!("{}{}", text, suffix)),
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Default, Serialize, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Embed {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_color", skip_serializing_if = "Option::is_none")]
+    pub color: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub footer: Option<EmbedFooter>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<EmbedMedia>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thumbnail: Option<EmbedMedia>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<EmbedAuthor>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<EmbedField>,
+}
+
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct EmbedFooter {
+    pub text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon_url: Option<String>,
+}
+
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct EmbedMedia {
+    pub url: String,
+}
+
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct EmbedAuthor {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon_url: Option<String>,
+}
+
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct EmbedField {
+    pub name: String,
+    pub value: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inline: Option<bool>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Color {
+    Integer(u64),
+    String(String),
+}
+
+fn deserialize_color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u32>, D::Error> {
+    let color = match Option::<Color>::deserialize(deserializer)? {
+        Some(color) => color,
+        None => return Ok(None),
+    };
+    let value = match color {
+        Color::Integer(value) if value <= MAX_COLOR as u64 => value as u32,
+        Color::Integer(value) => return Err(D::Error::custom(format!("invalid color {}: must not be larger than {}", value, MAX_COLOR))),
+        Color::String(name) => parse_color(&name).map_err(D::Error::custom)?,
+    };
+    Ok(Some(value))
+}
+
+fn parse_color(name: &str) -> Result<u32, String> {
+    if let Some(hex) = name.strip_prefix('#') {
+        if hex.len() == 6 {
+            if let Ok(value) = u32::from_str_radix(hex, 16) {
+                return Ok(value);
+            }
+        }
+    } else if let Some((_, value)) = NAMED_COLORS.iter().find(|(color_name, _)| color_name.eq_ignore_ascii_case(name)) {
+        return Ok(*value);
+    }
+
+    let color_names: Vec<&str> = NAMED_COLORS.iter().map(|(color_name, _)| *color_name).collect();
+    Err(format!("invalid color `{}`: expected an integer, a #RRGGBB hex string or one of {}", name, color_names.join(", ")))
+}
+
+impl Message {
+    pub fn content(&self) -> Option<&str> {
+        match self {
+            Message::String(content) => Some(content),
+            Message::WithEmbeds { content, .. } => content.as_deref(),
+            Message::WithAttachment { content, .. } => content.as_deref(),
+            Message::OneOf(_) => None,
+        }
+    }
+
+    // The text to post on platforms without embeds.
+    pub fn plain_text(&self) -> Option<&str> {
+        match self {
+            Message::WithEmbeds { plain_fallback: Some(plain_fallback), .. } => Some(plain_fallback),
+            _ => self.content(),
+        }
+    }
+
+    pub fn resolve(&self, rng: &mut dyn RngCore) -> Message {
+        match self {
+            Message::OneOf(messages) => match messages.choose(rng) {
+                Some(message) => message.resolve(rng),
+                None => self.clone(),
+            },
+            _ => self.clone(),
+        }
+    }
+
+    // Returns every message which can be posted by this message.
+    pub fn variants(&self) -> Vec<&Message> {
+        match self {
+            Message::OneOf(messages) => messages.iter().flat_map(|message| message.variants()).collect(),
+            _ => vec![self],
+        }
+    }
+
+    // Replaces each `{{name}}` in the content, the plain fallback and the texts of the embeds by its value.
+    pub fn replace_placeholders(&self, placeholders: &[(&str, String)]) -> Message {
+        let replace = |text: &str| placeholders.iter().fold(text.to_owned(), |text, (name, value)| text.replace(&format!("{{{{{}}}}}", name), value));
+        match self {
+            Message::String(content) => Message::String(replace(content)),
+            Message::WithAttachment { content, file } => Message::WithAttachment { content: content.as_deref().map(replace), file: file.clone() },
+            Message::WithEmbeds { content, embeds, plain_fallback } => Message::WithEmbeds {
+                content: content.as_deref().map(replace),
+                embeds: embeds.iter().map(|embed| replace_in_strings(embed, &replace)).collect(),
+                plain_fallback: plain_fallback.as_deref().map(replace),
+            },
+            Message::OneOf(messages) => Message::OneOf(messages.iter().map(|message| message.replace_placeholders(placeholders)).collect()),
+        }
+    }
+
+    // Joins the contents of the messages by `separator` into one message with every embed of them,
+    // which has a plain fallback if any of them has one. Attachments are dropped, so they should not be combined.
+    pub fn combine(messages: &[Message], separator: &str) -> Message {
+        let content = messages.iter().filter_map(Message::content).collect::<Vec<&str>>().join(separator);
+        let embeds: Vec<serde_json::Value> = messages.iter().flat_map(|message| message.embeds().iter().cloned()).collect();
+        if embeds.is_empty() {
+            return Message::String(content);
+        }
+
+        let has_plain_fallback = messages.iter().any(|message| matches!(message, Message::WithEmbeds { plain_fallback: Some(_), .. }));
+        Message::WithEmbeds {
+            content: if content.is_empty() { None } else { Some(content) },
+            embeds,
+            plain_fallback: if has_plain_fallback { Some(messages.iter().filter_map(Message::plain_text).collect::<Vec<&str>>().join(separator)) } else { None },
+        }
+    }
+
+    // Replaces the content and the plain fallback, which are the texts posted as they are.
+    pub fn map_text(&mut self, f: &dyn Fn(&str) -> String) {
+        match self {
+            Message::String(content) => *content = f(content),
+            Message::WithAttachment { content, .. } => *content = content.as_deref().map(f),
+            Message::WithEmbeds { content, plain_fallback, .. } => {
+                *content = content.as_deref().map(f);
+                *plain_fallback = plain_fallback.as_deref().map(f);
+            },
+            Message::OneOf(messages) => messages.iter_mut().for_each(|message| message.map_text(f)),
+        }
+    }
+
+    // Adds the keys of `defaults` which each embed does not have, keeping the values of the embed.
+    pub fn fill_embed_defaults(&mut self, defaults: &serde_json::Map<String, serde_json::Value>) {
+        match self {
+            Message::WithEmbeds { embeds, .. } => {
+                for embed in embeds.iter_mut().filter_map(|embed| embed.as_object_mut()) {
+                    for (key, value) in defaults {
+                        embed.entry(key.clone()).or_insert_with(|| value.clone());
+                    }
+                }
+            },
+            Message::OneOf(messages) => messages.iter_mut().for_each(|message| message.fill_embed_defaults(defaults)),
+            _ => (),
+        }
+    }
+
+    pub fn attachment(&self) -> Option<&Path> {
+        match self {
+            Message::WithAttachment { file, .. } => Some(file),
+            _ => None,
+        }
+    }
+
+    pub fn split_content(&self, max_length: usize) -> Vec<Message> {
+        let content = match self.content() {
+            Some(content) if content.chars().count() > max_length => content,
+            _ => return vec![self.clone()],
+        };
+
+        let mut chunks = split_text(content, max_length);
+        let last_chunk = chunks.pop().unwrap();
+        let mut messages: Vec<Message> = chunks.into_iter().map(Message::String).collect();
+        messages.push(match self {
+            Message::String(_) => Message::String(last_chunk),
+            Message::WithEmbeds { embeds, plain_fallback, .. } => Message::WithEmbeds { content: Some(last_chunk), embeds: embeds.clone(), plain_fallback: plain_fallback.clone() },
+            Message::WithAttachment { file, .. } => Message::WithAttachment { content: Some(last_chunk), file: file.clone() },
+            Message::OneOf(_) => unreachable!("one_of messages have no content"),
+        });
+        messages
+    }
+
+    pub fn embeds(&self) -> &[serde_json::Value] {
+        match self {
+            Message::WithEmbeds { embeds, .. } => embeds,
+            _ => &[],
+        }
+    }
+
+    // Converts the colors of the embeds into the integers Discord expects, and with `strict` also reads the embeds as typed `Embed`s
+    // so that unknown keys are rejected, which would reject the keys Discord adds later.
+    pub fn normalize_embeds(&mut self, strict: bool) -> Result<(), String> {
+        let embeds = match self {
+            Message::WithEmbeds { embeds, .. } => embeds,
+            Message::OneOf(messages) => {
+                for (index, message) in messages.iter_mut().enumerate() {
+                    message.normalize_embeds(strict).map_err(|e| format!("one_of {}: {}", index + 1, e))?;
+                }
+                return Ok(());
+            },
+            _ => return Ok(()),
+        };
+
+        for (index, embed) in embeds.iter_mut().enumerate() {
+            if strict {
+                let typed_embed: Embed = serde_json::from_value(embed.clone()).map_err(|e| format!("embed {}: {}", index + 1, e))?;
+                *embed = serde_json::to_value(typed_embed).unwrap();
+            } else if let Some(color) = embed.get_mut("color") {
+                // the other keys, and the embeds which are not maps, are left to validate_embeds
+                if let Some(value) = deserialize_color(color.clone()).map_err(|e| format!("embed {}: {}", index + 1, e))? {
+                    *color = serde_json::Value::from(value);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn validate_embeds(embeds: &[serde_json::Value]) -> Result<(), String> {
+    let mut violations = Vec::new();
+
+    if embeds.len() > MAX_EMBEDS {
+        violations.push(format!("there must not be more than {} embeds, but there are {}", MAX_EMBEDS, embeds.len()));
+    }
+
+    for (index, embed) in embeds.iter().enumerate() {
+        let name = format!("embed {}", index + 1);
+        let embed = match embed.as_object() {
+            Some(embed) => embed,
+            None => {
+                violations.push(format!("{} must be a map", name));
+                continue;
+            },
+        };
+
+        validate_text_length(&mut violations, &name, "title", embed.get("title"), MAX_TITLE_LENGTH);
+        validate_text_length(&mut violations, &name, "description", embed.get("description"), MAX_DESCRIPTION_LENGTH);
+        validate_text_length(&mut violations, &name, "footer.text", embed.get("footer").and_then(|footer| footer.get("text")), MAX_FOOTER_TEXT_LENGTH);
+        validate_text_length(&mut violations, &name, "author.name", embed.get("author").and_then(|author| author.get("name")), MAX_AUTHOR_NAME_LENGTH);
+
+        match embed.get("fields") {
+            None => {},
+            Some(serde_json::Value::Array(fields)) => {
+                if fields.len() > MAX_FIELDS {
+                    violations.push(format!("{}: there must not be more than {} fields, but there are {}", name, MAX_FIELDS, fields.len()));
+                }
+                for (field_index, field) in fields.iter().enumerate() {
+                    let field_name = format!("{} field {}", name, field_index + 1);
+                    validate_text_length(&mut violations, &field_name, "name", field.get("name"), MAX_FIELD_NAME_LENGTH);
+                    validate_text_length(&mut violations, &field_name, "value", field.get("value"), MAX_FIELD_VALUE_LENGTH);
+                }
+            },
+            Some(_) => violations.push(format!("{}: fields must be a list", name)),
+        }
+    }
+
+    let length: usize = embeds.iter().map(text_length).sum();
+    if length > MAX_EMBEDS_LENGTH {
+        violations.push(format!("embeds must not contain more than {} characters, but contain {}", MAX_EMBEDS_LENGTH, length));
+    }
+
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("invalid embeds: {}", violations.join("; ")))
+    }
+}
+
+fn validate_text_length(violations: &mut Vec<String>, name: &str, key: &str, value: Option<&serde_json::Value>, max_length: usize) {
+    match value {
+        None => {},
+        Some(serde_json::Value::String(text)) => {
+            let length = text.chars().count();
+            if length > max_length {
+                violations.push(format!("{}: {} must not be longer than {} characters, but is {}", name, key, max_length, length));
+            }
+        },
+        Some(_) => violations.push(format!("{}: {} must be a string", name, key)),
+    }
+}
+
+fn split_text(text: &str, max_length: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut chunk = String::new();
+    let mut chunk_length = 0;
+
+    for line in text.split_inclusive('\n') {
+        let line_length = line.chars().count();
+        if chunk_length + line_length > max_length && chunk_length > 0 {
+            chunks.push(std::mem::take(&mut chunk));
+            chunk_length = 0;
+        }
+
+        if line_length <= max_length {
+            chunk.push_str(line);
+            chunk_length += line_length;
+            continue;
+        }
+
+        let chars: Vec<char> = line.chars().collect();
+        for part in chars.chunks(max_length) {
+            if chunk_length > 0 {
+                chunks.push(std::mem::take(&mut chunk));
+            }
+            chunk = part.iter().collect();
+            chunk_length = part.len();
+        }
+    }
+    chunks.push(chunk);
+
+    chunks.into_iter().map(|chunk| chunk.trim_end_matches('\n').to_owned()).collect()
+}
+
+fn replace_in_strings(value: &serde_json::Value, replace: &dyn Fn(&str) -> String) -> serde_json::Value {
+    match value {
+        serde_json::Value::String(s) => serde_json::Value::String(replace(s)),
+        serde_json::Value::Array(values) => serde_json::Value::Array(values.iter().map(|value| replace_in_strings(value, replace)).collect()),
+        serde_json::Value::Object(map) => serde_json::Value::Object(map.iter().map(|(key, value)| (key.clone(), replace_in_strings(value, replace))).collect()),
+        _ => value.clone(),
+    }
+}
+
+fn text_length(value: &serde_json::Value) -> usize {
+    match value {
+        serde_json::Value::String(s) => s.chars().count(),
+        serde_json::Value::Array(values) => values.iter().map(text_length).sum(),
+        serde_json::Value::Object(map) => map.iter().filter(|(key, _)| !key.ends_with("url")).map(|(_, value)| text_length(value)).sum(),
+        _ => 0,
+    }
 }
 
 impl Serialize for Message {
@@ -18,17 +435,27 @@ impl Serialize for Message {
                 s.serialize_field("content", &content)?;
                 s.end()
             },
-            Message::WithEmbeds { content: None, embeds } => {
+            Message::WithEmbeds { content: None, embeds, .. } => {
                 let mut s = serializer.serialize_struct("Message", 1)?;
                 s.serialize_field("embeds", &embeds)?;
                 s.end()
             },
-            Message::WithEmbeds { content, embeds } => {
+            Message::WithEmbeds { content, embeds, .. } => {
                 let mut s = serializer.serialize_struct("Message", 2)?;
                 s.serialize_field("content", &content)?;
                 s.serialize_field("embeds", &embeds)?;
                 s.end()
             },
+            Message::WithAttachment { content: None, .. } => {
+                let s = serializer.serialize_struct("Message", 0)?;
+                s.end()
+            },
+            Message::WithAttachment { content, .. } => {
+                let mut s = serializer.serialize_struct("Message", 1)?;
+                s.serialize_field("content", &content)?;
+                s.end()
+            },
+            Message::OneOf(_) => Err(ser::Error::custom("one_of messages must be resolved before being serialized")),
         }
     }
 }
@@ -51,7 +478,7 @@ mod tests {
         "#};
 
         let json = serde_json::to_string(&message).unwrap();
-        assert_eq!(to_json_value(&expected), to_json_value(&json));
+        assert_eq!(to_json_value(expected), to_json_value(&json));
     }
 
     #[test]
@@ -74,6 +501,7 @@ mod tests {
                     },
                 }),
             ],
+            plain_fallback: None,
         };
         let expected = indoc! {r#"
             {
@@ -98,7 +526,7 @@ mod tests {
         "#};
 
         let json = serde_json::to_string(&message).unwrap();
-        assert_eq!(to_json_value(&expected), to_json_value(&json));
+        assert_eq!(to_json_value(expected), to_json_value(&json));
     }
 
     #[test]
@@ -114,6 +542,7 @@ mod tests {
                     },
                 }),
             ],
+            plain_fallback: None,
         };
         let expected = indoc! {r#"
             {
@@ -130,7 +559,302 @@ mod tests {
         "#};
 
         let json = serde_json::to_string(&message).unwrap();
-        assert_eq!(to_json_value(&expected), to_json_value(&json));
+        assert_eq!(to_json_value(expected), to_json_value(&json));
+    }
+
+    #[test]
+    fn read_settings_should_read_the_attachment() {
+        let message = Message::WithAttachment {
+            content: Some(String::from("message")),
+            file: PathBuf::from("conf/image.png"),
+        };
+        let expected = indoc! {r#"
+            {
+                "content": "message"
+            }
+        "#};
+
+        let json = serde_json::to_string(&message).unwrap();
+        assert_eq!(to_json_value(expected), to_json_value(&json));
+    }
+
+    #[test]
+    fn resolve_should_return_one_of_the_messages() {
+        let messages = vec![Message::String(String::from("message1")), Message::String(String::from("message2"))];
+        let message = Message::OneOf(messages.clone());
+
+        let mut rng = rand::rngs::mock::StepRng::new(0, u64::MAX / 3);
+        for _ in 0..10 {
+            let resolved = message.resolve(&mut rng);
+            assert!(messages.contains(&resolved), "{:?}", resolved);
+        }
+    }
+
+    #[test]
+    fn resolve_should_resolve_nested_one_of_messages() {
+        let message = Message::OneOf(vec![Message::OneOf(vec![Message::String(String::from("message1"))])]);
+        assert_eq!(message.resolve(&mut rand::thread_rng()), Message::String(String::from("message1")));
+    }
+
+    #[test]
+    fn read_settings_can_read_one_of() {
+        let message: Message = serde_yaml::from_str(r#"["message1", { content: "message2" }]"#).unwrap();
+        let expected = Message::OneOf(vec![
+            Message::String(String::from("message1")),
+            Message::WithEmbeds { content: Some(String::from("message2")), embeds: vec![], plain_fallback: None },
+        ]);
+        assert_eq!(message, expected);
+    }
+
+    #[test]
+    fn serialize_should_fail_if_one_of_is_not_resolved() {
+        let message = Message::OneOf(vec![Message::String(String::from("message1"))]);
+        assert!(serde_json::to_string(&message).is_err());
+    }
+
+    #[test]
+    fn serialize_should_skip_the_plain_fallback() {
+        let message = Message::WithEmbeds { content: Some(String::from("message")), embeds: vec![], plain_fallback: Some(String::from("plain")) };
+        let expected = serde_json::json!({ "content": "message", "embeds": [] });
+        assert_eq!(serde_json::to_value(&message).unwrap(), expected);
+    }
+
+    #[test]
+    fn split_content_should_split_long_content_into_multiple_messages() {
+        let message = Message::String("a".repeat(4500));
+        let messages = message.split_content(2000);
+
+        let expected = vec![
+            Message::String("a".repeat(2000)),
+            Message::String("a".repeat(2000)),
+            Message::String("a".repeat(500)),
+        ];
+        assert_eq!(messages, expected);
+    }
+
+    #[test]
+    fn split_content_should_split_content_at_line_breaks() {
+        let line = format!("{}\n", "a".repeat(99));
+        let message = Message::String(line.repeat(45));
+        let messages = message.split_content(2000);
+
+        let expected = vec![
+            Message::String(line.repeat(20).trim_end().to_owned()),
+            Message::String(line.repeat(20).trim_end().to_owned()),
+            Message::String(line.repeat(5).trim_end().to_owned()),
+        ];
+        assert_eq!(messages, expected);
+    }
+
+    #[test]
+    fn split_content_should_split_content_at_char_boundaries() {
+        let message = Message::String("あ".repeat(5));
+        let messages = message.split_content(2);
+
+        let expected = vec![
+            Message::String("あ".repeat(2)),
+            Message::String("あ".repeat(2)),
+            Message::String(String::from("あ")),
+        ];
+        assert_eq!(messages, expected);
+    }
+
+    #[test]
+    fn split_content_should_keep_embeds_in_the_last_message() {
+        let embeds = vec![serde_json::json!({ "title": "title1" })];
+        let message = Message::WithEmbeds { content: Some("a".repeat(3)), embeds: embeds.clone(), plain_fallback: None };
+        let messages = message.split_content(2);
+
+        let expected = vec![
+            Message::String("a".repeat(2)),
+            Message::WithEmbeds { content: Some(String::from("a")), embeds, plain_fallback: None },
+        ];
+        assert_eq!(messages, expected);
+    }
+
+    #[test]
+    fn split_content_should_not_split_short_content() {
+        let message = Message::String("a".repeat(2000));
+        assert_eq!(message.split_content(2000), vec![message]);
+    }
+
+    #[test]
+    fn validate_embeds_should_accept_valid_embeds() {
+        let embeds = vec![
+            serde_json::json!({
+                "title": "a".repeat(256),
+                "description": "a".repeat(4096),
+                "fields": [{ "name": "name", "value": "value" }],
+            }),
+        ];
+        assert_eq!(validate_embeds(&embeds), Ok(()));
+    }
+
+    #[test]
+    fn validate_embeds_should_fail_if_a_title_is_too_long() {
+        let embeds = vec![
+            serde_json::json!({ "title": "title1" }),
+            serde_json::json!({ "title": "a".repeat(257) }),
+        ];
+        assert_eq!(validate_embeds(&embeds), Err(String::from("invalid embeds: embed 2: title must not be longer than 256 characters, but is 257")));
+    }
+
+    #[test]
+    fn validate_embeds_should_fail_if_there_are_too_many_embeds() {
+        let embeds = vec![serde_json::json!({ "title": "title1" }); 11];
+        assert_eq!(validate_embeds(&embeds), Err(String::from("invalid embeds: there must not be more than 10 embeds, but there are 11")));
+    }
+
+    #[test]
+    fn validate_embeds_should_report_all_violations() {
+        let fields = vec![serde_json::json!({ "name": "name", "value": "value" }); 26];
+        let embeds = vec![
+            serde_json::json!({ "description": "a".repeat(4097), "fields": fields }),
+            serde_json::json!({ "title": 1 }),
+            serde_json::json!("embed"),
+        ];
+        let expected = String::from("invalid embeds: \
+            embed 1: description must not be longer than 4096 characters, but is 4097; \
+            embed 1: there must not be more than 25 fields, but there are 26; \
+            embed 2: title must be a string; \
+            embed 3 must be a map");
+        assert_eq!(validate_embeds(&embeds), Err(expected));
+    }
+
+    #[test]
+    fn validate_embeds_should_fail_if_the_embeds_are_too_long() {
+        let embeds = vec![
+            serde_json::json!({ "title": "a".repeat(256), "description": "a".repeat(2744), "url": "https://example.com/1" }),
+            serde_json::json!({ "description": "a".repeat(3001) }),
+        ];
+        assert_eq!(validate_embeds(&embeds), Err(String::from("invalid embeds: embeds must not contain more than 6000 characters, but contain 6001")));
+    }
+
+    #[test]
+    fn normalize_embeds_should_accept_known_keys() {
+        let embed = serde_json::json!({
+            "title": "title",
+            "url": "https://example.com/",
+            "color": 16711680,
+            "thumbnail": { "url": "https://example.com/thumbnail.png" },
+            "fields": [{ "name": "name", "value": "value", "inline": true }],
+        });
+        let mut message = Message::WithEmbeds { content: None, embeds: vec![embed.clone()], plain_fallback: None };
+
+        assert_eq!(message.normalize_embeds(true), Ok(()));
+        assert_eq!(message.embeds(), &[embed]);
+    }
+
+    #[test]
+    fn normalize_embeds_should_reject_unknown_keys() {
+        let mut message = Message::WithEmbeds { content: None, embeds: vec![serde_json::json!({ "title": "title" }), serde_json::json!({ "titel": "title" })], plain_fallback: None };
+
+        let result = message.normalize_embeds(true);
+        assert!(result.as_ref().unwrap_err().starts_with("embed 2: unknown field `titel`, expected one of `title`, "), "{:?}", result);
+    }
+
+    #[test]
+    fn normalize_embeds_should_keep_unknown_keys_unless_it_is_strict() {
+        let embed = serde_json::json!({ "title": "title", "provider": { "name": "provider" }, "footer": { "text": "text", "icon": "icon.png" }, "color": "Red" });
+        let mut message = Message::WithEmbeds { content: None, embeds: vec![embed], plain_fallback: None };
+
+        assert_eq!(message.normalize_embeds(false), Ok(()));
+        let expected = serde_json::json!({ "title": "title", "provider": { "name": "provider" }, "footer": { "text": "text", "icon": "icon.png" }, "color": 0xFF0000 });
+        assert_eq!(message.embeds(), &[expected]);
+    }
+
+    #[test]
+    fn normalize_embeds_should_reject_unknown_keys_in_nested_maps() {
+        let mut message = Message::WithEmbeds { content: None, embeds: vec![serde_json::json!({ "footer": { "text": "text", "icon": "icon.png" } })], plain_fallback: None };
+
+        let result = message.normalize_embeds(true);
+        assert_eq!(result, Err(String::from("embed 1: unknown field `icon`, expected `text` or `icon_url`")));
+    }
+
+    #[test]
+    fn normalize_embeds_should_convert_a_hex_color() {
+        assert_eq!(normalize_color(serde_json::json!("#FF8000")), Ok(serde_json::json!(0xFF8000)));
+    }
+
+    #[test]
+    fn normalize_embeds_should_convert_a_named_color() {
+        assert_eq!(normalize_color(serde_json::json!("Red")), Ok(serde_json::json!(0xFF0000)));
+    }
+
+    #[test]
+    fn normalize_embeds_should_keep_an_integer_color() {
+        assert_eq!(normalize_color(serde_json::json!(255)), Ok(serde_json::json!(255)));
+    }
+
+    #[test]
+    fn normalize_embeds_should_reject_an_invalid_color() {
+        let expected = "embed 1: invalid color `#F00`: expected an integer, a #RRGGBB hex string or one of black, white, gray, red, orange, yellow, green, blue, purple";
+        assert_eq!(normalize_color(serde_json::json!("#F00")), Err(String::from(expected)));
+    }
+
+    #[test]
+    fn normalize_embeds_should_reject_a_too_large_color() {
+        assert_eq!(normalize_color(serde_json::json!(0x1000000)), Err(String::from("embed 1: invalid color 16777216: must not be larger than 16777215")));
+    }
+
+    #[test]
+    fn replace_placeholders_should_replace_the_content_and_the_texts_of_the_embeds() {
+        let message = Message::WithEmbeds {
+            content: Some(String::from("Day #{{run_number}}")),
+            embeds: vec![serde_json::json!({ "title": "{{date}}", "fields": [{ "name": "posts", "value": "{{total_posts}}" }], "color": 255 })],
+            plain_fallback: Some(String::from("Day #{{run_number}} {{unknown}}")),
+        };
+        let placeholders = vec![("run_number", String::from("3")), ("total_posts", String::from("5")), ("date", String::from("2021-09-01"))];
+
+        let expected = Message::WithEmbeds {
+            content: Some(String::from("Day #3")),
+            embeds: vec![serde_json::json!({ "title": "2021-09-01", "fields": [{ "name": "posts", "value": "5" }], "color": 255 })],
+            plain_fallback: Some(String::from("Day #3 {{unknown}}")),
+        };
+        assert_eq!(message.replace_placeholders(&placeholders), expected);
+    }
+
+    #[test]
+    fn transformers_should_change_only_the_existing_texts() {
+        let prefix = Transformer::Prefix { text: String::from("[tip] ") };
+        let suffix = Transformer::Suffix { text: String::from(" #daily") };
+
+        let mut message = Message::String(String::from("message"));
+        prefix.apply(&mut message);
+        suffix.apply(&mut message);
+        assert_eq!(message, Message::String(String::from("[tip] message #daily")));
+
+        let mut message = Message::WithEmbeds { content: None, embeds: vec![serde_json::json!({ "title": "title" })], plain_fallback: Some(String::from("fallback")) };
+        prefix.apply(&mut message);
+        let expected = Message::WithEmbeds { content: None, embeds: vec![serde_json::json!({ "title": "title" })], plain_fallback: Some(String::from("[tip] fallback")) };
+        assert_eq!(message, expected);
+    }
+
+    #[test]
+    fn combine_should_join_the_contents_and_keep_every_embed() {
+        let strings = vec![Message::String(String::from("tip1")), Message::String(String::from("tip2"))];
+        assert_eq!(Message::combine(&strings, "\n"), Message::String(String::from("tip1\ntip2")));
+
+        let messages = vec![
+            Message::String(String::from("tip1")),
+            Message::WithEmbeds { content: None, embeds: vec![serde_json::json!({ "title": "a" })], plain_fallback: Some(String::from("fallback")) },
+            Message::WithEmbeds { content: Some(String::from("tip3")), embeds: vec![serde_json::json!({ "title": "b" })], plain_fallback: None },
+        ];
+        let expected = Message::WithEmbeds {
+            content: Some(String::from("tip1\n\ntip3")),
+            embeds: vec![serde_json::json!({ "title": "a" }), serde_json::json!({ "title": "b" })],
+            plain_fallback: Some(String::from("tip1\n\nfallback\n\ntip3")),
+        };
+        assert_eq!(Message::combine(&messages, "\n\n"), expected);
+    }
+
+    fn normalize_color(color: Value) -> Result<Value, String> {
+        let mut message = Message::WithEmbeds { content: None, embeds: vec![serde_json::json!({ "color": color })], plain_fallback: None };
+        let mut strict_message = message.clone();
+        strict_message.normalize_embeds(true)?;
+        message.normalize_embeds(false)?;
+        assert_eq!(message, strict_message);
+        Ok(message.embeds()[0]["color"].clone())
     }
 
     fn to_json_value(s: &str) -> Value {
@@ -0,0 +1,78 @@
+use std::io::Write;
+use std::path::Path;
+
+use super::file::write_atomically;
+
+pub fn write_metrics(path: &Path, counts: &[(&String, u64)], last_status: u16) -> Result<(), String> {
+    let metrics = format_metrics(counts, last_status);
+    write_atomically(path, |file_writer| {
+        file_writer.write_all(metrics.as_bytes()).map_err(|e| format!("failed to write metrics: {}", e))
+    })
+}
+
+fn format_metrics(counts: &[(&String, u64)], last_status: u16) -> String {
+    let mut sorted_counts = counts.to_vec();
+    sorted_counts.sort();
+    let total_posts = counts.iter().fold(0, |sum: u64, (_, count)| sum.saturating_add(*count));
+
+    let mut lines = vec![
+        String::from("# HELP random_poster_total_posts Total number of posted messages."),
+        String::from("# TYPE random_poster_total_posts counter"),
+        format!("random_poster_total_posts {}", total_posts),
+        String::from("# HELP random_poster_pick_count Number of times each message has been picked."),
+        String::from("# TYPE random_poster_pick_count counter"),
+    ];
+    for (message_id, count) in sorted_counts {
+        lines.push(format!("random_poster_pick_count{{message=\"{}\"}} {}", escape_label_value(message_id), count));
+    }
+    lines.push(String::from("# HELP random_poster_last_status HTTP status of the last post."));
+    lines.push(String::from("# TYPE random_poster_last_status gauge"));
+    lines.push(format!("random_poster_last_status {}", last_status));
+
+    lines.join("\n") + "\n"
+}
+
+fn escape_label_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate indoc;
+    extern crate tempfile;
+
+    use super::*;
+    use indoc::indoc;
+    use tempfile::TempDir;
+
+    #[test]
+    fn write_metrics_should_write_the_metrics_in_the_exposition_format() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("random_poster.prom");
+
+        let abc = String::from("abc");
+        let def = String::from("def");
+        write_metrics(&path, &[(&def, 2), (&abc, 3)], 204).unwrap();
+
+        let expected = indoc! {r#"
+            # HELP random_poster_total_posts Total number of posted messages.
+            # TYPE random_poster_total_posts counter
+            random_poster_total_posts 5
+            # HELP random_poster_pick_count Number of times each message has been picked.
+            # TYPE random_poster_pick_count counter
+            random_poster_pick_count{message="abc"} 3
+            random_poster_pick_count{message="def"} 2
+            # HELP random_poster_last_status HTTP status of the last post.
+            # TYPE random_poster_last_status gauge
+            random_poster_last_status 204
+        "#};
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), expected);
+    }
+
+    #[test]
+    fn format_metrics_should_escape_label_values() {
+        let id = String::from("a\"b\\c\nd");
+        let metrics = format_metrics(&[(&id, 1)], 200);
+        assert!(metrics.contains(r#"random_poster_pick_count{message="a\"b\\c\nd"} 1"#));
+    }
+}
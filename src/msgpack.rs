@@ -0,0 +1,250 @@
+// A minimal MessagePack encoder and decoder for the log, converting from and to `serde_json::Value` so that the log keeps
+// the same serde types in both formats. Binary and extension types are never written, so they are rejected when reading.
+
+use serde_json::{Map, Number, Value};
+use std::convert::TryInto;
+
+pub fn encode(value: &Value) -> Vec<u8> {
+    let mut output = Vec::new();
+    write_value(&mut output, value);
+    output
+}
+
+fn write_value(output: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Null => output.push(0xc0),
+        Value::Bool(false) => output.push(0xc2),
+        Value::Bool(true) => output.push(0xc3),
+        Value::Number(number) => write_number(output, number),
+        Value::String(s) => {
+            write_length(output, s.len(), 0xa0, 31, [0xd9, 0xda, 0xdb]);
+            output.extend(s.as_bytes());
+        },
+        Value::Array(values) => {
+            write_length(output, values.len(), 0x90, 15, [0xdc, 0xdc, 0xdd]);
+            for value in values {
+                write_value(output, value);
+            }
+        },
+        Value::Object(map) => {
+            write_length(output, map.len(), 0x80, 15, [0xde, 0xde, 0xdf]);
+            for (key, value) in map {
+                write_value(output, &Value::String(key.clone()));
+                write_value(output, value);
+            }
+        },
+    }
+}
+
+fn write_number(output: &mut Vec<u8>, number: &Number) {
+    if let Some(value) = number.as_u64() {
+        match value {
+            0..=0x7f => output.push(value as u8),
+            0x80..=0xff => output.extend([0xcc, value as u8]),
+            0x100..=0xffff => { output.push(0xcd); output.extend((value as u16).to_be_bytes()); },
+            0x10000..=0xffff_ffff => { output.push(0xce); output.extend((value as u32).to_be_bytes()); },
+            _ => { output.push(0xcf); output.extend(value.to_be_bytes()); },
+        }
+    } else if let Some(value) = number.as_i64() {
+        // only negative values are left here
+        match value {
+            -32..=-1 => output.push(value as u8),
+            -0x80..=-33 => output.extend([0xd0, value as u8]),
+            -0x8000..=-0x81 => { output.push(0xd1); output.extend((value as i16).to_be_bytes()); },
+            -0x8000_0000..=-0x8001 => { output.push(0xd2); output.extend((value as i32).to_be_bytes()); },
+            _ => { output.push(0xd3); output.extend(value.to_be_bytes()); },
+        }
+    } else {
+        output.push(0xcb);
+        output.extend(number.as_f64().unwrap_or(f64::NAN).to_be_bytes());
+    }
+}
+
+// The markers are for 8, 16 and 32 bit lengths, where arrays and maps have no 8 bit form and use the 16 bit one.
+fn write_length(output: &mut Vec<u8>, length: usize, fix_marker: u8, max_fix_length: usize, markers: [u8; 3]) {
+    if length <= max_fix_length {
+        output.push(fix_marker | length as u8);
+    } else if length <= 0xff && markers[0] != markers[1] {
+        output.extend([markers[0], length as u8]);
+    } else if length <= 0xffff {
+        output.push(markers[1]);
+        output.extend((length as u16).to_be_bytes());
+    } else {
+        output.push(markers[2]);
+        output.extend((length as u32).to_be_bytes());
+    }
+}
+
+pub fn decode(data: &[u8]) -> Result<Value, String> {
+    let mut reader = Reader { data, position: 0 };
+    let value = reader.read_value()?;
+    if reader.position != data.len() {
+        return Err(format!("unexpected data at {}", reader.position));
+    }
+    Ok(value)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl Reader<'_> {
+    fn read_value(&mut self) -> Result<Value, String> {
+        let marker = self.read_bytes::<1>()?[0];
+        match marker {
+            0x00..=0x7f => Ok(Value::from(marker)),
+            0x80..=0x8f => self.read_map((marker & 0x0f) as usize),
+            0x90..=0x9f => self.read_array((marker & 0x0f) as usize),
+            0xa0..=0xbf => self.read_string((marker & 0x1f) as usize),
+            0xc0 => Ok(Value::Null),
+            0xc2 => Ok(Value::Bool(false)),
+            0xc3 => Ok(Value::Bool(true)),
+            0xca => Ok(float(f32::from_be_bytes(self.read_bytes()?) as f64)),
+            0xcb => Ok(float(f64::from_be_bytes(self.read_bytes()?))),
+            0xcc => Ok(Value::from(self.read_bytes::<1>()?[0])),
+            0xcd => Ok(Value::from(u16::from_be_bytes(self.read_bytes()?))),
+            0xce => Ok(Value::from(u32::from_be_bytes(self.read_bytes()?))),
+            0xcf => Ok(Value::from(u64::from_be_bytes(self.read_bytes()?))),
+            0xd0 => Ok(Value::from(i8::from_be_bytes(self.read_bytes()?))),
+            0xd1 => Ok(Value::from(i16::from_be_bytes(self.read_bytes()?))),
+            0xd2 => Ok(Value::from(i32::from_be_bytes(self.read_bytes()?))),
+            0xd3 => Ok(Value::from(i64::from_be_bytes(self.read_bytes()?))),
+            0xd9 => { let length = self.read_bytes::<1>()?[0] as usize; self.read_string(length) },
+            0xda => { let length = u16::from_be_bytes(self.read_bytes()?) as usize; self.read_string(length) },
+            0xdb => { let length = u32::from_be_bytes(self.read_bytes()?) as usize; self.read_string(length) },
+            0xdc => { let length = u16::from_be_bytes(self.read_bytes()?) as usize; self.read_array(length) },
+            0xdd => { let length = u32::from_be_bytes(self.read_bytes()?) as usize; self.read_array(length) },
+            0xde => { let length = u16::from_be_bytes(self.read_bytes()?) as usize; self.read_map(length) },
+            0xdf => { let length = u32::from_be_bytes(self.read_bytes()?) as usize; self.read_map(length) },
+            0xe0..=0xff => Ok(Value::from(marker as i8)),
+            _ => Err(format!("unsupported type 0x{:02x} at {}", marker, self.position - 1)),
+        }
+    }
+
+    fn read_string(&mut self, length: usize) -> Result<Value, String> {
+        let position = self.position;
+        let bytes = self.read_slice(length)?;
+        let s = std::str::from_utf8(bytes).map_err(|_| format!("invalid UTF-8 at {}", position))?;
+        Ok(Value::String(s.to_owned()))
+    }
+
+    // the lengths are not trusted for preallocation, since a corrupt length could be huge
+    fn read_array(&mut self, length: usize) -> Result<Value, String> {
+        let mut values = Vec::new();
+        for _ in 0..length {
+            values.push(self.read_value()?);
+        }
+        Ok(Value::Array(values))
+    }
+
+    fn read_map(&mut self, length: usize) -> Result<Value, String> {
+        let mut map = Map::new();
+        for _ in 0..length {
+            let position = self.position;
+            let key = match self.read_value()? {
+                Value::String(key) => key,
+                _ => return Err(format!("a map key must be a string at {}", position)),
+            };
+            map.insert(key, self.read_value()?);
+        }
+        Ok(Value::Object(map))
+    }
+
+    fn read_bytes<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        Ok(self.read_slice(N)?.try_into().unwrap())
+    }
+
+    fn read_slice(&mut self, length: usize) -> Result<&[u8], String> {
+        let end = self.position.checked_add(length).filter(|end| *end <= self.data.len()).ok_or("unexpected end of the data")?;
+        let slice = &self.data[self.position..end];
+        self.position = end;
+        Ok(slice)
+    }
+}
+
+// JSON has no NaN or infinity, so they are read as null like `serde_json` writes them
+fn float(value: f64) -> Value {
+    Number::from_f64(value).map_or(Value::Null, Value::Number)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_should_use_the_smallest_forms() {
+        assert_eq!(encode(&serde_json::json!(1)), vec![0x01]);
+        assert_eq!(encode(&serde_json::json!(-1)), vec![0xff]);
+        assert_eq!(encode(&serde_json::json!(300)), vec![0xcd, 0x01, 0x2c]);
+        assert_eq!(encode(&serde_json::json!("abc")), vec![0xa3, b'a', b'b', b'c']);
+        assert_eq!(encode(&serde_json::json!({ "a": [true, null] })), vec![0x81, 0xa1, b'a', 0x92, 0xc3, 0xc0]);
+    }
+
+    // the byte sequences of the MessagePack specification, which do not depend on this encoder
+    #[test]
+    fn encode_and_decode_should_match_the_formats_of_the_specification() {
+        let fixtures: Vec<(Value, Vec<u8>)> = vec![
+            (serde_json::json!(null), vec![0xc0]),
+            (serde_json::json!(false), vec![0xc2]),
+            (serde_json::json!(true), vec![0xc3]),
+            (serde_json::json!(127), vec![0x7f]),
+            (serde_json::json!(128), vec![0xcc, 0x80]),
+            (serde_json::json!(256), vec![0xcd, 0x01, 0x00]),
+            (serde_json::json!(65536), vec![0xce, 0x00, 0x01, 0x00, 0x00]),
+            (serde_json::json!(4294967296u64), vec![0xcf, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00]),
+            (serde_json::json!(-32), vec![0xe0]),
+            (serde_json::json!(-33), vec![0xd0, 0xdf]),
+            (serde_json::json!(-129), vec![0xd1, 0xff, 0x7f]),
+            (serde_json::json!(-32769), vec![0xd2, 0xff, 0xff, 0x7f, 0xff]),
+            (serde_json::json!(-2147483649i64), vec![0xd3, 0xff, 0xff, 0xff, 0xff, 0x7f, 0xff, 0xff, 0xff]),
+            // a float stays a float even if it has no fraction
+            (serde_json::json!(1.0), vec![0xcb, 0x3f, 0xf0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]),
+            (serde_json::json!(1.5), vec![0xcb, 0x3f, 0xf8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]),
+            (serde_json::json!(""), vec![0xa0]),
+            (serde_json::json!([]), vec![0x90]),
+            (serde_json::json!({}), vec![0x80]),
+            // the example on msgpack.org
+            (serde_json::json!({ "compact": true, "schema": 0 }), vec![0x82, 0xa7, b'c', b'o', b'm', b'p', b'a', b'c', b't', 0xc3, 0xa6, b's', b'c', b'h', b'e', b'm', b'a', 0x00]),
+        ];
+        for (value, bytes) in fixtures {
+            assert_eq!(encode(&value), bytes, "{}", value);
+            assert_eq!(decode(&bytes), Ok(value));
+        }
+    }
+
+    #[test]
+    fn encode_should_use_the_8_and_16_bit_lengths_of_the_specification() {
+        assert_eq!(encode(&serde_json::json!("a".repeat(32)))[..2], [0xd9, 0x20]);
+        assert_eq!(encode(&serde_json::json!("a".repeat(256)))[..3], [0xda, 0x01, 0x00]);
+        assert_eq!(encode(&serde_json::json!(vec![0; 16]))[..3], [0xdc, 0x00, 0x10]);
+        let map: Map<String, Value> = (0..16).map(|index| (format!("{:02}", index), Value::Null)).collect();
+        assert_eq!(encode(&Value::Object(map))[..3], [0xde, 0x00, 0x10]);
+    }
+
+    #[test]
+    fn decode_should_read_a_float32_of_the_specification() {
+        assert_eq!(decode(&[0xca, 0x3f, 0xc0, 0x00, 0x00]), Ok(serde_json::json!(1.5)));
+    }
+
+    #[test]
+    fn decode_should_read_what_encode_writes() {
+        let value = serde_json::json!({
+            "small": [0, 127, 128, 255, 256, 65535, 65536, 4294967295u64, 4294967296u64, 18446744073709551615u64],
+            "negative": [-1, -32, -33, -128, -129, -32768, -32769, -2147483648i64, -2147483649i64, -9223372036854775808i64],
+            "float": 0.5,
+            "string": "a".repeat(40),
+            "long": "b".repeat(300),
+            "array": vec![1; 20],
+            "unicode": "メッセージ",
+        });
+        assert_eq!(decode(&encode(&value)), Ok(value));
+    }
+
+    #[test]
+    fn decode_should_fail_on_truncated_or_trailing_data() {
+        assert_eq!(decode(&[0xa3, b'a']), Err(String::from("unexpected end of the data")));
+        assert_eq!(decode(&[0x01, 0x02]), Err(String::from("unexpected data at 1")));
+        assert_eq!(decode(&[0xc4, 0x00]), Err(String::from("unsupported type 0xc4 at 0")));
+    }
+}
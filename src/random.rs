@@ -1,26 +1,168 @@
+use chrono::{DateTime, Utc};
 use rand::distributions::WeightedIndex;
 use rand::prelude::Distribution;
-use rand::rngs::ThreadRng;
+use rand::rngs::{StdRng, ThreadRng};
+use rand::{RngCore, SeedableRng};
 use serde::{de::DeserializeOwned, Serialize, Deserialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::hash::Hash;
 use std::fs::File;
-use std::io::{BufReader, BufWriter};
+use std::io::{BufReader, Write};
 use std::path::{Path, PathBuf};
 
-use super::weight::WeightType;
+use super::file::write_atomically;
+use super::msgpack;
+use super::settings::HumanDuration;
+use super::weight::{Count, WeightType};
 
 #[derive(Clone)]
 pub struct RandomPicker<T> {
     items: Vec<RandomPickerItem<T>>,
     path: PathBuf,
     weight_type: WeightType,
+    rng: Option<StdRng>,
+    state: LogState,
+    groups: HashMap<T, String>,
+    group_weight_types: HashMap<String, WeightType>,
+    new_values: HashSet<T>,
+    excluded_values: HashSet<T>,
+    weight_multipliers: HashMap<T, f64>,
+    targets: HashMap<T, f64>,
+    // divides the counts used for the weights, so that an important value seems to be picked less often
+    importances: HashMap<T, f64>,
+    cooldown: usize,
+    recent_values: VecDeque<T>,
+    max_ratio: Option<f64>,
+    epsilon: f64,
+    group_smoothing: f64,
+    penalize_failures: bool,
+    normalize_counts: bool,
+    stale_boost: Option<StaleBoost>,
+    // the current time given to the builder, which decides both the stale values and the oldest ones for OldestFirst
+    now: Option<DateTime<Utc>>,
+    log_format: LogFormat,
+    // why the log is reset by `OnCorruptLog::Reset`, for the caller to report
+    corrupt_log: Option<String>,
 }
 
-#[derive(PartialEq, Eq, Clone, Serialize, Deserialize, Debug)]
-struct RandomPickerItem<T> {
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+pub struct RandomPickerItem<T> {
     value: T,
-    count: u32,
+    count: u64,
+    #[serde(default, flatten, skip_serializing_if = "Option::is_none")]
+    boost: Option<Boost>,
+    // the number of failed posts since the last successful post
+    #[serde(default, skip_serializing_if = "is_zero")]
+    fail_count: u64,
+    // recorded only for the messages with their own minimum interval, or for every message with a stale boost or OldestFirst
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_posted_at: Option<DateTime<Utc>>,
+    // never picked again in the grab bag mode, after it is posted once
+    #[serde(default, skip_serializing_if = "is_false")]
+    retired: bool,
+}
+
+fn is_zero(value: &u64) -> bool {
+    *value == 0
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+/// A temporary boost which multiplies the weight by `1 + boost` and is multiplied by `boost_decay` after each pick.
+#[derive(PartialEq, Clone, Copy, Serialize, Deserialize, Debug)]
+pub struct Boost {
+    pub boost: f64,
+    pub boost_decay: f64,
+}
+
+impl Boost {
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self.boost.is_nan() || self.boost < 0.0 {
+            return Err("boost must be positive");
+        }
+        if !(0.0..=1.0).contains(&self.boost_decay) {
+            return Err("boost_decay must be between 0 and 1");
+        }
+        Ok(())
+    }
+}
+
+/// Multiplies the weight by `factor` for the values not posted within `after`, or never posted.
+#[derive(PartialEq, Clone, Copy, Deserialize, Debug)]
+pub struct StaleBoost {
+    pub after: HumanDuration,
+    pub factor: f64,
+}
+
+impl StaleBoost {
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if !self.factor.is_finite() || self.factor < 0.0 {
+            return Err("factor must be finite and non-negative");
+        }
+        Ok(())
+    }
+
+    fn is_stale(&self, last_posted_at: Option<DateTime<Utc>>, now: DateTime<Utc>) -> bool {
+        let after = self.after.to_chrono();
+        last_posted_at.is_none_or(|last_posted_at| now.signed_duration_since(last_posted_at) >= after)
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Default, Serialize, Deserialize, Debug)]
+pub struct LogState {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_posted_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_message_id: Option<String>,
+    // the index of the destination which the last message is posted to, where it is replied to or edited
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_destination_index: Option<usize>,
+    // the number of runs with a seed, which advances the seed so that each run continues the sequence instead of repeating it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seeded_runs: Option<u64>,
+    // when the next post is expected, for external schedulers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_post_after: Option<DateTime<Utc>>,
+    // the number of runs with a successful post, for `{{run_number}}`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_number: Option<u64>,
+    // the number of successful posts, for `{{total_posts}}`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_posts: Option<u64>,
+    // the values posted in the last runs, for the messages which exclude each other
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub recent_picks: Vec<RecentPick>,
+}
+
+#[derive(PartialEq, Eq, Clone, Serialize, Deserialize, Debug)]
+pub struct RecentPick {
+    // the run number of the run which posted the value, counting that run
+    pub run_number: u64,
+    pub value: String,
+}
+
+const LOG_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+struct LogRef<'a, T> {
+    version: u32,
+    #[serde(flatten)]
+    state: &'a LogState,
+    items: Vec<&'a RandomPickerItem<T>>,
+    #[serde(skip_serializing_if = "VecDeque::is_empty")]
+    recent_values: &'a VecDeque<T>,
+}
+
+#[derive(Deserialize)]
+struct Log<T> {
+    version: u32,
+    #[serde(flatten)]
+    state: LogState,
+    items: Vec<RandomPickerItem<T>>,
+    #[serde(default = "VecDeque::new")]
+    recent_values: VecDeque<T>,
 }
 
 #[derive(PartialEq, Eq, Clone, Copy, Deserialize, Debug)]
@@ -28,289 +170,2235 @@ pub enum InitialCountType {
     Zero,
     Min,
     Max,
+    Average,
 }
 
 impl InitialCountType {
     pub fn default() -> InitialCountType {
         InitialCountType::Zero
     }
-}
+}
+
+/// What to do if the log is not valid JSON; `Reset` moves it to `<log>.corrupt` and starts over from an empty log, which is written on the next `write_log`.
+#[derive(PartialEq, Eq, Clone, Copy, Deserialize, Debug)]
+pub enum OnCorruptLog {
+    Fail,
+    Reset,
+}
+
+impl OnCorruptLog {
+    pub fn default() -> OnCorruptLog {
+        OnCorruptLog::Fail
+    }
+}
+
+/// The encoding of the log file, which is the same envelope in either format.
+#[derive(PartialEq, Eq, Clone, Copy, Deserialize, Debug)]
+pub enum LogFormat {
+    Json,
+    MessagePack,
+}
+
+impl LogFormat {
+    pub fn default() -> LogFormat {
+        LogFormat::Json
+    }
+}
+
+pub struct RandomPickerBuilder {
+    weight_type: WeightType,
+    initial_count_type: InitialCountType,
+    seed: Option<u64>,
+    avoid_repeat: bool,
+    cooldown: usize,
+    max_ratio: Option<f64>,
+    epsilon: f64,
+    group_smoothing: f64,
+    on_corrupt_log: OnCorruptLog,
+    penalize_failures: bool,
+    normalize_counts: bool,
+    log_format: LogFormat,
+    now: Option<DateTime<Utc>>,
+}
+
+impl Default for RandomPickerBuilder {
+    fn default() -> RandomPickerBuilder {
+        RandomPickerBuilder {
+            weight_type: WeightType::Uniform,
+            initial_count_type: InitialCountType::default(),
+            seed: None,
+            avoid_repeat: false,
+            cooldown: 0,
+            max_ratio: None,
+            epsilon: 0.0,
+            group_smoothing: 0.0,
+            on_corrupt_log: OnCorruptLog::default(),
+            penalize_failures: false,
+            normalize_counts: false,
+            log_format: LogFormat::default(),
+            now: None,
+        }
+    }
+}
+
+impl RandomPickerBuilder {
+    pub fn new() -> RandomPickerBuilder {
+        RandomPickerBuilder::default()
+    }
+
+    pub fn weight_type(mut self, weight_type: WeightType) -> RandomPickerBuilder {
+        self.weight_type = weight_type;
+        self
+    }
+
+    pub fn initial_count_type(mut self, initial_count_type: InitialCountType) -> RandomPickerBuilder {
+        self.initial_count_type = initial_count_type;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> RandomPickerBuilder {
+        self.seed = Some(seed);
+        self
+    }
+
+    // Never picks the last picked value again, unless it is the only one which can be picked.
+    pub fn avoid_repeat(mut self, avoid_repeat: bool) -> RandomPickerBuilder {
+        self.avoid_repeat = avoid_repeat;
+        self
+    }
+
+    // Never picks the values picked in the last `cooldown` picks, unless there are no other values which can be picked.
+    pub fn cooldown(mut self, cooldown: usize) -> RandomPickerBuilder {
+        self.cooldown = cooldown;
+        self
+    }
+
+    // Raises small weights so that no weight is more than `max_ratio` times as large as another nonzero weight.
+    pub fn max_ratio(mut self, max_ratio: f64) -> RandomPickerBuilder {
+        self.max_ratio = Some(max_ratio);
+        self
+    }
+
+    // Added to every weight after infinite weights are replaced by 1 and the others by 0, so that no value has zero probability.
+    pub fn epsilon(mut self, epsilon: f64) -> RandomPickerBuilder {
+        self.epsilon = epsilon;
+        self
+    }
+
+    // Smooths the probabilities of the groups like a Dirichlet prior of `alpha` posts in every group, as `(n * p + alpha) / (n + k * alpha)`
+    // for `n` posts in `k` groups, so that the groups are picked more evenly while there are few posts.
+    pub fn group_smoothing(mut self, alpha: f64) -> RandomPickerBuilder {
+        self.group_smoothing = alpha;
+        self
+    }
+
+    pub fn on_corrupt_log(mut self, on_corrupt_log: OnCorruptLog) -> RandomPickerBuilder {
+        self.on_corrupt_log = on_corrupt_log;
+        self
+    }
+
+    // Multiplies the weight by `1 / (1 + fail_count)`, where `fail_count` is counted by `RandomPicker::record_failure`.
+    pub fn penalize_failures(mut self, penalize_failures: bool) -> RandomPickerBuilder {
+        self.penalize_failures = penalize_failures;
+        self
+    }
+
+    // Replaces each count by its per-thousand share of the total count before weighting, so that the weights depend on the shares
+    // rather than the number of posts so far.
+    pub fn normalize_counts(mut self, normalize_counts: bool) -> RandomPickerBuilder {
+        self.normalize_counts = normalize_counts;
+        self
+    }
+
+    pub fn log_format(mut self, log_format: LogFormat) -> RandomPickerBuilder {
+        self.log_format = log_format;
+        self
+    }
+
+    // The current time, which is required by OldestFirst and `RandomPicker::set_stale_boost`.
+    pub fn now(mut self, now: DateTime<Utc>) -> RandomPickerBuilder {
+        self.now = Some(now);
+        self
+    }
+
+    pub fn build<T: Hash + Eq + Clone + Serialize + DeserializeOwned, P: AsRef<Path>>(self, path: P, values: Vec<T>) -> Result<RandomPicker<T>, String> {
+        if values.is_empty() {
+            return Err(String::from("values is empty"));
+        }
+        let cooldown = self.effective_cooldown();
+        let weight_type = self.weight_type;
+        weight_type.validate()?;
+        check_now(&weight_type, self.now)?;
+        if self.max_ratio.is_some_and(|max_ratio| max_ratio.is_nan() || max_ratio < 1.0) {
+            return Err(String::from("max_ratio must be at least 1"));
+        }
+        if !self.epsilon.is_finite() || self.epsilon < 0.0 {
+            return Err(String::from("epsilon must be finite and non-negative"));
+        }
+        if !self.group_smoothing.is_finite() || self.group_smoothing < 0.0 {
+            return Err(String::from("group_smoothing must be finite and non-negative"));
+        }
+
+        let path_buf = path.as_ref().to_owned();
+        let mut corrupt_log = None;
+        let log = if path_buf.exists() {
+            match read_log::<T>(&path_buf, self.log_format) {
+                Ok(log) => Some(log),
+                Err(LogError::Corrupt(message)) if self.on_corrupt_log == OnCorruptLog::Reset => {
+                    let mut corrupt_path = path_buf.clone().into_os_string();
+                    corrupt_path.push(".corrupt");
+                    let corrupt_path = PathBuf::from(corrupt_path);
+                    std::fs::rename(&path_buf, &corrupt_path).map_err(|e| format!("failed to move the corrupt log to {}: {}", corrupt_path.display(), e))?;
+                    corrupt_log = Some(format!("{}, so the log is moved to {} and reset", message, corrupt_path.display()));
+                    None
+                },
+                Err(e) => return Err(e.into()),
+            }
+        } else {
+            None
+        };
+
+        // a missing log is the same as an empty one
+        let Log { mut state, items: log, mut recent_values, .. } = log.unwrap_or(Log { version: LOG_VERSION, state: LogState::default(), items: Vec::new(), recent_values: VecDeque::new() });
+        let initial_count = match self.initial_count_type {
+            InitialCountType::Zero => 0,
+            InitialCountType::Min => log.iter().map(|item| item.count).min().unwrap_or(0),
+            InitialCountType::Max => log.iter().map(|item| item.count).max().unwrap_or(0),
+            InitialCountType::Average if log.is_empty() => 0,
+            InitialCountType::Average => {
+                let len = log.len() as u128;
+                let sum: u128 = log.iter().map(|item| item.count as u128).sum();
+                ((sum + len / 2) / len) as u64
+            },
+        };
+        let values_set: HashSet<T> = values.iter().cloned().collect();
+        let mut log_map: HashMap<T, RandomPickerItem<T>> = log.into_iter().map(|item| (item.value.clone(), item)).collect();
+        let new_values = values.iter().filter(|value| !log_map.contains_key(value)).cloned().collect();
+        let value_into_item = |value| {
+            log_map.remove(&value).unwrap_or(RandomPickerItem { value, count: initial_count, boost: None, fail_count: 0, last_posted_at: None, retired: false })
+        };
+        let items = values.into_iter().map(value_into_item).collect();
+
+        let rng = self.seed.map(|seed| {
+            let seeded_runs = state.seeded_runs.unwrap_or(0);
+            state.seeded_runs = Some(seeded_runs.saturating_add(1));
+            seeded_rng(seed, seeded_runs)
+        });
+        recent_values.retain(|value| values_set.contains(value));
+        recent_values.truncate(cooldown);
+        Ok(RandomPicker {
+            items,
+            path: path_buf,
+            weight_type,
+            rng,
+            state,
+            groups: HashMap::new(),
+            group_weight_types: HashMap::new(),
+            new_values,
+            excluded_values: HashSet::new(),
+            weight_multipliers: HashMap::new(),
+            targets: HashMap::new(),
+            importances: HashMap::new(),
+            cooldown,
+            recent_values,
+            max_ratio: self.max_ratio,
+            epsilon: self.epsilon,
+            group_smoothing: self.group_smoothing,
+            penalize_failures: self.penalize_failures,
+            normalize_counts: self.normalize_counts,
+            stale_boost: None,
+            now: self.now,
+            log_format: self.log_format,
+            corrupt_log,
+        })
+    }
+
+    fn effective_cooldown(&self) -> usize {
+        if self.avoid_repeat { self.cooldown.max(1) } else { self.cooldown }
+    }
+}
+
+impl<T: Hash + Eq + Clone + Serialize + DeserializeOwned> RandomPicker<T> {
+    // a shorthand of `RandomPickerBuilder` for the tests, which set only these options
+    #[cfg(test)]
+    pub fn from_log_file<P: AsRef<Path>>(path: P, values: Vec<T>, weight_type: WeightType, initial_count_type: InitialCountType) -> Result<RandomPicker<T>, String> {
+        RandomPickerBuilder::new()
+            .weight_type(weight_type)
+            .initial_count_type(initial_count_type)
+            .build(path, values)
+    }
+
+    pub fn set_groups(&mut self, groups: HashMap<T, String>) {
+        self.groups = groups;
+    }
+
+    pub fn set_group_weight_types(&mut self, group_weight_types: HashMap<String, WeightType>) -> Result<(), String> {
+        for weight_type in group_weight_types.values() {
+            weight_type.validate()?;
+            check_now(weight_type, self.now)?;
+        }
+        self.group_weight_types = group_weight_types;
+        Ok(())
+    }
+
+    // Moves every count toward the minimum by `rate` percent, so that old picks matter less than recent ones.
+    pub fn decay_counts(&mut self, rate: f64) -> Result<(), String> {
+        if !(0.0..=100.0).contains(&rate) {
+            return Err(String::from("decay_rate must be between 0 and 100"));
+        }
+
+        let min_count = self.items.iter().map(|item| item.count).min().unwrap();
+        let factor = 1.0 - rate / 100.0;
+        for item in &mut self.items {
+            let difference = item.count - min_count;
+            // rounded so that a small rate does not wipe out the small differences at once
+            item.count = min_count + (difference as f64 * factor).round() as u64;
+        }
+        Ok(())
+    }
+
+    // Excluded values are never picked, but their counts are kept in the log.
+    pub fn set_excluded_values(&mut self, excluded_values: HashSet<T>) {
+        self.excluded_values = excluded_values;
+    }
+
+    // The weights of the values in a group are multiplied by these before being normalized, and values missing from them keep their weights.
+    pub fn set_weight_multipliers(&mut self, weight_multipliers: HashMap<T, f64>) {
+        self.weight_multipliers = weight_multipliers;
+    }
+
+    // Multiplies the multipliers which are already set, such as by the time of the post.
+    pub fn extend_weight_multipliers(&mut self, weight_multipliers: HashMap<T, f64>) {
+        for (value, multiplier) in weight_multipliers {
+            *self.weight_multipliers.entry(value).or_insert(1.0) *= multiplier;
+        }
+    }
+
+    // The long-run fractions for `WeightType::Target`, and values missing from them are picked only if no other values can be picked.
+    pub fn set_targets(&mut self, targets: HashMap<T, f64>) {
+        self.targets = targets;
+    }
+
+    pub fn set_importances(&mut self, importances: HashMap<T, f64>) {
+        self.importances = importances;
+    }
+
+    pub fn excluded_values(&self) -> &HashSet<T> {
+        &self.excluded_values
+    }
+
+    pub fn has_eligible_values(&self) -> bool {
+        self.items.iter().any(|item| !self.excluded_values.contains(&item.value))
+    }
+
+    // Overrides the initial counts of the values which are not in the log, and the values in the log keep their counts.
+    pub fn set_new_value_counts(&mut self, counts: HashMap<T, u64>) {
+        let new_values = &self.new_values;
+        for item in self.items.iter_mut().filter(|item| new_values.contains(&item.value)) {
+            if let Some(count) = counts.get(&item.value) {
+                item.count = *count;
+            }
+        }
+    }
+
+    // Records a value which is not in the log yet, either an item which is new in this run or a new item, with the given count.
+    pub fn add_item(&mut self, value: T, count: u64) -> Result<(), String> {
+        match self.items.iter_mut().find(|item| item.value == value) {
+            Some(_) if !self.new_values.contains(&value) => return Err(String::from("the value is already in the log")),
+            Some(item) => item.count = count,
+            None => self.items.push(RandomPickerItem { value: value.clone(), count, boost: None, fail_count: 0, last_posted_at: None, retired: false }),
+        }
+        self.new_values.remove(&value);
+        Ok(())
+    }
+
+    // Returns false if the value is not in the log, where the items which are new in this run are not in the log yet.
+    pub fn remove_item(&mut self, value: &T) -> bool {
+        if self.new_values.contains(value) {
+            return false;
+        }
+        let Some(index) = self.items.iter().position(|item| item.value == *value) else {
+            return false;
+        };
+        self.items.remove(index);
+        self.recent_values.retain(|recent_value| recent_value != value);
+        true
+    }
+
+    pub fn set_new_value_boost(&mut self, boost: Boost) -> Result<(), String> {
+        boost.validate()?;
+        let new_values = &self.new_values;
+        for item in self.items.iter_mut().filter(|item| new_values.contains(&item.value)) {
+            item.boost = Some(boost);
+        }
+        Ok(())
+    }
+
+    // Only the values whose times are recorded by `record_posted_at` can be fresh, by the time given to the builder.
+    pub fn set_stale_boost(&mut self, stale_boost: StaleBoost) -> Result<(), String> {
+        stale_boost.validate()?;
+        if self.now.is_none() {
+            return Err(String::from("stale_boost needs the current time"));
+        }
+        self.stale_boost = Some(stale_boost);
+        Ok(())
+    }
+
+    pub fn write_log(&self) -> Result<(), String> where T: Ord {
+        let log = LogRef { version: LOG_VERSION, state: &self.state, items: sorted_items(&self.items), recent_values: &self.recent_values };
+        write_log_file(&self.path, &log, self.log_format)
+    }
+
+    pub fn state(&self) -> &LogState {
+        &self.state
+    }
+
+    pub fn corrupt_log(&self) -> Option<&str> {
+        self.corrupt_log.as_deref()
+    }
+
+    pub fn state_mut(&mut self) -> &mut LogState {
+        &mut self.state
+    }
+
+    pub fn pick(&mut self) -> Result<&T, String> {
+        match self.rng.take() {
+            Some(mut rng) => {
+                let picked_index = self.sample_index(&mut rng);
+                self.rng = Some(rng);
+                Ok(self.consume(picked_index?))
+            },
+            None => self.pick_with_rng(&mut ThreadRng::default()),
+        }
+    }
+
+    // Runs `f` with the seeded random number generator if any, so that a seed also fixes the other random choices in a post.
+    pub fn with_rng<R, F: FnOnce(&mut dyn RngCore) -> R>(&mut self, f: F) -> R {
+        match &mut self.rng {
+            Some(rng) => f(rng),
+            None => f(&mut ThreadRng::default()),
+        }
+    }
+
+    // Consumes `value` as if it were picked, or returns None if it is not in the picker.
+    pub fn force_pick(&mut self, value: &T) -> Option<&T> {
+        let index = self.items.iter().position(|item| item.value == *value)?;
+        Some(self.consume(index))
+    }
+
+    pub fn pick_with_rng(&mut self, rng: &mut dyn RngCore) -> Result<&T, String> {
+        let picked_index = self.sample_index(rng)?;
+        Ok(self.consume(picked_index))
+    }
+
+    fn sample_index(&self, rng: &mut dyn RngCore) -> Result<usize, String> {
+        let weighted_index = WeightedIndex::new(self.weights()).map_err(|e| format!("failed to pick a message: {}", e))?;
+        Ok(weighted_index.sample(rng))
+    }
+
+    fn consume(&mut self, picked_index: usize) -> &T {
+        for boost in self.items.iter_mut().filter_map(|item| item.boost.as_mut()) {
+            boost.boost *= boost.boost_decay;
+        }
+
+        if self.cooldown > 0 {
+            self.recent_values.push_front(self.items[picked_index].value.clone());
+            self.recent_values.truncate(self.cooldown);
+        }
+
+        let item = self.items.get_mut(picked_index).unwrap();
+        item.count = item.count.saturating_add(1);
+        // a pick which is kept means a successful post, since a failed post restores the picker before the pick
+        item.fail_count = 0;
+
+        &item.value
+    }
+
+    pub fn record_posted_at(&mut self, value: &T, posted_at: DateTime<Utc>) {
+        if let Some(item) = self.items.iter_mut().find(|item| item.value == *value) {
+            item.last_posted_at = Some(posted_at);
+        }
+    }
+
+    pub fn last_posted_at(&self, value: &T) -> Option<DateTime<Utc>> {
+        self.items.iter().find(|item| item.value == *value).and_then(|item| item.last_posted_at)
+    }
+
+    pub fn retire(&mut self, value: &T) {
+        if let Some(item) = self.items.iter_mut().find(|item| item.value == *value) {
+            item.retired = true;
+        }
+    }
+
+    pub fn is_retired(&self, value: &T) -> bool {
+        self.items.iter().any(|item| item.value == *value && item.retired)
+    }
+
+    pub fn record_failure(&mut self, value: &T) {
+        if let Some(item) = self.items.iter_mut().find(|item| item.value == *value) {
+            item.fail_count = item.fail_count.saturating_add(1);
+        }
+    }
+
+    pub fn counts(&self) -> Vec<(&T, u64)> {
+        self.items.iter().map(|item| (&item.value, item.count)).collect()
+    }
+
+    // The probabilities which `pick` uses, computed without changing the counts or the random number generator.
+    pub fn probabilities(&self) -> Vec<(&T, f64)> {
+        let probabilities = to_probabilities(self.weights());
+        self.items.iter().zip(probabilities).map(|(item, probability)| (&item.value, probability)).collect()
+    }
+
+    fn weights(&self) -> Vec<f64> {
+        let weights = self.unboosted_weights();
+        let boosted_weights = bound_weights(self.items.iter().zip(weights).map(|(item, weight)| match item.boost {
+            Some(boost) => weight * (1.0 + boost.boost),
+            None => weight,
+        }).collect());
+        match self.max_ratio {
+            Some(max_ratio) => clamp_weight_ratio(boosted_weights, max_ratio),
+            None => boosted_weights,
+        }
+    }
+
+    // Picking a group and then an item in it is equivalent to picking an item by the product of both probabilities.
+    fn unboosted_weights(&self) -> Vec<f64> {
+        let groups = self.group_indices();
+        if groups.len() == 1 {
+            let (group, indices) = &groups[0];
+            let mut weights = vec![0.0; self.items.len()];
+            for (index, weight) in indices.iter().zip(self.group_weights(*group, indices)) {
+                weights[*index] = weight;
+            }
+            return weights;
+        }
+
+        let group_weights = if self.importances.is_empty() {
+            let group_counts: Vec<u64> = groups.iter()
+                .map(|(_, indices)| self.weighted_counts(indices).iter().fold(0, |sum: u64, count| sum.saturating_add(*count)))
+                .collect();
+            self.weight_type.get_weights(&group_counts)
+        } else {
+            let group_counts: Vec<f64> = groups.iter().map(|(_, indices)| self.important_counts(indices).iter().sum()).collect();
+            self.weight_type.get_count_weights(&group_counts)
+        };
+        let group_probabilities = self.smooth_group_probabilities(&groups, to_probabilities(self.floor_weights(normalize_weights(group_weights))));
+
+        let mut weights = vec![0.0; self.items.len()];
+        for ((group, indices), group_probability) in groups.iter().zip(group_probabilities) {
+            let item_probabilities = to_probabilities(self.group_weights(*group, indices));
+            for (index, item_probability) in indices.iter().zip(item_probabilities) {
+                weights[*index] = group_probability * item_probability;
+            }
+        }
+        weights
+    }
+
+    fn smooth_group_probabilities(&self, groups: &[(Option<&String>, Vec<usize>)], probabilities: Vec<f64>) -> Vec<f64> {
+        if self.group_smoothing == 0.0 {
+            return probabilities;
+        }
+        let total_count = groups.iter().flat_map(|(_, indices)| indices).map(|index| self.items[*index].count as f64).sum::<f64>();
+        let total_weight = total_count + groups.len() as f64 * self.group_smoothing;
+        probabilities.into_iter().map(|probability| (total_count * probability + self.group_smoothing) / total_weight).collect()
+    }
+
+    fn group_weights(&self, group: Option<&String>, indices: &[usize]) -> Vec<f64> {
+        let weight_type = group.and_then(|group| self.group_weight_types.get(group)).unwrap_or(&self.weight_type);
+        let raw_weights = if self.importances.is_empty() {
+            self.raw_weights(weight_type, indices, &self.weighted_counts(indices))
+        } else {
+            self.raw_weights(weight_type, indices, &self.important_counts(indices))
+        };
+        let weights = self.floor_weights(normalize_weights(raw_weights));
+        if self.weight_multipliers.is_empty() && !self.penalize_failures && self.stale_boost.is_none() {
+            return weights;
+        }
+
+        let multiplied_weights = indices.iter().zip(weights).map(|(index, weight)| {
+            let item = &self.items[*index];
+            let multiplier = self.weight_multipliers.get(&item.value).unwrap_or(&1.0);
+            let failure_multiplier = if self.penalize_failures { 1.0 / (1.0 + item.fail_count as f64) } else { 1.0 };
+            let stale_multiplier = match (self.stale_boost, self.now) {
+                (Some(stale_boost), Some(now)) if stale_boost.is_stale(item.last_posted_at, now) => stale_boost.factor,
+                _ => 1.0,
+            };
+            weight * multiplier * failure_multiplier * stale_multiplier
+        }).collect();
+        normalize_weights(multiplied_weights)
+    }
+
+    fn raw_weights<C: Count>(&self, weight_type: &WeightType, indices: &[usize], counts: &[C]) -> Vec<f64> {
+        match weight_type {
+            WeightType::Target => self.target_weights(indices, counts),
+            WeightType::OldestFirst => self.oldest_first_weights(indices),
+            _ => weight_type.get_count_weights(counts),
+        }
+    }
+
+    // The share is taken of the counts of every item, including the excluded ones, so that it does not change with the schedules.
+    fn weighted_counts(&self, indices: &[usize]) -> Vec<u64> {
+        let counts = indices.iter().map(|index| self.items[*index].count);
+        if self.normalize_counts {
+            let total_count: u128 = self.items.iter().map(|item| item.count as u128).sum();
+            counts.map(|count| (count as u128 * 1000 + total_count / 2).checked_div(total_count).unwrap_or(0) as u64).collect()
+        } else {
+            counts.collect()
+        }
+    }
+
+    // The counts divided by the importances are kept fractional, so that a count of 1 of a value twice as important still counts as less than 1.
+    fn important_counts(&self, indices: &[usize]) -> Vec<f64> {
+        indices.iter().zip(self.weighted_counts(indices)).map(|(index, count)| match self.importances.get(&self.items[*index].value) {
+            Some(importance) => count as f64 / importance,
+            None => count as f64,
+        }).collect()
+    }
+
+    fn target_weights<C: Count>(&self, indices: &[usize], counts: &[C]) -> Vec<f64> {
+        let total_count: f64 = counts.iter().map(|count| count.as_f64()).sum();
+        indices.iter().zip(counts).map(|(index, count)| {
+            let target = self.targets.get(&self.items[*index].value).unwrap_or(&0.0);
+            let observed_fraction = if total_count == 0.0 { 0.0 } else { count.as_f64() / total_count };
+            (target - observed_fraction).max(0.0)
+        }).collect()
+    }
+
+    // The times after the clock count as just posted, and the never posted values as longer ago than any other.
+    fn oldest_first_weights(&self, indices: &[usize]) -> Vec<f64> {
+        let now = self.now.expect("OldestFirst is checked to have the current time");
+        let staleness: Vec<(bool, chrono::Duration)> = indices.iter()
+            .map(|index| match self.items[*index].last_posted_at {
+                Some(last_posted_at) => (false, now.signed_duration_since(last_posted_at).max(chrono::Duration::zero())),
+                None => (true, chrono::Duration::zero()),
+            })
+            .collect();
+        let max_staleness = staleness.iter().max().unwrap();
+        staleness.iter().map(|staleness| if staleness == max_staleness { 1.0 } else { 0.0 }).collect()
+    }
+
+    fn floor_weights(&self, weights: Vec<f64>) -> Vec<f64> {
+        if self.epsilon == 0.0 {
+            return weights;
+        }
+        weights.into_iter().map(|weight| weight + self.epsilon).collect()
+    }
+
+    fn group_indices(&self) -> Vec<(Option<&String>, Vec<usize>)> {
+        let is_eligible = |item: &RandomPickerItem<T>| !self.excluded_values.contains(&item.value);
+        let is_cooled_down = |item: &RandomPickerItem<T>| !self.recent_values.contains(&item.value);
+        let ignores_cooldown = !self.items.iter().any(|item| is_eligible(item) && is_cooled_down(item));
+
+        let mut groups: Vec<(Option<&String>, Vec<usize>)> = Vec::new();
+        for (index, item) in self.items.iter().enumerate().filter(|(_, item)| is_eligible(item) && (ignores_cooldown || is_cooled_down(item))) {
+            let group = self.groups.get(&item.value);
+            match groups.iter_mut().find(|(name, _)| *name == group) {
+                Some((_, indices)) => indices.push(index),
+                None => groups.push((group, vec![index])),
+            }
+        }
+        groups
+    }
+}
+
+// Seeds with both the seed and the run, so that the runs of one seed never repeat the runs of another.
+fn seeded_rng(seed: u64, run: u64) -> StdRng {
+    let mut rng_seed = <StdRng as SeedableRng>::Seed::default();
+    rng_seed[..8].copy_from_slice(&seed.to_le_bytes());
+    rng_seed[8..16].copy_from_slice(&run.to_le_bytes());
+    StdRng::from_seed(rng_seed)
+}
+
+fn check_now(weight_type: &WeightType, now: Option<DateTime<Utc>>) -> Result<(), String> {
+    if *weight_type == WeightType::OldestFirst && now.is_none() {
+        return Err(String::from("OldestFirst needs the current time"));
+    }
+    Ok(())
+}
+
+fn normalize_weights(raw_weights: Vec<f64>) -> Vec<f64> {
+    if raw_weights.iter().all(|w| *w == 0.0) {
+        vec![1.0; raw_weights.len()]
+    } else {
+        bound_weights(raw_weights)
+    }
+}
+
+// The infinite weights share every pick, and the weights whose sum overflows are scaled down, so that the sum is always finite.
+fn bound_weights(weights: Vec<f64>) -> Vec<f64> {
+    if weights.iter().any(|w| w.is_infinite()) {
+        return weights.iter().map(|w| if w.is_infinite() { 1.0 } else { 0.0 }).collect();
+    }
+    if weights.iter().sum::<f64>().is_finite() {
+        return weights;
+    }
+    let max_weight = weights.iter().cloned().fold(0.0, f64::max);
+    weights.into_iter().map(|w| w / max_weight).collect()
+}
+
+fn clamp_weight_ratio(weights: Vec<f64>, max_ratio: f64) -> Vec<f64> {
+    let max_weight = weights.iter().cloned().fold(0.0, f64::max);
+    let min_weight = max_weight / max_ratio;
+    weights.into_iter().map(|weight| if weight > 0.0 { weight.max(min_weight) } else { weight }).collect()
+}
+
+fn to_probabilities(weights: Vec<f64>) -> Vec<f64> {
+    let total_weight: f64 = weights.iter().sum();
+    weights.into_iter().map(|weight| weight / total_weight).collect()
+}
+
+enum LogError {
+    Unreadable(String),
+    Corrupt(String),
+}
+
+impl From<LogError> for String {
+    fn from(error: LogError) -> String {
+        match error {
+            LogError::Unreadable(message) | LogError::Corrupt(message) => message,
+        }
+    }
+}
+
+fn read_log<T: DeserializeOwned>(path: &Path, log_format: LogFormat) -> Result<Log<T>, LogError> {
+    let mut file = File::open(path).map_err(|_| LogError::Unreadable(format!("could not open file: {}", path.display())))?;
+    let mut file_reader = BufReader::new(&mut file);
+
+    let to_error = |e: serde_json::Error| LogError::Corrupt(format!("failed to read log: {}", e));
+    let value: serde_json::Value = match log_format {
+        LogFormat::Json => serde_json::from_reader(&mut file_reader).map_err(to_error)?,
+        LogFormat::MessagePack => {
+            let mut data = Vec::new();
+            std::io::Read::read_to_end(&mut file_reader, &mut data).map_err(|e| LogError::Unreadable(format!("failed to read log: {}", e)))?;
+            msgpack::decode(&data).map_err(|e| LogError::Corrupt(format!("failed to read log: {}", e)))?
+        },
+    };
+    if value.is_array() {
+        let items = serde_json::from_value(value).map_err(to_error)?;
+        return Ok(Log { version: 0, state: LogState::default(), items, recent_values: VecDeque::new() });
+    }
+
+    let log: Log<T> = serde_json::from_value(value).map_err(to_error)?;
+    if log.version > LOG_VERSION {
+        return Err(LogError::Unreadable(format!("failed to read log: unsupported version {}", log.version)));
+    }
+    Ok(log)
+}
+
+fn write_log_file<S: Serialize>(path: &Path, log: &S, log_format: LogFormat) -> Result<(), String> {
+    write_atomically(path, |file_writer| match log_format {
+        LogFormat::Json => serde_json::to_writer_pretty(file_writer, log).map_err(|e| format!("failed to write log: {}", e)),
+        LogFormat::MessagePack => {
+            let value = serde_json::to_value(log).map_err(|e| format!("failed to write log: {}", e))?;
+            file_writer.write_all(&msgpack::encode(&value)).map_err(|e| format!("failed to write log: {}", e))
+        },
+    })
+}
+
+// Reads the counts in the log as they are, including values which are no longer picked.
+pub fn read_log_counts<T: Hash + Eq + DeserializeOwned>(path: &Path, log_format: LogFormat) -> Result<HashMap<T, u64>, String> {
+    if !path.exists() {
+        return Ok(HashMap::new());
+    }
+    let log = read_log::<T>(path, log_format).map_err(String::from)?;
+    Ok(log.items.into_iter().map(|item| (item.value, item.count)).collect())
+}
+
+// Sums the counts of each value in the logs, where a value missing from a log counts as 0 in it, and a value retired in any log stays retired.
+pub fn merge_logs<T: Hash + Eq + Clone + DeserializeOwned>(paths: &[PathBuf], log_format: LogFormat) -> Result<Vec<RandomPickerItem<T>>, String> {
+    let mut merged_items: Vec<RandomPickerItem<T>> = Vec::new();
+    let mut indices: HashMap<T, usize> = HashMap::new();
+    for path in paths {
+        for item in read_log::<T>(path, log_format).map_err(String::from)?.items {
+            match indices.get(&item.value) {
+                Some(index) => {
+                    merged_items[*index].count = merged_items[*index].count.saturating_add(item.count);
+                    merged_items[*index].retired |= item.retired;
+                },
+                None => {
+                    indices.insert(item.value.clone(), merged_items.len());
+                    merged_items.push(RandomPickerItem { value: item.value, count: item.count, boost: None, fail_count: 0, last_posted_at: None, retired: item.retired });
+                },
+            }
+        }
+    }
+    Ok(merged_items)
+}
+
+// Writes the items as a log, keeping the state of the log which is already at the path.
+pub fn write_merged_log<T: Ord + Serialize + DeserializeOwned>(path: &Path, items: &[RandomPickerItem<T>], log_format: LogFormat) -> Result<(), String> {
+    let state = if path.exists() { read_log::<T>(path, log_format).map_err(String::from)?.state } else { LogState::default() };
+    let log = LogRef { version: LOG_VERSION, state: &state, items: sorted_items(items), recent_values: &VecDeque::new() };
+    write_log_file(path, &log, log_format)
+}
+
+// Rewrites a log of an older version in the current version, and returns what changed, which is nothing if the log is already current.
+// A bare array has no counters, which start from the sum of the counts.
+pub fn migrate_log<T: Ord + Serialize + DeserializeOwned>(path: &Path, log_format: LogFormat) -> Result<Vec<String>, String> {
+    let mut log = read_log::<T>(path, log_format).map_err(String::from)?;
+    if log.version == LOG_VERSION {
+        return Ok(Vec::new());
+    }
+
+    let mut changes = vec![format!("version {} to {}", log.version, LOG_VERSION)];
+    let total_count = log.items.iter().fold(0, |sum: u64, item| sum.saturating_add(item.count));
+    if log.state.run_number.is_none() {
+        log.state.run_number = Some(total_count);
+        changes.push(format!("run_number set to {}", total_count));
+    }
+    if log.state.total_posts.is_none() {
+        log.state.total_posts = Some(total_count);
+        changes.push(format!("total_posts set to {}", total_count));
+    }
+    let migrated_log = LogRef { version: LOG_VERSION, state: &log.state, items: sorted_items(&log.items), recent_values: &log.recent_values };
+    write_log_file(path, &migrated_log, log_format)?;
+    Ok(changes)
+}
+
+// Copies the log at `path` to `snapshot_path` in the current version, which can be read as a log without touching the live one.
+pub fn write_snapshot<T: Ord + Serialize + DeserializeOwned>(path: &Path, snapshot_path: &Path, log_format: LogFormat) -> Result<usize, String> {
+    let log = read_log::<T>(path, log_format).map_err(String::from)?;
+    let snapshot = LogRef { version: LOG_VERSION, state: &log.state, items: sorted_items(&log.items), recent_values: &log.recent_values };
+    write_log_file(snapshot_path, &snapshot, log_format)?;
+    Ok(log.items.len())
+}
+
+// The log is written in the order of the values so that it does not change with the order of the settings,
+// while the order of the items in memory is kept, which the weights do not depend on.
+fn sorted_items<T: Ord>(items: &[RandomPickerItem<T>]) -> Vec<&RandomPickerItem<T>> {
+    let mut items: Vec<&RandomPickerItem<T>> = items.iter().collect();
+    items.sort_by(|a, b| a.value.cmp(&b.value));
+    items
+}
+
+impl<T: Ord> RandomPicker<T> {
+    pub fn sort_items(&mut self) {
+        self.items.sort_by(|a, b| a.value.cmp(&b.value));
+    }
+
+    // The ties are broken by the smallest value, regardless of the order of the items.
+    pub fn least_picked(&self) -> &T {
+        &self.items.iter().min_by(|a, b| a.count.cmp(&b.count).then_with(|| a.value.cmp(&b.value))).unwrap().value
+    }
+
+    pub fn most_picked(&self) -> &T {
+        &self.items.iter().min_by(|a, b| b.count.cmp(&a.count).then_with(|| a.value.cmp(&b.value))).unwrap().value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate indoc;
+    extern crate tempfile;
+
+    use super::*;
+    use chrono::TimeZone;
+    use indoc::indoc;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn from_log_file_should_read_the_log_file() {
+        let mut file = NamedTempFile::new().unwrap();
+        let log = indoc! {r#"
+            [
+                { "value": "a", "count": 10 },
+                { "value": "b", "count": 2 },
+                { "value": "c", "count": 5 }
+            ]
+        "#};
+        write!(file, "{}", log).unwrap();
+
+        let expected = vec![
+            RandomPickerItem { value: String::from("a"), count: 10, boost: None, fail_count: 0, last_posted_at: None, retired: false },
+            RandomPickerItem { value: String::from("b"), count: 2, boost: None, fail_count: 0, last_posted_at: None, retired: false },
+            RandomPickerItem { value: String::from("c"), count: 5, boost: None, fail_count: 0, last_posted_at: None, retired: false },
+        ];
+
+        let values = vec![String::from("a"), String::from("b"), String::from("c")];
+        let picker = RandomPicker::from_log_file(file.path(), values, WeightType::Uniform, InitialCountType::Zero).unwrap();
+        assert_eq!(picker.items, expected);
+    }
+
+    #[test]
+    fn from_log_file_should_read_counts_larger_than_u32() {
+        let mut file = NamedTempFile::new().unwrap();
+        let log = indoc! {r#"
+            [
+                { "value": "a", "count": 4294967296 },
+                { "value": "b", "count": 4294967295 }
+            ]
+        "#};
+        write!(file, "{}", log).unwrap();
+
+        let expected = vec![
+            RandomPickerItem { value: String::from("a"), count: 4294967296, boost: None, fail_count: 0, last_posted_at: None, retired: false },
+            RandomPickerItem { value: String::from("b"), count: 4294967295, boost: None, fail_count: 0, last_posted_at: None, retired: false },
+        ];
+
+        let values = vec![String::from("a"), String::from("b")];
+        let picker = RandomPicker::from_log_file(file.path(), values, WeightType::Uniform, InitialCountType::Zero).unwrap();
+        assert_eq!(picker.items, expected);
+    }
+
+    #[test]
+    fn from_log_file_should_read_a_versioned_log_file() {
+        let mut file = NamedTempFile::new().unwrap();
+        let log = indoc! {r#"
+            {
+                "version": 1,
+                "last_posted_at": "2021-09-01T12:00:00Z",
+                "last_message_id": "1234567890",
+                "items": [
+                    { "value": "a", "count": 10 },
+                    { "value": "b", "count": 2 }
+                ]
+            }
+        "#};
+        write!(file, "{}", log).unwrap();
+
+        let expected_items = vec![
+            RandomPickerItem { value: String::from("a"), count: 10, boost: None, fail_count: 0, last_posted_at: None, retired: false },
+            RandomPickerItem { value: String::from("b"), count: 2, boost: None, fail_count: 0, last_posted_at: None, retired: false },
+        ];
+        let expected_state = LogState {
+            last_posted_at: Some(Utc.with_ymd_and_hms(2021, 9, 1, 12, 0, 0).unwrap()),
+            last_message_id: Some(String::from("1234567890")),
+            last_destination_index: None,
+            seeded_runs: None,
+            next_post_after: None,
+            run_number: None,
+            total_posts: None,
+            recent_picks: Vec::new(),
+        };
+
+        let values = vec![String::from("a"), String::from("b")];
+        let picker = RandomPicker::from_log_file(file.path(), values, WeightType::Uniform, InitialCountType::Zero).unwrap();
+        assert_eq!(picker.items, expected_items);
+        assert_eq!(picker.state, expected_state);
+    }
+
+    #[test]
+    fn from_log_file_should_read_boosts() {
+        let mut file = NamedTempFile::new().unwrap();
+        let log = indoc! {r#"
+            {
+                "version": 1,
+                "items": [
+                    { "value": "a", "count": 10, "boost": 2.0, "boost_decay": 0.5 },
+                    { "value": "b", "count": 2 }
+                ]
+            }
+        "#};
+        write!(file, "{}", log).unwrap();
+
+        let expected = vec![
+            RandomPickerItem { value: String::from("a"), count: 10, boost: Some(Boost { boost: 2.0, boost_decay: 0.5 }), fail_count: 0, last_posted_at: None, retired: false },
+            RandomPickerItem { value: String::from("b"), count: 2, boost: None, fail_count: 0, last_posted_at: None, retired: false },
+        ];
+
+        let values = vec![String::from("a"), String::from("b")];
+        let picker = RandomPicker::from_log_file(file.path(), values, WeightType::Uniform, InitialCountType::Zero).unwrap();
+        assert_eq!(picker.items, expected);
+    }
+
+    #[test]
+    fn from_log_file_should_fail_if_the_log_version_is_not_supported() {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, r#"{{ "version": 2, "items": [] }}"#).unwrap();
+
+        let values = vec![String::from("a")];
+        let result = RandomPicker::from_log_file(file.path(), values, WeightType::Uniform, InitialCountType::Zero);
+        assert_eq!(result.err(), Some(String::from("failed to read log: unsupported version 2")));
+    }
+
+    #[test]
+    fn write_log_should_write_a_log_which_can_be_read_again() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_owned();
+        file.close().unwrap();
+
+        let values = vec![String::from("a"), String::from("b")];
+        let mut picker = RandomPicker::from_log_file(&path, values.clone(), WeightType::Uniform, InitialCountType::Zero).unwrap();
+        picker.pick().unwrap();
+        picker.state_mut().last_posted_at = Some(Utc.with_ymd_and_hms(2021, 9, 1, 12, 0, 0).unwrap());
+        picker.write_log().unwrap();
+
+        let read_picker = RandomPicker::from_log_file(&path, values, WeightType::Uniform, InitialCountType::Zero).unwrap();
+        assert_eq!(read_picker.items, picker.items);
+        assert_eq!(read_picker.state, picker.state);
+    }
+
+    #[test]
+    fn write_log_should_write_the_same_sorted_and_pretty_log_regardless_of_the_order_of_the_values() {
+        let write = |values: Vec<&str>| {
+            let file = NamedTempFile::new().unwrap();
+            write!(file.as_file(), r#"{{ "version": 1, "items": [{{ "value": "a", "count": 2 }}, {{ "value": "c", "count": 1 }}] }}"#).unwrap();
+            let values = values.into_iter().map(String::from).collect();
+            let picker = RandomPicker::from_log_file(file.path(), values, WeightType::Uniform, InitialCountType::Zero).unwrap();
+            picker.write_log().unwrap();
+            std::fs::read_to_string(file.path()).unwrap()
+        };
+
+        let expected = indoc! {r#"
+            {
+              "version": 1,
+              "items": [
+                {
+                  "value": "a",
+                  "count": 2
+                },
+                {
+                  "value": "b",
+                  "count": 0
+                },
+                {
+                  "value": "c",
+                  "count": 1
+                }
+              ]
+            }"#};
+        assert_eq!(write(vec!["c", "a", "b"]), expected);
+        assert_eq!(write(vec!["b", "c", "a"]), expected);
+    }
+
+    #[test]
+    fn write_log_should_write_a_message_pack_log_which_can_be_read_again() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_owned();
+        file.close().unwrap();
+
+        let values = vec![String::from("a"), String::from("b")];
+        let builder = || RandomPickerBuilder::new().log_format(LogFormat::MessagePack).cooldown(1);
+        let mut picker = builder().build(&path, values.clone()).unwrap();
+        picker.pick().unwrap();
+        picker.record_failure(&String::from("b"));
+        picker.state_mut().last_posted_at = Some(Utc.with_ymd_and_hms(2021, 9, 1, 12, 0, 0).unwrap());
+        picker.write_log().unwrap();
+
+        let read_picker = builder().build(&path, values.clone()).unwrap();
+        assert_eq!(read_picker.items, picker.items);
+        assert_eq!(read_picker.state, picker.state);
+        assert_eq!(read_picker.recent_values, picker.recent_values);
+        // the envelope is a map, which is not JSON
+        assert_eq!(std::fs::read(&path).unwrap()[0] & 0xf0, 0x80);
+        assert!(RandomPickerBuilder::new().build(&path, values).is_err());
+    }
+
+    #[test]
+    fn least_picked_and_most_picked_should_break_ties_by_the_smallest_value() {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, r#"[{{ "value": "d", "count": 1 }}, {{ "value": "c", "count": 5 }}, {{ "value": "b", "count": 1 }}, {{ "value": "a", "count": 5 }}, {{ "value": "e", "count": 3 }}]"#).unwrap();
+
+        let values = vec![String::from("e"), String::from("d"), String::from("c"), String::from("b"), String::from("a")];
+        let mut picker = RandomPicker::from_log_file(file.path(), values, WeightType::Uniform, InitialCountType::Zero).unwrap();
+        assert_eq!((picker.least_picked().as_str(), picker.most_picked().as_str()), ("b", "a"));
+
+        picker.sort_items();
+        assert_eq!((picker.least_picked().as_str(), picker.most_picked().as_str()), ("b", "a"));
+    }
+
+    macro_rules! from_log_file_tests {
+        (
+            $(
+                $name:ident: $initial_count_type:expr, $log:expr, $values:expr, $expected:expr;
+            )*
+        ) => {
+            $(
+                #[test]
+                fn $name() {
+                    let mut file = NamedTempFile::new().unwrap();
+                    write!(file, "{}", $log).unwrap();
+
+                    let picker = RandomPicker::from_log_file(file.path(), $values, WeightType::Uniform, $initial_count_type).unwrap();
+                    assert_eq!(picker.items, $expected);
+                }
+            )*
+        }
+    }
+
+    from_log_file_tests!(
+        from_log_file_should_set_zero_to_initial_count_if_the_initial_count_type_is_min:
+            InitialCountType::Zero,
+            indoc! {r#"
+                [
+                    { "value": "a", "count": 1 },
+                    { "value": "b", "count": 2 },
+                    { "value": "c", "count": 3 }
+                ]
+            "#},
+            vec![String::from("b"), String::from("c"), String::from("d")],
+            vec![
+                RandomPickerItem { value: String::from("b"), count: 2, boost: None, fail_count: 0, last_posted_at: None, retired: false },
+                RandomPickerItem { value: String::from("c"), count: 3, boost: None, fail_count: 0, last_posted_at: None, retired: false },
+                RandomPickerItem { value: String::from("d"), count: 0, boost: None, fail_count: 0, last_posted_at: None, retired: false },
+            ];
+        from_log_file_should_set_the_minimum_value_of_the_log_to_initial_count_if_the_initial_count_type_is_min:
+            InitialCountType::Min,
+            indoc! {r#"
+                [
+                    { "value": "a", "count": 1 },
+                    { "value": "b", "count": 2 },
+                    { "value": "c", "count": 3 }
+                ]
+            "#},
+            vec![String::from("b"), String::from("c"), String::from("d")],
+            vec![
+                RandomPickerItem { value: String::from("b"), count: 2, boost: None, fail_count: 0, last_posted_at: None, retired: false },
+                RandomPickerItem { value: String::from("c"), count: 3, boost: None, fail_count: 0, last_posted_at: None, retired: false },
+                RandomPickerItem { value: String::from("d"), count: 1, boost: None, fail_count: 0, last_posted_at: None, retired: false },
+            ];
+        from_log_file_should_set_the_maximum_value_of_the_log_to_initial_count_if_the_initial_count_type_is_min: 
+            InitialCountType::Max,
+            indoc! {r#"
+                [
+                    { "value": "a", "count": 1 },
+                    { "value": "b", "count": 2 },
+                    { "value": "c", "count": 3 }
+                ]
+            "#},
+            vec![String::from("b"), String::from("c"), String::from("d")],
+            vec![
+                RandomPickerItem { value: String::from("b"), count: 2, boost: None, fail_count: 0, last_posted_at: None, retired: false },
+                RandomPickerItem { value: String::from("c"), count: 3, boost: None, fail_count: 0, last_posted_at: None, retired: false },
+                RandomPickerItem { value: String::from("d"), count: 3, boost: None, fail_count: 0, last_posted_at: None, retired: false },
+            ];
+        from_log_file_should_set_the_rounded_average_value_of_the_log_to_initial_count_if_the_initial_count_type_is_average:
+            InitialCountType::Average,
+            indoc! {r#"
+                [
+                    { "value": "a", "count": 1 },
+                    { "value": "b", "count": 2 },
+                    { "value": "c", "count": 4 }
+                ]
+            "#},
+            vec![String::from("b"), String::from("c"), String::from("d")],
+            vec![
+                RandomPickerItem { value: String::from("b"), count: 2, boost: None, fail_count: 0, last_posted_at: None, retired: false },
+                RandomPickerItem { value: String::from("c"), count: 4, boost: None, fail_count: 0, last_posted_at: None, retired: false },
+                RandomPickerItem { value: String::from("d"), count: 2, boost: None, fail_count: 0, last_posted_at: None, retired: false },
+            ];
+        from_log_file_should_not_overflow_if_the_initial_count_type_is_average:
+            InitialCountType::Average,
+            indoc! {r#"
+                [
+                    { "value": "a", "count": 18446744073709551615 },
+                    { "value": "b", "count": 18446744073709551615 }
+                ]
+            "#},
+            vec![String::from("a"), String::from("c")],
+            vec![
+                RandomPickerItem { value: String::from("a"), count: 18446744073709551615, boost: None, fail_count: 0, last_posted_at: None, retired: false },
+                RandomPickerItem { value: String::from("c"), count: 18446744073709551615, boost: None, fail_count: 0, last_posted_at: None, retired: false },
+            ];
+        from_log_file_should_set_zero_if_the_log_is_empty_and_the_initial_count_type_is_average:
+            InitialCountType::Average,
+            "[]",
+            vec![String::from("a")],
+            vec![
+                RandomPickerItem { value: String::from("a"), count: 0, boost: None, fail_count: 0, last_posted_at: None, retired: false },
+            ];
+        from_log_file_should_set_zero_if_the_log_is_empty_and_the_initial_count_type_is_min: 
+            InitialCountType::Min,
+            "[]",
+            vec![String::from("a")],
+            vec![
+                RandomPickerItem { value: String::from("a"), count: 0, boost: None, fail_count: 0, last_posted_at: None, retired: false },
+            ];
+        from_log_file_should_set_zero_if_the_log_is_empty_and_the_initial_count_type_is_max: 
+            InitialCountType::Max,
+            "[]",
+            vec![String::from("a")],
+            vec![
+                RandomPickerItem { value: String::from("a"), count: 0, boost: None, fail_count: 0, last_posted_at: None, retired: false },
+            ];
+    );
+
+    #[test]
+    fn from_log_file_should_return_a_random_picker_which_has_zero_initialized_items_if_the_log_file_does_not_exist() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_owned();
+        file.close().unwrap();
+
+        let expected = vec![
+            RandomPickerItem { value: String::from("a"), count: 0, boost: None, fail_count: 0, last_posted_at: None, retired: false },
+            RandomPickerItem { value: String::from("b"), count: 0, boost: None, fail_count: 0, last_posted_at: None, retired: false },
+            RandomPickerItem { value: String::from("c"), count: 0, boost: None, fail_count: 0, last_posted_at: None, retired: false },
+        ];
+
+        let values = vec![String::from("a"), String::from("b"), String::from("c")];
+        let picker = RandomPicker::from_log_file(path, values, WeightType::Uniform, InitialCountType::Zero).unwrap();
+        assert_eq!(picker.items, expected);
+    }
+
+    #[test]
+    fn read_log_counts_should_return_all_counts_in_the_log() {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, r#"{{ "version": 1, "items": [{{ "value": "a", "count": 10 }}, {{ "value": "b", "count": 2 }}] }}"#).unwrap();
+
+        let expected = vec![(String::from("a"), 10), (String::from("b"), 2)].into_iter().collect();
+        assert_eq!(read_log_counts::<String>(file.path(), LogFormat::Json), Ok(expected));
+    }
+
+    #[test]
+    fn migrate_log_should_rewrite_a_bare_array_in_the_current_version() {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, r#"[{{ "value": "b", "count": 2 }}, {{ "value": "a", "count": 10 }}]"#).unwrap();
+
+        let changes = migrate_log::<String>(file.path(), LogFormat::Json).unwrap();
+        assert_eq!(changes, vec![String::from("version 0 to 1"), String::from("run_number set to 12"), String::from("total_posts set to 12")]);
+
+        let log: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(file.path()).unwrap()).unwrap();
+        let expected = serde_json::json!({
+            "version": 1,
+            "run_number": 12,
+            "total_posts": 12,
+            "items": [{ "value": "a", "count": 10 }, { "value": "b", "count": 2 }],
+        });
+        assert_eq!(log, expected);
+
+        assert_eq!(migrate_log::<String>(file.path(), LogFormat::Json), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn write_snapshot_should_copy_the_log_which_can_be_read_as_a_log() {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, r#"{{ "version": 1, "total_posts": 12, "items": [{{ "value": "b", "count": 2 }}, {{ "value": "a", "count": 10 }}] }}"#).unwrap();
+        let snapshot_file = NamedTempFile::new().unwrap();
+
+        assert_eq!(write_snapshot::<String>(file.path(), snapshot_file.path(), LogFormat::Json), Ok(2));
+
+        let values = vec![String::from("a"), String::from("b")];
+        let picker = RandomPicker::from_log_file(snapshot_file.path(), values.clone(), WeightType::Uniform, InitialCountType::Zero).unwrap();
+        assert_eq!(picker.counts(), vec![(&values[0], 10), (&values[1], 2)]);
+        assert_eq!(picker.state().total_posts, Some(12));
+    }
+
+    #[test]
+    fn build_should_fail_if_the_log_is_corrupt_and_on_corrupt_log_is_fail() {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, r#"[{{ "value": "a", "count": 1 "#).unwrap();
+
+        let result = RandomPickerBuilder::new().on_corrupt_log(OnCorruptLog::Fail).build(file.path(), vec![String::from("a")]);
+        assert!(result.err().unwrap().starts_with("failed to read log: "));
+    }
+
+    #[test]
+    fn build_should_reset_the_log_if_the_log_is_corrupt_and_on_corrupt_log_is_reset() {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, r#"[{{ "value": "a", "count": 1 "#).unwrap();
+
+        let values = vec![String::from("a"), String::from("b")];
+        let mut picker = RandomPickerBuilder::new().on_corrupt_log(OnCorruptLog::Reset).build(file.path(), values.clone()).unwrap();
+        assert_eq!(picker.counts(), vec![(&values[0], 0), (&values[1], 0)]);
+        let corrupt_path = PathBuf::from(format!("{}.corrupt", file.path().display()));
+        assert_eq!(std::fs::read_to_string(&corrupt_path).unwrap(), r#"[{ "value": "a", "count": 1 "#);
+        assert!(picker.corrupt_log().unwrap().ends_with(&format!(", so the log is moved to {} and reset", corrupt_path.display())));
+        std::fs::remove_file(corrupt_path).unwrap();
+
+        picker.pick().unwrap();
+        picker.write_log().unwrap();
+        let read_picker = RandomPickerBuilder::new().build(file.path(), values).unwrap();
+        assert_eq!(read_picker.counts().iter().map(|(_, count)| count).sum::<u64>(), 1);
+    }
+
+    #[test]
+    fn build_should_fail_if_the_log_version_is_not_supported_even_if_on_corrupt_log_is_reset() {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, r#"{{ "version": 2, "items": [] }}"#).unwrap();
+
+        let result = RandomPickerBuilder::new().on_corrupt_log(OnCorruptLog::Reset).build(file.path(), vec![String::from("a")]);
+        assert_eq!(result.err(), Some(String::from("failed to read log: unsupported version 2")));
+    }
+
+    #[test]
+    fn merge_logs_should_sum_the_counts_of_each_value() {
+        let mut file1 = NamedTempFile::new().unwrap();
+        write!(file1, r#"[{{ "value": "a", "count": 10 }}, {{ "value": "b", "count": 2 }}]"#).unwrap();
+        let mut file2 = NamedTempFile::new().unwrap();
+        write!(file2, r#"{{ "version": 1, "items": [{{ "value": "b", "count": 3 }}, {{ "value": "c", "count": 5, "boost": 1.0, "boost_decay": 0.5 }}] }}"#).unwrap();
+
+        let items = merge_logs::<String>(&[file1.path().to_owned(), file2.path().to_owned()], LogFormat::Json).unwrap();
+        let expected = vec![
+            RandomPickerItem { value: String::from("a"), count: 10, boost: None, fail_count: 0, last_posted_at: None, retired: false },
+            RandomPickerItem { value: String::from("b"), count: 5, boost: None, fail_count: 0, last_posted_at: None, retired: false },
+            RandomPickerItem { value: String::from("c"), count: 5, boost: None, fail_count: 0, last_posted_at: None, retired: false },
+        ];
+        assert_eq!(items, expected);
+    }
+
+    #[test]
+    fn write_merged_log_should_keep_the_state_of_the_log() {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, r#"{{ "version": 1, "last_posted_at": "2021-09-01T12:00:00Z", "items": [{{ "value": "a", "count": 1 }}] }}"#).unwrap();
+
+        let items = vec![RandomPickerItem { value: String::from("a"), count: 3, boost: None, fail_count: 0, last_posted_at: None, retired: false }];
+        write_merged_log(file.path(), &items, LogFormat::Json).unwrap();
+
+        let log = read_log::<String>(file.path(), LogFormat::Json).map_err(String::from).unwrap();
+        assert_eq!(log.state.last_posted_at, Some(Utc.with_ymd_and_hms(2021, 9, 1, 12, 0, 0).unwrap()));
+        assert_eq!(log.items, items);
+    }
+
+    #[test]
+    fn from_log_file_should_fail_if_the_values_is_empty() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_owned();
+        file.close().unwrap();
+
+        let result = RandomPicker::from_log_file(path, Vec::<String>::new(), WeightType::Uniform, InitialCountType::Zero);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn build_should_return_the_same_picker_as_from_log_file() {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, r#"[{{ "value": "a", "count": 10 }}, {{ "value": "b", "count": 2 }}]"#).unwrap();
+
+        let values = vec![String::from("a"), String::from("b"), String::from("c")];
+        let built_picker = RandomPickerBuilder::new()
+            .weight_type(WeightType::MinOnly)
+            .initial_count_type(InitialCountType::Max)
+            .build(file.path(), values.clone())
+            .unwrap();
+        let constructed_picker = RandomPicker::from_log_file(file.path(), values, WeightType::MinOnly, InitialCountType::Max).unwrap();
+
+        assert_eq!(built_picker.items, constructed_picker.items);
+        assert_eq!(built_picker.weight_type, constructed_picker.weight_type);
+        assert_eq!(built_picker.probabilities(), constructed_picker.probabilities());
+    }
+
+    #[test]
+    fn build_should_fail_if_the_weight_type_is_invalid() {
+        let file = NamedTempFile::new().unwrap();
+        let result = RandomPickerBuilder::new()
+            .weight_type(WeightType::Linear { bias: -1.0 })
+            .build(file.path(), vec![String::from("a")]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn probabilities_should_be_clamped_by_the_max_ratio() {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, r#"[{{ "value": "a", "count": 0 }}, {{ "value": "b", "count": 100 }}, {{ "value": "c", "count": 1000 }}]"#).unwrap();
+
+        let values = vec![String::from("a"), String::from("b"), String::from("c")];
+        let picker = RandomPickerBuilder::new()
+            .weight_type(WeightType::Linear { bias: 1.0 })
+            .max_ratio(10.0)
+            .build(file.path(), values)
+            .unwrap();
+
+        let probabilities: Vec<f64> = picker.probabilities().into_iter().map(|(_, probability)| probability).collect();
+        let max_probability = probabilities.iter().cloned().fold(0.0, f64::max);
+        let min_probability = probabilities.iter().cloned().fold(1.0, f64::min);
+        assert!(max_probability / min_probability <= 10.0 + 1e-9, "{:?}", probabilities);
+        assert!(probabilities[0] > probabilities[1] && probabilities[1] > probabilities[2], "{:?}", probabilities);
+    }
+
+    #[test]
+    fn normalize_counts_should_make_the_probabilities_less_perturbed_by_a_new_zero_count_message() {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, r#"[{{ "value": "a", "count": 3000 }}, {{ "value": "b", "count": 3100 }}]"#).unwrap();
+
+        let existing_probability = |normalize_counts: bool| {
+            let values = vec![String::from("a"), String::from("b"), String::from("c")];
+            let picker = RandomPickerBuilder::new()
+                .weight_type(WeightType::Linear { bias: 100.0 })
+                .normalize_counts(normalize_counts)
+                .build(file.path(), values)
+                .unwrap();
+            picker.probabilities().into_iter().filter(|(value, _)| *value != "c").map(|(_, probability)| probability).sum::<f64>()
+        };
+
+        // the shares are 492 and 508 per thousand, so the bias outweighs the difference of the counts
+        let raw_probability = existing_probability(false);
+        let normalized_probability = existing_probability(true);
+        assert!((raw_probability - 300.0 / 3500.0).abs() < 1e-9, "{}", raw_probability);
+        assert!((normalized_probability - 216.0 / 824.0).abs() < 1e-9, "{}", normalized_probability);
+        assert!(normalized_probability > raw_probability * 3.0);
+    }
+
+    #[test]
+    fn probabilities_should_favor_the_stale_values() {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, "[]").unwrap();
+        let values = vec![String::from("fresh"), String::from("stale"), String::from("never")];
+        let now = Utc.with_ymd_and_hms(2021, 9, 1, 12, 0, 0).unwrap();
+        let stale_boost = StaleBoost { after: HumanDuration::from_secs(30 * 24 * 60 * 60), factor: 4.0 };
+        let mut picker = RandomPickerBuilder::new().now(now).build(file.path(), values.clone()).unwrap();
+        picker.record_posted_at(&String::from("fresh"), now - chrono::Duration::days(1));
+        picker.record_posted_at(&String::from("stale"), now - chrono::Duration::days(30));
+        picker.set_stale_boost(stale_boost).unwrap();
+
+        let probabilities: Vec<f64> = picker.probabilities().into_iter().map(|(_, probability)| probability).collect();
+        assert_eq!(probabilities, vec![1.0 / 9.0, 4.0 / 9.0, 4.0 / 9.0]);
+        picker.write_log().unwrap();
+
+        // 29 days later, the fresh value is as stale as the others
+        let mut picker = RandomPickerBuilder::new().now(now + chrono::Duration::days(29)).build(file.path(), values).unwrap();
+        picker.set_stale_boost(stale_boost).unwrap();
+        let probabilities: Vec<f64> = picker.probabilities().into_iter().map(|(_, probability)| probability).collect();
+        assert_eq!(probabilities, vec![1.0 / 3.0, 1.0 / 3.0, 1.0 / 3.0]);
+    }
+
+    #[test]
+    fn set_stale_boost_should_fail_without_the_current_time() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_owned();
+        file.close().unwrap();
+
+        let mut picker = RandomPickerBuilder::new().build(path, vec![String::from("a")]).unwrap();
+        let stale_boost = StaleBoost { after: HumanDuration::from_secs(60), factor: 2.0 };
+        assert_eq!(picker.set_stale_boost(stale_boost), Err(String::from("stale_boost needs the current time")));
+    }
+
+    #[test]
+    fn pick_should_not_panic_if_the_weights_overflow() {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, r#"[{{ "value": "a", "count": 800 }}, {{ "value": "b", "count": 900 }}, {{ "value": "c", "count": 0 }}]"#).unwrap();
+        let values = vec![String::from("a"), String::from("b"), String::from("c")];
+        let weight_type = WeightType::Expr { formula: String::from("exp(count)") };
+        let mut picker = RandomPickerBuilder::new().weight_type(weight_type).build(file.path(), values).unwrap();
+
+        // both infinite weights share every pick
+        let probabilities: Vec<f64> = picker.probabilities().into_iter().map(|(_, probability)| probability).collect();
+        assert_eq!(probabilities, vec![0.5, 0.5, 0.0]);
+        assert!(picker.pick().is_ok());
+    }
+
+    #[test]
+    fn pick_should_scale_down_the_finite_weights_whose_sum_overflows() {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, r#"[{{ "value": "a", "count": 709 }}, {{ "value": "b", "count": 709 }}, {{ "value": "c", "count": 709 }}]"#).unwrap();
+        let values = vec![String::from("a"), String::from("b"), String::from("c")];
+        let weight_type = WeightType::Expr { formula: String::from("exp(count)") };
+        let mut picker = RandomPickerBuilder::new().weight_type(weight_type).build(file.path(), values).unwrap();
+
+        let probabilities: Vec<f64> = picker.probabilities().into_iter().map(|(_, probability)| probability).collect();
+        assert_eq!(probabilities, vec![1.0 / 3.0, 1.0 / 3.0, 1.0 / 3.0]);
+        assert!(picker.pick().is_ok());
+    }
+
+    #[test]
+    fn oldest_first_should_pick_the_value_posted_the_longest_ago_by_the_given_clock() {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, "[]").unwrap();
+        let values = vec![String::from("a"), String::from("b"), String::from("c")];
+        let now = Utc.with_ymd_and_hms(2021, 9, 1, 12, 0, 0).unwrap();
+        let mut picker = RandomPickerBuilder::new().weight_type(WeightType::OldestFirst).now(now).build(file.path(), values).unwrap();
+        picker.record_posted_at(&String::from("a"), now - chrono::Duration::days(1));
+        picker.record_posted_at(&String::from("b"), now - chrono::Duration::days(3));
+
+        // never posted, and so the stalest
+        assert_eq!(picker.pick().unwrap(), &String::from("c"));
+        picker.record_posted_at(&String::from("c"), now);
+        let probabilities: Vec<f64> = picker.probabilities().into_iter().map(|(_, probability)| probability).collect();
+        assert_eq!(probabilities, vec![0.0, 1.0, 0.0]);
+
+        // the posts after the clock count as just posted
+        picker.record_posted_at(&String::from("b"), now + chrono::Duration::days(1));
+        assert_eq!(picker.pick().unwrap(), &String::from("a"));
+    }
+
+    #[test]
+    fn build_should_fail_if_oldest_first_has_no_current_time() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_owned();
+        file.close().unwrap();
+
+        let values = vec![String::from("a")];
+        let result = RandomPickerBuilder::new().weight_type(WeightType::OldestFirst).build(&path, values.clone());
+        assert_eq!(result.err(), Some(String::from("OldestFirst needs the current time")));
+
+        let mut picker = RandomPickerBuilder::new().build(&path, values).unwrap();
+        let group_weight_types = vec![(String::from("group"), WeightType::OldestFirst)].into_iter().collect();
+        assert_eq!(picker.set_group_weight_types(group_weight_types), Err(String::from("OldestFirst needs the current time")));
+    }
+
+    #[test]
+    fn probabilities_should_not_be_zero_if_the_epsilon_is_set() {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, r#"[{{ "value": "a", "count": 0 }}, {{ "value": "b", "count": 1 }}]"#).unwrap();
+
+        let values = vec![String::from("a"), String::from("b")];
+        let picker = RandomPickerBuilder::new()
+            .weight_type(WeightType::MinOnly)
+            .epsilon(0.5)
+            .build(file.path(), values)
+            .unwrap();
+
+        let probabilities: Vec<f64> = picker.probabilities().into_iter().map(|(_, probability)| probability).collect();
+        assert_eq!(probabilities, vec![0.75, 0.25]);
+    }
+
+    #[test]
+    fn probabilities_should_add_the_epsilon_after_replacing_infinite_weights() {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, r#"[{{ "value": "a", "count": 0 }}, {{ "value": "b", "count": 1 }}]"#).unwrap();
+
+        let values = vec![String::from("a"), String::from("b")];
+        let picker = RandomPickerBuilder::new()
+            .weight_type(WeightType::Linear { bias: f64::INFINITY })
+            .epsilon(1.0)
+            .build(file.path(), values)
+            .unwrap();
+
+        let probabilities: Vec<f64> = picker.probabilities().into_iter().map(|(_, probability)| probability).collect();
+        assert_eq!(probabilities, vec![0.5, 0.5]);
+    }
+
+    #[test]
+    fn seeded_rng_should_differ_between_the_neighbouring_seeds_of_the_next_run() {
+        let numbers = |seed: u64, run: u64| { let mut rng = seeded_rng(seed, run); (0..4).map(|_| rng.next_u64()).collect::<Vec<u64>>() };
+        assert_ne!(numbers(5, 1), numbers(6, 0));
+        assert_ne!(numbers(5, 1), numbers(5, 0));
+        assert_eq!(numbers(5, 1), numbers(5, 1));
+    }
+
+    #[test]
+    fn pick_should_continue_the_seeded_sequence_across_runs() {
+        let pick_in_runs = |runs: usize| {
+            let file = NamedTempFile::new().unwrap();
+            let path = file.path().to_owned();
+            file.close().unwrap();
+
+            let values: Vec<String> = (0..100).map(|value| value.to_string()).collect();
+            let mut picks = Vec::new();
+            for _ in 0..runs {
+                let mut picker = RandomPickerBuilder::new().seed(42).build(&path, values.clone()).unwrap();
+                // a few picks in each run, so that the runs do not look alike by chance
+                picks.push((0..3).map(|_| picker.pick().unwrap().clone()).collect::<Vec<String>>());
+                picker.write_log().unwrap();
+            }
+            picks
+        };
+
+        let picks = pick_in_runs(3);
+        assert_eq!(picks, pick_in_runs(3));
+        assert_ne!(picks[0], picks[1]);
+        assert_ne!(picks[1], picks[2]);
+    }
+
+    #[test]
+    fn pick_should_approach_the_targets_if_the_weight_type_is_target() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_owned();
+        file.close().unwrap();
+
+        let values = vec![String::from("a"), String::from("b"), String::from("c")];
+        let mut picker = RandomPickerBuilder::new().weight_type(WeightType::Target).seed(0).build(&path, values.clone()).unwrap();
+        picker.set_targets(vec![(String::from("a"), 0.5), (String::from("b"), 0.3), (String::from("c"), 0.2)].into_iter().collect());
+        for _ in 0..1000 {
+            picker.pick().unwrap();
+        }
+
+        let fractions: Vec<f64> = picker.counts().into_iter().map(|(_, count)| count as f64 / 1000.0).collect();
+        for (fraction, target) in fractions.iter().zip([0.5, 0.3, 0.2].iter()) {
+            assert!((fraction - target).abs() < 0.01, "{:?}", fractions);
+        }
+    }
+
+    #[test]
+    fn probabilities_should_treat_an_important_value_as_picked_less_often() {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, r#"[{{ "value": "a", "count": 10 }}, {{ "value": "b", "count": 10 }}]"#).unwrap();
+
+        let values = vec![String::from("a"), String::from("b")];
+        let mut picker = RandomPicker::from_log_file(file.path(), values.clone(), WeightType::Linear { bias: 1.0 }, InitialCountType::Zero).unwrap();
+        assert_eq!(picker.probabilities(), vec![(&values[0], 0.5), (&values[1], 0.5)]);
+
+        picker.set_importances(vec![(String::from("b"), 2.0)].into_iter().collect());
+        // b counts as 5 against 10, so the weights are 1 and 6
+        assert_eq!(picker.probabilities(), vec![(&values[0], 1.0 / 7.0), (&values[1], 6.0 / 7.0)]);
+    }
+
+    #[test]
+    fn probabilities_should_treat_an_important_value_as_picked_less_often_at_a_count_of_one() {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, r#"[{{ "value": "a", "count": 1 }}, {{ "value": "b", "count": 1 }}]"#).unwrap();
+
+        let values = vec![String::from("a"), String::from("b")];
+        let mut picker = RandomPicker::from_log_file(file.path(), values.clone(), WeightType::Linear { bias: 1.0 }, InitialCountType::Zero).unwrap();
+        picker.set_importances(vec![(String::from("b"), 2.0)].into_iter().collect());
+        // b counts as 0.5 against 1, so the weights are 1 and 1.5
+        assert_eq!(picker.probabilities(), vec![(&values[0], 0.4), (&values[1], 0.6)]);
+    }
+
+    #[test]
+    fn build_should_fail_if_the_max_ratio_is_less_than_one() {
+        let file = NamedTempFile::new().unwrap();
+        let result = RandomPickerBuilder::new().max_ratio(0.5).build(file.path(), vec![String::from("a")]);
+        assert_eq!(result.err(), Some(String::from("max_ratio must be at least 1")));
+    }
+
+    #[test]
+    fn pick_should_not_pick_the_same_value_twice_in_a_row_if_avoid_repeat_is_set() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_owned();
+        file.close().unwrap();
+
+        let values = vec![String::from("a"), String::from("b")];
+        let mut picker = RandomPickerBuilder::new().seed(0).avoid_repeat(true).build(&path, values).unwrap();
+        let mut previous_value = picker.pick().unwrap().clone();
+        for _ in 0..20 {
+            let value = picker.pick().unwrap().clone();
+            assert_ne!(value, previous_value);
+            previous_value = value;
+        }
+    }
+
+    #[test]
+    fn pick_should_not_pick_the_values_in_the_cooldown_across_runs() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_owned();
+        file.close().unwrap();
+
+        let values = vec![String::from("a"), String::from("b"), String::from("c")];
+        let mut picked_values = Vec::new();
+        for seed in 0..9 {
+            let mut picker = RandomPickerBuilder::new().seed(seed).cooldown(2).build(&path, values.clone()).unwrap();
+            picked_values.push(picker.pick().unwrap().clone());
+            picker.write_log().unwrap();
+        }
+
+        for window in picked_values.windows(3) {
+            assert!(window[0] != window[1] && window[1] != window[2] && window[0] != window[2], "{:?}", picked_values);
+        }
+    }
+
+    #[test]
+    fn pick_should_ignore_the_cooldown_if_no_other_values_can_be_picked() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_owned();
+        file.close().unwrap();
+
+        let values = vec![String::from("a")];
+        let mut picker = RandomPickerBuilder::new().avoid_repeat(true).build(&path, values).unwrap();
+        assert_eq!(picker.pick().unwrap(), "a");
+        assert_eq!(picker.pick().unwrap(), "a");
+    }
+
+    #[test]
+    fn pick_should_pick_the_value_randomly() {
+        let mut file = NamedTempFile::new().unwrap();
+        let log = indoc! {r#"
+            [
+                { "value": "a", "count": 30 },
+                { "value": "b", "count": 0 },
+                { "value": "c", "count": 20 }
+            ]
+        "#};
+        write!(file, "{}", log).unwrap();
+
+        let values = vec![String::from("a"), String::from("b"), String::from("c")];
+        let picker_template = RandomPicker::from_log_file(file.path(), values.clone(), WeightType::Linear { bias: 20.0 }, InitialCountType::Zero).unwrap();
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut count: HashMap<String, u64> = values.into_iter().map(|s| (s, 0)).collect();
+        for _ in 1..=10000 {
+            let mut picker = picker_template.clone();
+            let value = picker.pick_with_rng(&mut rng).unwrap();
+            *count.get_mut(value).unwrap() += 1;
+        }
+        // P(count["a"] > 1829) = P(X > 1829) for X ~ N(2000, 1600) > 0.99999
+        assert!(count["a"] > 1829);
+        // P(count["b"] > 4786) = P(X > 4786) for X ~ N(5000, 2500) > 0.99999
+        assert!(count["b"] > 4786);
+        // P(count["c"] > 2804) = P(X > 2804) for X ~ N(3000, 2100) > 0.99999
+        assert!(count["c"] > 2804);
+    }
+
+    #[test]
+    fn pick_should_pick_the_most_weighted_value() {
+        let mut file = NamedTempFile::new().unwrap();
+        let log = indoc! {r#"
+            [
+                { "value": "a", "count": 10 },
+                { "value": "b", "count": 0 },
+                { "value": "c", "count": 10 }
+            ]
+        "#};
+        write!(file, "{}", log).unwrap();
+
+        let values = vec![String::from("a"), String::from("b"), String::from("c")];
+        let mut picker = RandomPicker::from_log_file(file.path(), values, WeightType::Linear { bias: 0.0 }, InitialCountType::Zero).unwrap();
+
+        for _ in 1..=10 {
+            let value = picker.pick().unwrap();
+            assert_eq!(value, "b");
+        }
+    }
+
+    #[test]
+    fn pick_should_pick_every_value_once_in_a_cycle_if_the_weight_type_is_round_robin() {
+        let mut file = NamedTempFile::new().unwrap();
+        let log = indoc! {r#"
+            [
+                { "value": "a", "count": 3 },
+                { "value": "b", "count": 3 },
+                { "value": "c", "count": 3 },
+                { "value": "d", "count": 3 },
+                { "value": "e", "count": 3 }
+            ]
+        "#};
+        write!(file, "{}", log).unwrap();
+
+        let values = vec![String::from("a"), String::from("b"), String::from("c"), String::from("d"), String::from("e")];
+        let mut picker = RandomPicker::from_log_file(file.path(), values.clone(), WeightType::RoundRobin, InitialCountType::Zero).unwrap();
+
+        for _ in 1..=3 {
+            let mut picked: Vec<String> = (0..values.len()).map(|_| picker.pick().unwrap().to_owned()).collect();
+            picked.sort();
+            assert_eq!(picked, values);
+        }
+    }
+
+    #[test]
+    fn probabilities_should_be_the_same_as_min_only_if_the_weight_type_is_round_robin() {
+        let mut file = NamedTempFile::new().unwrap();
+        let log = indoc! {r#"
+            [
+                { "value": "a", "count": 0 },
+                { "value": "b", "count": 1 },
+                { "value": "c", "count": 0 },
+                { "value": "d", "count": 1 }
+            ]
+        "#};
+        write!(file, "{}", log).unwrap();
+
+        let values = vec![String::from("a"), String::from("b"), String::from("c"), String::from("d")];
+        let groups: HashMap<String, String> = vec![
+            (String::from("a"), String::from("announcements")),
+            (String::from("b"), String::from("announcements")),
+            (String::from("c"), String::from("tips")),
+            (String::from("d"), String::from("tips")),
+        ].into_iter().collect();
+        let probabilities = |weight_type: WeightType| -> Vec<f64> {
+            let mut picker = RandomPickerBuilder::new().weight_type(weight_type.clone()).epsilon(0.5).build(file.path(), values.clone()).unwrap();
+            picker.set_groups(groups.clone());
+            picker.set_group_weight_types(vec![(String::from("tips"), weight_type)].into_iter().collect()).unwrap();
+            picker.probabilities().into_iter().map(|(_, probability)| probability).collect()
+        };
+
+        let round_robin_probabilities = probabilities(WeightType::RoundRobin);
+        assert_eq!(round_robin_probabilities, probabilities(WeightType::MinOnly));
+        // the epsilon lets b and d be picked before a and c catch up
+        assert!(round_robin_probabilities.iter().all(|probability| *probability > 0.0), "{:?}", round_robin_probabilities);
+    }
+
+    #[test]
+    fn set_new_value_boost_should_boost_only_values_which_are_not_in_the_log() {
+        let mut file = NamedTempFile::new().unwrap();
+        let log = indoc! {r#"
+            [
+                { "value": "a", "count": 0 },
+                { "value": "b", "count": 0 }
+            ]
+        "#};
+        write!(file, "{}", log).unwrap();
+
+        let values = vec![String::from("a"), String::from("b"), String::from("c")];
+        let mut picker = RandomPicker::from_log_file(file.path(), values, WeightType::Uniform, InitialCountType::Zero).unwrap();
+        picker.set_new_value_boost(Boost { boost: 2.0, boost_decay: 0.5 }).unwrap();
+
+        let probabilities: Vec<f64> = picker.probabilities().into_iter().map(|(_, probability)| probability).collect();
+        assert_eq!(probabilities, vec![0.2, 0.2, 0.6]);
+    }
+
+    #[test]
+    fn record_failure_should_lower_the_weight_until_the_value_is_picked() {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, r#"[{{ "value": "a", "count": 0 }}, {{ "value": "b", "count": 0 }}]"#).unwrap();
+
+        let values = vec![String::from("a"), String::from("b")];
+        let mut picker = RandomPickerBuilder::new()
+            .weight_type(WeightType::Uniform)
+            .penalize_failures(true)
+            .build(file.path(), values.clone())
+            .unwrap();
+        picker.record_failure(&values[0]);
+        picker.write_log().unwrap();
+
+        let mut picker = RandomPickerBuilder::new()
+            .weight_type(WeightType::Uniform)
+            .penalize_failures(true)
+            .build(file.path(), values.clone())
+            .unwrap();
+        assert_eq!(picker.probabilities(), vec![(&values[0], 1.0 / 3.0), (&values[1], 2.0 / 3.0)]);
+
+        picker.set_excluded_values(vec![values[1].clone()].into_iter().collect());
+        assert_eq!(picker.pick().unwrap(), &values[0]);
+        picker.set_excluded_values(HashSet::new());
+        assert_eq!(picker.probabilities(), vec![(&values[0], 0.5), (&values[1], 0.5)]);
+    }
+
+    #[test]
+    fn record_failure_should_not_change_the_weight_unless_failures_are_penalized() {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, "[]").unwrap();
+        let values = vec![String::from("a"), String::from("b")];
+        let mut picker = RandomPicker::from_log_file(file.path(), values.clone(), WeightType::Uniform, InitialCountType::Zero).unwrap();
+        picker.record_failure(&values[0]);
+
+        assert_eq!(picker.probabilities(), vec![(&values[0], 0.5), (&values[1], 0.5)]);
+    }
+
+    #[test]
+    fn add_item_should_set_the_count_of_a_value_which_is_not_in_the_log() {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, r#"[{{ "value": "a", "count": 10 }}]"#).unwrap();
+
+        let values = vec![String::from("a"), String::from("b")];
+        let mut picker = RandomPicker::from_log_file(file.path(), values.clone(), WeightType::Uniform, InitialCountType::Min).unwrap();
+        picker.add_item(String::from("b"), 3).unwrap();
+        picker.add_item(String::from("c"), 5).unwrap();
+        picker.write_log().unwrap();
+
+        let counts = read_log_counts::<String>(file.path(), LogFormat::Json).unwrap();
+        let expected: HashMap<String, u64> = vec![(String::from("a"), 10), (String::from("b"), 3), (String::from("c"), 5)].into_iter().collect();
+        assert_eq!(counts, expected);
+    }
+
+    #[test]
+    fn add_item_should_fail_if_the_value_is_already_in_the_log() {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, r#"[{{ "value": "a", "count": 10 }}]"#).unwrap();
+
+        let mut picker = RandomPicker::from_log_file(file.path(), vec![String::from("a")], WeightType::Uniform, InitialCountType::Min).unwrap();
+
+        assert_eq!(picker.add_item(String::from("a"), 3), Err(String::from("the value is already in the log")));
+        assert_eq!(picker.counts(), vec![(&String::from("a"), 10)]);
+    }
+
+    #[test]
+    fn force_pick_should_consume_only_the_given_value() {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, r#"[{{ "value": "a", "count": 0 }}, {{ "value": "b", "count": 0 }}]"#).unwrap();
+
+        let values = vec![String::from("a"), String::from("b")];
+        let mut picker = RandomPicker::from_log_file(file.path(), values.clone(), WeightType::MinOnly, InitialCountType::Min).unwrap();
+
+        assert_eq!(picker.force_pick(&values[1]), Some(&values[1]));
+        assert_eq!(picker.force_pick(&values[1]), Some(&values[1]));
+        assert_eq!(picker.force_pick(&String::from("c")), None);
+        assert_eq!(picker.counts(), vec![(&values[0], 0), (&values[1], 2)]);
+    }
+
+    #[test]
+    fn remove_item_should_remove_only_values_which_are_in_the_log() {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, r#"[{{ "value": "a", "count": 10 }}, {{ "value": "b", "count": 2 }}]"#).unwrap();
+
+        let values = vec![String::from("a"), String::from("b"), String::from("c")];
+        let mut picker = RandomPicker::from_log_file(file.path(), values.clone(), WeightType::Uniform, InitialCountType::Min).unwrap();
+
+        assert!(picker.remove_item(&values[0]));
+        assert!(!picker.remove_item(&values[2]));
+        assert!(!picker.remove_item(&String::from("d")));
+        assert_eq!(picker.counts(), vec![(&values[1], 2), (&values[2], 2)]);
+    }
+
+    #[test]
+    fn set_new_value_counts_should_override_only_the_counts_of_values_which_are_not_in_the_log() {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, r#"[{{ "value": "a", "count": 10 }}, {{ "value": "b", "count": 2 }}]"#).unwrap();
+
+        let values = vec![String::from("a"), String::from("b"), String::from("c"), String::from("d")];
+        let mut picker = RandomPicker::from_log_file(file.path(), values.clone(), WeightType::Uniform, InitialCountType::Min).unwrap();
+        picker.set_new_value_counts(vec![(String::from("a"), 1), (String::from("c"), 5)].into_iter().collect());
+
+        assert_eq!(picker.counts(), vec![(&values[0], 10), (&values[1], 2), (&values[2], 5), (&values[3], 2)]);
+    }
 
-impl<T: Hash + Eq + Serialize + DeserializeOwned> RandomPicker<T> {
-    pub fn from_log_file<P: AsRef<Path>>(path: P, values: Vec<T>, weight_type: WeightType, initial_count_type: InitialCountType) -> Result<RandomPicker<T>, String> {
-        if values.is_empty() {
-            return Err(String::from("values is empty"));
-        }
-        if let Err(message) = weight_type.validate() {
-            return Err(String::from(message));
-        }
+    #[test]
+    fn set_new_value_boost_should_fail_if_the_boost_decay_is_larger_than_one() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_owned();
+        file.close().unwrap();
 
-        let path_buf = path.as_ref().to_owned();
-        if !path_buf.exists() {
-            let items = values.into_iter().map(|value| RandomPickerItem { value, count: 0 }).collect();
-            return Ok(RandomPicker {
-                items,
-                path: path_buf,
-                weight_type,
-            });
-        }
+        let values = vec![String::from("a")];
+        let mut picker = RandomPicker::from_log_file(path, values, WeightType::Uniform, InitialCountType::Zero).unwrap();
+        let result = picker.set_new_value_boost(Boost { boost: 2.0, boost_decay: 1.5 });
+        assert_eq!(result, Err(String::from("boost_decay must be between 0 and 1")));
+    }
 
-        let mut file = File::open(&path_buf).map_err(|_| format!("could not open file: {}", path_buf.display()))?;
-        let mut file_reader = BufReader::new(&mut file);
+    #[test]
+    fn pick_should_decay_the_boost() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_owned();
+        file.close().unwrap();
 
-        let log: Vec<RandomPickerItem<T>> = serde_json::from_reader(&mut file_reader).map_err(|e| format!("failed to read log: {}", e))?;
-        let initial_count = match initial_count_type {
-            InitialCountType::Zero => 0,
-            InitialCountType::Min => log.iter().map(|item| item.count).min().unwrap_or(0),
-            InitialCountType::Max => log.iter().map(|item| item.count).max().unwrap_or(0),
-        };
-        let log_map: HashMap<T, u32> = log.into_iter().map(|item| (item.value, item.count)).collect();
-        let value_into_item = |value| {
-            let count = log_map.get(&value).map_or(initial_count, |v| v.to_owned());
-            RandomPickerItem {
-                value,
-                count,
-            }
-        };
-        let items = values.into_iter().map(value_into_item).collect();
+        let values = vec![String::from("a"), String::from("b")];
+        let mut picker = RandomPicker::from_log_file(&path, values.clone(), WeightType::Uniform, InitialCountType::Zero).unwrap();
+        picker.set_new_value_boost(Boost { boost: 4.0, boost_decay: 0.5 }).unwrap();
 
-        Ok(RandomPicker {
-            items,
-            path: path_buf,
-            weight_type,
-        })
+        let mut boosts = Vec::new();
+        for _ in 1..=3 {
+            picker.pick().unwrap();
+            picker.write_log().unwrap();
+            picker = RandomPicker::from_log_file(&path, values.clone(), WeightType::Uniform, InitialCountType::Zero).unwrap();
+            boosts.push(picker.items[0].boost.unwrap().boost);
+        }
+        assert_eq!(boosts, vec![2.0, 1.0, 0.5]);
     }
 
-    pub fn write_log(&self) -> Result<(), String> {
-        let mut file = File::create(&self.path).map_err(|_| format!("could not open file: {}", self.path.display()))?;
-        let file_writer = BufWriter::new(&mut file);
+    #[test]
+    fn decay_counts_should_move_the_counts_toward_the_minimum() {
+        let mut file = NamedTempFile::new().unwrap();
+        let log = indoc! {r#"
+            [
+                { "value": "a", "count": 10 },
+                { "value": "b", "count": 30 },
+                { "value": "c", "count": 110 }
+            ]
+        "#};
+        write!(file, "{}", log).unwrap();
 
-        serde_json::to_writer(file_writer, &self.items).map_err(|e| format!("failed to write log: {}", e))
+        let values = vec![String::from("a"), String::from("b"), String::from("c")];
+        let mut counts = Vec::new();
+        for _ in 1..=3 {
+            let mut picker = RandomPicker::from_log_file(file.path(), values.clone(), WeightType::Uniform, InitialCountType::Zero).unwrap();
+            picker.decay_counts(50.0).unwrap();
+            picker.write_log().unwrap();
+            counts.push(picker.counts().into_iter().map(|(_, count)| count).collect::<Vec<u64>>());
+        }
+        assert_eq!(counts, vec![vec![10, 20, 60], vec![10, 15, 35], vec![10, 13, 23]]);
     }
 
-    pub fn pick(&mut self) -> &T {
-        let counts: Vec<u32> = self.items.iter().map(|item| item.count).collect();
-        let raw_weights = self.weight_type.get_weights(&counts);
-        let weights = if raw_weights.iter().any(|w| w.is_infinite()) {
-            raw_weights.iter().map(|w| if w.is_infinite() { 1.0 } else { 0.0 }).collect()
-        } else if raw_weights.iter().all(|w| *w == 0.0) {
-            vec![1.0; raw_weights.len()]
-        } else {
-            raw_weights
-        };
+    #[test]
+    fn decay_counts_should_keep_a_difference_of_one_at_a_small_rate() {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, r#"[{{ "value": "a", "count": 10 }}, {{ "value": "b", "count": 11 }}]"#).unwrap();
 
-        let weighted_index = WeightedIndex::new(weights).unwrap();
-        let mut rng = ThreadRng::default();
-        let picked_index = weighted_index.sample(&mut rng);
+        let values = vec![String::from("a"), String::from("b")];
+        let mut picker = RandomPicker::from_log_file(file.path(), values, WeightType::Uniform, InitialCountType::Zero).unwrap();
+        picker.decay_counts(0.99).unwrap();
+        let counts: Vec<u64> = picker.counts().into_iter().map(|(_, count)| count).collect();
+        assert_eq!(counts, vec![10, 11]);
+    }
 
-        let item = self.items.get_mut(picked_index).unwrap();
-        item.count += 1;
+    #[test]
+    fn decay_counts_should_fail_if_the_rate_is_out_of_range() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_owned();
+        file.close().unwrap();
 
-        &item.value
+        let values = vec![String::from("a")];
+        let mut picker = RandomPicker::from_log_file(path, values, WeightType::Uniform, InitialCountType::Zero).unwrap();
+        assert_eq!(picker.decay_counts(101.0), Err(String::from("decay_rate must be between 0 and 100")));
+        assert_eq!(picker.decay_counts(f64::NAN), Err(String::from("decay_rate must be between 0 and 100")));
     }
-}
 
-#[cfg(test)]
-mod tests {
-    extern crate indoc;
-    extern crate tempfile;
+    #[test]
+    fn pick_should_not_fail_even_if_all_weights_are_zero() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_owned();
+        file.close().unwrap();
 
-    use super::*;
-    use indoc::indoc;
-    use std::io::Write;
-    use tempfile::NamedTempFile;
+        let values = vec![String::from("a"), String::from("b"), String::from("c")];
+        let mut picker = RandomPicker::from_log_file(path, values.clone(), WeightType::Linear { bias: 0.0 }, InitialCountType::Zero).unwrap();
+        let value = picker.pick().unwrap();
+        assert!(values.iter().any(|s| s == value));
+    }
 
     #[test]
-    fn from_log_file_should_read_the_log_file() {
+    fn probabilities_should_return_the_normalized_weights() {
         let mut file = NamedTempFile::new().unwrap();
         let log = indoc! {r#"
             [
-                { "value": "a", "count": 10 },
-                { "value": "b", "count": 2 },
-                { "value": "c", "count": 5 }
+                { "value": "a", "count": 1 },
+                { "value": "b", "count": 0 },
+                { "value": "c", "count": 1 }
             ]
         "#};
         write!(file, "{}", log).unwrap();
 
-        let expected = vec![
-            RandomPickerItem { value: String::from("a"), count: 10 },
-            RandomPickerItem { value: String::from("b"), count: 2 },
-            RandomPickerItem { value: String::from("c"), count: 5 },
-        ];
-
         let values = vec![String::from("a"), String::from("b"), String::from("c")];
-        let picker = RandomPicker::from_log_file(file.path(), values, WeightType::Uniform, InitialCountType::Zero).unwrap();
-        assert_eq!(picker.items, expected);
+        let picker = RandomPicker::from_log_file(file.path(), values, WeightType::MinOnly, InitialCountType::Zero).unwrap();
+
+        let a = String::from("a");
+        let b = String::from("b");
+        let c = String::from("c");
+        assert_eq!(picker.probabilities(), vec![(&a, 0.0), (&b, 1.0), (&c, 0.0)]);
     }
 
-    macro_rules! from_log_file_tests {
-        (
-            $(
-                $name:ident: $initial_count_type:expr, $log:expr, $values:expr, $expected:expr;
-            )*
-        ) => {
-            $(
-                #[test]
-                fn $name() {
-                    let mut file = NamedTempFile::new().unwrap();
-                    write!(file, "{}", $log).unwrap();
+    #[test]
+    fn probabilities_should_sum_to_one_for_the_linear_weight_type() {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, r#"[{{ "value": "a", "count": 3 }}, {{ "value": "b", "count": 1 }}, {{ "value": "c", "count": 0 }}]"#).unwrap();
 
-                    let picker = RandomPicker::from_log_file(file.path(), $values, WeightType::Uniform, $initial_count_type).unwrap();
-                    assert_eq!(picker.items, $expected);
-                }
-            )*
+        let values = vec![String::from("a"), String::from("b"), String::from("c")];
+        let picker = RandomPicker::from_log_file(file.path(), values, WeightType::Linear { bias: 1.0 }, InitialCountType::Zero).unwrap();
+
+        let probabilities: Vec<f64> = picker.probabilities().into_iter().map(|(_, probability)| probability).collect();
+        let expected = [1.0 / 8.0, 3.0 / 8.0, 4.0 / 8.0];
+        assert!((probabilities.iter().sum::<f64>() - 1.0).abs() < 1e-12, "{:?}", probabilities);
+        for (probability, expected_probability) in probabilities.iter().zip(expected.iter()) {
+            assert!((probability - expected_probability).abs() < 1e-12, "{:?} != {:?}", probabilities, expected);
         }
     }
 
-    from_log_file_tests!(
-        from_log_file_should_set_zero_to_initial_count_if_the_initial_count_type_is_min:
-            InitialCountType::Zero,
-            indoc! {r#"
-                [
-                    { "value": "a", "count": 1 },
-                    { "value": "b", "count": 2 },
-                    { "value": "c", "count": 3 }
-                ]
-            "#},
-            vec![String::from("b"), String::from("c"), String::from("d")],
-            vec![
-                RandomPickerItem { value: String::from("b"), count: 2 },
-                RandomPickerItem { value: String::from("c"), count: 3 },
-                RandomPickerItem { value: String::from("d"), count: 0 },
-            ];
-        from_log_file_should_set_the_minimum_value_of_the_log_to_initial_count_if_the_initial_count_type_is_min:
-            InitialCountType::Min,
-            indoc! {r#"
-                [
-                    { "value": "a", "count": 1 },
-                    { "value": "b", "count": 2 },
-                    { "value": "c", "count": 3 }
-                ]
-            "#},
-            vec![String::from("b"), String::from("c"), String::from("d")],
-            vec![
-                RandomPickerItem { value: String::from("b"), count: 2 },
-                RandomPickerItem { value: String::from("c"), count: 3 },
-                RandomPickerItem { value: String::from("d"), count: 1 },
-            ];
-        from_log_file_should_set_the_maximum_value_of_the_log_to_initial_count_if_the_initial_count_type_is_min: 
-            InitialCountType::Max,
-            indoc! {r#"
-                [
-                    { "value": "a", "count": 1 },
-                    { "value": "b", "count": 2 },
-                    { "value": "c", "count": 3 }
-                ]
-            "#},
-            vec![String::from("b"), String::from("c"), String::from("d")],
-            vec![
-                RandomPickerItem { value: String::from("b"), count: 2 },
-                RandomPickerItem { value: String::from("c"), count: 3 },
-                RandomPickerItem { value: String::from("d"), count: 3 },
-            ];
-        from_log_file_should_set_zero_if_the_log_is_empty_and_the_initial_count_type_is_min: 
-            InitialCountType::Min,
-            "[]",
-            vec![String::from("a")],
-            vec![
-                RandomPickerItem { value: String::from("a"), count: 0 },
-            ];
-        from_log_file_should_set_zero_if_the_log_is_empty_and_the_initial_count_type_is_max: 
-            InitialCountType::Max,
-            "[]",
-            vec![String::from("a")],
-            vec![
-                RandomPickerItem { value: String::from("a"), count: 0 },
-            ];
-    );
-
     #[test]
-    fn from_log_file_should_return_a_random_picker_which_has_zero_initialized_items_if_the_log_file_does_not_exist() {
+    fn probabilities_should_be_uniform_if_all_weights_are_zero() {
         let file = NamedTempFile::new().unwrap();
         let path = file.path().to_owned();
         file.close().unwrap();
 
-        let expected = vec![
-            RandomPickerItem { value: String::from("a"), count: 0 },
-            RandomPickerItem { value: String::from("b"), count: 0 },
-            RandomPickerItem { value: String::from("c"), count: 0 },
-        ];
+        let values = vec![String::from("a"), String::from("b")];
+        let picker = RandomPicker::from_log_file(path, values, WeightType::Linear { bias: 0.0 }, InitialCountType::Zero).unwrap();
 
-        let values = vec![String::from("a"), String::from("b"), String::from("c")];
-        let picker = RandomPicker::from_log_file(path, values, WeightType::Uniform, InitialCountType::Zero).unwrap();
-        assert_eq!(picker.items, expected);
+        let probabilities: Vec<f64> = picker.probabilities().into_iter().map(|(_, probability)| probability).collect();
+        assert_eq!(probabilities, vec![0.5, 0.5]);
     }
 
     #[test]
-    fn from_log_file_should_fail_if_the_values_is_empty() {
+    fn probabilities_should_not_change_the_counts_or_the_random_number_generator() {
         let file = NamedTempFile::new().unwrap();
         let path = file.path().to_owned();
         file.close().unwrap();
 
-        let result = RandomPicker::from_log_file(path, Vec::<String>::new(), WeightType::Uniform, InitialCountType::Zero);
-        assert!(result.is_err());
+        let values = vec![String::from("a"), String::from("b"), String::from("c")];
+        let mut picker = RandomPickerBuilder::new().seed(0).build(&path, values.clone()).unwrap();
+        let mut other_picker = RandomPickerBuilder::new().seed(0).build(&path, values).unwrap();
+        picker.probabilities();
+
+        assert_eq!(picker.counts(), other_picker.counts());
+        assert_eq!(picker.pick().unwrap(), other_picker.pick().unwrap());
     }
 
     #[test]
-    fn pick_should_pick_the_value_randomly() {
+    fn probabilities_should_pick_a_group_first_and_then_a_value_in_it() {
         let mut file = NamedTempFile::new().unwrap();
         let log = indoc! {r#"
             [
-                { "value": "a", "count": 30 },
-                { "value": "b", "count": 0 },
-                { "value": "c", "count": 20 }
+                { "value": "a", "count": 0 },
+                { "value": "b", "count": 2 },
+                { "value": "c", "count": 0 },
+                { "value": "d", "count": 1 }
             ]
         "#};
         write!(file, "{}", log).unwrap();
 
-        let values = vec![String::from("a"), String::from("b"), String::from("c")];
-        let picker_template = RandomPicker::from_log_file(file.path(), values.clone(), WeightType::Linear { bias: 20.0 }, InitialCountType::Zero).unwrap();
+        let values = vec![String::from("a"), String::from("b"), String::from("c"), String::from("d")];
+        let mut picker = RandomPicker::from_log_file(file.path(), values, WeightType::Linear { bias: 1.0 }, InitialCountType::Zero).unwrap();
+        picker.set_groups(vec![
+            (String::from("a"), String::from("group1")),
+            (String::from("b"), String::from("group1")),
+            (String::from("c"), String::from("group2")),
+        ].into_iter().collect());
+
+        // group1 (count 2), group2 (count 0) and the ungrouped (count 1) have weights 1, 3 and 2,
+        // and a and b in group1 have weights 3 and 1
+        let probabilities: Vec<f64> = picker.probabilities().into_iter().map(|(_, probability)| probability).collect();
+        let expected = [1.0 / 6.0 * 3.0 / 4.0, 1.0 / 6.0 * 1.0 / 4.0, 3.0 / 6.0, 2.0 / 6.0];
+        for (probability, expected_probability) in probabilities.iter().zip(expected.iter()) {
+            assert!((probability - expected_probability).abs() < 1e-12, "{:?} != {:?}", probabilities, expected);
+        }
+    }
+
+    #[test]
+    fn group_smoothing_should_give_every_group_a_share() {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, r#"[{{ "value": "a", "count": 3 }}, {{ "value": "b", "count": 0 }}]"#).unwrap();
+
+        let probabilities = |alpha: f64| {
+            let values = vec![String::from("a"), String::from("b")];
+            let mut picker = RandomPickerBuilder::new().weight_type(WeightType::MinOnly).group_smoothing(alpha).build(file.path(), values).unwrap();
+            picker.set_groups(vec![
+                (String::from("a"), String::from("group1")),
+                (String::from("b"), String::from("group2")),
+            ].into_iter().collect());
+            picker.probabilities().into_iter().map(|(_, probability)| probability).collect::<Vec<f64>>()
+        };
+
+        assert_eq!(probabilities(0.0), vec![0.0, 1.0]);
+        // with 3 posts in 2 groups, group1 gets (3 * 0 + 1) / (3 + 2 * 1) and group2 gets (3 * 1 + 1) / (3 + 2 * 1)
+        let smoothed = probabilities(1.0);
+        assert!((smoothed[0] - 0.2).abs() < 1e-12 && (smoothed[1] - 0.8).abs() < 1e-12, "{:?}", smoothed);
+    }
+
+    #[test]
+    fn build_should_fail_if_the_group_smoothing_is_negative() {
+        let file = NamedTempFile::new().unwrap();
+        let result = RandomPickerBuilder::new().group_smoothing(-1.0).build(file.path(), vec![String::from("a")]);
+        assert_eq!(result.err(), Some(String::from("group_smoothing must be finite and non-negative")));
+    }
+
+    #[test]
+    fn pick_should_pick_groups_with_the_expected_probabilities() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_owned();
+        file.close().unwrap();
+
+        let values = vec![String::from("a"), String::from("b"), String::from("c"), String::from("d")];
+        let mut picker = RandomPickerBuilder::new().seed(1).build(path, values.clone()).unwrap();
+        picker.set_groups(vec![
+            (String::from("a"), String::from("quotes")),
+            (String::from("b"), String::from("quotes")),
+            (String::from("c"), String::from("quotes")),
+            (String::from("d"), String::from("tips")),
+        ].into_iter().collect());
 
         let mut count: HashMap<String, u64> = values.into_iter().map(|s| (s, 0)).collect();
         for _ in 1..=10000 {
-            let mut picker = picker_template.clone();
-            let value = picker.pick();
+            let value = picker.pick().unwrap();
             *count.get_mut(value).unwrap() += 1;
         }
-        // P(count["a"] > 1829) = P(X > 1829) for X ~ N(2000, 1600) > 0.99999
-        assert!(count["a"] > 1829);
-        // P(count["b"] > 4786) = P(X > 4786) for X ~ N(5000, 2500) > 0.99999
-        assert!(count["b"] > 4786);
-        // P(count["c"] > 2804) = P(X > 2804) for X ~ N(3000, 2100) > 0.99999
-        assert!(count["c"] > 2804);
+        // P(count["d"] > 4786) = P(X > 4786) for X ~ N(5000, 2500) > 0.99999
+        assert!(count["d"] > 4786);
+        // P(count[*] > 1504) = P(X > 1504) for X ~ N(5000/3, 12500/9) > 0.99999 for a, b and c
+        assert!(count["a"] > 1504);
+        assert!(count["b"] > 1504);
+        assert!(count["c"] > 1504);
     }
 
     #[test]
-    fn pick_should_pick_the_most_weighted_value() {
+    fn probabilities_should_use_the_weight_type_of_each_group() {
         let mut file = NamedTempFile::new().unwrap();
         let log = indoc! {r#"
             [
-                { "value": "a", "count": 10 },
-                { "value": "b", "count": 0 },
-                { "value": "c", "count": 10 }
+                { "value": "a", "count": 0 },
+                { "value": "b", "count": 1 },
+                { "value": "c", "count": 0 },
+                { "value": "d", "count": 1 }
             ]
         "#};
         write!(file, "{}", log).unwrap();
 
-        let values = vec![String::from("a"), String::from("b"), String::from("c")];
-        let mut picker = RandomPicker::from_log_file(file.path(), values, WeightType::Linear { bias: 0.0 }, InitialCountType::Zero).unwrap();
+        let values = vec![String::from("a"), String::from("b"), String::from("c"), String::from("d")];
+        let mut picker = RandomPicker::from_log_file(file.path(), values, WeightType::Uniform, InitialCountType::Zero).unwrap();
+        picker.set_groups(vec![
+            (String::from("a"), String::from("announcements")),
+            (String::from("b"), String::from("announcements")),
+            (String::from("c"), String::from("tips")),
+            (String::from("d"), String::from("tips")),
+        ].into_iter().collect());
+        picker.set_group_weight_types(vec![
+            (String::from("tips"), WeightType::MinOnly),
+        ].into_iter().collect()).unwrap();
 
-        for _ in 1..=10 {
-            let value = picker.pick();
-            assert_eq!(value, "b");
+        let probabilities: Vec<f64> = picker.probabilities().into_iter().map(|(_, probability)| probability).collect();
+        assert_eq!(probabilities, vec![0.25, 0.25, 0.5, 0.0]);
+    }
+
+    #[test]
+    fn pick_should_pick_with_the_weight_type_of_each_group() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_owned();
+        file.close().unwrap();
+
+        let values = vec![String::from("a"), String::from("b"), String::from("c"), String::from("d")];
+        let mut picker = RandomPickerBuilder::new().seed(1).build(path, values.clone()).unwrap();
+        picker.set_groups(vec![
+            (String::from("a"), String::from("announcements")),
+            (String::from("b"), String::from("announcements")),
+            (String::from("c"), String::from("tips")),
+            (String::from("d"), String::from("tips")),
+        ].into_iter().collect());
+        picker.set_group_weight_types(vec![
+            (String::from("tips"), WeightType::MinOnly),
+        ].into_iter().collect()).unwrap();
+
+        let mut count: HashMap<String, u64> = values.into_iter().map(|s| (s, 0)).collect();
+        for _ in 1..=1000 {
+            let value = picker.pick().unwrap();
+            *count.get_mut(value).unwrap() += 1;
         }
+        // MinOnly keeps c and d at most one apart, while Uniform lets a and b drift apart
+        assert!(count["c"].abs_diff(count["d"]) <= 1);
+        assert!(count["a"].abs_diff(count["b"]) > 1);
     }
 
     #[test]
-    fn pick_should_not_fail_even_if_all_weights_are_zero() {
+    fn probabilities_should_ignore_the_excluded_values() {
+        let mut file = NamedTempFile::new().unwrap();
+        let log = indoc! {r#"
+            [
+                { "value": "a", "count": 0 },
+                { "value": "b", "count": 1 },
+                { "value": "c", "count": 1 }
+            ]
+        "#};
+        write!(file, "{}", log).unwrap();
+
+        let values = vec![String::from("a"), String::from("b"), String::from("c")];
+        let mut picker = RandomPicker::from_log_file(file.path(), values, WeightType::MinOnly, InitialCountType::Zero).unwrap();
+        picker.set_excluded_values(vec![String::from("a")].into_iter().collect());
+
+        let probabilities: Vec<f64> = picker.probabilities().into_iter().map(|(_, probability)| probability).collect();
+        assert_eq!(probabilities, vec![0.0, 0.5, 0.5]);
+        assert!(picker.has_eligible_values());
+    }
+
+    #[test]
+    fn write_log_should_keep_the_counts_of_the_excluded_values() {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, r#"[{{ "value": "a", "count": 5 }}, {{ "value": "b", "count": 0 }}]"#).unwrap();
+
+        let values = vec![String::from("a"), String::from("b")];
+        let mut picker = RandomPicker::from_log_file(file.path(), values.clone(), WeightType::Uniform, InitialCountType::Zero).unwrap();
+        picker.set_excluded_values(vec![String::from("a")].into_iter().collect());
+        assert_eq!(picker.pick().unwrap(), "b");
+        picker.write_log().unwrap();
+
+        let read_picker = RandomPicker::from_log_file(file.path(), values, WeightType::Uniform, InitialCountType::Zero).unwrap();
+        assert_eq!(read_picker.counts(), vec![(&String::from("a"), 5), (&String::from("b"), 1)]);
+    }
+
+    #[test]
+    fn has_eligible_values_should_return_false_if_all_values_are_excluded() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_owned();
+        file.close().unwrap();
+
+        let values = vec![String::from("a")];
+        let mut picker = RandomPicker::from_log_file(path, values.clone(), WeightType::Uniform, InitialCountType::Zero).unwrap();
+        picker.set_excluded_values(values.into_iter().collect());
+        assert!(!picker.has_eligible_values());
+    }
+
+    #[test]
+    fn probabilities_should_be_multiplied_by_the_weight_multipliers() {
         let file = NamedTempFile::new().unwrap();
         let path = file.path().to_owned();
         file.close().unwrap();
 
         let values = vec![String::from("a"), String::from("b"), String::from("c")];
-        let mut picker = RandomPicker::from_log_file(path, values.clone(), WeightType::Linear { bias: 0.0 }, InitialCountType::Zero).unwrap();
-        let value = picker.pick();
-        assert!(values.iter().any(|s| s == value));
+        let external = WeightType::External { path: PathBuf::from("weights.csv") };
+        let mut picker = RandomPicker::from_log_file(path, values, external, InitialCountType::Zero).unwrap();
+        picker.set_weight_multipliers(vec![
+            (String::from("a"), 3.0),
+            (String::from("b"), 0.0),
+        ].into_iter().collect());
+
+        let probabilities: Vec<f64> = picker.probabilities().into_iter().map(|(_, probability)| probability).collect();
+        assert_eq!(probabilities, vec![0.75, 0.0, 0.25]);
+    }
+
+    #[test]
+    fn pick_should_pick_the_same_values_for_the_same_seed_if_the_items_are_sorted() {
+        let mut file = NamedTempFile::new().unwrap();
+        let log = indoc! {r#"
+            [
+                { "value": "a", "count": 0 },
+                { "value": "b", "count": 0 },
+                { "value": "c", "count": 0 },
+                { "value": "d", "count": 0 }
+            ]
+        "#};
+        write!(file, "{}", log).unwrap();
+
+        let pick_values = |values: Vec<String>| {
+            let mut picker = RandomPickerBuilder::new().seed(42).build(file.path(), values).unwrap();
+            picker.sort_items();
+            (1..=10).map(|_| picker.pick().unwrap().to_owned()).collect::<Vec<String>>()
+        };
+
+        let values1 = vec![String::from("a"), String::from("b"), String::from("c"), String::from("d")];
+        let values2 = vec![String::from("c"), String::from("a"), String::from("d"), String::from("b")];
+        assert_eq!(pick_values(values1), pick_values(values2));
     }
 
     #[test]
@@ -328,10 +2416,11 @@ mod tests {
         let values = vec![String::from("a"), String::from("b"), String::from("c")];
         let picker_template = RandomPicker::from_log_file(file.path(), values.clone(), WeightType::Linear { bias: f64::INFINITY }, InitialCountType::Zero).unwrap();
 
+        let mut rng = StdRng::seed_from_u64(0);
         let mut count: HashMap<String, u64> = values.into_iter().map(|s| (s, 0)).collect();
         for _ in 1..=10000 {
             let mut picker = picker_template.clone();
-            let value = picker.pick();
+            let value = picker.pick_with_rng(&mut rng).unwrap();
             *count.get_mut(value).unwrap() += 1;
         }
         // P(count[*] > 3132) = P(X > 3132) for X ~ N(10000/3, 20000/9) > 0.99999
@@ -0,0 +1,77 @@
+use std::path::Path;
+
+use super::dead_letter::{read_dead_letters, write_dead_letters};
+use super::request::Poster;
+
+// Re-sends every dead letter, and keeps only the ones which fail again with their new errors.
+pub async fn replay<P: Poster>(poster: &P, path: &Path) -> Result<(), String> {
+    let entries = read_dead_letters(path)?;
+    let total = entries.len();
+    let mut remaining = Vec::new();
+    for mut entry in entries {
+        match poster.post_json(&entry.url, &entry.body).await {
+            Ok(_) => println!("replayed `{}`", entry.message_id),
+            Err(error) => {
+                entry.error = String::from(error);
+                println!("failed to replay `{}`: {}", entry.message_id, entry.error);
+                remaining.push(entry);
+            },
+        }
+    }
+    write_dead_letters(path, &remaining)?;
+
+    if remaining.is_empty() {
+        println!("replayed {} dead letters", total);
+        Ok(())
+    } else {
+        Err(format!("{} of {} dead letters failed again and were kept in {}", remaining.len(), total, path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate tempfile;
+
+    use super::*;
+    use chrono::Utc;
+    use tempfile::NamedTempFile;
+
+    use super::super::dead_letter::{DeadLetter, append_dead_letters};
+    use super::super::request::ReqwestPoster;
+
+    #[tokio::test]
+    async fn replay_should_resend_the_bodies_and_keep_only_the_failed_entries() {
+        let mut server = mockito::Server::new_async().await;
+        let replayed_mock = server.mock("POST", "/replayed")
+            .match_header("content-type", "application/json")
+            .match_body(r#"{"content":"message1"}"#)
+            .with_status(204)
+            .expect(1)
+            .create_async().await;
+        let failed_mock = server.mock("POST", "/failed")
+            .with_status(400)
+            .expect(1)
+            .create_async().await;
+
+        let dead_letter_file = NamedTempFile::new().unwrap();
+        let dead_letter_path = dead_letter_file.path().to_owned();
+        let entry = |message_id: &str, path: &str, content: &str| DeadLetter {
+            failed_at: Utc::now(),
+            message_id: String::from(message_id),
+            url: format!("{}{}", server.url(), path),
+            body: format!(r#"{{"content":"{}"}}"#, content),
+            error: String::from("failed to post"),
+        };
+        append_dead_letters(&dead_letter_path, &[entry("abc", "/replayed", "message1"), entry("def", "/failed", "message2")]).unwrap();
+
+        let result = replay(&ReqwestPoster::shared(), &dead_letter_path).await;
+        replayed_mock.assert_async().await;
+        failed_mock.assert_async().await;
+
+        assert_eq!(result, Err(format!("1 of 2 dead letters failed again and were kept in {}", dead_letter_path.display())));
+        let entries = read_dead_letters(&dead_letter_path).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].message_id, "def");
+        assert_ne!(entries[0].error, "failed to post");
+    }
+}
@@ -1,32 +1,1034 @@
-use serde::Serialize;
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+use reqwest::multipart::{Form, Part};
+use serde::{Serialize, Deserialize};
+use std::path::Path;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
 
+use super::gzip::gzip;
 use super::message::Message;
+use super::settings::HumanDuration;
+
+#[derive(PartialEq, Eq, Clone, Deserialize, Debug)]
+#[serde(tag = "type")]
+pub enum Platform {
+    Discord,
+    Telegram { chat_id: String },
+    Matrix { room_id: String, access_token: String },
+    // an incoming webhook of Slack, which posts only text
+    Slack,
+}
+
+impl Platform {
+    pub fn default() -> Platform {
+        Platform::Discord
+    }
+
+    pub fn max_content_length(&self) -> usize {
+        match self {
+            Platform::Discord => 2000,
+            Platform::Telegram { .. } => 4096,
+            Platform::Matrix { .. } => 32768,
+            Platform::Slack => 40000,
+        }
+    }
+}
 
 #[derive(Serialize)]
 pub struct SimpleWebhookRequest<'a> {
     pub username: &'a Option<String>,
     pub avatar_url: &'a Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nonce: &'a Option<String>,
+    // Discord drops a message whose nonce is already used only if this is set
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub enforce_nonce: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message_reference: &'a Option<MessageReference>,
+    // such as 4 to suppress embeds and 4096 to post silently
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flags: &'a Option<u32>,
+    // required by Discord forum channels to create a post
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thread_name: &'a Option<String>,
+    #[serde(skip)]
+    pub signing: &'a Option<Signing>,
+    // gzips JSON bodies larger than `COMPRESSION_THRESHOLD`
+    #[serde(skip)]
+    pub compress: bool,
+    // the statuses which count as success instead of 2xx, for receivers which answer with other statuses
+    #[serde(skip)]
+    pub accepted_statuses: &'a Option<Vec<u16>>,
     #[serde(flatten)]
     pub message: &'a Message,
 }
 
-pub async fn post(webhook_url: &str, request: &SimpleWebhookRequest<'_>) -> Result<(), String> {
-    let content_json = serde_json::to_string(request).unwrap();
+// smaller bodies are sent as they are, since compressing them saves less than it costs
+pub const COMPRESSION_THRESHOLD: usize = 1024;
+
+pub const DEFAULT_SIGNATURE_HEADER: &str = "X-Signature";
+
+// Signs the bytes of the JSON body as sent, gzipped if it is compressed, with HMAC-SHA256 for receivers which verify it; the multipart bodies of attachments are never signed.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Signing {
+    pub secret: String,
+    pub header: String,
+}
+
+#[derive(PartialEq, Eq, Clone, Serialize, Debug)]
+pub struct MessageReference {
+    pub message_id: String,
+}
+
+#[derive(PartialEq, Eq, Clone, Deserialize, Debug)]
+pub struct RetrySettings {
+    pub max_attempts: u32,
+    #[serde(default = "RetrySettings::default_initial_delay")]
+    pub initial_delay: HumanDuration,
+    // caps the doubled delay
+    #[serde(default)]
+    pub max_delay: Option<HumanDuration>,
+    // no retry is made if it would start after this long from the first attempt
+    #[serde(default)]
+    pub max_total_duration: Option<HumanDuration>,
+}
+
+impl RetrySettings {
+    fn default_initial_delay() -> HumanDuration {
+        HumanDuration::from_secs(1)
+    }
+}
+
+pub enum PostError {
+    Retryable(String),
+    Fatal(String),
+}
+
+impl From<PostError> for String {
+    fn from(error: PostError) -> String {
+        match error {
+            PostError::Retryable(message) | PostError::Fatal(message) => message,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub struct PostResponse {
+    pub status: u16,
+    // only returned by Discord when the webhook URL has `wait=true`
+    pub message_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct DiscordMessage {
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct DiscordError {
+    code: u64,
+    message: String,
+    #[serde(default)]
+    errors: serde_json::Value,
+}
+
+#[derive(Serialize)]
+struct TelegramRequest<'a> {
+    chat_id: &'a str,
+    text: &'a str,
+}
+
+#[derive(Serialize)]
+struct SlackRequest<'a> {
+    text: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    username: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    icon_url: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct MatrixRequest<'a> {
+    msgtype: &'a str,
+    body: &'a str,
+}
+
+static SHARED_CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+
+fn shared_client() -> &'static reqwest::Client {
+    SHARED_CLIENT.get_or_init(reqwest::Client::new)
+}
+
+// Sends the posts and the edits, so that tests and other transports can replace the HTTP client.
+pub trait Poster {
+    // Fails with a retryable error after network errors, 429 and 5xx responses.
+    async fn post(&self, webhook_url: &str, platform: &Platform, request: &SimpleWebhookRequest<'_>) -> Result<PostResponse, PostError>;
+
+    // Replaces the content and the embeds of a message posted by the webhook, which only Discord supports.
+    async fn edit(&self, webhook_url: &str, message_id: &str, message: &Message) -> Result<PostResponse, String>;
+
+    // Posts a body from `json_body` as it is, without signing or compressing it.
+    async fn post_json(&self, webhook_url: &str, body: &str) -> Result<PostResponse, PostError>;
+}
+
+pub struct ReqwestPoster<'a> {
+    client: &'a reqwest::Client,
+}
+
+impl ReqwestPoster<'_> {
+    // Reuses the connections of the given client, instead of the shared one.
+    pub fn new(client: &reqwest::Client) -> ReqwestPoster<'_> {
+        ReqwestPoster { client }
+    }
+
+    pub fn shared() -> ReqwestPoster<'static> {
+        ReqwestPoster::new(shared_client())
+    }
+}
+
+impl Poster for ReqwestPoster<'_> {
+    async fn post(&self, webhook_url: &str, platform: &Platform, request: &SimpleWebhookRequest<'_>) -> Result<PostResponse, PostError> {
+        post_with_client(self.client, webhook_url, platform, request).await
+    }
+
+    // The edit is not retried, since the next edit replaces the content anyway.
+    async fn edit(&self, webhook_url: &str, message_id: &str, message: &Message) -> Result<PostResponse, String> {
+        if message.attachment().is_some() {
+            return Err(String::from("a message with an attachment cannot be edited"));
+        }
+        let body = serde_json::to_string(message).map_err(|e| format!("failed to serialize the message: {}", e))?;
+        let response = self.client.patch(message_url(webhook_url, message_id)?)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body)
+            .send().await
+            .map_err(|e| format!("failed to edit: {}", e))?;
+        read_response(&Platform::Discord, None, response).await.map_err(String::from)
+    }
+
+    async fn post_json(&self, webhook_url: &str, body: &str) -> Result<PostResponse, PostError> {
+        let response = self.client.post(webhook_url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body.to_owned())
+            .send().await
+            .map_err(|e| PostError::Retryable(format!("failed to post: {}", e)))?;
+        read_response(&Platform::Discord, None, response).await
+    }
+}
+
+// The JSON body which is posted to the webhook URL, or None for Matrix and attachments, which are sent in other ways.
+pub fn json_body(platform: &Platform, request: &SimpleWebhookRequest<'_>) -> Result<Option<String>, String> {
+    match platform {
+        Platform::Discord if request.message.attachment().is_some() => Ok(None),
+        Platform::Discord => Ok(Some(serde_json::to_string(request).unwrap())),
+        Platform::Telegram { chat_id } => telegram_json(chat_id, request.message).map(Some),
+        Platform::Slack => slack_json(request).map(Some),
+        Platform::Matrix { .. } => Ok(None),
+    }
+}
+
+// Retries after network errors, 429 and 5xx responses, doubling the delay each time up to `max_delay`, and pushes each retry to `retries`.
+// The same request is sent on every attempt, so Discord drops a duplicated delivery only if the request has a nonce with `enforce_nonce`.
+pub async fn post_with_retry<P: Poster>(poster: &P, webhook_url: &str, platform: &Platform, request: &SimpleWebhookRequest<'_>, retry: &RetrySettings, retries: &mut Vec<String>) -> Result<PostResponse, String> {
+    let started_at = Instant::now();
+    let max_delay = retry.max_delay.map(HumanDuration::to_std);
+    let max_total_duration = retry.max_total_duration.map(HumanDuration::to_std);
+    let mut delay = cap_delay(retry.initial_delay.to_std(), max_delay);
+    let mut attempt = 1;
+    loop {
+        match poster.post(webhook_url, platform, request).await {
+            Ok(response) => return Ok(response),
+            Err(PostError::Retryable(message)) if attempt < retry.max_attempts => {
+                if max_total_duration.is_some_and(|max_total_duration| started_at.elapsed() + delay > max_total_duration) {
+                    retries.push(format!("giving up retrying within {}ms", max_total_duration.unwrap().as_millis()));
+                    return Err(message);
+                }
+                retries.push(format!("retrying in {}ms: {}", delay.as_millis(), message));
+            },
+            Err(PostError::Retryable(message)) | Err(PostError::Fatal(message)) => return Err(message),
+        }
+
+        tokio::time::sleep(delay).await;
+        delay = cap_delay(delay.saturating_mul(2), max_delay);
+        attempt += 1;
+    }
+}
+
+fn cap_delay(delay: Duration, max_delay: Option<Duration>) -> Duration {
+    max_delay.map_or(delay, |max_delay| delay.min(max_delay))
+}
+
+// Sends a GET request without posting anything.
+// Discord returns the webhook itself for a valid webhook URL, while the other platforms only need to respond without a server error.
+pub async fn check_reachable(webhook_url: &str, platform: &Platform) -> Result<(), String> {
+    let response = shared_client().get(webhook_url).send().await.map_err(|e| format!("failed to connect: {}", e))?;
+    let response_status = response.status();
+    let reachable = match platform {
+        Platform::Discord => response_status.is_success(),
+        _ => !response_status.is_server_error(),
+    };
+    if reachable { Ok(()) } else { Err(format!("failed with {}", response_status)) }
+}
 
-    let client = reqwest::Client::new();
-    let api_request = client.post(webhook_url)
-        .header(reqwest::header::CONTENT_TYPE, "application/json")
-        .body(content_json);
-    let response = api_request.send().await.map_err(|e| format!("failed to post: {}", e))?;
+// Posts once through the given client, which `ReqwestPoster` wraps for the callers of `Poster`.
+pub async fn post_with_client(client: &reqwest::Client, webhook_url: &str, platform: &Platform, request: &SimpleWebhookRequest<'_>) -> Result<PostResponse, PostError> {
+    let (api_request, signed_body) = match platform {
+        Platform::Discord => {
+            let content_json = serde_json::to_string(request).unwrap();
+            match request.message.attachment() {
+                // the boundary of the multipart body is chosen by reqwest, so its bytes cannot be signed
+                Some(_) if request.signing.is_some() => return Err(PostError::Fatal(String::from("a message with an attachment cannot be signed"))),
+                Some(file) => (client.post(webhook_url).multipart(multipart_form(content_json, file).map_err(PostError::Fatal)?), Vec::new()),
+                // the gzipped bytes are signed, since they are what the receiver gets
+                None if request.compress && content_json.len() > COMPRESSION_THRESHOLD => {
+                    let body = gzip(content_json.as_bytes());
+                    let api_request = client.post(webhook_url)
+                        .header(reqwest::header::CONTENT_TYPE, "application/json")
+                        .header(reqwest::header::CONTENT_ENCODING, "gzip")
+                        .body(body.clone());
+                    (api_request, body)
+                },
+                None => {
+                    let api_request = client.post(webhook_url)
+                        .header(reqwest::header::CONTENT_TYPE, "application/json")
+                        .body(content_json.clone());
+                    (api_request, content_json.into_bytes())
+                },
+            }
+        },
+        Platform::Telegram { chat_id } => {
+            let body = telegram_json(chat_id, request.message).map_err(PostError::Fatal)?;
+            let api_request = client.post(webhook_url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body.clone());
+            (api_request, body.into_bytes())
+        },
+        Platform::Slack => {
+            let body = slack_json(request).map_err(PostError::Fatal)?;
+            let api_request = client.post(webhook_url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body.clone());
+            (api_request, body.into_bytes())
+        },
+        Platform::Matrix { room_id, access_token } => {
+            // the nonce is reused across retries, so the homeserver can drop duplicated deliveries by the transaction id
+            let txn_id = request.nonce.clone().unwrap_or_else(|| rand::random::<u64>().to_string());
+            let body = matrix_json(request.message).map_err(PostError::Fatal)?;
+            let api_request = client.put(matrix_url(webhook_url, room_id, &txn_id).map_err(PostError::Fatal)?)
+                .bearer_auth(access_token)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body.clone());
+            (api_request, body.into_bytes())
+        },
+    };
+    let api_request = match request.signing {
+        Some(signing) => api_request.header(signing.header.as_str(), sign(&signing.secret, &signed_body).map_err(PostError::Fatal)?),
+        None => api_request,
+    };
+    let response = api_request.send().await.map_err(|e| PostError::Retryable(format!("failed to post: {}", e)))?;
+    read_response(platform, request.accepted_statuses.as_deref(), response).await
+}
 
+async fn read_response(platform: &Platform, accepted_statuses: Option<&[u16]>, response: reqwest::Response) -> Result<PostResponse, PostError> {
     let response_status = response.status();
-    if !response_status.is_success() {
-        return if let Ok(response_body) = response.text().await {
-            Err(format!("failed with {}: {}", response_status, response_body))
+    let accepted = match accepted_statuses {
+        Some(accepted_statuses) => accepted_statuses.contains(&response_status.as_u16()),
+        None => response_status.is_success(),
+    };
+    if !accepted {
+        let message = if let Ok(response_body) = response.text().await {
+            format!("failed with {}: {}", response_status, format_error_body(platform, &response_body))
+        } else {
+            format!("failed with {}", response_status)
+        };
+        return if response_status.is_server_error() || response_status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            Err(PostError::Retryable(message))
         } else {
-            Err(format!("failed with {}", response_status))
+            Err(PostError::Fatal(message))
+        };
+    }
+
+    let message_id = match platform {
+        Platform::Discord => response.text().await.ok()
+            .and_then(|response_body| serde_json::from_str::<DiscordMessage>(&response_body).ok())
+            .map(|message| message.id),
+        _ => None,
+    };
+    Ok(PostResponse { status: response_status.as_u16(), message_id })
+}
+
+fn format_error_body(platform: &Platform, response_body: &str) -> String {
+    let error = match platform {
+        Platform::Discord => serde_json::from_str::<DiscordError>(response_body),
+        _ => return response_body.to_owned(),
+    };
+
+    match error {
+        Ok(error) => {
+            let mut field_errors = Vec::new();
+            collect_field_errors(&error.errors, "", &mut field_errors);
+            if field_errors.is_empty() {
+                format!("Discord error {}: {}", error.code, error.message)
+            } else {
+                format!("Discord error {}: {} ({})", error.code, error.message, field_errors.join("; "))
+            }
+        },
+        Err(_) => response_body.to_owned(),
+    }
+}
+
+fn collect_field_errors(errors: &serde_json::Value, path: &str, field_errors: &mut Vec<String>) {
+    let map = match errors.as_object() {
+        Some(map) => map,
+        None => return,
+    };
+
+    for (key, value) in map {
+        if key == "_errors" {
+            let messages = value.as_array().into_iter().flatten().filter_map(|error| error.get("message")?.as_str());
+            field_errors.extend(messages.map(|message| format!("{}: {}", path, message)));
+        } else if path.is_empty() {
+            collect_field_errors(value, key, field_errors);
+        } else {
+            collect_field_errors(value, &format!("{}.{}", path, key), field_errors);
         }
     }
+}
+
+fn sign(secret: &str, body: &[u8]) -> Result<String, String> {
+    let to_error = |e| format!("failed to sign the request: {}", e);
+    let key = PKey::hmac(secret.as_bytes()).map_err(to_error)?;
+    let mut signer = Signer::new(MessageDigest::sha256(), &key).map_err(to_error)?;
+    let signature = signer.sign_oneshot_to_vec(body).map_err(to_error)?;
+    Ok(signature.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
 
-    Ok(())
+// `webhook_url/messages/message_id`, keeping the query such as `thread_id`
+fn message_url(webhook_url: &str, message_id: &str) -> Result<String, String> {
+    let mut url = reqwest::Url::parse(webhook_url).map_err(|e| format!("invalid webhook_url: {}", e))?;
+    url.path_segments_mut()
+        .map_err(|_| format!("invalid webhook_url: {}", webhook_url))?
+        .pop_if_empty()
+        .push("messages")
+        .push(message_id);
+    Ok(url.into())
+}
+
+fn telegram_json(chat_id: &str, message: &Message) -> Result<String, String> {
+    // sendMessage takes no file, which would be dropped without the error
+    if message.attachment().is_some() {
+        return Err(String::from("Telegram does not support messages with files"));
+    }
+    let text = message.plain_text().ok_or_else(|| String::from("Telegram only supports messages with content or plain_fallback"))?;
+    let request = TelegramRequest { chat_id, text };
+    Ok(serde_json::to_string(&request).unwrap())
+}
+
+fn slack_json(request: &SimpleWebhookRequest<'_>) -> Result<String, String> {
+    let text = request.message.plain_text().ok_or_else(|| String::from("Slack only supports messages with content or plain_fallback"))?;
+    let request = SlackRequest { text, username: request.username.as_deref(), icon_url: request.avatar_url.as_deref() };
+    Ok(serde_json::to_string(&request).unwrap())
+}
+
+fn matrix_url(homeserver_url: &str, room_id: &str, txn_id: &str) -> Result<String, String> {
+    let mut url = reqwest::Url::parse(homeserver_url).map_err(|e| format!("invalid homeserver URL: {}", e))?;
+    url.path_segments_mut()
+        .map_err(|_| format!("invalid homeserver URL: {}", homeserver_url))?
+        .pop_if_empty()
+        .extend(&["_matrix", "client", "v3", "rooms", room_id, "send", "m.room.message", txn_id]);
+    Ok(url.into())
+}
+
+fn matrix_json(message: &Message) -> Result<String, String> {
+    let body = message.plain_text().ok_or_else(|| String::from("Matrix only supports messages with content or plain_fallback"))?;
+    let request = MatrixRequest { msgtype: "m.text", body };
+    Ok(serde_json::to_string(&request).unwrap())
+}
+
+fn multipart_form(content_json: String, file: &Path) -> Result<Form, String> {
+    let file_content = std::fs::read(file).map_err(|_| format!("could not open file: {}", file.display()))?;
+    let file_name = file.file_name().map_or_else(|| String::from("file"), |name| name.to_string_lossy().into_owned());
+
+    let form = Form::new()
+        .text("payload_json", content_json)
+        .part("files[0]", Part::bytes(file_content).file_name(file_name));
+    Ok(form)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate indoc;
+    extern crate mockito;
+    extern crate tempfile;
+
+    use super::*;
+    use super::super::gzip::gunzip;
+    use indoc::indoc;
+    use mockito::Matcher;
+    use serde_json::Value;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    // A request with only the message, which the tests change with the struct update syntax.
+    fn test_request(message: &Message) -> SimpleWebhookRequest<'_> {
+        SimpleWebhookRequest { username: &None, avatar_url: &None, nonce: &None, enforce_nonce: false, message_reference: &None, flags: &None, thread_name: &None, signing: &None, compress: false, accepted_statuses: &None, message }
+    }
+
+    async fn post(webhook_url: &str, platform: &Platform, request: &SimpleWebhookRequest<'_>) -> Result<PostResponse, String> {
+        ReqwestPoster::shared().post(webhook_url, platform, request).await.map_err(String::from)
+    }
+
+    #[tokio::test]
+    async fn post_should_send_a_json_body() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server.mock("POST", "/")
+            .match_header("content-type", "application/json")
+            .match_body(Matcher::Json(serde_json::json!({ "username": null, "avatar_url": null, "content": "message" })))
+            .with_status(204)
+            .create_async().await;
+
+        let message = Message::String(String::from("message"));
+        let request = test_request(&message);
+        let result = post(&server.url(), &Platform::Discord, &request).await;
+
+        assert_eq!(result, Ok(PostResponse { status: 204, message_id: None }));
+        mock.assert_async().await;
+    }
+
+    #[test]
+    fn simple_webhook_request_should_serialize_the_flags() {
+        let message = Message::String(String::from("message"));
+        let flags = Some(4096);
+        let request = SimpleWebhookRequest { flags: &flags, ..test_request(&message) };
+
+        let expected = serde_json::json!({ "username": null, "avatar_url": null, "flags": 4096, "content": "message" });
+        assert_eq!(serde_json::to_value(&request).unwrap(), expected);
+    }
+
+    #[test]
+    fn simple_webhook_request_should_serialize_the_thread_name() {
+        let message = Message::String(String::from("message"));
+        let thread_name = Some(String::from("daily post"));
+        let request = SimpleWebhookRequest { thread_name: &thread_name, ..test_request(&message) };
+
+        let expected = serde_json::json!({ "username": null, "avatar_url": null, "thread_name": "daily post", "content": "message" });
+        assert_eq!(serde_json::to_value(&request).unwrap(), expected);
+    }
+
+    #[tokio::test]
+    async fn post_should_gzip_a_large_body_if_compress_is_enabled() {
+        let content = "message ".repeat(COMPRESSION_THRESHOLD);
+        let expected = serde_json::json!({ "username": null, "avatar_url": null, "content": content });
+        let mut server = mockito::Server::new_async().await;
+        let mock = server.mock("POST", "/")
+            .match_header("content-encoding", "gzip")
+            .match_header("content-type", "application/json")
+            .match_request(move |request| serde_json::from_slice::<Value>(&gunzip(request.body().unwrap())).unwrap() == expected)
+            .with_status(204)
+            .expect(1)
+            .create_async().await;
+
+        let message = Message::String(content.clone());
+        let request = SimpleWebhookRequest { compress: true, ..test_request(&message) };
+        let response = post(&server.url(), &Platform::Discord, &request).await.unwrap();
+
+        assert_eq!(response.status, 204);
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn post_should_sign_the_gzipped_body_which_is_sent() {
+        let message = Message::String("message ".repeat(COMPRESSION_THRESHOLD));
+        let signing = Some(Signing { secret: String::from("secret"), header: String::from(DEFAULT_SIGNATURE_HEADER) });
+        let request = SimpleWebhookRequest { signing: &signing, compress: true, ..test_request(&message) };
+        let mut server = mockito::Server::new_async().await;
+        let mock = server.mock("POST", "/")
+            .match_header("content-encoding", "gzip")
+            .match_request(|request| {
+                let body = request.body().unwrap();
+                request.header("x-signature").first().and_then(|value| value.to_str().ok()) == Some(sign("secret", body).unwrap().as_str())
+            })
+            .with_status(204)
+            .expect(1)
+            .create_async().await;
+
+        post(&server.url(), &Platform::Discord, &request).await.unwrap();
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn post_should_not_gzip_a_small_body() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server.mock("POST", "/")
+            .match_header("content-encoding", Matcher::Missing)
+            .match_body(Matcher::Json(serde_json::json!({ "username": null, "avatar_url": null, "content": "message" })))
+            .with_status(204)
+            .expect(1)
+            .create_async().await;
+
+        let message = Message::String(String::from("message"));
+        let request = SimpleWebhookRequest { compress: true, ..test_request(&message) };
+        post(&server.url(), &Platform::Discord, &request).await.unwrap();
+
+        mock.assert_async().await;
+    }
+
+    #[test]
+    fn sign_should_return_the_hex_encoded_hmac_sha256() {
+        // test case 2 of RFC 4231
+        let signature = sign("Jefe", b"what do ya want for nothing?").unwrap();
+        assert_eq!(signature, "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+    }
+
+    #[tokio::test]
+    async fn post_should_attach_the_signature_of_the_body() {
+        let message = Message::String(String::from("message"));
+        let signing = Some(Signing { secret: String::from("secret"), header: String::from(DEFAULT_SIGNATURE_HEADER) });
+        let request = SimpleWebhookRequest { signing: &signing, ..test_request(&message) };
+        let expected_signature = sign("secret", serde_json::to_string(&request).unwrap().as_bytes()).unwrap();
+
+        let mut server = mockito::Server::new_async().await;
+        let mock = server.mock("POST", "/")
+            .match_header("x-signature", expected_signature.as_str())
+            .match_body(Matcher::Json(serde_json::json!({ "username": null, "avatar_url": null, "content": "message" })))
+            .with_status(204)
+            .create_async().await;
+
+        let result = post(&server.url(), &Platform::Discord, &request).await;
+
+        assert_eq!(result, Ok(PostResponse { status: 204, message_id: None }));
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn post_should_not_sign_a_message_with_an_attachment() {
+        let file = NamedTempFile::new().unwrap();
+        let mut server = mockito::Server::new_async().await;
+        let mock = server.mock("POST", "/").expect(0).create_async().await;
+
+        let message = Message::WithAttachment { content: Some(String::from("message")), file: file.path().to_owned() };
+        let signing = Some(Signing { secret: String::from("secret"), header: String::from(DEFAULT_SIGNATURE_HEADER) });
+        let request = SimpleWebhookRequest { signing: &signing, ..test_request(&message) };
+        let result = post(&server.url(), &Platform::Discord, &request).await;
+
+        assert_eq!(result, Err(String::from("a message with an attachment cannot be signed")));
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn post_should_send_a_multipart_form_if_the_message_has_an_attachment() {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, "file content").unwrap();
+        let file_name = file.path().file_name().unwrap().to_str().unwrap().to_owned();
+
+        let mut server = mockito::Server::new_async().await;
+        let mock = server.mock("POST", "/")
+            .match_header("content-type", Matcher::Regex(String::from("^multipart/form-data; boundary=")))
+            .match_body(Matcher::AllOf(vec![
+                Matcher::Regex(String::from(r#"name="payload_json"\r\n\r\n\{"username":null,"avatar_url":null,"content":"message"\}\r\n"#)),
+                Matcher::Regex(format!(r#"name="files\[0\]"; filename="{}"\r\n\r\nfile content\r\n"#, file_name)),
+            ]))
+            .with_status(200)
+            .create_async().await;
+
+        let message = Message::WithAttachment { content: Some(String::from("message")), file: file.path().to_owned() };
+        let request = test_request(&message);
+        let result = post(&server.url(), &Platform::Discord, &request).await;
+
+        assert_eq!(result, Ok(PostResponse { status: 200, message_id: None }));
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn post_should_fail_without_sending_if_the_attachment_does_not_exist() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_owned();
+        file.close().unwrap();
+
+        let mut server = mockito::Server::new_async().await;
+        let mock = server.mock("POST", "/")
+            .expect(0)
+            .create_async().await;
+
+        let message = Message::WithAttachment { content: None, file: path.clone() };
+        let request = test_request(&message);
+        let result = post(&server.url(), &Platform::Discord, &request).await;
+
+        assert_eq!(result, Err(format!("could not open file: {}", path.display())));
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn post_should_format_discord_errors() {
+        let mut server = mockito::Server::new_async().await;
+        server.mock("POST", "/")
+            .with_status(400)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"message": "Cannot send an empty message", "code": 50006}"#)
+            .create_async().await;
+
+        let message = Message::String(String::new());
+        let request = test_request(&message);
+        let result = post(&server.url(), &Platform::Discord, &request).await;
+
+        assert_eq!(result, Err(String::from("failed with 400 Bad Request: Discord error 50006: Cannot send an empty message")));
+    }
+
+    #[tokio::test]
+    async fn post_should_format_discord_errors_with_field_errors() {
+        let mut server = mockito::Server::new_async().await;
+        server.mock("POST", "/")
+            .with_status(400)
+            .with_header("content-type", "application/json")
+            .with_body(indoc! {r#"
+                {
+                    "code": 50035,
+                    "errors": {
+                        "embeds": {
+                            "0": {
+                                "title": {
+                                    "_errors": [
+                                        { "code": "BASE_TYPE_MAX_LENGTH", "message": "Must be 256 or fewer in length." }
+                                    ]
+                                }
+                            }
+                        }
+                    },
+                    "message": "Invalid Form Body"
+                }
+            "#})
+            .create_async().await;
+
+        let message = Message::String(String::from("message"));
+        let request = test_request(&message);
+        let result = post(&server.url(), &Platform::Discord, &request).await;
+
+        let expected = "failed with 400 Bad Request: Discord error 50035: Invalid Form Body (embeds.0.title: Must be 256 or fewer in length.)";
+        assert_eq!(result, Err(String::from(expected)));
+    }
+
+    #[tokio::test]
+    async fn post_should_return_the_raw_error_body_if_it_is_not_a_discord_error() {
+        let mut server = mockito::Server::new_async().await;
+        server.mock("POST", "/")
+            .with_status(502)
+            .with_body("Bad Gateway")
+            .create_async().await;
+
+        let message = Message::String(String::from("message"));
+        let request = test_request(&message);
+        let result = post(&server.url(), &Platform::Discord, &request).await;
+
+        assert_eq!(result, Err(String::from("failed with 502 Bad Gateway: Bad Gateway")));
+    }
+
+    #[tokio::test]
+    async fn post_should_succeed_only_with_the_accepted_statuses() {
+        let mut server = mockito::Server::new_async().await;
+        server.mock("POST", "/")
+            .with_status(202)
+            .create_async().await;
+
+        let message = Message::String(String::from("message"));
+        let post_accepting = |accepted_statuses: Vec<u16>| {
+            let url = server.url();
+            let message = &message;
+            async move {
+                let accepted_statuses = Some(accepted_statuses);
+                let request = SimpleWebhookRequest { accepted_statuses: &accepted_statuses, ..test_request(message) };
+                post(&url, &Platform::Slack, &request).await
+            }
+        };
+
+        assert_eq!(post_accepting(vec![200, 202]).await, Ok(PostResponse { status: 202, message_id: None }));
+        assert_eq!(post_accepting(vec![200]).await, Err(String::from("failed with 202 Accepted: ")));
+    }
+
+    #[tokio::test]
+    async fn post_should_return_the_id_of_the_posted_discord_message() {
+        let mut server = mockito::Server::new_async().await;
+        server.mock("POST", "/?wait=true")
+            .with_status(200)
+            .with_body(r#"{"id": "1234567890", "type": 0, "content": "message"}"#)
+            .create_async().await;
+
+        let message = Message::String(String::from("message"));
+        let request = test_request(&message);
+        let result = post(&format!("{}/?wait=true", server.url()), &Platform::Discord, &request).await;
+
+        assert_eq!(result, Ok(PostResponse { status: 200, message_id: Some(String::from("1234567890")) }));
+    }
+
+    #[tokio::test]
+    async fn reqwest_poster_should_reuse_the_given_client() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server.mock("POST", "/")
+            .match_body(Matcher::Json(serde_json::json!({ "username": null, "avatar_url": null, "content": "message" })))
+            .with_status(204)
+            .expect(2)
+            .create_async().await;
+
+        let client = reqwest::Client::new();
+        let message = Message::String(String::from("message"));
+        let request = test_request(&message);
+        for _ in 0..2 {
+            let result = ReqwestPoster::new(&client).post(&server.url(), &Platform::Discord, &request).await.map_err(String::from);
+            assert_eq!(result, Ok(PostResponse { status: 204, message_id: None }));
+        }
+
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn post_with_client_should_reuse_the_given_client() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server.mock("POST", "/")
+            .match_body(Matcher::Json(serde_json::json!({ "username": null, "avatar_url": null, "content": "message" })))
+            .with_status(204)
+            .expect(2)
+            .create_async().await;
+
+        let client = reqwest::Client::new();
+        let message = Message::String(String::from("message"));
+        let request = test_request(&message);
+        for _ in 0..2 {
+            let result = post_with_client(&client, &server.url(), &Platform::Discord, &request).await.map_err(String::from);
+            assert_eq!(result, Ok(PostResponse { status: 204, message_id: None }));
+        }
+
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn post_with_retry_should_retry_after_server_errors() {
+        let mut server = mockito::Server::new_async().await;
+        let failed_mock = server.mock("POST", "/")
+            .with_status(500)
+            .expect(2)
+            .create_async().await;
+        let succeeded_mock = server.mock("POST", "/")
+            .with_status(204)
+            .expect(1)
+            .create_async().await;
+
+        let message = Message::String(String::from("message"));
+        let request = test_request(&message);
+        let retry = RetrySettings { max_attempts: 3, initial_delay: HumanDuration::from_millis(0), max_delay: None, max_total_duration: None };
+        let mut retries = Vec::new();
+        let result = post_with_retry(&ReqwestPoster::shared(), &server.url(), &Platform::Discord, &request, &retry, &mut retries).await;
+
+        assert_eq!(result, Ok(PostResponse { status: 204, message_id: None }));
+        assert_eq!(retries, vec![String::from("retrying in 0ms: failed with 500 Internal Server Error: "); 2]);
+        failed_mock.assert_async().await;
+        succeeded_mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn post_with_retry_should_give_up_after_the_maximum_attempts() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server.mock("POST", "/")
+            .with_status(503)
+            .with_body("unavailable")
+            .expect(2)
+            .create_async().await;
+
+        let message = Message::String(String::from("message"));
+        let request = test_request(&message);
+        let retry = RetrySettings { max_attempts: 2, initial_delay: HumanDuration::from_millis(0), max_delay: None, max_total_duration: None };
+        let mut retries = Vec::new();
+        let result = post_with_retry(&ReqwestPoster::shared(), &server.url(), &Platform::Discord, &request, &retry, &mut retries).await;
+
+        assert_eq!(result, Err(String::from("failed with 503 Service Unavailable: unavailable")));
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn post_with_retry_should_stop_retrying_once_the_total_duration_is_exhausted() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server.mock("POST", "/")
+            .with_status(503)
+            .with_body("unavailable")
+            .expect(1)
+            .create_async().await;
+
+        let message = Message::String(String::from("message"));
+        let request = test_request(&message);
+        let retry = RetrySettings { max_attempts: 5, initial_delay: HumanDuration::from_millis(0), max_delay: None, max_total_duration: Some(HumanDuration::from_millis(0)) };
+        let mut retries = Vec::new();
+        let result = post_with_retry(&ReqwestPoster::shared(), &server.url(), &Platform::Discord, &request, &retry, &mut retries).await;
+
+        assert_eq!(result, Err(String::from("failed with 503 Service Unavailable: unavailable")));
+        assert_eq!(retries, vec![String::from("giving up retrying within 0ms")]);
+        mock.assert_async().await;
+    }
+
+    #[test]
+    fn cap_delay_should_limit_the_delay_to_the_maximum() {
+        assert_eq!(cap_delay(Duration::from_millis(8000), Some(Duration::from_millis(5000))), Duration::from_millis(5000));
+        assert_eq!(cap_delay(Duration::from_millis(2000), Some(Duration::from_millis(5000))), Duration::from_millis(2000));
+        assert_eq!(cap_delay(Duration::from_millis(8000), None), Duration::from_millis(8000));
+    }
+
+    #[tokio::test]
+    async fn post_with_retry_should_not_retry_after_client_errors() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server.mock("POST", "/")
+            .with_status(404)
+            .expect(1)
+            .create_async().await;
+
+        let message = Message::String(String::from("message"));
+        let request = test_request(&message);
+        let retry = RetrySettings { max_attempts: 3, initial_delay: HumanDuration::from_millis(0), max_delay: None, max_total_duration: None };
+        let mut retries = Vec::new();
+        let result = post_with_retry(&ReqwestPoster::shared(), &server.url(), &Platform::Discord, &request, &retry, &mut retries).await;
+
+        assert!(result.is_err());
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn post_should_send_a_telegram_request_if_the_platform_is_telegram() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server.mock("POST", "/")
+            .match_header("content-type", "application/json")
+            .match_body(Matcher::Json(serde_json::json!({ "chat_id": "@channel", "text": "message" })))
+            .with_status(200)
+            .create_async().await;
+
+        let message = Message::String(String::from("message"));
+        let request = test_request(&message);
+        let platform = Platform::Telegram { chat_id: String::from("@channel") };
+        let result = post(&server.url(), &platform, &request).await;
+
+        assert_eq!(result, Ok(PostResponse { status: 200, message_id: None }));
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn post_should_send_a_matrix_event_if_the_platform_is_matrix() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server.mock("PUT", "/_matrix/client/v3/rooms/!room:example.org/send/m.room.message/1234")
+            .match_header("authorization", "Bearer secret")
+            .match_header("content-type", "application/json")
+            .match_body(Matcher::Json(serde_json::json!({ "msgtype": "m.text", "body": "message" })))
+            .with_status(200)
+            .with_body(r#"{"event_id": "$event"}"#)
+            .create_async().await;
+
+        let message = Message::String(String::from("message"));
+        let nonce = Some(String::from("1234"));
+        let request = SimpleWebhookRequest { nonce: &nonce, ..test_request(&message) };
+        let platform = Platform::Matrix { room_id: String::from("!room:example.org"), access_token: String::from("secret") };
+        let result = post(&server.url(), &platform, &request).await;
+
+        assert_eq!(result, Ok(PostResponse { status: 200, message_id: None }));
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn check_reachable_should_succeed_if_discord_returns_the_webhook() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server.mock("GET", "/")
+            .with_status(200)
+            .with_body(r#"{ "id": "1234", "type": 1 }"#)
+            .expect(1)
+            .create_async().await;
+
+        assert_eq!(check_reachable(&server.url(), &Platform::Discord).await, Ok(()));
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn check_reachable_should_fail_if_discord_does_not_know_the_webhook() {
+        let mut server = mockito::Server::new_async().await;
+        server.mock("GET", "/").with_status(401).create_async().await;
+
+        assert_eq!(check_reachable(&server.url(), &Platform::Discord).await, Err(String::from("failed with 401 Unauthorized")));
+    }
+
+    #[tokio::test]
+    async fn check_reachable_should_accept_client_errors_from_the_other_platforms() {
+        let mut server = mockito::Server::new_async().await;
+        server.mock("GET", "/").with_status(404).create_async().await;
+
+        let platform = Platform::Telegram { chat_id: String::from("@channel") };
+        assert_eq!(check_reachable(&server.url(), &platform).await, Ok(()));
+    }
+
+    #[test]
+    fn matrix_url_should_escape_the_room_id_and_the_transaction_id() {
+        let url = matrix_url("https://matrix.example.org/", "!room/1:example.org", "a b").unwrap();
+        assert_eq!(url, "https://matrix.example.org/_matrix/client/v3/rooms/!room%2F1:example.org/send/m.room.message/a%20b");
+    }
+
+    #[test]
+    fn message_url_should_append_the_message_id_before_the_query() {
+        let url = message_url("https://discord.com/api/webhooks/123/abc?wait=true&thread_id=456", "789").unwrap();
+        assert_eq!(url, "https://discord.com/api/webhooks/123/abc/messages/789?wait=true&thread_id=456");
+        let url = message_url("https://discord.com/api/webhooks/123/abc/", "789").unwrap();
+        assert_eq!(url, "https://discord.com/api/webhooks/123/abc/messages/789");
+    }
+
+    #[test]
+    fn telegram_json_should_contain_the_chat_id_and_the_text() {
+        let message = Message::String(String::from("message"));
+        let json = telegram_json("-1001234567890", &message).unwrap();
+
+        let expected = serde_json::json!({ "chat_id": "-1001234567890", "text": "message" });
+        assert_eq!(serde_json::from_str::<Value>(&json).unwrap(), expected);
+    }
+
+    #[test]
+    fn telegram_json_should_send_only_the_content_of_messages_with_embeds() {
+        let message = Message::WithEmbeds {
+            content: Some(String::from("message")),
+            embeds: vec![serde_json::json!({ "title": "title1" })],
+            plain_fallback: None,
+        };
+        let json = telegram_json("@channel", &message).unwrap();
+
+        let expected = serde_json::json!({ "chat_id": "@channel", "text": "message" });
+        assert_eq!(serde_json::from_str::<Value>(&json).unwrap(), expected);
+    }
+
+    #[test]
+    fn telegram_json_should_send_the_plain_fallback_of_messages_with_embeds() {
+        let message = Message::WithEmbeds {
+            content: Some(String::from("message")),
+            embeds: vec![serde_json::json!({ "title": "title1", "url": "https://example.com/1" })],
+            plain_fallback: Some(String::from("title1: https://example.com/1")),
+        };
+        let json = telegram_json("@channel", &message).unwrap();
+
+        let expected = serde_json::json!({ "chat_id": "@channel", "text": "title1: https://example.com/1" });
+        assert_eq!(serde_json::from_str::<Value>(&json).unwrap(), expected);
+    }
+
+    #[test]
+    fn slack_json_should_contain_the_text_and_the_user() {
+        let message = Message::WithEmbeds { content: Some(String::from("message")), embeds: vec![], plain_fallback: None };
+        let username = Some(String::from("user"));
+        let request = SimpleWebhookRequest { username: &username, ..test_request(&message) };
+
+        let json: Value = serde_json::from_str(&slack_json(&request).unwrap()).unwrap();
+        assert_eq!(json, serde_json::json!({ "text": "message", "username": "user" }));
+    }
+
+    #[test]
+    fn telegram_json_should_fail_if_the_message_has_no_content() {
+        let message = Message::WithEmbeds {
+            content: None,
+            embeds: vec![serde_json::json!({ "title": "title1" })],
+            plain_fallback: None,
+        };
+        assert!(telegram_json("@channel", &message).is_err());
+    }
+
+    #[test]
+    fn telegram_json_should_fail_if_the_message_has_a_file() {
+        let message = Message::WithAttachment { content: Some(String::from("message")), file: std::path::PathBuf::from("image.png") };
+        assert_eq!(telegram_json("@channel", &message), Err(String::from("Telegram does not support messages with files")));
+    }
 }
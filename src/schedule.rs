@@ -0,0 +1,222 @@
+use chrono::{Datelike, NaiveDate, NaiveTime, Weekday};
+use serde::{Deserialize, Deserializer, de::Error};
+
+#[derive(PartialEq, Eq, Clone, Deserialize, Debug)]
+pub struct Schedule {
+    #[serde(default)]
+    pub weekdays: Option<Vec<Weekday>>,
+    #[serde(default)]
+    pub date_range: Option<DateRange>,
+}
+
+// The range repeats every year, and wraps around the end of the year if `from` is after `to`.
+#[derive(PartialEq, Eq, Clone, Deserialize, Debug)]
+pub struct DateRange {
+    pub from: MonthDay,
+    pub to: MonthDay,
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
+pub struct MonthDay {
+    pub month: u32,
+    pub day: u32,
+}
+
+impl Schedule {
+    pub fn is_active(&self, date: NaiveDate) -> bool {
+        let weekday_matches = self.weekdays.as_ref().is_none_or(|weekdays| weekdays.contains(&date.weekday()));
+        let date_matches = self.date_range.as_ref().is_none_or(|date_range| date_range.contains(date));
+        weekday_matches && date_matches
+    }
+}
+
+impl DateRange {
+    pub fn contains(&self, date: NaiveDate) -> bool {
+        let month_day = MonthDay { month: date.month(), day: date.day() };
+        if self.from <= self.to {
+            self.from <= month_day && month_day <= self.to
+        } else {
+            self.from <= month_day || month_day <= self.to
+        }
+    }
+}
+
+// A daily window in the local time from `from` until `to`, which wraps around midnight if `from` is after `to`.
+#[derive(PartialEq, Eq, Clone, Deserialize, Debug)]
+pub struct QuietHours {
+    #[serde(deserialize_with = "deserialize_hour_minute")]
+    pub from: NaiveTime,
+    #[serde(deserialize_with = "deserialize_hour_minute")]
+    pub to: NaiveTime,
+}
+
+impl QuietHours {
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.from <= self.to {
+            self.from <= time && time < self.to
+        } else {
+            self.from <= time || time < self.to
+        }
+    }
+}
+
+// The hours from `start` through `end` in the local time, written as `[start, end]`, which wrap around midnight if `start` is after `end`.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct PreferredHours {
+    pub start: u32,
+    pub end: u32,
+}
+
+impl PreferredHours {
+    pub fn contains(&self, hour: u32) -> bool {
+        if self.start <= self.end {
+            self.start <= hour && hour <= self.end
+        } else {
+            self.start <= hour || hour <= self.end
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for PreferredHours {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<PreferredHours, D::Error> {
+        let [start, end] = <[u32; 2]>::deserialize(deserializer)?;
+        if start > 23 || end > 23 {
+            return Err(D::Error::custom(format!("invalid preferred hours [{}, {}]: hours must be between 0 and 23", start, end)));
+        }
+        Ok(PreferredHours { start, end })
+    }
+}
+
+fn deserialize_hour_minute<'de, D: Deserializer<'de>>(deserializer: D) -> Result<NaiveTime, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    NaiveTime::parse_from_str(&value, "%H:%M").map_err(|_| D::Error::custom(format!("invalid time `{}`: expected HH:MM", value)))
+}
+
+impl<'de> Deserialize<'de> for MonthDay {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<MonthDay, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        let to_error = || D::Error::custom(format!("invalid date `{}`: expected MM-DD", value));
+
+        let (month, day) = value.split_once('-').ok_or_else(to_error)?;
+        let month = month.parse().map_err(|_| to_error())?;
+        let day = day.parse().map_err(|_| to_error())?;
+        // a leap year accepts every day which can appear in some year
+        NaiveDate::from_ymd_opt(2000, month, day).ok_or_else(to_error)?;
+        Ok(MonthDay { month, day })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate indoc;
+
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn is_active_should_check_the_weekday() {
+        let schedule = Schedule { weekdays: Some(vec![Weekday::Sat, Weekday::Sun]), date_range: None };
+
+        assert!(schedule.is_active(NaiveDate::from_ymd_opt(2021, 9, 4).unwrap()));
+        assert!(!schedule.is_active(NaiveDate::from_ymd_opt(2021, 9, 3).unwrap()));
+    }
+
+    #[test]
+    fn is_active_should_check_the_date_range() {
+        let schedule = Schedule {
+            weekdays: None,
+            date_range: Some(DateRange { from: MonthDay { month: 12, day: 1 }, to: MonthDay { month: 12, day: 31 } }),
+        };
+
+        assert!(schedule.is_active(NaiveDate::from_ymd_opt(2021, 12, 1).unwrap()));
+        assert!(schedule.is_active(NaiveDate::from_ymd_opt(2021, 12, 31).unwrap()));
+        assert!(!schedule.is_active(NaiveDate::from_ymd_opt(2021, 11, 30).unwrap()));
+    }
+
+    #[test]
+    fn is_active_should_check_the_date_range_over_the_end_of_the_year() {
+        let schedule = Schedule {
+            weekdays: None,
+            date_range: Some(DateRange { from: MonthDay { month: 12, day: 20 }, to: MonthDay { month: 1, day: 5 } }),
+        };
+
+        assert!(schedule.is_active(NaiveDate::from_ymd_opt(2021, 12, 25).unwrap()));
+        assert!(schedule.is_active(NaiveDate::from_ymd_opt(2022, 1, 5).unwrap()));
+        assert!(!schedule.is_active(NaiveDate::from_ymd_opt(2022, 1, 6).unwrap()));
+    }
+
+    #[test]
+    fn quiet_hours_should_contain_the_times_in_the_window_over_midnight() {
+        let quiet_hours = QuietHours { from: NaiveTime::from_hms_opt(22, 0, 0).unwrap(), to: NaiveTime::from_hms_opt(7, 0, 0).unwrap() };
+
+        assert!(quiet_hours.contains(NaiveTime::from_hms_opt(22, 0, 0).unwrap()));
+        assert!(quiet_hours.contains(NaiveTime::from_hms_opt(3, 0, 0).unwrap()));
+        assert!(!quiet_hours.contains(NaiveTime::from_hms_opt(7, 0, 0).unwrap()));
+        assert!(!quiet_hours.contains(NaiveTime::from_hms_opt(12, 0, 0).unwrap()));
+    }
+
+    #[test]
+    fn preferred_hours_should_contain_both_ends_and_wrap_around_midnight() {
+        let morning: PreferredHours = serde_yaml::from_str("[6, 10]").unwrap();
+        assert!(morning.contains(6) && morning.contains(10));
+        assert!(!morning.contains(5) && !morning.contains(11));
+
+        let night = PreferredHours { start: 22, end: 2 };
+        assert!(night.contains(23) && night.contains(0) && night.contains(2));
+        assert!(!night.contains(12));
+    }
+
+    #[test]
+    fn preferred_hours_should_fail_to_be_deserialized_if_an_hour_is_invalid() {
+        let result = serde_yaml::from_str::<PreferredHours>("[6, 24]");
+        assert!(result.unwrap_err().to_string().contains("invalid preferred hours [6, 24]: hours must be between 0 and 23"));
+    }
+
+    #[test]
+    fn quiet_hours_should_contain_the_times_in_the_window_within_a_day() {
+        let quiet_hours = QuietHours { from: NaiveTime::from_hms_opt(12, 0, 0).unwrap(), to: NaiveTime::from_hms_opt(13, 30, 0).unwrap() };
+
+        assert!(quiet_hours.contains(NaiveTime::from_hms_opt(12, 45, 0).unwrap()));
+        assert!(!quiet_hours.contains(NaiveTime::from_hms_opt(13, 30, 0).unwrap()));
+        assert!(!quiet_hours.contains(NaiveTime::from_hms_opt(3, 0, 0).unwrap()));
+    }
+
+    #[test]
+    fn quiet_hours_should_fail_to_be_deserialized_if_a_time_is_invalid() {
+        let input = indoc! {r#"
+            from: "22:00"
+            to: "7am"
+        "#};
+
+        let result = serde_yaml::from_str::<QuietHours>(input);
+        assert!(result.as_ref().unwrap_err().to_string().starts_with("invalid time `7am`: expected HH:MM"), "{:?}", result);
+    }
+
+    #[test]
+    fn schedule_can_be_deserialized() {
+        let input = indoc! {r#"
+            weekdays: [Sat, Sun]
+            date_range:
+              from: "12-01"
+              to: "12-31"
+        "#};
+        let expected = Schedule {
+            weekdays: Some(vec![Weekday::Sat, Weekday::Sun]),
+            date_range: Some(DateRange { from: MonthDay { month: 12, day: 1 }, to: MonthDay { month: 12, day: 31 } }),
+        };
+
+        assert_eq!(serde_yaml::from_str::<Schedule>(input).unwrap(), expected);
+    }
+
+    #[test]
+    fn schedule_should_fail_to_be_deserialized_if_a_date_is_invalid() {
+        let input = indoc! {r#"
+            date_range:
+              from: "02-30"
+              to: "12-31"
+        "#};
+
+        let result = serde_yaml::from_str::<Schedule>(input);
+        assert!(result.as_ref().unwrap_err().to_string().starts_with("date_range: invalid date `02-30`: expected MM-DD"), "{:?}", result);
+    }
+}
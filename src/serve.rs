@@ -0,0 +1,130 @@
+use chrono::Local;
+use hyper::{Body, Method, Request, Response, StatusCode};
+use hyper::rt::Executor;
+use hyper::server::conn::Http;
+use hyper::service::service_fn;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::path::Path;
+use tokio::net::TcpListener;
+
+use super::{Message, OutputFormat, PostOptions, Poster, Settings, load_picker, post_random, write_outcome};
+
+// HTTP/1 connections never spawn tasks, so the handlers can borrow the poster and the settings, which need not be `Send`.
+#[derive(Clone, Copy)]
+struct NoExecutor;
+
+impl<F> Executor<F> for NoExecutor {
+    fn execute(&self, _: F) {
+        unreachable!("HTTP/1 connections do not spawn tasks");
+    }
+}
+
+pub async fn serve_on<P: Poster>(poster: &P, settings: &Settings, log_path: &Path, addr: SocketAddr) -> Result<(), String> {
+    let listener = TcpListener::bind(addr).await.map_err(|e| format!("failed to listen on {}: {}", addr, e))?;
+    println!("serving on {}", addr);
+    serve(poster, settings, log_path, listener).await
+}
+
+// Serves the connections one at a time without keep-alive, so that two posts never race on the log.
+pub async fn serve<P: Poster>(poster: &P, settings: &Settings, log_path: &Path, listener: TcpListener) -> Result<(), String> {
+    let mut http = Http::new().with_executor(NoExecutor);
+    http.http1_only(true).http1_keep_alive(false);
+    loop {
+        let (stream, _) = listener.accept().await.map_err(|e| format!("failed to accept a connection: {}", e))?;
+        let service = service_fn(|request| async move { Ok::<_, Infallible>(respond(poster, settings, log_path, request).await) });
+        if let Err(e) = http.serve_connection(stream, service).await {
+            eprintln!("failed to serve a connection: {}", e);
+        }
+    }
+}
+
+async fn respond<P: Poster>(poster: &P, settings: &Settings, log_path: &Path, request: Request<Body>) -> Response<Body> {
+    let result = match (request.method(), request.uri().path()) {
+        (&Method::GET, "/status") => status(settings, log_path),
+        (&Method::POST, "/post") => post(poster, settings, log_path).await,
+        _ => return json_response(StatusCode::NOT_FOUND, serde_json::json!({ "error": "not found" }).to_string()),
+    };
+    match result {
+        Ok(body) => json_response(StatusCode::OK, body),
+        Err(message) => json_response(StatusCode::INTERNAL_SERVER_ERROR, serde_json::json!({ "error": message }).to_string()),
+    }
+}
+
+// The counts and the probabilities by the weights alone, without the schedules and the other filters of a post.
+fn status(settings: &Settings, log_path: &Path) -> Result<String, String> {
+    let random_picker = load_picker(settings, log_path)?;
+    let mut messages: Vec<(&String, u64, f64)> = random_picker.counts().into_iter()
+        .zip(random_picker.probabilities())
+        .map(|((id, count), (_, probability))| (id, count, probability))
+        .collect();
+    messages.sort_by(|a, b| a.0.cmp(b.0));
+    let messages: Vec<serde_json::Value> = messages.into_iter()
+        .map(|(id, count, probability)| serde_json::json!({ "id": id, "count": count, "probability": probability }))
+        .collect();
+    Ok(serde_json::json!({ "messages": messages }).to_string())
+}
+
+async fn post<P: Poster>(poster: &P, settings: &Settings, log_path: &Path) -> Result<String, String> {
+    let transform = |message: &mut Message| settings.environment.transformers.iter().for_each(|transformer| transformer.apply(message));
+    let options = PostOptions { transform: Some(&transform), ..PostOptions::default() };
+    let outcome = post_random(poster, settings, log_path, Local::now(), &options).await?;
+    let mut body = Vec::new();
+    write_outcome(&mut body, &outcome, log_path, OutputFormat::Json, false).map_err(|e| format!("failed to write the output: {}", e))?;
+    String::from_utf8(body).map_err(|e| format!("failed to write the output: {}", e))
+}
+
+fn json_response(status: StatusCode, body: String) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(Body::from(body))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate indoc;
+    extern crate tempfile;
+
+    use super::*;
+    use indoc::indoc;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    use super::super::ReqwestPoster;
+
+    #[tokio::test]
+    async fn serve_should_return_the_counts_and_the_probabilities_on_status() {
+        let settings: Settings = serde_yaml::from_str(indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/XXXX/YYYY"
+              weight_type:
+                type: "MinOnly"
+            messages:
+              def: "message2"
+              abc: "message1"
+        "#}).unwrap();
+        let mut log_file = NamedTempFile::new().unwrap();
+        write!(log_file, r#"[{{ "value": "abc", "count": 3 }}, {{ "value": "def", "count": 1 }}]"#).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/status", listener.local_addr().unwrap());
+        let poster = ReqwestPoster::shared();
+        let server = serve(&poster, &settings, log_file.path(), listener);
+        let client = async { reqwest::get(&url).await.unwrap().text().await.unwrap() };
+
+        let body = tokio::select! {
+            result = server => panic!("the server stopped: {:?}", result),
+            body = client => body,
+        };
+        let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let expected = serde_json::json!({
+            "messages": [
+                { "id": "abc", "count": 3, "probability": 0.0 },
+                { "id": "def", "count": 1, "probability": 1.0 },
+            ],
+        });
+        assert_eq!(body, expected);
+    }
+}
@@ -1,29 +1,262 @@
-use serde::Deserialize;
-use std::collections::HashMap;
+use serde::{Deserialize, Deserializer, de::{Error, MapAccess, Visitor}};
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fmt;
 use std::fs::File;
-use std::io::BufReader;
-use std::path::Path;
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
 
-use super::random::InitialCountType;
+use super::file::LockMode;
+use super::random::{Boost, InitialCountType, LogFormat, OnCorruptLog, StaleBoost};
 use super::weight::WeightType;
-use super::message::Message;
+use super::message::{Embed, Message, Transformer, validate_embeds};
+use super::request::{Platform, RetrySettings};
+use super::schedule::{PreferredHours, QuietHours, Schedule};
 
 #[derive(PartialEq, Deserialize, Debug)]
 pub struct Settings {
     pub environment: EnvironmentSettings,
-    pub messages: HashMap<String, Message>,
+    #[serde(deserialize_with = "deserialize_messages")]
+    pub messages: HashMap<String, MessageSettings>,
+    #[serde(default)]
+    pub groups: HashMap<String, GroupSettings>,
+    #[serde(default)]
+    pub defaults: Defaults,
+}
+
+// Shared values which fill what each message leaves out.
+#[derive(PartialEq, Default, Deserialize, Debug)]
+pub struct Defaults {
+    // the keys of every embed which it does not have, such as the footer, the color or the author
+    #[serde(default)]
+    pub embed: Option<Embed>,
 }
 
 #[derive(PartialEq, Deserialize, Debug)]
 pub struct EnvironmentSettings {
     pub webhook_url: String,
+    #[serde(default = "Platform::default")]
+    pub platform: Platform,
+    #[serde(default)]
+    pub destinations: Vec<Destination>,
+    #[serde(default = "Strategy::default")]
+    pub strategy: Strategy,
+    // the statuses which count as success instead of 2xx
+    #[serde(default)]
+    pub accepted_statuses: Option<Vec<u16>>,
     pub weight_type: WeightType,
     #[serde(default = "InitialCountType::default")]
     pub initial_count_type: InitialCountType,
+    #[serde(default)]
+    pub seed: Option<u64>,
+    #[serde(default)]
+    pub stable_order: bool,
+    #[serde(default)]
+    pub min_interval: Option<HumanDuration>,
+    #[serde(default)]
+    pub quiet_hours: Option<QuietHours>,
+    #[serde(default)]
+    pub post_interval: Option<HumanDuration>,
+    #[serde(default)]
+    pub post_interval_jitter: Option<HumanDuration>,
+    #[serde(default)]
+    pub new_message_boost: Option<Boost>,
+    #[serde(default)]
+    pub stale_boost: Option<StaleBoost>,
+    #[serde(default)]
+    pub preferred_hours_factor: Option<f64>,
+    #[serde(default)]
+    pub exclusion_runs: Option<u64>,
+    // posts each message only once, and then the message of `grab_bag_done_message` if any
+    #[serde(default)]
+    pub grab_bag: bool,
+    #[serde(default)]
+    pub grab_bag_done_message: Option<String>,
+    #[serde(default)]
+    pub metrics_path: Option<PathBuf>,
+    // sends the embeds as they are written, without rejecting the keys which are not in `Embed`
+    #[serde(default)]
+    pub raw_embeds: bool,
+    #[serde(default)]
+    pub history_file: Option<PathBuf>,
+    #[serde(default)]
+    pub dead_letter_file: Option<PathBuf>,
+    #[serde(default)]
+    pub retry: Option<RetrySettings>,
+    #[serde(default)]
+    pub decay_rate: Option<f64>,
+    #[serde(default)]
+    pub reply_to_previous: bool,
+    #[serde(default)]
+    pub avoid_repeat: bool,
+    #[serde(default)]
+    pub cooldown: Option<usize>,
+    #[serde(default)]
+    pub weight_by_cost: bool,
+    #[serde(default)]
+    pub penalize_failures: bool,
+    #[serde(default)]
+    pub normalize_counts: bool,
+    #[serde(default)]
+    pub max_ratio: Option<f64>,
+    #[serde(default = "OnCorruptLog::default")]
+    pub on_corrupt_log: OnCorruptLog,
+    #[serde(default = "OnEmpty::default")]
+    pub on_empty: OnEmpty,
+    // locks the log for the whole run, so that concurrent runs do not overwrite each other's counts
+    #[serde(default)]
+    pub lock: Option<LockMode>,
+    #[serde(default = "LogFormat::default")]
+    pub log_format: LogFormat,
+    #[serde(default)]
+    pub epsilon: Option<f64>,
+    #[serde(default)]
+    pub group_smoothing: Option<f64>,
+    #[serde(default)]
+    pub flags: Option<u32>,
+    #[serde(default)]
+    pub signing_secret: Option<String>,
+    #[serde(default)]
+    pub signature_header: Option<String>,
+    #[serde(default)]
+    pub thread_name: Option<String>,
+    #[serde(default)]
+    pub compress: bool,
+    // joins the contents of the messages combined by `--combine`
+    #[serde(default)]
+    pub combine_separator: Option<String>,
+    #[serde(default)]
+    pub transformers: Vec<Transformer>,
+    // wrap the content of each post after the transformers, with the next emoji of the rotation on each run
+    #[serde(default)]
+    pub content_prefix: Option<String>,
+    #[serde(default)]
+    pub content_suffix: Option<String>,
+    #[serde(default)]
+    pub emoji_rotation: Vec<String>,
     #[serde(rename = "user", default = "UserSettings::default")]
     pub user_settings: UserSettings,
 }
 
+// What to do if the schedules, with the messages with files excluded by `--combine`, leave no message to pick; the other filters fall back to another message instead.
+#[derive(PartialEq, Eq, Clone, Copy, Deserialize, Debug)]
+pub enum OnEmpty {
+    Skip,
+    Error,
+}
+
+impl OnEmpty {
+    pub fn default() -> OnEmpty {
+        OnEmpty::Skip
+    }
+}
+
+// FanOut posts to the webhook URL and every destination, and Failover tries them in order until one of them succeeds.
+#[derive(PartialEq, Eq, Clone, Copy, Deserialize, Debug)]
+pub enum Strategy {
+    FanOut,
+    Failover,
+}
+
+impl Strategy {
+    pub fn default() -> Strategy {
+        Strategy::FanOut
+    }
+}
+
+// A duration written as a number of seconds or as a string with one of the units ms, s, m, h and d, such as "500ms", "30s" or "2h".
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct HumanDuration(u64);
+
+// 100 years in milliseconds, which can be added to any time of this era without overflowing
+const MAX_MILLIS: u64 = 100 * 365 * 24 * 60 * 60 * 1000;
+
+impl HumanDuration {
+    pub fn from_secs(secs: u64) -> HumanDuration {
+        HumanDuration(secs.saturating_mul(1000))
+    }
+
+    pub fn from_millis(millis: u64) -> HumanDuration {
+        HumanDuration(millis)
+    }
+
+    // The settings never have longer durations, but the ones made in the code are clamped to the same bound.
+    pub fn to_chrono(self) -> chrono::Duration {
+        chrono::Duration::milliseconds(self.0.min(MAX_MILLIS) as i64)
+    }
+
+    pub fn to_std(self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.0.min(MAX_MILLIS))
+    }
+
+    fn bounded(self, written: &str) -> Result<HumanDuration, String> {
+        if self.0 > MAX_MILLIS {
+            return Err(format!("duration `{}` is longer than 100 years", written));
+        }
+        Ok(self)
+    }
+}
+
+impl std::str::FromStr for HumanDuration {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<HumanDuration, String> {
+        let invalid = || format!("invalid duration `{}`, which must be a number with one of the units ms, s, m, h and d", s);
+        let (number, unit) = s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()));
+        let multiplier = match unit.trim() {
+            "ms" => 1,
+            "s" => 1000,
+            "m" => 60 * 1000,
+            "h" => 60 * 60 * 1000,
+            "d" => 24 * 60 * 60 * 1000,
+            _ => return Err(invalid()),
+        };
+        let number: u64 = number.parse().map_err(|_| invalid())?;
+        number.checked_mul(multiplier).map(HumanDuration::from_millis).ok_or_else(|| format!("duration `{}` is longer than 100 years", s))?.bounded(s)
+    }
+}
+
+impl<'de> Deserialize<'de> for HumanDuration {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<HumanDuration, D::Error> {
+        deserializer.deserialize_any(HumanDurationVisitor)
+    }
+}
+
+struct HumanDurationVisitor;
+
+impl<'de> Visitor<'de> for HumanDurationVisitor {
+    type Value = HumanDuration;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a number of seconds or a string such as \"500ms\", \"30s\", \"5m\", \"2h\" or \"1d\"")
+    }
+
+    fn visit_u64<E: Error>(self, value: u64) -> Result<HumanDuration, E> {
+        let written = value.to_string();
+        let millis = value.checked_mul(1000).ok_or_else(|| E::custom(format!("duration `{}` is longer than 100 years", written)))?;
+        HumanDuration(millis).bounded(&written).map_err(E::custom)
+    }
+
+    fn visit_i64<E: Error>(self, value: i64) -> Result<HumanDuration, E> {
+        let value = u64::try_from(value).map_err(|_| E::custom(format!("duration {} must not be negative", value)))?;
+        self.visit_u64(value)
+    }
+
+    fn visit_str<E: Error>(self, value: &str) -> Result<HumanDuration, E> {
+        value.parse().map_err(E::custom)
+    }
+}
+
+// another webhook which the same message is posted to, in the format of its platform
+#[derive(PartialEq, Eq, Deserialize, Debug)]
+pub struct Destination {
+    pub url: String,
+    #[serde(default = "Platform::default")]
+    pub platform: Platform,
+    #[serde(default)]
+    pub accepted_statuses: Option<Vec<u16>>,
+}
+
 #[derive(PartialEq, Eq, Deserialize, Debug)]
 pub struct UserSettings {
     pub name: Option<String>,
@@ -36,57 +269,643 @@ impl UserSettings {
     }
 }
 
+#[derive(PartialEq, Deserialize, Debug)]
+pub struct MessageSettings {
+    #[serde(flatten)]
+    pub message: Message,
+    #[serde(default)]
+    pub group: Option<String>,
+    #[serde(rename = "user", default = "UserSettings::default")]
+    pub user_settings: UserSettings,
+    #[serde(default)]
+    pub schedule: Option<Schedule>,
+    // notes for the maintainers of the settings, which are never posted
+    #[serde(default)]
+    pub meta: HashMap<String, serde_yaml::Value>,
+    #[serde(default)]
+    pub cost: Option<f64>,
+    // divides the count of this message in the weighting, so that it is treated as if it were picked less often
+    #[serde(default)]
+    pub importance: Option<f64>,
+    #[serde(default)]
+    pub flags: Option<u32>,
+    // used instead of `initial_count_type` while the message is not in the log
+    #[serde(default)]
+    pub initial_count: Option<u32>,
+    // the long-run fraction of posts for `WeightType::Target`
+    #[serde(default)]
+    pub target: Option<f64>,
+    // the title of the thread created when posting to a Discord forum channel
+    #[serde(default)]
+    pub thread_name: Option<String>,
+    // the message is not picked again until this many seconds after it was posted
+    #[serde(default)]
+    pub min_interval: Option<HumanDuration>,
+    // the message is likelier within these hours and less likely outside them, by `preferred_hours_factor`
+    #[serde(default)]
+    pub preferred_hours: Option<PreferredHours>,
+    // these messages are not picked for `exclusion_runs` runs after this message is posted, and vice versa
+    #[serde(default)]
+    pub excludes: Vec<String>,
+}
+
+impl From<Message> for MessageSettings {
+    fn from(message: Message) -> MessageSettings {
+        MessageSettings { message, group: None, user_settings: UserSettings::default(), schedule: None, meta: HashMap::new(), cost: None, importance: None, flags: None, initial_count: None, target: None, thread_name: None, min_interval: None, preferred_hours: None, excludes: Vec::new() }
+    }
+}
+
+#[derive(PartialEq, Deserialize, Debug)]
+pub struct GroupSettings {
+    #[serde(default)]
+    pub weight_type: Option<WeightType>,
+}
+
+fn deserialize_messages<'de, D: Deserializer<'de>>(deserializer: D) -> Result<HashMap<String, MessageSettings>, D::Error> {
+    let values = deserializer.deserialize_map(MessagesVisitor)?;
+    let mut ids = HashSet::new();
+    let mut duplicated_ids: Vec<&String> = values.iter().map(|(id, _)| id).filter(|id| !ids.insert(*id)).collect();
+    if !duplicated_ids.is_empty() {
+        duplicated_ids.sort();
+        duplicated_ids.dedup();
+        let duplicated_ids: Vec<String> = duplicated_ids.into_iter().map(|id| format!("`{}`", id)).collect();
+        return Err(D::Error::custom(format!("duplicate message ids: {}", duplicated_ids.join(", "))));
+    }
+
+    values.into_iter().map(|(id, value)| {
+        let message_settings = match value {
+            serde_yaml::Value::String(content) => MessageSettings::from(Message::String(content)),
+            value @ serde_yaml::Value::Sequence(_) => {
+                let message = serde_yaml::from_value(value).map_err(|e| D::Error::custom(format!("invalid message `{}`: {}", id, e)))?;
+                MessageSettings::from(Message::OneOf(message))
+            },
+            value => serde_yaml::from_value(value).map_err(|e| D::Error::custom(format!("invalid message `{}`: {}", id, e)))?,
+        };
+        if message_settings.message.variants().is_empty() {
+            return Err(D::Error::custom(format!("invalid message `{}`: must contain at least one message", id)));
+        }
+        for message in message_settings.message.variants() {
+            if let Message::WithEmbeds { content: None, embeds, .. } = message {
+                if embeds.is_empty() {
+                    return Err(D::Error::custom(format!("invalid message `{}`: must contain content, embeds or a file", id)));
+                }
+            }
+        }
+        if message_settings.cost.is_some_and(|cost| !cost.is_finite() || cost < 0.0) {
+            return Err(D::Error::custom(format!("invalid message `{}`: cost must be finite and non-negative", id)));
+        }
+        if message_settings.importance.is_some_and(|importance| !importance.is_finite() || importance <= 0.0) {
+            return Err(D::Error::custom(format!("invalid message `{}`: importance must be finite and positive", id)));
+        }
+        if message_settings.target.is_some_and(|target| !target.is_finite() || target < 0.0) {
+            return Err(D::Error::custom(format!("invalid message `{}`: target must be finite and non-negative", id)));
+        }
+        Ok((id, message_settings))
+    }).collect()
+}
+
+// Keeps every entry of the messages mapping, since a map type would silently drop duplicate ids.
+struct MessagesVisitor;
+
+impl<'de> Visitor<'de> for MessagesVisitor {
+    type Value = Vec<(String, serde_yaml::Value)>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a map of messages")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut values = Vec::new();
+        while let Some(entry) = map.next_entry()? {
+            values.push(entry);
+        }
+        Ok(values)
+    }
+}
+
+pub const STDIN_PATH: &str = "-";
+
+// The path `-` reads the settings from the standard input, and relative paths in them are resolved against the working directory.
 pub fn read_settings<P: AsRef<Path>>(path: P) -> Result<Settings, String> {
     let path_ref = path.as_ref();
+    if path_ref == Path::new(STDIN_PATH) {
+        return read_settings_from_reader(std::io::stdin().lock(), Path::new(""));
+    }
+
     let mut file = File::open(path_ref).map_err(|_| format!("could not open file: {}", path_ref.display()))?;
-    let mut file_reader = BufReader::new(&mut file);
+    let file_reader = BufReader::new(&mut file);
+    read_settings_from_reader(file_reader, path_ref.parent().unwrap_or_else(|| Path::new("")))
+}
+
+// The overlay of `settings.yaml` for the environment `prod` is `settings.prod.yaml` next to it.
+pub fn overlay_path(path: &Path, env: &str) -> Result<PathBuf, String> {
+    if path == Path::new(STDIN_PATH) {
+        return Err(String::from("an overlay cannot be used with the settings from the standard input"));
+    }
+    let mut file_name = path.file_stem().map(|stem| stem.to_owned()).unwrap_or_default();
+    file_name.push(".");
+    file_name.push(env);
+    if let Some(extension) = path.extension() {
+        file_name.push(".");
+        file_name.push(extension);
+    }
+    Ok(path.with_file_name(file_name))
+}
+
+// The scalars and the sequences of the overlay replace those of the settings, and the mappings are merged key by key.
+pub fn read_settings_with_overlay<P: AsRef<Path>>(path: P, overlay_path: Option<&Path>) -> Result<Settings, String> {
+    let path_ref = path.as_ref();
+    let overlay_path = match overlay_path {
+        Some(overlay_path) => overlay_path,
+        None => return read_settings(path_ref),
+    };
+    let input = merged_input(path_ref, overlay_path)?;
+    read_settings_from_reader(input.as_bytes(), path_ref.parent().unwrap_or_else(|| Path::new("")))
+}
+
+fn merged_input(path: &Path, overlay_path: &Path) -> Result<String, String> {
+    let read_value = |path: &Path| {
+        let input = std::fs::read_to_string(path).map_err(|_| format!("could not open file: {}", path.display()))?;
+        serde_yaml::from_str(&input).map_err(|e| format!("failed to read settings: {}: {}", path.display(), e))
+    };
+    let mut value: serde_yaml::Value = read_value(path)?;
+    merge_values(&mut value, read_value(overlay_path)?);
+    serde_yaml::to_string(&value).map_err(|e| format!("failed to read settings: {}", e))
+}
 
-    let settings = serde_yaml::from_reader(&mut file_reader).map_err(|e| format!("failed to read settings: {}", e))?;
+fn merge_values(base: &mut serde_yaml::Value, overlay: serde_yaml::Value) {
+    match (base, overlay) {
+        (serde_yaml::Value::Mapping(base), serde_yaml::Value::Mapping(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(base_value) => merge_values(base_value, value),
+                    None => { base.insert(key, value); },
+                }
+            }
+        },
+        (base, overlay) => *base = overlay,
+    }
+}
+
+pub fn read_settings_from_reader<R: Read>(reader: R, base_path: &Path) -> Result<Settings, String> {
+    let mut settings: Settings = serde_yaml::from_reader(reader).map_err(|e| format!("failed to read settings: {}", e))?;
+    if settings.messages.is_empty() {
+        return Err(String::from("settings.messages must contain at least one message"));
+    }
+    validate_webhook_url(&settings.environment.webhook_url, &settings.environment.platform).map_err(|e| format!("failed to read settings: webhook_url is not a valid URL: {}", e))?;
+    for (index, destination) in settings.environment.destinations.iter().enumerate() {
+        validate_webhook_url(&destination.url, &destination.platform).map_err(|e| format!("failed to read settings: destinations[{}].url is not a valid URL: {}", index, e))?;
+    }
+    resolve_weight_type_path(&mut settings.environment.weight_type, base_path);
+    for group_settings in settings.groups.values_mut() {
+        if let Some(weight_type) = &mut group_settings.weight_type {
+            resolve_weight_type_path(weight_type, base_path);
+        }
+    }
+    for (id, message_settings) in &mut settings.messages {
+        message_settings.message.normalize_embeds(!settings.environment.raw_embeds).map_err(|e| format!("failed to read settings: invalid message `{}`: {}", id, e))?;
+    }
     Ok(settings)
 }
 
+const REQUIRED_ENVIRONMENT_FIELDS: &[&str] = &["webhook_url", "weight_type"];
+const OPTIONAL_ENVIRONMENT_FIELDS: &[&str] = &[
+    "platform",
+    "destinations",
+    "strategy",
+    "accepted_statuses",
+    "initial_count_type",
+    "seed",
+    "stable_order",
+    "min_interval",
+    "quiet_hours",
+    "post_interval",
+    "post_interval_jitter",
+    "new_message_boost",
+    "stale_boost",
+    "preferred_hours_factor",
+    "exclusion_runs",
+    "grab_bag",
+    "grab_bag_done_message",
+    "metrics_path",
+    "raw_embeds",
+    "history_file",
+    "dead_letter_file",
+    "retry",
+    "decay_rate",
+    "reply_to_previous",
+    "avoid_repeat",
+    "cooldown",
+    "weight_by_cost",
+    "penalize_failures",
+    "normalize_counts",
+    "max_ratio",
+    "on_corrupt_log",
+    "on_empty",
+    "lock",
+    "log_format",
+    "epsilon",
+    "group_smoothing",
+    "flags",
+    "signing_secret",
+    "signature_header",
+    "thread_name",
+    "compress",
+    "combine_separator",
+    "transformers",
+    "content_prefix",
+    "content_suffix",
+    "emoji_rotation",
+    "user",
+];
+const OPTIONAL_FIELDS: &[&str] = &["groups", "defaults"];
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum FieldSource {
+    File,
+    Default,
+    // not a field of the settings, which is probably misspelled
+    Unknown,
+}
+
+// Reads the settings twice, to validate them and to see which optional fields are written in the file.
+pub fn explain_settings<P: AsRef<Path>>(path: P, overlay_path: Option<&Path>) -> Result<Vec<(String, FieldSource)>, String> {
+    let path_ref = path.as_ref();
+    let mut input = String::new();
+    if let Some(overlay_path) = overlay_path {
+        input = merged_input(path_ref, overlay_path)?;
+    } else if path_ref == Path::new(STDIN_PATH) {
+        std::io::stdin().read_to_string(&mut input).map_err(|e| format!("failed to read settings: {}", e))?;
+    } else {
+        input = std::fs::read_to_string(path_ref).map_err(|_| format!("could not open file: {}", path_ref.display()))?;
+    }
+
+    read_settings_from_reader(input.as_bytes(), path_ref.parent().unwrap_or_else(|| Path::new("")))?;
+    let value: serde_yaml::Value = serde_yaml::from_str(&input).map_err(|e| format!("failed to read settings: {}", e))?;
+    Ok(explain_fields(&value))
+}
+
+pub fn explain_fields(value: &serde_yaml::Value) -> Vec<(String, FieldSource)> {
+    let source = |mapping: Option<&serde_yaml::Value>, name: &str| {
+        if mapping.and_then(|mapping| mapping.get(name)).is_some() { FieldSource::File } else { FieldSource::Default }
+    };
+
+    let environment = value.get("environment");
+    let mut fields: Vec<(String, FieldSource)> = OPTIONAL_ENVIRONMENT_FIELDS.iter()
+        .map(|name| (format!("environment.{}", name), source(environment, name)))
+        .collect();
+    fields.extend(OPTIONAL_FIELDS.iter().map(|name| (String::from(*name), source(Some(value), name))));
+
+    let environment_keys = environment.and_then(|environment| environment.as_mapping()).into_iter().flat_map(|mapping| mapping.iter().map(|(key, _)| key));
+    let mut unknown_fields: Vec<String> = environment_keys
+        .filter_map(|key| key.as_str())
+        .filter(|key| !REQUIRED_ENVIRONMENT_FIELDS.contains(key) && !OPTIONAL_ENVIRONMENT_FIELDS.contains(key))
+        .map(|key| format!("environment.{}", key))
+        .collect();
+    unknown_fields.sort();
+    fields.extend(unknown_fields.into_iter().map(|name| (name, FieldSource::Unknown)));
+    fields
+}
+
+const DISCORD_HOSTS: &[&str] = &["discord.com", "discordapp.com"];
+const DISCORD_WEBHOOK_PATH: &str = "/api/webhooks/";
+
+// The host is checked only for Discord, since the other platforms can be self-hosted.
+fn validate_webhook_url(webhook_url: &str, platform: &Platform) -> Result<(), String> {
+    let url = reqwest::Url::parse(webhook_url).map_err(|e| e.to_string())?;
+    if url.scheme() != "https" {
+        return Err(format!("the scheme must be https, but was {}", url.scheme()));
+    }
+    if *platform == Platform::Discord {
+        let host = url.host_str().unwrap_or("");
+        if !DISCORD_HOSTS.iter().any(|discord_host| host == *discord_host || host.ends_with(&format!(".{}", discord_host))) {
+            return Err(format!("the host must be discord.com or discordapp.com, but was {}", host));
+        }
+        if !url.path().starts_with(DISCORD_WEBHOOK_PATH) {
+            return Err(format!("the path must start with {}", DISCORD_WEBHOOK_PATH));
+        }
+    }
+    Ok(())
+}
+
+fn resolve_weight_type_path(weight_type: &mut WeightType, base_path: &Path) {
+    if let WeightType::External { path } = weight_type {
+        *path = base_path.join(&*path);
+    }
+}
+
+pub fn check_settings(settings: &Settings) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    let environment = &settings.environment;
+    if let Err(e) = validate_webhook_url(&environment.webhook_url, &environment.platform) {
+        problems.push(format!("webhook_url is not a valid URL: {}", e));
+    }
+    if let Err(message) = environment.weight_type.validate() {
+        problems.push(format!("weight_type: {}", message));
+    }
+    if let Some(Err(message)) = environment.new_message_boost.map(|boost| boost.validate()) {
+        problems.push(format!("new_message_boost: {}", message));
+    }
+    if let Some(Err(message)) = environment.stale_boost.map(|stale_boost| stale_boost.validate()) {
+        problems.push(format!("stale_boost: {}", message));
+    }
+    if environment.preferred_hours_factor.is_some_and(|factor| !factor.is_finite() || factor < 1.0) {
+        problems.push(String::from("preferred_hours_factor must be finite and at least 1"));
+    }
+    if environment.exclusion_runs == Some(0) {
+        problems.push(String::from("exclusion_runs must be positive"));
+    }
+    if let Some(done_message) = environment.grab_bag_done_message.as_ref().filter(|id| !settings.messages.contains_key(*id)) {
+        problems.push(format!("grab_bag_done_message `{}` is not in the settings", done_message));
+    }
+    if environment.accepted_statuses.as_ref().is_some_and(|statuses| statuses.is_empty()) {
+        problems.push(String::from("accepted_statuses must not be empty"));
+    }
+    for (index, destination) in environment.destinations.iter().enumerate() {
+        if destination.accepted_statuses.as_ref().is_some_and(|statuses| statuses.is_empty()) {
+            problems.push(format!("destinations[{}].accepted_statuses must not be empty", index));
+        }
+    }
+    let mut group_names: Vec<&String> = settings.groups.keys().collect();
+    group_names.sort();
+    for group_name in group_names {
+        if let Some(Err(message)) = settings.groups[group_name].weight_type.as_ref().map(|weight_type| weight_type.validate()) {
+            problems.push(format!("group `{}`: weight_type: {}", group_name, message));
+        }
+    }
+
+    let posts_to_discord = environment.platform == Platform::Discord
+        || environment.destinations.iter().any(|destination| destination.platform == Platform::Discord);
+    let mut ids: Vec<&String> = settings.messages.keys().collect();
+    ids.sort();
+    for id in ids {
+        for excluded_id in &settings.messages[id].excludes {
+            if !settings.messages.contains_key(excluded_id) {
+                problems.push(format!("message `{}`: excludes `{}`, which is not in the settings", id, excluded_id));
+            }
+        }
+        if environment.signing_secret.is_some() && settings.messages[id].message.variants().iter().any(|message| message.attachment().is_some()) {
+            problems.push(format!("message `{}`: a file cannot be posted with signing_secret, which cannot sign a multipart body", id));
+        }
+        if posts_to_discord {
+            for message in settings.messages[id].message.variants() {
+                if let Err(message) = validate_embeds(message.embeds()) {
+                    problems.push(format!("message `{}`: {}", id, message));
+                }
+            }
+        }
+    }
+
+    problems
+}
+
 #[cfg(test)]
 mod tests {
     extern crate indoc;
     extern crate tempfile;
 
     use super::*;
+    use super::super::message::EmbedFooter;
+    use chrono::NaiveTime;
     use indoc::indoc;
     use std::io::Write;
     use tempfile::NamedTempFile;
 
+    // the environment read from a file which contains only the required settings
+    fn default_environment() -> EnvironmentSettings {
+        EnvironmentSettings {
+            webhook_url: String::from("https://discord.com/api/webhooks/XXXX/YYYY"),
+            platform: Platform::Discord,
+            destinations: vec![],
+            strategy: Strategy::FanOut,
+            accepted_statuses: None,
+            weight_type: WeightType::Uniform,
+            initial_count_type: InitialCountType::Zero,
+            seed: None,
+            stable_order: false,
+            min_interval: None,
+            quiet_hours: None,
+            post_interval: None,
+            post_interval_jitter: None,
+            new_message_boost: None,
+            stale_boost: None,
+            preferred_hours_factor: None,
+            exclusion_runs: None,
+            grab_bag: false,
+            grab_bag_done_message: None,
+            metrics_path: None,
+            raw_embeds: false,
+            history_file: None,
+            dead_letter_file: None,
+            retry: None,
+            decay_rate: None,
+            reply_to_previous: false,
+            avoid_repeat: false,
+            cooldown: None,
+            weight_by_cost: false,
+            penalize_failures: false,
+            normalize_counts: false,
+            max_ratio: None,
+            on_corrupt_log: OnCorruptLog::Fail,
+            on_empty: OnEmpty::Skip,
+            lock: None,
+            log_format: LogFormat::Json,
+            epsilon: None,
+            group_smoothing: None,
+            flags: None,
+            signing_secret: None,
+            signature_header: None,
+            thread_name: None,
+            compress: false,
+            combine_separator: None,
+            transformers: vec![],
+            content_prefix: None,
+            content_suffix: None,
+            emoji_rotation: vec![],
+            user_settings: UserSettings {
+                name: None,
+                icon_url: None,
+            },
+        }
+    }
+
+    #[test]
+    fn seconds_should_be_read_from_a_number_or_a_string_with_a_unit() {
+        assert_eq!(serde_yaml::from_str::<HumanDuration>("45").unwrap(), HumanDuration::from_secs(45));
+        assert_eq!(serde_yaml::from_str::<HumanDuration>(r#""30s""#).unwrap(), HumanDuration::from_secs(30));
+        assert_eq!(serde_yaml::from_str::<HumanDuration>(r#""5m""#).unwrap(), HumanDuration::from_secs(5 * 60));
+        assert_eq!(serde_yaml::from_str::<HumanDuration>(r#""2h""#).unwrap(), HumanDuration::from_secs(2 * 60 * 60));
+        assert_eq!(serde_yaml::from_str::<HumanDuration>(r#""1d""#).unwrap(), HumanDuration::from_secs(24 * 60 * 60));
+        assert_eq!(serde_yaml::from_str::<HumanDuration>(r#""500ms""#).unwrap(), HumanDuration::from_millis(500));
+        assert_eq!(HumanDuration::from_secs(90).to_chrono(), chrono::Duration::seconds(90));
+        assert_eq!(HumanDuration::from_millis(1500).to_std(), std::time::Duration::from_millis(1500));
+    }
+
+    #[test]
+    fn seconds_should_not_be_read_from_an_invalid_duration() {
+        assert_eq!("5x".parse::<HumanDuration>(), Err(String::from("invalid duration `5x`, which must be a number with one of the units ms, s, m, h and d")));
+        assert!("m".parse::<HumanDuration>().is_err());
+        assert!("30".parse::<HumanDuration>().is_err());
+        assert!("1.5h".parse::<HumanDuration>().is_err());
+        assert_eq!("18446744073709551615d".parse::<HumanDuration>(), Err(String::from("duration `18446744073709551615d` is longer than 100 years")));
+        assert_eq!("999999999d".parse::<HumanDuration>(), Err(String::from("duration `999999999d` is longer than 100 years")));
+        assert!(serde_yaml::from_str::<HumanDuration>("9223372036854775").unwrap_err().to_string().contains("duration `9223372036854775` is longer than 100 years"));
+        assert_eq!("36500d".parse::<HumanDuration>(), Ok(HumanDuration::from_millis(MAX_MILLIS)));
+        assert!(serde_yaml::from_str::<HumanDuration>("-1").is_err());
+        assert!(serde_yaml::from_str::<HumanDuration>(r#""5x""#).unwrap_err().to_string().contains("invalid duration `5x`"));
+    }
+
     #[test]
     fn read_settings_can_read_a_yaml_file_which_contains_all_settings() {
         let input = indoc! {r#"
             environment:
               webhook_url: "https://discord.com/api/webhooks/XXXX/YYYY"
+              destinations:
+                - url: "https://hooks.slack.com/services/XXXX"
+                  platform:
+                    type: "Slack"
+                  accepted_statuses: [200]
+              strategy: "Failover"
+              accepted_statuses: [200, 202]
               weight_type:
                 type: "Uniform"
               initial_count_type: "Min"
+              seed: 42
+              stable_order: true
+              min_interval: 3600
+              quiet_hours:
+                from: "22:00"
+                to: "07:00"
+              post_interval: "1d"
+              post_interval_jitter: 600
+              new_message_boost:
+                boost: 4.0
+                boost_decay: 0.8
+              stale_boost:
+                after: 2592000
+                factor: 3.0
+              preferred_hours_factor: 5.0
+              exclusion_runs: 2
+              grab_bag: true
+              grab_bag_done_message: "abc"
+              metrics_path: "/var/lib/node_exporter/random_poster.prom"
+              raw_embeds: true
+              history_file: "conf/history.jsonl"
+              dead_letter_file: "conf/dead-letters.jsonl"
+              retry:
+                max_attempts: 3
+                initial_delay: "500ms"
+                max_delay: "5s"
+                max_total_duration: "1m"
+              decay_rate: 10.0
+              reply_to_previous: true
+              avoid_repeat: true
+              cooldown: 3
+              weight_by_cost: true
+              penalize_failures: true
+              normalize_counts: true
+              max_ratio: 20.0
+              on_corrupt_log: "Reset"
+              on_empty: "Error"
+              lock: "Wait"
+              log_format: "MessagePack"
+              epsilon: 0.01
+              group_smoothing: 1.0
+              flags: 4
+              signing_secret: "secret"
+              signature_header: "X-Hub-Signature"
+              thread_name: "daily post"
+              compress: true
+              combine_separator: "\n\n"
+              transformers:
+                - type: "Prefix"
+                  text: "[tip] "
+              content_prefix: "> "
+              content_suffix: " <"
+              emoji_rotation: ["a", "b"]
               user:
                 name: "user_name"
                 icon_url: "https://example.com/icon.png"
             messages:
               abc: "message1"
               def: "message2"
+            defaults:
+              embed:
+                color: 16744448
+                footer:
+                  text: "footer"
         "#};
         let expected = Settings {
             environment: EnvironmentSettings {
                 webhook_url: String::from("https://discord.com/api/webhooks/XXXX/YYYY"),
+                platform: Platform::Discord,
+                destinations: vec![Destination { url: String::from("https://hooks.slack.com/services/XXXX"), platform: Platform::Slack, accepted_statuses: Some(vec![200]) }],
+                strategy: Strategy::Failover,
+                accepted_statuses: Some(vec![200, 202]),
                 weight_type: WeightType::Uniform,
                 initial_count_type: InitialCountType::Min,
+                seed: Some(42),
+                stable_order: true,
+                min_interval: Some(HumanDuration::from_secs(3600)),
+                quiet_hours: Some(QuietHours { from: NaiveTime::from_hms_opt(22, 0, 0).unwrap(), to: NaiveTime::from_hms_opt(7, 0, 0).unwrap() }),
+                post_interval: Some(HumanDuration::from_secs(86400)),
+                post_interval_jitter: Some(HumanDuration::from_secs(600)),
+                new_message_boost: Some(Boost { boost: 4.0, boost_decay: 0.8 }),
+                stale_boost: Some(StaleBoost { after: HumanDuration::from_secs(2592000), factor: 3.0 }),
+                preferred_hours_factor: Some(5.0),
+                exclusion_runs: Some(2),
+                grab_bag: true,
+                grab_bag_done_message: Some(String::from("abc")),
+                metrics_path: Some(PathBuf::from("/var/lib/node_exporter/random_poster.prom")),
+                raw_embeds: true,
+                history_file: Some(PathBuf::from("conf/history.jsonl")),
+                dead_letter_file: Some(PathBuf::from("conf/dead-letters.jsonl")),
+                retry: Some(RetrySettings { max_attempts: 3, initial_delay: HumanDuration::from_millis(500), max_delay: Some(HumanDuration::from_secs(5)), max_total_duration: Some(HumanDuration::from_secs(60)) }),
+                decay_rate: Some(10.0),
+                reply_to_previous: true,
+                avoid_repeat: true,
+                cooldown: Some(3),
+                weight_by_cost: true,
+                penalize_failures: true,
+                normalize_counts: true,
+                max_ratio: Some(20.0),
+                on_corrupt_log: OnCorruptLog::Reset,
+                on_empty: OnEmpty::Error,
+                lock: Some(LockMode::Wait),
+                log_format: LogFormat::MessagePack,
+                epsilon: Some(0.01),
+                group_smoothing: Some(1.0),
+                flags: Some(4),
+                signing_secret: Some(String::from("secret")),
+                signature_header: Some(String::from("X-Hub-Signature")),
+                thread_name: Some(String::from("daily post")),
+                compress: true,
+                combine_separator: Some(String::from("\n\n")),
+                transformers: vec![Transformer::Prefix { text: String::from("[tip] ") }],
+                content_prefix: Some(String::from("> ")),
+                content_suffix: Some(String::from(" <")),
+                emoji_rotation: vec![String::from("a"), String::from("b")],
                 user_settings: UserSettings {
                     name: Some(String::from("user_name")),
                     icon_url: Some(String::from("https://example.com/icon.png")),
                 },
             },
             messages: vec![
-                (String::from("abc"), Message::String(String::from("message1"))),
-                (String::from("def"), Message::String(String::from("message2"))),
+                (String::from("abc"), Message::String(String::from("message1")).into()),
+                (String::from("def"), Message::String(String::from("message2")).into()),
             ].into_iter().collect(),
+            groups: HashMap::new(),
+            defaults: Defaults { embed: Some(Embed { color: Some(0xFF8000), footer: Some(EmbedFooter { text: String::from("footer"), icon_url: None }), ..Embed::default() }) },
         };
 
         assert_eq!(Ok(expected), from_str(input));
+        let explained_fields = explain_fields(&serde_yaml::from_str(input).unwrap());
+        let defaulted_fields: Vec<&str> = explained_fields.iter()
+            .filter(|(_, source)| *source != FieldSource::File)
+            .map(|(name, _)| name.as_str())
+            .collect();
+        assert_eq!(defaulted_fields, vec!["environment.platform", "groups"]);
     }
 
     #[test]
@@ -101,19 +920,13 @@ mod tests {
               def: "message2"
         "#};
         let expected = Settings {
-            environment: EnvironmentSettings {
-                webhook_url: String::from("https://discord.com/api/webhooks/XXXX/YYYY"),
-                weight_type: WeightType::Uniform,
-                initial_count_type: InitialCountType::Zero,
-                user_settings: UserSettings {
-                    name: None,
-                    icon_url: None,
-                },
-            },
+            environment: default_environment(),
             messages: vec![
-                (String::from("abc"), Message::String(String::from("message1"))),
-                (String::from("def"), Message::String(String::from("message2"))),
+                (String::from("abc"), Message::String(String::from("message1")).into()),
+                (String::from("def"), Message::String(String::from("message2")).into()),
             ].into_iter().collect(),
+            groups: HashMap::new(),
+            defaults: Defaults::default(),
         };
 
         assert_eq!(Ok(expected), from_str(input));
@@ -132,23 +945,34 @@ mod tests {
         "#};
         let expected = Settings {
             environment: EnvironmentSettings {
-                webhook_url: String::from("https://discord.com/api/webhooks/XXXX/YYYY"),
                 weight_type: WeightType::MinOnly,
-                initial_count_type: InitialCountType::Zero,
-                user_settings: UserSettings {
-                    name: None,
-                    icon_url: None,
-                },
+                ..default_environment()
             },
             messages: vec![
-                (String::from("abc"), Message::String(String::from("message1"))),
-                (String::from("def"), Message::String(String::from("message2"))),
+                (String::from("abc"), Message::String(String::from("message1")).into()),
+                (String::from("def"), Message::String(String::from("message2")).into()),
             ].into_iter().collect(),
+            groups: HashMap::new(),
+            defaults: Defaults::default(),
         };
 
         assert_eq!(Ok(expected), from_str(input));
     }
 
+    #[test]
+    fn read_settings_can_read_round_robin_weight() {
+        let input = indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/XXXX/YYYY"
+              weight_type:
+                type: "RoundRobin"
+            messages:
+              abc: "message1"
+        "#};
+
+        assert_eq!(from_str(input).unwrap().environment.weight_type, WeightType::RoundRobin);
+    }
+
     #[test]
     fn read_settings_can_read_linear_weight() {
         let input = indoc! {r#"
@@ -163,18 +987,15 @@ mod tests {
         "#};
         let expected = Settings {
             environment: EnvironmentSettings {
-                webhook_url: String::from("https://discord.com/api/webhooks/XXXX/YYYY"),
                 weight_type: WeightType::Linear { bias: 10.0 },
-                initial_count_type: InitialCountType::Zero,
-                user_settings: UserSettings {
-                    name: None,
-                    icon_url: None,
-                },
+                ..default_environment()
             },
             messages: vec![
-                (String::from("abc"), Message::String(String::from("message1"))),
-                (String::from("def"), Message::String(String::from("message2"))),
+                (String::from("abc"), Message::String(String::from("message1")).into()),
+                (String::from("def"), Message::String(String::from("message2")).into()),
             ].into_iter().collect(),
+            groups: HashMap::new(),
+            defaults: Defaults::default(),
         };
 
         assert_eq!(Ok(expected), from_str(input));
@@ -194,18 +1015,15 @@ mod tests {
         "#};
         let expected = Settings {
             environment: EnvironmentSettings {
-                webhook_url: String::from("https://discord.com/api/webhooks/XXXX/YYYY"),
                 weight_type: WeightType::Boltzmann { beta: 10.0 },
-                initial_count_type: InitialCountType::Zero,
-                user_settings: UserSettings {
-                    name: None,
-                    icon_url: None,
-                },
+                ..default_environment()
             },
             messages: vec![
-                (String::from("abc"), Message::String(String::from("message1"))),
-                (String::from("def"), Message::String(String::from("message2"))),
+                (String::from("abc"), Message::String(String::from("message1")).into()),
+                (String::from("def"), Message::String(String::from("message2")).into()),
             ].into_iter().collect(),
+            groups: HashMap::new(),
+            defaults: Defaults::default(),
         };
 
         assert_eq!(Ok(expected), from_str(input));
@@ -233,17 +1051,9 @@ mod tests {
                       url: "https://example.com/thumbnail2.png"
         "#};
         let expected = Settings {
-            environment: EnvironmentSettings {
-                webhook_url: String::from("https://discord.com/api/webhooks/XXXX/YYYY"),
-                weight_type: WeightType::Uniform,
-                initial_count_type: InitialCountType::Zero,
-                user_settings: UserSettings {
-                    name: None,
-                    icon_url: None,
-                },
-            },
+            environment: default_environment(),
             messages: vec![
-                (String::from("abc"), Message::String(String::from("message1"))),
+                (String::from("abc"), Message::String(String::from("message1")).into()),
                 (String::from("def"), Message::WithEmbeds {
                     content: Some(String::from("message2")),
                     embeds: vec![
@@ -262,13 +1072,665 @@ mod tests {
                             },
                         }),
                     ],
-                }),
+                    plain_fallback: None,
+                }.into()),
+            ].into_iter().collect(),
+            groups: HashMap::new(),
+            defaults: Defaults::default(),
+        };
+
+        assert_eq!(Ok(expected), from_str(input));
+    }
+
+    #[test]
+    fn read_settings_can_read_attachment_messages() {
+        let input = indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/XXXX/YYYY"
+              weight_type:
+                type: "Uniform"
+            messages:
+              abc:
+                content: "message1"
+                file: "conf/image1.png"
+              def:
+                file: "conf/image2.png"
+        "#};
+        let expected = Settings {
+            environment: default_environment(),
+            messages: vec![
+                (String::from("abc"), Message::WithAttachment {
+                    content: Some(String::from("message1")),
+                    file: PathBuf::from("conf/image1.png"),
+                }.into()),
+                (String::from("def"), Message::WithAttachment {
+                    content: None,
+                    file: PathBuf::from("conf/image2.png"),
+                }.into()),
             ].into_iter().collect(),
+            groups: HashMap::new(),
+            defaults: Defaults::default(),
         };
 
         assert_eq!(Ok(expected), from_str(input));
     }
 
+    #[test]
+    fn read_settings_can_read_telegram_platform() {
+        let input = indoc! {r#"
+            environment:
+              webhook_url: "https://api.telegram.org/botXXXX/sendMessage"
+              platform:
+                type: "Telegram"
+                chat_id: "-1001234567890"
+              weight_type:
+                type: "Uniform"
+            messages:
+              abc: "message1"
+        "#};
+
+        let settings = from_str(input).unwrap();
+        assert_eq!(settings.environment.platform, Platform::Telegram { chat_id: String::from("-1001234567890") });
+    }
+
+    #[test]
+    fn read_settings_can_read_destinations() {
+        let input = indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/XXXX/YYYY"
+              destinations:
+                - url: "https://hooks.slack.com/services/XXXX"
+                  platform:
+                    type: "Slack"
+                - url: "https://discord.com/api/webhooks/ZZZZ/WWWW"
+              weight_type:
+                type: "Uniform"
+            messages:
+              abc: "message1"
+        "#};
+        let expected = vec![
+            Destination { url: String::from("https://hooks.slack.com/services/XXXX"), platform: Platform::Slack, accepted_statuses: None },
+            Destination { url: String::from("https://discord.com/api/webhooks/ZZZZ/WWWW"), platform: Platform::Discord, accepted_statuses: None },
+        ];
+
+        assert_eq!(from_str(input).unwrap().environment.destinations, expected);
+    }
+
+    #[test]
+    fn read_settings_should_fail_if_the_url_of_a_destination_is_invalid() {
+        let input = indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/XXXX/YYYY"
+              destinations:
+                - url: "https://hooks.slack.com/services/XXXX"
+                  platform:
+                    type: "Slack"
+                - url: "https://example.com/webhook"
+              weight_type:
+                type: "Uniform"
+            messages:
+              abc: "message1"
+        "#};
+
+        assert_eq!(from_str(input), Err(String::from("failed to read settings: destinations[1].url is not a valid URL: the host must be discord.com or discordapp.com, but was example.com")));
+    }
+
+    #[test]
+    fn read_settings_can_read_message_groups() {
+        let input = indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/XXXX/YYYY"
+              weight_type:
+                type: "Uniform"
+            messages:
+              abc: "message1"
+              def:
+                content: "message2"
+                embeds: []
+                group: "group1"
+              ghi:
+                file: "conf/image.png"
+                group: "group2"
+        "#};
+        let expected: HashMap<String, MessageSettings> = vec![
+            (String::from("abc"), Message::String(String::from("message1")).into()),
+            (String::from("def"), MessageSettings {
+                group: Some(String::from("group1")),
+                ..Message::WithEmbeds { content: Some(String::from("message2")), embeds: vec![], plain_fallback: None }.into()
+            }),
+            (String::from("ghi"), MessageSettings {
+                group: Some(String::from("group2")),
+                ..Message::WithAttachment { content: None, file: PathBuf::from("conf/image.png") }.into()
+            }),
+        ].into_iter().collect();
+
+        assert_eq!(from_str(input).unwrap().messages, expected);
+    }
+
+    #[test]
+    fn read_settings_can_read_per_message_user_settings() {
+        let input = indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/XXXX/YYYY"
+              weight_type:
+                type: "Uniform"
+            messages:
+              abc:
+                content: "message1"
+                embeds: []
+                user:
+                  name: "persona"
+        "#};
+        let expected = MessageSettings {
+            user_settings: UserSettings { name: Some(String::from("persona")), icon_url: None },
+            ..Message::WithEmbeds { content: Some(String::from("message1")), embeds: vec![], plain_fallback: None }.into()
+        };
+
+        assert_eq!(from_str(input).unwrap().messages["abc"], expected);
+    }
+
+    #[test]
+    fn read_settings_can_read_group_settings() {
+        let input = indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/XXXX/YYYY"
+              weight_type:
+                type: "Uniform"
+            messages:
+              abc: "message1"
+            groups:
+              group1:
+                weight_type:
+                  type: "MinOnly"
+              group2: {}
+        "#};
+        let expected: HashMap<String, GroupSettings> = vec![
+            (String::from("group1"), GroupSettings { weight_type: Some(WeightType::MinOnly) }),
+            (String::from("group2"), GroupSettings { weight_type: None }),
+        ].into_iter().collect();
+
+        assert_eq!(from_str(input).unwrap().groups, expected);
+    }
+
+    #[test]
+    fn read_settings_should_fail_if_an_embed_has_an_unknown_key() {
+        let input = indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/XXXX/YYYY"
+              weight_type:
+                type: "Uniform"
+            messages:
+              abc:
+                embeds:
+                  - titel: "title"
+        "#};
+
+        let result = from_str(input);
+        assert!(result.as_ref().unwrap_err().starts_with("failed to read settings: invalid message `abc`: embed 1: unknown field `titel`"), "{:?}", result);
+    }
+
+    #[test]
+    fn read_settings_should_accept_unknown_embed_keys_with_raw_embeds() {
+        let input = indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/XXXX/YYYY"
+              weight_type:
+                type: "Uniform"
+              raw_embeds: true
+            messages:
+              abc:
+                embeds:
+                  - provider:
+                      name: "provider"
+        "#};
+
+        let expected = Message::WithEmbeds { content: None, embeds: vec![serde_json::json!({ "provider": { "name": "provider" } })], plain_fallback: None };
+        assert_eq!(from_str(input).unwrap().messages["abc"].message, expected);
+    }
+
+    #[test]
+    fn read_settings_with_overlay_should_replace_only_the_values_in_the_overlay() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("settings.yaml");
+        std::fs::write(&path, indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/XXXX/YYYY"
+              weight_type:
+                type: "Uniform"
+              user:
+                name: "username"
+              cooldown: 2
+              destinations:
+                - url: "https://discord.com/api/webhooks/AAAA/BBBB"
+            messages:
+              abc: "message1"
+              def: "message2"
+        "#}).unwrap();
+        let overlay_path = overlay_path(&path, "prod").unwrap();
+        std::fs::write(&overlay_path, indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/ZZZZ/WWWW"
+        "#}).unwrap();
+
+        let base = read_settings(&path).unwrap();
+        let settings = read_settings_with_overlay(&path, Some(&overlay_path)).unwrap();
+        assert_eq!(overlay_path, dir.path().join("settings.prod.yaml"));
+        assert_eq!(settings.environment.webhook_url, "https://discord.com/api/webhooks/ZZZZ/WWWW");
+        assert_eq!(Settings { environment: EnvironmentSettings { webhook_url: base.environment.webhook_url.clone(), ..settings.environment }, ..settings }, base);
+    }
+
+    #[test]
+    fn read_settings_with_overlay_should_merge_mappings_and_replace_sequences() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("settings.yaml");
+        std::fs::write(&path, indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/XXXX/YYYY"
+              weight_type:
+                type: "Uniform"
+              user:
+                name: "username"
+                icon_url: "https://example.com/icon.png"
+              destinations:
+                - url: "https://discord.com/api/webhooks/AAAA/BBBB"
+                - url: "https://discord.com/api/webhooks/CCCC/DDDD"
+            messages:
+              abc: "message1"
+        "#}).unwrap();
+        let overlay_path = dir.path().join("settings.prod.yaml");
+        std::fs::write(&overlay_path, indoc! {r#"
+            environment:
+              user:
+                name: "production"
+              destinations:
+                - url: "https://discord.com/api/webhooks/EEEE/FFFF"
+            messages:
+              def: "message2"
+        "#}).unwrap();
+
+        let settings = read_settings_with_overlay(&path, Some(&overlay_path)).unwrap();
+        assert_eq!(settings.environment.user_settings.name.as_deref(), Some("production"));
+        assert_eq!(settings.environment.user_settings.icon_url.as_deref(), Some("https://example.com/icon.png"));
+        let urls: Vec<&str> = settings.environment.destinations.iter().map(|destination| destination.url.as_str()).collect();
+        assert_eq!(urls, vec!["https://discord.com/api/webhooks/EEEE/FFFF"]);
+        let mut ids: Vec<&String> = settings.messages.keys().collect();
+        ids.sort();
+        assert_eq!(ids, vec!["abc", "def"]);
+    }
+
+    #[test]
+    fn overlay_path_should_fail_for_the_standard_input() {
+        assert_eq!(overlay_path(Path::new(STDIN_PATH), "prod"), Err(String::from("an overlay cannot be used with the settings from the standard input")));
+    }
+
+    #[test]
+    fn read_settings_should_resolve_external_weights_relative_to_the_settings_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("settings.yaml");
+        std::fs::write(&path, indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/XXXX/YYYY"
+              weight_type:
+                type: "External"
+                path: "weights.csv"
+            messages:
+              abc: "message1"
+            groups:
+              group1:
+                weight_type:
+                  type: "External"
+                  path: "/var/lib/weights.csv"
+        "#}).unwrap();
+
+        let settings = read_settings(&path).unwrap();
+        assert_eq!(settings.environment.weight_type, WeightType::External { path: dir.path().join("weights.csv") });
+        assert_eq!(settings.groups["group1"].weight_type, Some(WeightType::External { path: PathBuf::from("/var/lib/weights.csv") }));
+    }
+
+    #[test]
+    fn read_settings_should_fail_if_there_are_no_messages() {
+        let input = indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/XXXX/YYYY"
+              weight_type:
+                type: "Uniform"
+            messages: {}
+        "#};
+
+        assert_eq!(from_str(input), Err(String::from("settings.messages must contain at least one message")));
+    }
+
+    #[test]
+    fn read_settings_can_read_meta_fields() {
+        let input = indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/XXXX/YYYY"
+              weight_type:
+                type: "Uniform"
+            messages:
+              abc:
+                content: "message1"
+                meta:
+                  note: "posted on the first day"
+              def:
+                content: "message2"
+                embeds: []
+                meta:
+                  note: "with embeds"
+        "#};
+        let expected: HashMap<String, MessageSettings> = vec![
+            (String::from("abc"), MessageSettings {
+                meta: vec![(String::from("note"), serde_yaml::Value::from("posted on the first day"))].into_iter().collect(),
+                ..Message::WithEmbeds { content: Some(String::from("message1")), embeds: vec![], plain_fallback: None }.into()
+            }),
+            (String::from("def"), MessageSettings {
+                meta: vec![(String::from("note"), serde_yaml::Value::from("with embeds"))].into_iter().collect(),
+                ..Message::WithEmbeds { content: Some(String::from("message2")), embeds: vec![], plain_fallback: None }.into()
+            }),
+        ].into_iter().collect();
+
+        assert_eq!(from_str(input).unwrap().messages, expected);
+    }
+
+    #[test]
+    fn read_settings_should_fail_if_a_message_is_invalid() {
+        let input = indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/XXXX/YYYY"
+              weight_type:
+                type: "Uniform"
+            messages:
+              abc:
+                group: "group1"
+        "#};
+
+        let error = from_str(input).unwrap_err();
+        assert!(error.starts_with("failed to read settings: invalid message `abc`: must contain content, embeds or a file"), "{}", error);
+    }
+
+    #[test]
+    fn read_settings_should_fail_if_message_ids_are_duplicated() {
+        let input = indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/XXXX/YYYY"
+              weight_type:
+                type: "Uniform"
+            messages:
+              abc: &message "message1"
+              def: "message2"
+              abc: *message
+              ghi: "message3"
+              def: "message4"
+        "#};
+
+        let error = from_str(input).unwrap_err();
+        assert!(error.starts_with("failed to read settings: duplicate message ids: `abc`, `def`"), "{}", error);
+    }
+
+    #[test]
+    fn read_settings_can_read_one_of_messages() {
+        let input = indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/XXXX/YYYY"
+              weight_type:
+                type: "Uniform"
+            messages:
+              abc:
+                - "hello"
+                - content: "hi"
+                  embeds:
+                    - title: "title"
+        "#};
+
+        let expected = Message::OneOf(vec![
+            Message::String(String::from("hello")),
+            Message::WithEmbeds { content: Some(String::from("hi")), embeds: vec![serde_json::json!({ "title": "title" })], plain_fallback: None },
+        ]);
+        assert_eq!(from_str(input).unwrap().messages["abc"].message, expected);
+    }
+
+    #[test]
+    fn read_settings_should_fail_if_one_of_is_empty() {
+        let input = indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/XXXX/YYYY"
+              weight_type:
+                type: "Uniform"
+            messages:
+              abc: []
+        "#};
+
+        let error = from_str(input).unwrap_err();
+        assert!(error.starts_with("failed to read settings: invalid message `abc`: must contain at least one message"), "{}", error);
+    }
+
+    #[test]
+    fn read_settings_should_fail_if_a_cost_is_negative() {
+        let input = indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/XXXX/YYYY"
+              weight_type:
+                type: "Uniform"
+            messages:
+              abc:
+                content: "message1"
+                cost: -1.0
+        "#};
+
+        let error = from_str(input).unwrap_err();
+        assert!(error.starts_with("failed to read settings: invalid message `abc`: cost must be finite and non-negative"), "{}", error);
+    }
+
+    #[test]
+    fn read_settings_should_fail_if_an_importance_is_not_positive() {
+        let input = indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/XXXX/YYYY"
+              weight_type:
+                type: "Uniform"
+            messages:
+              abc:
+                content: "message1"
+                importance: 0.0
+        "#};
+
+        let error = from_str(input).unwrap_err();
+        assert!(error.starts_with("failed to read settings: invalid message `abc`: importance must be finite and positive"), "{}", error);
+    }
+
+    #[test]
+    fn read_settings_should_fail_if_a_target_is_negative() {
+        let input = indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/XXXX/YYYY"
+              weight_type:
+                type: "Target"
+            messages:
+              abc:
+                content: "message1"
+                target: -0.5
+        "#};
+
+        let error = from_str(input).unwrap_err();
+        assert!(error.starts_with("failed to read settings: invalid message `abc`: target must be finite and non-negative"), "{}", error);
+    }
+
+    #[test]
+    fn validate_webhook_url_should_accept_discord_webhook_urls() {
+        assert_eq!(validate_webhook_url("https://discord.com/api/webhooks/XXXX/YYYY", &Platform::Discord), Ok(()));
+        assert_eq!(validate_webhook_url("https://discordapp.com/api/webhooks/XXXX/YYYY", &Platform::Discord), Ok(()));
+        assert_eq!(validate_webhook_url("https://canary.discord.com/api/webhooks/XXXX/YYYY", &Platform::Discord), Ok(()));
+    }
+
+    #[test]
+    fn validate_webhook_url_should_reject_malformed_urls() {
+        assert_eq!(validate_webhook_url("discord webhook", &Platform::Discord), Err(String::from("relative URL without a base")));
+        assert_eq!(validate_webhook_url("http://discord.com/api/webhooks/XXXX/YYYY", &Platform::Discord), Err(String::from("the scheme must be https, but was http")));
+        assert_eq!(validate_webhook_url("https://example.com/api/webhooks/XXXX/YYYY", &Platform::Discord), Err(String::from("the host must be discord.com or discordapp.com, but was example.com")));
+        assert_eq!(validate_webhook_url("https://notdiscord.com/api/webhooks/XXXX/YYYY", &Platform::Discord), Err(String::from("the host must be discord.com or discordapp.com, but was notdiscord.com")));
+        assert_eq!(validate_webhook_url("https://discord.com/channels/XXXX/YYYY", &Platform::Discord), Err(String::from("the path must start with /api/webhooks/")));
+    }
+
+    #[test]
+    fn validate_webhook_url_should_not_check_the_host_for_other_platforms() {
+        let platform = Platform::Matrix { room_id: String::from("!room:example.com"), access_token: String::from("token") };
+        assert_eq!(validate_webhook_url("https://matrix.example.com", &platform), Ok(()));
+        assert_eq!(validate_webhook_url("http://matrix.example.com", &platform), Err(String::from("the scheme must be https, but was http")));
+    }
+
+    #[test]
+    fn read_settings_should_fail_if_the_webhook_url_is_invalid() {
+        let input = indoc! {r#"
+            environment:
+              webhook_url: "https://example.com/webhook"
+              weight_type:
+                type: "Uniform"
+            messages:
+              abc: "message1"
+        "#};
+
+        assert_eq!(from_str(input), Err(String::from("failed to read settings: webhook_url is not a valid URL: the host must be discord.com or discordapp.com, but was example.com")));
+    }
+
+    #[test]
+    fn check_settings_should_return_no_problems_for_valid_settings() {
+        let input = indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/XXXX/YYYY"
+              weight_type:
+                type: "Linear"
+                bias: 1.0
+            messages:
+              abc: "message1"
+              def:
+                content: "message2"
+                embeds:
+                  - title: "title"
+        "#};
+
+        assert_eq!(check_settings(&from_str(input).unwrap()), Vec::<String>::new());
+    }
+
+    #[test]
+    fn check_settings_should_report_all_problems() {
+        let input = indoc! {r#"
+            environment:
+              webhook_url: "discord webhook"
+              weight_type:
+                type: "Linear"
+                bias: -1.0
+              new_message_boost:
+                boost: 1.0
+                boost_decay: 2.0
+              accepted_statuses: []
+              exclusion_runs: 0
+              grab_bag_done_message: "done"
+            messages:
+              abc:
+                content: "message1"
+                excludes: ["abc", "old"]
+            groups:
+              group1:
+                weight_type:
+                  type: "Boltzmann"
+                  beta: .nan
+        "#};
+        let expected = vec![
+            String::from("webhook_url is not a valid URL: relative URL without a base"),
+            String::from("weight_type: bias must be positive"),
+            String::from("new_message_boost: boost_decay must be between 0 and 1"),
+            String::from("exclusion_runs must be positive"),
+            String::from("grab_bag_done_message `done` is not in the settings"),
+            String::from("accepted_statuses must not be empty"),
+            String::from("group `group1`: weight_type: beta must not be NaN"),
+            String::from("message `abc`: excludes `old`, which is not in the settings"),
+        ];
+
+        // read_settings would stop at the invalid URL
+        assert_eq!(check_settings(&serde_yaml::from_str(input).unwrap()), expected);
+    }
+
+    #[test]
+    fn check_settings_should_report_invalid_embeds() {
+        let input = indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/XXXX/YYYY"
+              weight_type:
+                type: "Uniform"
+              raw_embeds: true
+            messages:
+              abc:
+                embeds:
+                  - title: 1
+              def: "message2"
+              ghi:
+                embeds:
+                  - "embed"
+        "#};
+        let expected = vec![
+            String::from("message `abc`: invalid embeds: embed 1: title must be a string"),
+            String::from("message `ghi`: invalid embeds: embed 1 must be a map"),
+        ];
+
+        assert_eq!(check_settings(&from_str(input).unwrap()), expected);
+    }
+
+    #[test]
+    fn check_settings_should_report_files_with_the_signing_secret() {
+        let input = indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/XXXX/YYYY"
+              weight_type:
+                type: "Uniform"
+              signing_secret: "secret"
+            messages:
+              abc:
+                file: "image.png"
+              def: "message2"
+        "#};
+        let expected = vec![String::from("message `abc`: a file cannot be posted with signing_secret, which cannot sign a multipart body")];
+
+        assert_eq!(check_settings(&from_str(input).unwrap()), expected);
+    }
+
+    #[test]
+    fn read_settings_from_reader_should_read_the_settings_and_resolve_paths_against_the_base_path() {
+        let input = indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/XXXX/YYYY"
+              weight_type:
+                type: "External"
+                path: "weights.csv"
+            messages:
+              abc: "message1"
+        "#};
+
+        let settings = read_settings_from_reader(std::io::Cursor::new(input), Path::new("conf")).unwrap();
+        assert_eq!(settings.environment.weight_type, WeightType::External { path: PathBuf::from("conf/weights.csv") });
+        assert_eq!(settings.messages["abc"], Message::String(String::from("message1")).into());
+    }
+
+    #[test]
+    fn explain_fields_should_report_omitted_and_unknown_fields() {
+        let input = indoc! {r#"
+            environment:
+              webhook_url: "https://discord.com/api/webhooks/XXXX/YYYY"
+              weight_type:
+                type: "Uniform"
+              intial_count_type: "Min"
+              seed: 42
+            messages:
+              abc: "message1"
+        "#};
+
+        let explained_fields = explain_fields(&serde_yaml::from_str(input).unwrap());
+        let source = |name: &str| explained_fields.iter().find(|(field, _)| field == name).map(|(_, source)| *source);
+        assert_eq!(source("environment.initial_count_type"), Some(FieldSource::Default));
+        assert_eq!(source("environment.seed"), Some(FieldSource::File));
+        assert_eq!(source("environment.intial_count_type"), Some(FieldSource::Unknown));
+        assert_eq!(source("environment.webhook_url"), None);
+    }
+
     fn from_str(input: &str) -> Result<Settings, String> {
         let mut file = NamedTempFile::new().unwrap();
         write!(file, "{}", input).unwrap();
@@ -1,74 +1,356 @@
-use serde::{Deserialize};
+use serde::{Deserialize, Deserializer, de::Error};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
 
+use super::expr::Expr;
+
+// `remote = "Self"` derives an inherent `deserialize` for the map form, which the `Deserialize` impl below falls back to.
 #[derive(PartialEq, Clone, Deserialize, Debug)]
-#[serde(tag = "type")]
+#[serde(tag = "type", remote = "Self")]
 pub enum WeightType {
     Uniform,
     MinOnly,
+    /// Picks from the messages whose counts are at most `tolerance` above the minimum, so that new messages do not take every post until they catch up.
+    MinOnlySmooth { tolerance: u32 },
+    /// An alias of `MinOnly`, which posts every message once before any repeats, but not in a fixed order.
+    /// There is no cursor in the log, so `epsilon` and the groups make it pick other messages just as they do for `MinOnly`.
+    RoundRobin,
     Linear { bias: f64 },
     Boltzmann { beta: f64 },
+    /// Uses the weights in an `id,weight` CSV file, which are read by `read_external_weights`, and 1 for the ids missing from it.
+    External { path: PathBuf },
+    /// Picks the messages whose observed fraction is furthest below their `target`, which is given to `RandomPicker::set_targets`.
+    Target,
+    /// Evaluates `formula` for each message with the variables in `EXPR_VARIABLES`, using negative results as 0 and keeping infinite results for `normalize_weights`.
+    Expr { formula: String },
+    /// Picks only from the messages posted the longest ago like a round robin by time, with the current time given to `RandomPickerBuilder::now`, and the never posted messages first.
+    OldestFirst,
+}
+
+// Also accepts the name alone, such as `weight_type: "Uniform"`, for the variants without parameters.
+impl<'de> Deserialize<'de> for WeightType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<WeightType, D::Error> {
+        let value = match serde_yaml::Value::deserialize(deserializer)? {
+            serde_yaml::Value::String(name) => {
+                let mut map = serde_yaml::Mapping::new();
+                map.insert(serde_yaml::Value::from("type"), serde_yaml::Value::from(name));
+                serde_yaml::Value::Mapping(map)
+            },
+            value => value,
+        };
+        WeightType::deserialize(value).map_err(D::Error::custom)
+    }
+}
+
+// `index` is the position of the message in the counts given to `get_weights`.
+const EXPR_VARIABLES: &[&str] = &["count", "min", "max", "index"];
+
+// The counts which the weights are computed from, which are fractional once they are divided by the importances.
+pub trait Count: Copy + PartialOrd {
+    fn as_f64(self) -> f64;
+    // `self - other`, or 0 if `other` is larger
+    fn difference(self, other: Self) -> f64;
+}
+
+impl Count for u64 {
+    fn as_f64(self) -> f64 {
+        self as f64
+    }
+
+    fn difference(self, other: u64) -> f64 {
+        self.saturating_sub(other) as f64
+    }
+}
+
+impl Count for f64 {
+    fn as_f64(self) -> f64 {
+        self
+    }
+
+    fn difference(self, other: f64) -> f64 {
+        (self - other).max(0.0)
+    }
+}
+
+fn min_count<C: Count>(counts: &[C]) -> C {
+    counts.iter().copied().reduce(|min, count| if count < min { count } else { min }).unwrap()
+}
+
+fn max_count<C: Count>(counts: &[C]) -> C {
+    counts.iter().copied().reduce(|max, count| if count > max { count } else { max }).unwrap()
 }
 
 impl WeightType {
-    pub fn get_weights(&self, counts: &Vec<u32>) -> Vec<f64> {
-        match self {
-            &WeightType::Uniform => vec![1.0; counts.len()],
-            &WeightType::MinOnly => {
-                let min_count = counts.iter().min().unwrap();
-                counts.iter().map(|count| if count == min_count { 1.0 } else { 0.0 }).collect()
+    pub fn get_weights(&self, counts: &[u64]) -> Vec<f64> {
+        self.get_count_weights(counts)
+    }
+
+    pub fn get_count_weights<C: Count>(&self, counts: &[C]) -> Vec<f64> {
+        let weights: Vec<f64> = match *self {
+            WeightType::Uniform | WeightType::External { .. } | WeightType::Target | WeightType::OldestFirst => vec![1.0; counts.len()],
+            WeightType::MinOnly | WeightType::RoundRobin => {
+                let min_count = min_count(counts);
+                counts.iter().map(|count| if *count == min_count { 1.0 } else { 0.0 }).collect()
             },
-            &WeightType::Linear { bias } => {
-                let max_count = counts.iter().max().unwrap();
-                counts.iter().map(|count| (max_count - *count) as f64 + bias).collect()
+            WeightType::MinOnlySmooth { tolerance } => {
+                let min_count = min_count(counts);
+                counts.iter().map(|count| if count.difference(min_count) <= tolerance as f64 { 1.0 } else { 0.0 }).collect()
             },
-            &WeightType::Boltzmann { beta } => {
-                let min_count = counts.iter().min().unwrap();
-                counts.iter().map(|count| (- beta * (count - min_count) as f64).exp()).collect()
+            WeightType::Linear { bias } => {
+                let max_count = max_count(counts);
+                counts.iter().map(|count| max_count.difference(*count) + bias).collect()
             },
+            WeightType::Boltzmann { beta } => {
+                let min_count = min_count(counts);
+                counts.iter().map(|count| {
+                    let difference = count.difference(min_count);
+                    if difference == 0.0 { 1.0 } else { (- beta * difference).exp() }
+                }).collect()
+            },
+            WeightType::Expr { ref formula } => {
+                let expr = Expr::parse(formula, EXPR_VARIABLES).expect("the formula must be validated");
+                let min_count = min_count(counts).as_f64();
+                let max_count = max_count(counts).as_f64();
+                counts.iter().enumerate().map(|(index, count)| {
+                    let weight = expr.evaluate(&|name| match name {
+                        "count" => Some(count.as_f64()),
+                        "min" => Some(min_count),
+                        "max" => Some(max_count),
+                        "index" => Some(index as f64),
+                        _ => None,
+                    });
+                    if weight.is_nan() { 0.0 } else { weight.max(0.0) }
+                }).collect()
+            },
+        };
+        debug_assert!(weights.iter().all(|weight| *weight >= 0.0), "weights must be non-negative: {:?}", weights);
+        weights
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        match *self {
+            WeightType::Uniform => Ok(()),
+            WeightType::MinOnly => Ok(()),
+            WeightType::MinOnlySmooth { .. } => Ok(()),
+            WeightType::RoundRobin => Ok(()),
+            WeightType::Linear { bias } => if bias.is_nan() || bias < 0.0 { Err(String::from("bias must be positive")) } else { Ok(()) }
+            WeightType::Boltzmann { beta } => if beta.is_nan() { Err(String::from("beta must not be NaN")) } else { Ok(()) }
+            WeightType::External { .. } => Ok(()),
+            WeightType::Target => Ok(()),
+            WeightType::OldestFirst => Ok(()),
+            WeightType::Expr { ref formula } => Expr::parse(formula, EXPR_VARIABLES).map(|_| ()).map_err(|e| format!("invalid formula: {}", e)),
         }
     }
+}
+
+pub fn read_external_weights(path: &Path) -> Result<HashMap<String, f64>, String> {
+    let file = File::open(path).map_err(|_| format!("could not open file: {}", path.display()))?;
+    let to_error = |line_number: usize, message: String| format!("failed to read weights: {} line {}: {}", path.display(), line_number, message);
 
-    pub fn validate(&self) -> Result<(), &str> {
-        match self {
-            &WeightType::Uniform => Ok(()),
-            &WeightType::MinOnly => Ok(()),
-            &WeightType::Linear { bias } => if bias.is_nan() || bias < 0.0 { Err("bias must be positive") } else { Ok(()) }
-            &WeightType::Boltzmann { beta } => if beta.is_nan() { Err("beta must not be NaN") } else { Ok(()) }
+    let mut weights = HashMap::new();
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| to_error(index + 1, e.to_string()))?;
+        let line = line.trim();
+        if line.is_empty() || (index == 0 && line == "id,weight") {
+            continue;
         }
+
+        let (id, weight) = line.rsplit_once(',').ok_or_else(|| to_error(index + 1, String::from("expected `id,weight`")))?;
+        let weight: f64 = weight.trim().parse().map_err(|_| to_error(index + 1, format!("invalid weight `{}`", weight.trim())))?;
+        if !weight.is_finite() || weight < 0.0 {
+            return Err(to_error(index + 1, format!("weight must be finite and non-negative, but is {}", weight)));
+        }
+        weights.insert(id.trim().to_owned(), weight);
     }
+    Ok(weights)
 }
 
 #[cfg(test)]
 mod tests {
+    extern crate indoc;
+    extern crate tempfile;
+
     use super::*;
+    use indoc::indoc;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn deserialize_should_accept_the_name_alone_as_well_as_the_map_form() {
+        for name in ["Uniform", "MinOnly", "RoundRobin", "Target", "OldestFirst"] {
+            let shorthand: WeightType = serde_yaml::from_str(&format!("\"{}\"", name)).unwrap();
+            let map: WeightType = serde_yaml::from_str(&format!("type: \"{}\"", name)).unwrap();
+            assert_eq!(shorthand, map);
+        }
+        let map: WeightType = serde_yaml::from_str("{ type: \"Boltzmann\", beta: 2.0 }").unwrap();
+        assert_eq!(map, WeightType::Boltzmann { beta: 2.0 });
+    }
+
+    #[test]
+    fn deserialize_should_require_the_map_form_for_the_variants_with_parameters() {
+        let error = serde_yaml::from_str::<WeightType>("\"Boltzmann\"").unwrap_err();
+        assert!(error.to_string().contains("missing field `beta`"), "{}", error);
+    }
 
     #[test]
     fn get_weights_should_return_uniform_weights() {
-        let weights = WeightType::Uniform.get_weights(&vec![2, 1, 3, 4]);
+        let weights = WeightType::Uniform.get_weights(&[2, 1, 3, 4]);
         assert_eq!(weights, vec![1.0, 1.0, 1.0, 1.0]);
     }
 
     #[test]
     fn get_weights_should_return_min_only_weights() {
-        let weights = WeightType::MinOnly.get_weights(&vec![2, 1, 3, 4]);
+        let weights = WeightType::MinOnly.get_weights(&[2, 1, 3, 4]);
         assert_eq!(weights, vec![0.0, 1.0, 0.0, 0.0]);
     }
 
     #[test]
     fn get_weights_should_return_min_only_weights_if_all_the_values_are_the_same() {
-        let weights = WeightType::MinOnly.get_weights(&vec![0, 0, 0, 0]);
+        let weights = WeightType::MinOnly.get_weights(&[0, 0, 0, 0]);
         assert_eq!(weights, vec![1.0, 1.0, 1.0, 1.0]);
     }
 
+    #[test]
+    fn get_weights_should_return_min_only_smooth_weights() {
+        let weights = WeightType::MinOnlySmooth { tolerance: 1 }.get_weights(&[2, 1, 3, 4]);
+        assert_eq!(weights, vec![1.0, 1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn get_weights_should_return_min_only_weights_if_the_tolerance_is_zero() {
+        let weights = WeightType::MinOnlySmooth { tolerance: 0 }.get_weights(&[2, 1, 3, 1]);
+        assert_eq!(weights, vec![0.0, 1.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn get_weights_should_return_the_same_weights_as_linear_for_an_equivalent_formula() {
+        let counts = [2, 1, 3, 4];
+        let weights = WeightType::Expr { formula: String::from("max - count + 1") }.get_weights(&counts);
+        assert_eq!(weights, WeightType::Linear { bias: 1.0 }.get_weights(&counts));
+    }
+
+    #[test]
+    fn get_weights_should_keep_infinite_formula_results() {
+        let weights = WeightType::Expr { formula: String::from("exp(count)") }.get_weights(&[800, 900, 0]);
+        assert_eq!(weights, vec![f64::INFINITY, f64::INFINITY, 1.0]);
+    }
+
+    #[test]
+    fn get_weights_should_use_zero_for_negative_or_nan_formula_results() {
+        let weights = WeightType::Expr { formula: String::from("index - 1 + min / (count - min)") }.get_weights(&[0, 1, 2]);
+        assert_eq!(weights, vec![0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn validate_should_fail_if_the_formula_is_invalid() {
+        assert_eq!(WeightType::Expr { formula: String::from("max - count + 1") }.validate(), Ok(()));
+        assert_eq!(WeightType::Expr { formula: String::from("max - ") }.validate(), Err(String::from("invalid formula: unexpected end of the formula")));
+        assert_eq!(WeightType::Expr { formula: String::from("total - count") }.validate(), Err(String::from("invalid formula: unknown variable `total`")));
+    }
+
+    #[test]
+    fn get_weights_should_return_round_robin_weights() {
+        let weights = WeightType::RoundRobin.get_weights(&[2, 1, 3, 1]);
+        assert_eq!(weights, vec![0.0, 1.0, 0.0, 1.0]);
+    }
+
     #[test]
     fn get_weights_should_return_linear_weights() {
-        let weights = WeightType::Linear { bias: 0.25 }.get_weights(&vec![2, 1, 3, 4]);
+        let weights = WeightType::Linear { bias: 0.25 }.get_weights(&[2, 1, 3, 4]);
         assert_eq!(weights, vec![2.25, 3.25, 1.25, 0.25]);
     }
 
+    #[test]
+    fn get_weights_should_return_linear_weights_for_large_counts() {
+        let max = u32::MAX as u64;
+        let weights = WeightType::Linear { bias: 0.5 }.get_weights(&[max + 2, max, max - 1]);
+        assert_eq!(weights, vec![0.5, 2.5, 3.5]);
+    }
+
+    #[test]
+    fn get_weights_should_return_boltzmann_weights_for_large_counts() {
+        let max = u32::MAX as u64;
+        let weights = WeightType::Boltzmann { beta: 0.5 }.get_weights(&[max + 2, max, max + 1]);
+        assert_eq!(weights, vec![(-1.0_f64).exp(), 1.0, (-0.5_f64).exp()]);
+    }
+
     #[test]
     fn get_weights_should_return_boltzmann_weights() {
-        let weights = WeightType::Boltzmann { beta: 0.25 }.get_weights(&vec![0, 2, 1, 3, 4]);
+        let weights = WeightType::Boltzmann { beta: 0.25 }.get_weights(&[0, 2, 1, 3, 4]);
         assert_eq!(weights, vec![1.0, (-0.5_f64).exp(), (-0.25_f64).exp(), (-0.75_f64).exp(), (-1.0_f64).exp()]);
     }
+
+    #[test]
+    fn get_weights_should_return_finite_linear_weights_for_widely_spread_counts() {
+        let weights = WeightType::Linear { bias: 1.0 }.get_weights(&[0, u64::MAX, 5]);
+        assert_eq!(weights, vec![u64::MAX as f64 + 1.0, 1.0, (u64::MAX - 5) as f64 + 1.0]);
+        assert!(weights.iter().all(|weight| weight.is_finite()));
+    }
+
+    #[test]
+    fn get_weights_should_return_finite_boltzmann_weights_for_widely_spread_counts() {
+        let weights = WeightType::Boltzmann { beta: 10.0 }.get_weights(&[u64::MAX, 0, u64::MAX - 1]);
+        assert_eq!(weights, vec![0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn get_weights_should_not_return_nan_if_beta_is_infinity() {
+        let weights = WeightType::Boltzmann { beta: f64::INFINITY }.get_weights(&[3, 2, 2, 5]);
+        assert_eq!(weights, vec![0.0, 1.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn get_weights_should_return_the_same_weights_for_all_equal_counts() {
+        let counts = [u64::MAX, u64::MAX, u64::MAX];
+        assert_eq!(WeightType::Linear { bias: 0.5 }.get_weights(&counts), vec![0.5, 0.5, 0.5]);
+        assert_eq!(WeightType::Boltzmann { beta: 0.5 }.get_weights(&counts), vec![1.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn read_external_weights_should_read_a_csv_file() {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, "{}", indoc! {"
+            id,weight
+            abc,2.5
+            def, 0
+
+            ghi,1
+        "}).unwrap();
+
+        let expected: HashMap<String, f64> = vec![
+            (String::from("abc"), 2.5),
+            (String::from("def"), 0.0),
+            (String::from("ghi"), 1.0),
+        ].into_iter().collect();
+        assert_eq!(read_external_weights(file.path()), Ok(expected));
+    }
+
+    #[test]
+    fn read_external_weights_should_fail_if_a_row_is_malformed() {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, "abc,1\ndef\n").unwrap();
+
+        let expected = format!("failed to read weights: {} line 2: expected `id,weight`", file.path().display());
+        assert_eq!(read_external_weights(file.path()), Err(expected));
+    }
+
+    #[test]
+    fn read_external_weights_should_fail_if_a_weight_is_invalid() {
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(file, "abc,high").unwrap();
+
+        let expected = format!("failed to read weights: {} line 1: invalid weight `high`", file.path().display());
+        assert_eq!(read_external_weights(file.path()), Err(expected));
+    }
+
+    #[test]
+    fn read_external_weights_should_fail_if_a_weight_is_negative() {
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(file, "abc,-1").unwrap();
+
+        let expected = format!("failed to read weights: {} line 1: weight must be finite and non-negative, but is -1", file.path().display());
+        assert_eq!(read_external_weights(file.path()), Err(expected));
+    }
 }